@@ -11,6 +11,7 @@ pub enum SvsmPlatformType {
     Native = 0,
     Snp = 1,
     // Tdp = 2,
+    Cca = 3,
 }
 
 impl From<u32> for SvsmPlatformType {
@@ -18,6 +19,7 @@ impl From<u32> for SvsmPlatformType {
         match value {
             1 => Self::Snp,
             // 2 => Self::Tdp,
+            3 => Self::Cca,
             _ => Self::Native,
         }
     }
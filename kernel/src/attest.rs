@@ -7,6 +7,8 @@
 
 extern crate alloc;
 
+#[cfg(feature = "cca")]
+use crate::realm::rsi::rsi_cmd::rsi_attestation_token_vec;
 use crate::{
     error::SvsmError,
     greq::{pld_report::*, services::get_regular_report},
@@ -45,6 +47,7 @@ pub struct AttestationDriver<'a> {
     sp: SerialPort<'a>,
     tee: Tee,
     ecc: EccKey,
+    nv_counter: u64,
 }
 
 impl TryFrom<Tee> for AttestationDriver<'_> {
@@ -58,17 +61,30 @@ impl TryFrom<Tee> for AttestationDriver<'_> {
 
         match tee {
             Tee::Snp => (),
+            #[cfg(feature = "cca")]
+            Tee::Cca => (),
             _ => return Err(AttestationError::UnsupportedTee.into()),
         }
 
         let curve = Curve::new(TpmEccCurve::NistP521).map_err(AttestationError::Crypto)?;
         let ecc = sc_key_generate(&curve).map_err(AttestationError::Crypto)?;
 
-        Ok(Self { sp, tee, ecc })
+        Ok(Self {
+            sp,
+            tee,
+            ecc,
+            nv_counter: 0,
+        })
     }
 }
 
 impl AttestationDriver<'_> {
+    /// Bind the counter of the vTPM NV image that the released secret unseals into the
+    /// attestation request, and into the evidence if the server asks for it.
+    pub fn bind_nv_counter(&mut self, counter: u64) {
+        self.nv_counter = counter;
+    }
+
     /// Attest SVSM's launch state by communicating with the attestation proxy.
     pub fn attest(&mut self) -> Result<Vec<u8>, SvsmError> {
         let negotiation = self.negotiation()?;
@@ -104,13 +120,14 @@ impl AttestationDriver<'_> {
             .to_tpms_ecc_point(&curve.curve_ops().map_err(AttestationError::Crypto)?)
             .map_err(AttestationError::Crypto)?;
 
-        let evidence = evidence(&self.tee, hash(n, &pub_key)?)?;
+        let evidence = evidence(&self.tee, hash(n, &pub_key, self.nv_counter)?)?;
 
         let req = AttestationRequest {
             evidence: BASE64_URL_SAFE.encode(evidence),
             key: (self.ecc.pub_key().get_curve_id(), &pub_key)
                 .try_into()
                 .map_err(|_| AttestationError::AttestationDeserialize)?,
+            nv_counter: self.nv_counter,
         };
 
         self.write(req)?;
@@ -230,6 +247,8 @@ pub enum AttestationError {
     SecretMissing,
     /// Unable to fetch SEV-SNP attestation report.
     SnpGetReport,
+    /// Unable to fetch CCA realm attestation token.
+    CcaGetToken,
     /// Unable to allocate memory for Vec.
     VecAlloc,
 }
@@ -297,6 +316,10 @@ fn evidence(tee: &Tee, hash: Vec<u8>) -> Result<Vec<u8>, AttestationError> {
             // AttestationRequest.
             try_to_vec(resp.report().as_bytes()).or(Err(AttestationError::VecAlloc))?
         }
+        // The negotiation hash is the 64-byte realm challenge, which the RMM includes in the
+        // realm token.
+        #[cfg(feature = "cca")]
+        &Tee::Cca => rsi_attestation_token_vec(&hash).or(Err(AttestationError::CcaGetToken))?,
         // We check for supported TEE architectures in the AttestationDriver's constructor.
         _ => unreachable!(),
     };
@@ -309,6 +332,7 @@ fn evidence(tee: &Tee, hash: Vec<u8>) -> Result<Vec<u8>, AttestationError> {
 fn hash(
     n: NegotiationResponse,
     pub_key: &TpmsEccPoint<'static>,
+    nv_counter: u64,
 ) -> Result<Vec<u8>, AttestationError> {
     let mut sha = Sha512::new();
 
@@ -326,6 +350,7 @@ fn hash(
                 sha.update(&*pub_key.x.buffer);
                 sha.update(&*pub_key.y.buffer);
             }
+            NegotiationParam::NvCounter => sha.update(nv_counter.to_le_bytes()),
         }
     }
 
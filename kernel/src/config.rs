@@ -9,6 +9,7 @@ extern crate alloc;
 use crate::acpi::tables::{load_fw_cpu_info, ACPICPUInfo};
 use crate::address::PhysAddr;
 use crate::error::SvsmError;
+use crate::fdt::DeviceTreeInfo;
 use crate::fw_cfg::FwCfg;
 use crate::igvm_params::IgvmParams;
 use crate::platform::{SevFWMetaData, SvsmPlatform};
@@ -58,6 +59,7 @@ fn check_ovmf_regions(
 pub struct SvsmConfig<'a> {
     fw_cfg: Option<FwCfg<'a>>,
     igvm_params: IgvmParams<'a>,
+    device_tree: Option<&'a DeviceTreeInfo>,
 }
 
 impl<'a> SvsmConfig<'a> {
@@ -77,6 +79,16 @@ impl<'a> SvsmConfig<'a> {
         Self {
             igvm_params,
             fw_cfg,
+            device_tree: None,
+        }
+    }
+
+    /// Uses the platform description from a device tree where it is
+    /// available, instead of the IGVM parameters.
+    pub fn with_device_tree(self, device_tree: &'a DeviceTreeInfo) -> Self {
+        Self {
+            device_tree: Some(device_tree),
+            ..self
         }
     }
 
@@ -91,6 +103,9 @@ impl<'a> SvsmConfig<'a> {
         self.igvm_params.page_state_change_required()
     }
     pub fn get_memory_regions(&self) -> Result<Vec<MemoryRegion<PhysAddr>>, SvsmError> {
+        if let Some(device_tree) = self.device_tree {
+            return Ok(device_tree.memory.clone());
+        }
         self.igvm_params.get_memory_regions()
     }
     pub fn write_guest_memory_map(&self, map: &[MemoryRegion<PhysAddr>]) -> Result<(), SvsmError> {
@@ -11,27 +11,21 @@ use crate::serial::{SerialPort, Terminal, DEFAULT_SERIAL_PORT};
 use crate::utils::immut_after_init::{ImmutAfterInitCell, ImmutAfterInitResult};
 use core::ptr;
 use core::fmt;
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use release::COCONUT_VERSION;
 #[cfg(feature = "cca")]
-use crate::address::VirtAddr;
-#[cfg(feature = "cca")]
-use crate::realm::rsi::rsi_cmd::REALM_CONFIG;
-#[cfg(feature = "cca")]
-use crate::mm::pagetable::PageTable;
-#[cfg(feature = "cca")]
-use crate::mm::pagetable::PTEntry;
-#[cfg(feature = "cca")]
 use crate::address::PhysAddr;
 #[cfg(feature = "cca")]
-use crate::address::Address;
+use crate::realm::mem::map_boot_mmio;
 
 #[derive(Clone, Copy, Debug)]
 struct Console {
     writer: &'static dyn Terminal,
 }
 
-static UART0: usize = 0x0900_0000;
+/// MMIO base of the PL011 UART the console writes to, taken from the debug
+/// serial port passed to [`init_svsm_console`].
+static UART0: AtomicUsize = AtomicUsize::new(0);
 
 impl fmt::Write for Console {
     fn write_str(&mut self, s: &str) -> fmt::Result {
@@ -45,7 +39,7 @@ impl Console {
         for &b in buffer.iter() {
             unsafe {
                 // Direct to write arm uart mmio address
-                ptr::write_volatile(UART0 as *mut u8, b);
+                ptr::write_volatile(UART0.load(Ordering::Relaxed) as *mut u8, b);
             }
         }
     }
@@ -72,6 +66,7 @@ fn init_console(writer: &'static dyn Terminal) -> ImmutAfterInitResult<()> {
 }
 
 pub fn init_svsm_console(writer: &'static dyn IOPort, port: u64) -> Result<(), SvsmError> {
+    UART0.store(port as usize, Ordering::Relaxed);
     CONSOLE_SERIAL
         .init_from_ref(&SerialPort::new(writer, port))
         .map_err(|_| SvsmError::Console)?;
@@ -179,41 +174,16 @@ macro_rules! println {
     ($($arg:tt)*) => (log::info!($($arg)*));
 }
 
-
-// Map uart address in unprotected address
+/// Maps the UART MMIO at `base` through its unprotected alias in the boot
+/// page table, so that accesses are emulated by the host. The page table of
+/// the SVSM maps the UART when it is built.
+///
+/// # Safety
+///
+/// The boot page table must be in use, and only the boot PE may run.
 #[cfg(feature = "cca")]
-pub fn init_mmio_uart() -> Result<(), SvsmError> {
-    let uart_va = VirtAddr::new(UART0);
-
-    let l3_addr = PageTable::get_pte_address(uart_va);
-    let l2_addr = PageTable::get_pte_address(l3_addr);
-
-    let mut l2_pte = unsafe { PTEntry::read_pte(l2_addr) };
-
-    /*
-    if !L2.present() {
-        return Err(SvsmError::General("UART PDE not present"));
-    }
-
-    if !L2.huge() {
-        return Err(SvsmError::General(
-            "UART is not mapped as 2MB block entry",
-        ));
-    }
-    */
-
-    let ipa_bits: u64 = REALM_CONFIG.ipa_bits;
-    let ipa_width: u64 = ipa_bits - 1;
-
-    let frame_mask = 0x0000_FFFF_FFFF_F000;
-    let flag_mask = 0x0000_0000_0000_0FFF;
-
-    let frame_addr: PhysAddr = PhysAddr::from(l2_pte.0.bits() & frame_mask);
-    let flag: usize = l2_pte.0.bits() & frame_mask;
-
-    let unprotected_frame_addr: u64 = (frame_addr.bits() as u64) | ipa_width;
-
-    l2_pte.0 = PhysAddr::from(unprotected_frame_addr | (flag as u64));
-
-    Ok(())
+pub unsafe fn init_mmio_uart(base: PhysAddr) -> Result<(), SvsmError> {
+    // SAFETY: demanded to the caller. The 2MB block of the UART only holds
+    // device MMIO.
+    unsafe { map_boot_mmio(base) }
 }
@@ -88,6 +88,20 @@ fn handle_exception_table_common(
     }
 }
 
+/// Looks up `addr` in the exception table and returns the address to resume
+/// execution at when a fault at `addr` is to be recovered.
+pub fn search_exception_table(addr: VirtAddr) -> Option<VirtAddr> {
+    let ex_table = MemoryRegion::from_addresses(
+        VirtAddr::from(&raw const exception_table_start),
+        VirtAddr::from(&raw const exception_table_end),
+    );
+    if ex_table.is_empty() {
+        return None;
+    }
+    let fixup = check_exception_table(addr, ex_table);
+    (fixup != addr).then_some(fixup)
+}
+
 pub fn handle_exception_table_early(ctx: &mut X86ExceptionContext) -> bool {
     let ex_table = MemoryRegion::from_addresses(
         VirtAddr::from(&raw const early_exception_table_start),
@@ -21,13 +21,22 @@
 use crate::attest::AttestationError;
 use crate::block::BlockDeviceError;
 use crate::cpu::vc::VcError;
+use crate::fdt::FdtError;
 use crate::fs::FsError;
 use crate::fw_cfg::FwCfgError;
 use crate::insn_decode::InsnError;
 use crate::mm::alloc::AllocError;
+#[cfg(feature = "cca")]
+use crate::realm::measure::MeasureError;
+#[cfg(feature = "cca")]
+use crate::realm::plane::PlaneError;
+#[cfg(feature = "cca")]
+use crate::realm::rsi::RsiError;
 use crate::sev::ghcb::GhcbError;
 use crate::sev::msr_protocol::GhcbMsrError;
 use crate::sev::SevSnpError;
+#[cfg(feature = "cca")]
+use crate::svsm_arm64::cpu::smp::SmpError;
 use crate::syscall::ObjError;
 use crate::task::TaskError;
 // use crate::tdx::TdxError;
@@ -50,6 +59,19 @@ pub enum ApicError {
     Registration,
 }
 
+/// Errors related to the GICv3 interrupt controller.
+#[derive(Clone, Copy, Debug)]
+pub enum GicError {
+    /// The INTID is not valid for the requested operation.
+    InvalidIntId,
+
+    /// A handler is already registered for the INTID.
+    AlreadyRegistered,
+
+    /// No redistributor frame was found for the current PE.
+    NoRedistributor,
+}
+
 /// Errors related to Attestation handling. These may originate from multiple
 /// layers in the system. Added to protocol base for protocol error.
 #[repr(u64)]
@@ -103,6 +125,8 @@ pub enum SvsmError {
     Console,
     /// Errors related to firmware configuration contents
     FwCfg(FwCfgError),
+    /// Errors related to parsing the flattened device tree.
+    Fdt(FdtError),
     /// Errors related to ACPI parsing.
     Acpi,
     /// Errors from the filesystem.
@@ -117,6 +141,8 @@ pub enum SvsmError {
     NotSupported,
     /// Generic errors related to APIC emulation.
     Apic(ApicError),
+    /// Errors related to the GICv3 interrupt controller.
+    Gic(GicError),
     /// Generic errors related to attestation handling.
     Attestation(AttestError),
     /// Errors related to Hyper-V.
@@ -129,6 +155,18 @@ pub enum SvsmError {
     /// Errors related to attesting SVSM's launch evidence.
     #[cfg(feature = "attest")]
     TeeAttestation(AttestationError),
+    /// Errors related to running CCA aux planes.
+    #[cfg(feature = "cca")]
+    Plane(PlaneError),
+    /// Errors returned by the Realm Services Interface.
+    #[cfg(feature = "cca")]
+    Rsi(RsiError),
+    /// Errors related to measuring aux plane images.
+    #[cfg(feature = "cca")]
+    Measure(MeasureError),
+    /// Errors related to starting secondary PEs.
+    #[cfg(feature = "cca")]
+    Smp(SmpError),
 }
 
 impl From<ElfError> for SvsmError {
@@ -143,6 +181,12 @@ impl From<ApicError> for SvsmError {
     }
 }
 
+impl From<GicError> for SvsmError {
+    fn from(err: GicError) -> Self {
+        Self::Gic(err)
+    }
+}
+
 impl From<AttestError> for SvsmError {
     fn from(err: AttestError) -> Self {
         Self::Attestation(err)
@@ -0,0 +1,779 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! A minimal reader for flattened device trees (DTB).
+//!
+//! On Arm the SVSM is started with a pointer to the DTB describing the
+//! realm. [`Fdt`] gives read-only access to the nodes and properties of the
+//! blob without allocating, and [`DeviceTreeInfo`] collects the parts of the
+//! platform description the SVSM needs during boot.
+
+extern crate alloc;
+
+use crate::address::{PhysAddr, VirtAddr};
+use crate::error::SvsmError;
+use crate::utils::immut_after_init::ImmutAfterInitCell;
+use crate::utils::MemoryRegion;
+use alloc::vec::Vec;
+use core::slice;
+use core::str;
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_HEADER_SIZE: usize = 40;
+/// The newest version of the format which this parser understands.
+const FDT_VERSION: u32 = 17;
+
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+/// Maximum node nesting supported by the parser.
+const FDT_MAX_DEPTH: usize = 16;
+
+/// Default `#address-cells` and `#size-cells` of a node without the
+/// corresponding properties, as defined by the devicetree specification.
+const FDT_DEFAULT_CELLS: Cells = Cells {
+    address: 2,
+    size: 1,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FdtError {
+    /// The blob does not start with the FDT magic number.
+    BadMagic,
+    /// The blob uses an incompatible version of the format.
+    BadVersion,
+    /// A header offset or size points outside of the blob.
+    Truncated,
+    /// The structure block contains an unexpected token.
+    BadStructure,
+    /// A node or property name is not a valid string.
+    BadString,
+    /// Nodes are nested deeper than the parser supports.
+    TooDeep,
+}
+
+impl From<FdtError> for SvsmError {
+    fn from(err: FdtError) -> Self {
+        Self::Fdt(err)
+    }
+}
+
+fn be32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+fn be64(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset.checked_add(8)?)?;
+    Some(u64::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+const fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+/// Returns the NUL-terminated string at the start of `data`.
+fn c_str(data: &[u8]) -> Option<&str> {
+    let len = data.iter().position(|&b| b == 0)?;
+    str::from_utf8(&data[..len]).ok()
+}
+
+/// Reads a big-endian number of `cells` 32-bit cells.
+fn read_cells(data: &[u8], cells: u32) -> Option<u64> {
+    match cells {
+        0 => Some(0),
+        1 => be32(data, 0).map(u64::from),
+        2 => be64(data, 0),
+        _ => None,
+    }
+}
+
+/// The `#address-cells` and `#size-cells` values in effect for a node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Cells {
+    address: u32,
+    size: u32,
+}
+
+/// A validated flattened device tree blob.
+#[derive(Clone, Copy, Debug)]
+pub struct Fdt<'a> {
+    blob: &'a [u8],
+    structs: &'a [u8],
+    strings: &'a [u8],
+    mem_rsvmap: &'a [u8],
+}
+
+impl<'a> Fdt<'a> {
+    /// Parses the header of `blob` and validates its structure block.
+    pub fn new(blob: &'a [u8]) -> Result<Self, FdtError> {
+        let header = |index: usize| be32(blob, index * 4).ok_or(FdtError::Truncated);
+
+        if header(0)? != FDT_MAGIC {
+            return Err(FdtError::BadMagic);
+        }
+        let total_size = header(1)? as usize;
+        let off_struct = header(2)? as usize;
+        let off_strings = header(3)? as usize;
+        let off_rsvmap = header(4)? as usize;
+        let last_comp_version = header(6)?;
+        let size_strings = header(8)? as usize;
+        let size_struct = header(9)? as usize;
+
+        if last_comp_version > FDT_VERSION {
+            return Err(FdtError::BadVersion);
+        }
+
+        let blob = blob.get(..total_size).ok_or(FdtError::Truncated)?;
+        let region = |offset: usize, size: usize| {
+            offset
+                .checked_add(size)
+                .and_then(|end| blob.get(offset..end))
+                .ok_or(FdtError::Truncated)
+        };
+        let fdt = Self {
+            blob,
+            structs: region(off_struct, size_struct)?,
+            strings: region(off_strings, size_strings)?,
+            mem_rsvmap: blob.get(off_rsvmap..).ok_or(FdtError::Truncated)?,
+        };
+        fdt.validate()?;
+        Ok(fdt)
+    }
+
+    /// Parses the DTB at `addr`.
+    ///
+    /// # Safety
+    ///
+    /// `addr` must point to a DTB whose header-declared size is mapped and
+    /// which is not modified for the lifetime `'a`.
+    pub unsafe fn from_addr(addr: VirtAddr) -> Result<Self, FdtError> {
+        // SAFETY: the caller guarantees that at least the header is mapped.
+        let header = unsafe { slice::from_raw_parts(addr.as_ptr::<u8>(), FDT_HEADER_SIZE) };
+        if be32(header, 0) != Some(FDT_MAGIC) {
+            return Err(FdtError::BadMagic);
+        }
+        let total_size = be32(header, 4).unwrap() as usize;
+        if total_size < FDT_HEADER_SIZE {
+            return Err(FdtError::Truncated);
+        }
+        // SAFETY: the caller guarantees that the whole blob is mapped.
+        let blob = unsafe { slice::from_raw_parts(addr.as_ptr::<u8>(), total_size) };
+        Self::new(blob)
+    }
+
+    /// Returns the size of the blob in bytes.
+    pub fn size(&self) -> usize {
+        self.blob.len()
+    }
+
+    /// Walks the whole structure block once, so that later accessors can
+    /// rely on it being well-formed.
+    fn validate(&self) -> Result<(), FdtError> {
+        let mut offset = 0;
+        let mut depth = 0usize;
+        loop {
+            let token = be32(self.structs, offset).ok_or(FdtError::Truncated)?;
+            offset += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    depth += 1;
+                    if depth > FDT_MAX_DEPTH {
+                        return Err(FdtError::TooDeep);
+                    }
+                    let name = self.structs.get(offset..).ok_or(FdtError::Truncated)?;
+                    let name = c_str(name).ok_or(FdtError::BadString)?;
+                    offset = align4(offset + name.len() + 1);
+                }
+                FDT_END_NODE => {
+                    depth = depth.checked_sub(1).ok_or(FdtError::BadStructure)?;
+                }
+                FDT_PROP => {
+                    let len = be32(self.structs, offset).ok_or(FdtError::Truncated)? as usize;
+                    let name_off = be32(self.structs, offset + 4).ok_or(FdtError::Truncated)?;
+                    self.string_at(name_off).ok_or(FdtError::BadString)?;
+                    offset = align4(offset + 8 + len);
+                    if offset > self.structs.len() {
+                        return Err(FdtError::Truncated);
+                    }
+                }
+                FDT_NOP => {}
+                FDT_END if depth == 0 => return Ok(()),
+                _ => return Err(FdtError::BadStructure),
+            }
+        }
+    }
+
+    fn token(&self, offset: usize) -> u32 {
+        be32(self.structs, offset).unwrap_or(FDT_END)
+    }
+
+    fn string_at(&self, offset: u32) -> Option<&'a str> {
+        c_str(self.strings.get(offset as usize..)?)
+    }
+
+    /// Returns an iterator over the memory reservation block.
+    pub fn reserved_regions(&self) -> impl Iterator<Item = MemoryRegion<PhysAddr>> + 'a {
+        let rsvmap = self.mem_rsvmap;
+        (0..)
+            .map(move |i| (be64(rsvmap, i * 16), be64(rsvmap, i * 16 + 8)))
+            .map_while(|entry| match entry {
+                (Some(addr), Some(size)) if size != 0 => Some((addr, size)),
+                _ => None,
+            })
+            .map(|(addr, size)| MemoryRegion::new(PhysAddr::from(addr), size as usize))
+    }
+
+    /// Returns an iterator over all nodes in depth-first order, starting
+    /// with the root node.
+    pub fn nodes(&self) -> FdtNodeIter<'a> {
+        FdtNodeIter {
+            fdt: *self,
+            offset: 0,
+            depth: 0,
+            cells: [FDT_DEFAULT_CELLS; FDT_MAX_DEPTH + 1],
+        }
+    }
+
+    /// Returns the root node.
+    pub fn root(&self) -> Option<FdtNode<'a>> {
+        self.nodes().next()
+    }
+
+    /// Returns an iterator over the enabled nodes compatible with `compat`.
+    pub fn find_compatible(&self, compat: &'a str) -> impl Iterator<Item = FdtNode<'a>> + 'a {
+        self.nodes()
+            .filter(move |node| node.is_enabled() && node.is_compatible(compat))
+    }
+
+    /// Looks up a node by its full path, e.g. `/chosen`, or by an alias
+    /// defined in `/aliases`.
+    pub fn find_node(&self, path: &str) -> Option<FdtNode<'a>> {
+        if !path.starts_with('/') {
+            let alias = self.find_path("/aliases")?.property_str(path)?;
+            return self.find_path(alias);
+        }
+        self.find_path(path)
+    }
+
+    fn find_path(&self, path: &str) -> Option<FdtNode<'a>> {
+        let mut components = [""; FDT_MAX_DEPTH];
+        let mut count = 0;
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            *components.get_mut(count)? = component;
+            count += 1;
+        }
+
+        let mut matched = 0;
+        for node in self.nodes() {
+            if node.depth == 0 {
+                if count == 0 {
+                    return Some(node);
+                }
+                continue;
+            }
+            // Leaving the subtree of a matched node resets the match.
+            matched = matched.min(node.depth - 1);
+            if node.depth == matched + 1 && node.matches_name(components[matched]) {
+                matched += 1;
+                if matched == count {
+                    return Some(node);
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns the `/chosen` node.
+    pub fn chosen(&self) -> Option<FdtNode<'a>> {
+        self.find_node("/chosen")
+    }
+
+    /// Returns the node referenced by `stdout-path` in `/chosen`.
+    pub fn stdout(&self) -> Option<FdtNode<'a>> {
+        let path = self.chosen()?.property_str("stdout-path")?;
+        // Options for the console follow a ':'.
+        let path = path.split(':').next()?;
+        self.find_node(path)
+    }
+}
+
+/// Iterator over the nodes of an [`Fdt`].
+#[derive(Clone, Debug)]
+pub struct FdtNodeIter<'a> {
+    fdt: Fdt<'a>,
+    offset: usize,
+    depth: usize,
+    /// Cells in effect for the children of the node at each depth; entry 0
+    /// applies to the root node itself.
+    cells: [Cells; FDT_MAX_DEPTH + 1],
+}
+
+impl<'a> Iterator for FdtNodeIter<'a> {
+    type Item = FdtNode<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let token = self.fdt.token(self.offset);
+            self.offset += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name = c_str(&self.fdt.structs[self.offset..])?;
+                    self.offset = align4(self.offset + name.len() + 1);
+                    let node = FdtNode {
+                        fdt: self.fdt,
+                        name,
+                        depth: self.depth,
+                        props: self.offset,
+                        cells: self.cells[self.depth],
+                    };
+                    self.depth += 1;
+                    self.cells[self.depth] = Cells {
+                        address: node
+                            .property_u32("#address-cells")
+                            .unwrap_or(FDT_DEFAULT_CELLS.address),
+                        size: node
+                            .property_u32("#size-cells")
+                            .unwrap_or(FDT_DEFAULT_CELLS.size),
+                    };
+                    return Some(node);
+                }
+                FDT_END_NODE => self.depth -= 1,
+                FDT_PROP => {
+                    let len = be32(self.fdt.structs, self.offset)? as usize;
+                    self.offset = align4(self.offset + 8 + len);
+                }
+                FDT_NOP => {}
+                _ => return None,
+            }
+        }
+    }
+}
+
+/// A property of an [`FdtNode`].
+#[derive(Clone, Copy, Debug)]
+pub struct FdtProperty<'a> {
+    pub name: &'a str,
+    pub value: &'a [u8],
+}
+
+/// A node of an [`Fdt`].
+#[derive(Clone, Copy, Debug)]
+pub struct FdtNode<'a> {
+    fdt: Fdt<'a>,
+    name: &'a str,
+    depth: usize,
+    /// Offset of the first token after the node name.
+    props: usize,
+    /// Cells defined by the parent node, used to decode `reg`.
+    cells: Cells,
+}
+
+impl<'a> FdtNode<'a> {
+    /// Returns the node name including the unit address, e.g.
+    /// `pl011@9000000`.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Returns the nesting depth of the node; the root node has depth 0.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Checks whether the node name matches a path component. A component
+    /// without a unit address matches any unit address.
+    fn matches_name(&self, component: &str) -> bool {
+        self.name == component
+            || (!component.contains('@') && self.name.split('@').next() == Some(component))
+    }
+
+    /// Returns an iterator over the properties of the node.
+    pub fn properties(&self) -> impl Iterator<Item = FdtProperty<'a>> + 'a {
+        let fdt = self.fdt;
+        let mut offset = self.props;
+        core::iter::from_fn(move || loop {
+            match fdt.token(offset) {
+                FDT_NOP => offset += 4,
+                FDT_PROP => {
+                    let len = be32(fdt.structs, offset + 4)? as usize;
+                    let name = fdt.string_at(be32(fdt.structs, offset + 8)?)?;
+                    let value = fdt.structs.get(offset + 12..offset + 12 + len)?;
+                    offset = align4(offset + 12 + len);
+                    return Some(FdtProperty { name, value });
+                }
+                _ => return None,
+            }
+        })
+    }
+
+    /// Returns the value of the property `name`.
+    pub fn property(&self, name: &str) -> Option<&'a [u8]> {
+        self.properties()
+            .find(|prop| prop.name == name)
+            .map(|prop| prop.value)
+    }
+
+    /// Returns the value of the single-cell property `name`.
+    pub fn property_u32(&self, name: &str) -> Option<u32> {
+        match self.property(name)? {
+            value if value.len() == 4 => be32(value, 0),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of the one- or two-cell property `name`.
+    pub fn property_u64(&self, name: &str) -> Option<u64> {
+        let value = self.property(name)?;
+        read_cells(value, (value.len() / 4) as u32)
+    }
+
+    /// Returns the string value of the property `name`.
+    pub fn property_str(&self, name: &str) -> Option<&'a str> {
+        c_str(self.property(name)?)
+    }
+
+    /// Returns an iterator over the strings of the `compatible` property.
+    pub fn compatible(&self) -> impl Iterator<Item = &'a str> + 'a {
+        self.property("compatible")
+            .unwrap_or_default()
+            .split(|&b| b == 0)
+            .filter(|s| !s.is_empty())
+            .filter_map(|s| str::from_utf8(s).ok())
+    }
+
+    /// Checks whether `compat` is one of the node's compatible strings.
+    pub fn is_compatible(&self, compat: &str) -> bool {
+        self.compatible().any(|c| c == compat)
+    }
+
+    /// Checks whether the `status` property, if present, marks the node as
+    /// enabled.
+    pub fn is_enabled(&self) -> bool {
+        matches!(
+            self.property_str("status"),
+            None | Some("okay") | Some("ok")
+        )
+    }
+
+    /// Returns an iterator over the regions in the `reg` property.
+    pub fn reg(&self) -> impl Iterator<Item = MemoryRegion<PhysAddr>> + 'a {
+        let Cells { address, size } = self.cells;
+        let entry_size = ((address + size) * 4) as usize;
+        self.property("reg")
+            .unwrap_or_default()
+            .chunks_exact(entry_size.max(1))
+            .filter_map(move |entry| {
+                let base = read_cells(entry, address)?;
+                let len = read_cells(&entry[(address * 4) as usize..], size)?;
+                Some(MemoryRegion::new(PhysAddr::from(base), len as usize))
+            })
+    }
+}
+
+/// Compatible of the virtio-mmio transports reserved for the SVSM. No
+/// aux-plane kernel binds a driver to them, and aux-plane accesses to them
+/// are not emulated.
+pub const SVSM_VIRTIO_MMIO_COMPATIBLE: &str = "coconut,svsm-virtio-mmio";
+
+/// Parts of the platform description needed to bring up the SVSM.
+#[derive(Clone, Debug)]
+pub struct DeviceTreeInfo {
+    /// Location of the DTB itself, which is passed on to the plane-N kernel.
+    pub dtb: MemoryRegion<PhysAddr>,
+    /// RAM regions from the `/memory` nodes.
+    pub memory: Vec<MemoryRegion<PhysAddr>>,
+    /// Base of the PL011 UART used as the console.
+    pub console: Option<PhysAddr>,
+    /// Register region of the GICv3 distributor.
+    pub gicd: Option<MemoryRegion<PhysAddr>>,
+    /// Region containing the GICv3 redistributors.
+    pub gicr: Option<MemoryRegion<PhysAddr>>,
+    /// Register regions of the virtio-mmio transports.
+    pub virtio_mmio: Vec<MemoryRegion<PhysAddr>>,
+    /// Register regions of the virtio-mmio transports reserved for the SVSM
+    /// with [`SVSM_VIRTIO_MMIO_COMPATIBLE`]. Nodes which are also compatible
+    /// with `virtio,mmio` are left out, as aux planes would drive them.
+    pub svsm_virtio_mmio: Vec<MemoryRegion<PhysAddr>>,
+    /// Location of the initrd handed over in `/chosen`.
+    pub initrd: Option<MemoryRegion<PhysAddr>>,
+    /// MPIDR affinity values of the `/cpus` nodes.
+    pub cpus: Vec<u64>,
+}
+
+impl DeviceTreeInfo {
+    /// Extracts the platform description from `fdt`, which is located at
+    /// `dtb_addr`.
+    pub fn parse(fdt: &Fdt<'_>, dtb_addr: PhysAddr) -> Self {
+        let memory = fdt
+            .nodes()
+            .filter(|node| node.depth() == 1 && node.matches_name("memory"))
+            .filter(|node| node.is_enabled())
+            .flat_map(|node| node.reg())
+            .filter(|region| !region.is_empty())
+            .collect();
+
+        let gic = fdt.find_compatible("arm,gic-v3").next();
+        let mut gic_regs = gic.into_iter().flat_map(|node| node.reg());
+
+        let initrd = fdt.chosen().and_then(|chosen| {
+            let start = chosen.property_u64("linux,initrd-start")?;
+            let end = chosen.property_u64("linux,initrd-end")?;
+            (start < end)
+                .then(|| MemoryRegion::from_addresses(PhysAddr::from(start), PhysAddr::from(end)))
+        });
+
+        let cpus = fdt
+            .nodes()
+            .filter(|node| node.depth() == 2 && node.property_str("device_type") == Some("cpu"))
+            .filter(|node| node.is_enabled())
+            .filter_map(|node| node.reg().next())
+            .map(|region| u64::from(region.start()))
+            .collect();
+
+        Self {
+            dtb: MemoryRegion::new(dtb_addr, fdt.size()),
+            memory,
+            console: console_base(fdt),
+            gicd: gic_regs.next(),
+            gicr: gic_regs.next(),
+            virtio_mmio: fdt
+                .find_compatible("virtio,mmio")
+                .flat_map(|node| node.reg().next())
+                .collect(),
+            svsm_virtio_mmio: fdt
+                .find_compatible(SVSM_VIRTIO_MMIO_COMPATIBLE)
+                .filter(|node| !node.is_compatible("virtio,mmio"))
+                .flat_map(|node| node.reg().next())
+                .collect(),
+            initrd,
+            cpus,
+        }
+    }
+}
+
+/// Returns the base of the console UART: the `stdout-path` device if it is
+/// a PL011, otherwise the first enabled PL011. Does not allocate, so it can
+/// be used before the heap is set up.
+pub fn console_base(fdt: &Fdt<'_>) -> Option<PhysAddr> {
+    fdt.stdout()
+        .filter(|node| node.is_compatible("arm,pl011"))
+        .or_else(|| fdt.find_compatible("arm,pl011").next())
+        .and_then(|node| node.reg().next())
+        .map(|region| region.start())
+}
+
+/// The platform description of the DTB the SVSM was booted with.
+pub static DEVICE_TREE: ImmutAfterInitCell<DeviceTreeInfo> = ImmutAfterInitCell::uninit();
+
+/// Parses the DTB at `dtb_addr` and makes its contents available through
+/// [`DEVICE_TREE`].
+///
+/// # Safety
+///
+/// See [`Fdt::from_addr`]. The SVSM runs identity mapped, so `dtb_addr` is
+/// also the virtual address of the blob.
+pub unsafe fn init_device_tree(dtb_addr: PhysAddr) -> Result<(), SvsmError> {
+    // SAFETY: the caller guarantees that the DTB is mapped.
+    let fdt = unsafe { Fdt::from_addr(VirtAddr::from(u64::from(dtb_addr)))? };
+    DEVICE_TREE
+        .init(DeviceTreeInfo::parse(&fdt, dtb_addr))
+        .map_err(|_| SvsmError::PlatformInit)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Assembles a DTB from structure tokens, collecting property names in
+    /// the strings block.
+    #[derive(Default)]
+    pub(crate) struct Builder {
+        structs: Vec<u8>,
+        strings: Vec<u8>,
+    }
+
+    impl Builder {
+        fn token(&mut self, token: u32) {
+            self.structs.extend_from_slice(&token.to_be_bytes());
+        }
+
+        fn pad(&mut self) {
+            self.structs.resize(align4(self.structs.len()), 0);
+        }
+
+        pub(crate) fn begin(&mut self, name: &str) -> &mut Self {
+            self.token(FDT_BEGIN_NODE);
+            self.structs.extend_from_slice(name.as_bytes());
+            self.structs.push(0);
+            self.pad();
+            self
+        }
+
+        pub(crate) fn end(&mut self) -> &mut Self {
+            self.token(FDT_END_NODE);
+            self
+        }
+
+        pub(crate) fn prop(&mut self, name: &str, value: &[u8]) -> &mut Self {
+            let name_off = self.strings.len() as u32;
+            self.strings.extend_from_slice(name.as_bytes());
+            self.strings.push(0);
+            self.token(FDT_PROP);
+            self.token(value.len() as u32);
+            self.token(name_off);
+            self.structs.extend_from_slice(value);
+            self.pad();
+            self
+        }
+
+        pub(crate) fn prop_cells(&mut self, name: &str, cells: &[u32]) -> &mut Self {
+            let value: Vec<u8> = cells.iter().flat_map(|c| c.to_be_bytes()).collect();
+            self.prop(name, &value)
+        }
+
+        pub(crate) fn finish(&mut self) -> Vec<u8> {
+            self.token(FDT_END);
+            let rsvmap = [0x4800_0000u64, 0x1000, 0, 0];
+            let off_rsvmap = FDT_HEADER_SIZE;
+            let off_struct = off_rsvmap + rsvmap.len() * 8;
+            let off_strings = off_struct + self.structs.len();
+            let total = off_strings + self.strings.len();
+            let header = [
+                FDT_MAGIC,
+                total as u32,
+                off_struct as u32,
+                off_strings as u32,
+                off_rsvmap as u32,
+                FDT_VERSION,
+                16,
+                0,
+                self.strings.len() as u32,
+                self.structs.len() as u32,
+            ];
+            let mut blob: Vec<u8> = header.iter().flat_map(|h| h.to_be_bytes()).collect();
+            blob.extend(rsvmap.iter().flat_map(|r| r.to_be_bytes()));
+            blob.extend_from_slice(&self.structs);
+            blob.extend_from_slice(&self.strings);
+            blob
+        }
+    }
+
+    fn qemu_virt_dtb() -> Vec<u8> {
+        let mut b = Builder::default();
+        b.begin("")
+            .prop_cells("#address-cells", &[2])
+            .prop_cells("#size-cells", &[2])
+            .begin("chosen")
+            .prop("stdout-path", b"serial0:115200n8\0")
+            .prop_cells("linux,initrd-start", &[0x4800_0000])
+            .prop_cells("linux,initrd-end", &[0x4810_0000])
+            .end()
+            .begin("aliases")
+            .prop("serial0", b"/pl011@9000000\0")
+            .end()
+            .begin("cpus")
+            .prop_cells("#address-cells", &[1])
+            .prop_cells("#size-cells", &[0])
+            .begin("cpu@0")
+            .prop("device_type", b"cpu\0")
+            .prop_cells("reg", &[0])
+            .end()
+            .begin("cpu@100")
+            .prop("device_type", b"cpu\0")
+            .prop_cells("reg", &[0x100])
+            .end()
+            .end()
+            .begin("memory@40000000")
+            .prop("device_type", b"memory\0")
+            .prop_cells("reg", &[0, 0x4000_0000, 0, 0x8000_0000])
+            .end()
+            .begin("pl011@9040000")
+            .prop("compatible", b"arm,pl011\0arm,primecell\0")
+            .prop_cells("reg", &[0, 0x0904_0000, 0, 0x1000])
+            .end()
+            .begin("pl011@9000000")
+            .prop("compatible", b"arm,pl011\0arm,primecell\0")
+            .prop_cells("reg", &[0, 0x0900_0000, 0, 0x1000])
+            .end()
+            .begin("intc@8000000")
+            .prop("compatible", b"arm,gic-v3\0")
+            .prop_cells(
+                "reg",
+                &[0, 0x0800_0000, 0, 0x1_0000, 0, 0x080a_0000, 0, 0xf6_0000],
+            )
+            .end()
+            .begin("virtio_mmio@a000000")
+            .prop("compatible", b"virtio,mmio\0")
+            .prop_cells("reg", &[0, 0x0a00_0000, 0, 0x200])
+            .end()
+            .begin("virtio_mmio@a000200")
+            .prop("compatible", b"virtio,mmio\0")
+            .prop("status", b"disabled\0")
+            .prop_cells("reg", &[0, 0x0a00_0200, 0, 0x200])
+            .end()
+            .begin("virtio_mmio@a000400")
+            .prop("compatible", b"coconut,svsm-virtio-mmio\0")
+            .prop_cells("reg", &[0, 0x0a00_0400, 0, 0x200])
+            .end()
+            .begin("virtio_mmio@a000600")
+            .prop("compatible", b"coconut,svsm-virtio-mmio\0virtio,mmio\0")
+            .prop_cells("reg", &[0, 0x0a00_0600, 0, 0x200])
+            .end()
+            .end();
+        b.finish()
+    }
+
+    #[test]
+    fn header_checks() {
+        let mut blob = qemu_virt_dtb();
+        assert!(Fdt::new(&blob).is_ok());
+        assert_eq!(Fdt::new(&blob[..20]).unwrap_err(), FdtError::Truncated);
+        blob[0] = 0;
+        assert_eq!(Fdt::new(&blob).unwrap_err(), FdtError::BadMagic);
+    }
+
+    #[test]
+    fn find_nodes() {
+        let blob = qemu_virt_dtb();
+        let fdt = Fdt::new(&blob).unwrap();
+
+        assert_eq!(fdt.root().unwrap().name(), "");
+        assert_eq!(fdt.find_node("/memory").unwrap().name(), "memory@40000000");
+        assert_eq!(fdt.find_node("/pl011@9000000").unwrap().depth(), 1);
+        assert_eq!(fdt.find_node("serial0").unwrap().name(), "pl011@9000000");
+        assert!(fdt.find_node("/chosen/memory").is_none());
+        assert_eq!(fdt.stdout().unwrap().name(), "pl011@9000000");
+        assert_eq!(fdt.find_compatible("virtio,mmio").count(), 2);
+
+        let reserved: Vec<_> = fdt.reserved_regions().collect();
+        assert_eq!(reserved.len(), 1);
+        assert_eq!(reserved[0].start(), PhysAddr::from(0x4800_0000u64));
+    }
+
+    #[test]
+    fn platform_info() {
+        let blob = qemu_virt_dtb();
+        let fdt = Fdt::new(&blob).unwrap();
+        let info = DeviceTreeInfo::parse(&fdt, PhysAddr::from(0x4000_0000u64));
+
+        assert_eq!(info.dtb.len(), blob.len());
+        assert_eq!(info.memory.len(), 1);
+        assert_eq!(info.memory[0].start(), PhysAddr::from(0x4000_0000u64));
+        assert_eq!(info.memory[0].len(), 0x8000_0000);
+        assert_eq!(info.console, Some(PhysAddr::from(0x0900_0000u64)));
+        assert_eq!(info.gicd.unwrap().start(), PhysAddr::from(0x0800_0000u64));
+        assert_eq!(info.gicr.unwrap().len(), 0xf6_0000);
+        assert_eq!(info.virtio_mmio.len(), 2);
+        assert_eq!(info.virtio_mmio[0].start(), PhysAddr::from(0x0a00_0000u64));
+        assert_eq!(info.svsm_virtio_mmio.len(), 1);
+        assert_eq!(
+            info.svsm_virtio_mmio[0].start(),
+            PhysAddr::from(0x0a00_0400u64)
+        );
+        assert_eq!(info.initrd.unwrap().len(), 0x10_0000);
+        assert_eq!(info.cpus, [0, 0x100]);
+    }
+}
@@ -0,0 +1,679 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! AArch64 load/store decoder for MMIO emulation.
+//!
+//! A stage-2 data abort only describes the faulting access in ESR_EL2 when
+//! ESR.ISV is set, which is never the case for register pairs or accesses
+//! with writeback. [`A64LoadStore::decode()`] decodes the faulting
+//! instruction instead, covering the integer forms of LDR/STR with all
+//! addressing modes, LDUR/STUR, the sign-extending loads and LDP/STP. The
+//! access is then emulated through an [`A64MachineCtx`], which provides the
+//! register file of the faulting context and performs the MMIO accesses.
+
+use super::InsnError;
+use crate::types::Bytes;
+
+/// Size of an AArch64 instruction in bytes.
+pub const A64_INSN_SIZE: usize = 4;
+
+/// Register number which encodes XZR or SP, depending on the operand.
+const REG_ZR_SP: u8 = 31;
+
+/// Trait representing the machine state of an AArch64 context for
+/// emulating its load and store instructions.
+pub trait A64MachineCtx: core::fmt::Debug {
+    /// Read general purpose register `reg`, with `reg` in 0..=30.
+    fn read_gpr(&self, reg: u8) -> u64;
+
+    /// Write general purpose register `reg`, with `reg` in 0..=30.
+    fn write_gpr(&mut self, reg: u8, val: u64);
+
+    /// Read the stack pointer selected by the context's PSTATE.
+    fn read_sp(&self) -> Result<u64, InsnError> {
+        Err(InsnError::InvalidRegister)
+    }
+
+    /// Write the stack pointer selected by the context's PSTATE.
+    fn write_sp(&mut self, _val: u64) -> Result<(), InsnError> {
+        Err(InsnError::InvalidRegister)
+    }
+
+    /// Translate the virtual address `va` of the context to an IPA.
+    ///
+    /// # Arguments
+    ///
+    /// * `va` - The virtual address to translate.
+    /// * `write` - Whether the translation is for a write operation.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the IPA or an `InsnError` if the translation
+    /// fails.
+    fn translate_addr(&self, _va: u64, _write: bool) -> Result<u64, InsnError> {
+        Err(InsnError::TranslateLinearAddr)
+    }
+
+    /// Handle a memory-mapped I/O read operation of `size` bytes at `ipa`.
+    ///
+    /// # Safety
+    ///
+    /// Caller must ensure that, when memory safety may be impacted, this
+    /// operation does not cause Undefined Behavior.
+    unsafe fn handle_mmio_read(&self, _ipa: u64, _size: Bytes) -> Result<u64, InsnError> {
+        Err(InsnError::HandleMmioRead)
+    }
+
+    /// Handle a memory-mapped I/O write operation of `size` bytes of `data`
+    /// at `ipa`.
+    ///
+    /// # Safety
+    ///
+    /// Caller must ensure that, when memory safety may be impacted, this
+    /// operation does not cause Undefined Behavior.
+    unsafe fn handle_mmio_write(
+        &mut self,
+        _ipa: u64,
+        _size: Bytes,
+        _data: u64,
+    ) -> Result<(), InsnError> {
+        Err(InsnError::HandleMmioWrite)
+    }
+}
+
+/// Extension applied to the offset register of a register offset access.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum A64Extend {
+    Uxtw,
+    Lsl,
+    Sxtw,
+    Sxtx,
+}
+
+impl A64Extend {
+    fn apply(self, val: u64) -> u64 {
+        match self {
+            Self::Uxtw => val as u32 as u64,
+            Self::Sxtw => val as u32 as i32 as i64 as u64,
+            Self::Lsl | Self::Sxtx => val,
+        }
+    }
+}
+
+/// Offset added to the base register.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum A64Offset {
+    /// An immediate, already scaled by the access size.
+    Imm(i64),
+    /// A register, extended and shifted left by `shift`.
+    Reg {
+        rm: u8,
+        extend: A64Extend,
+        shift: u8,
+    },
+}
+
+/// Base register update of an access.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum A64Writeback {
+    /// The base register is not updated.
+    None,
+    /// The offset is added to the base register before the access.
+    Pre,
+    /// The offset is added to the base register after the access.
+    Post,
+}
+
+/// A decoded AArch64 load or store of one or two general purpose registers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct A64LoadStore {
+    pub load: bool,
+    /// Size of each register access.
+    pub size: Bytes,
+    /// The loaded value is sign-extended.
+    pub sign_extend: bool,
+    /// The destination of a load is an X register rather than a W register.
+    pub sixty_four: bool,
+    pub rt: u8,
+    /// Second register of LDP/STP.
+    pub rt2: Option<u8>,
+    /// Base register, where 31 is SP.
+    pub rn: u8,
+    pub offset: A64Offset,
+    pub writeback: A64Writeback,
+}
+
+const fn field(insn: u32, shift: u32, width: u32) -> u32 {
+    (insn >> shift) & ((1 << width) - 1)
+}
+
+/// Sign-extends the low `bits` bits of `val`.
+const fn sign_extend_bits(val: u64, bits: u32) -> i64 {
+    ((val << (64 - bits)) as i64) >> (64 - bits)
+}
+
+const fn size_from_log2(log2: u32) -> Bytes {
+    match log2 {
+        0 => Bytes::One,
+        1 => Bytes::Two,
+        2 => Bytes::Four,
+        _ => Bytes::Eight,
+    }
+}
+
+/// Extends the `size` bytes loaded into `data` the way a load into an X
+/// register (`sixty_four`) or a W register does.
+pub fn extend_load(data: u64, size: Bytes, sign_extend: bool, sixty_four: bool) -> u64 {
+    let data = data & size.mask();
+    let data = if sign_extend && size != Bytes::Eight {
+        sign_extend_bits(data, size as u32 * 8) as u64
+    } else {
+        data
+    };
+    if sixty_four {
+        data
+    } else {
+        data as u32 as u64
+    }
+}
+
+impl A64LoadStore {
+    /// Decodes the load or store instruction `insn`.
+    ///
+    /// # Returns
+    ///
+    /// The decoded access, [`InsnError::UnSupportedInsn`] for instructions
+    /// other than integer loads and stores of one or two registers, or
+    /// [`InsnError::InvalidDecode`] for unallocated or unpredictable
+    /// encodings.
+    pub fn decode(insn: u32) -> Result<Self, InsnError> {
+        // Bits 29:27 and 25 select the class, bit 26 is the SIMD&FP flag.
+        let class = field(insn, 25, 5) & 0b11101;
+        let decoded = match class {
+            0b11100 => Self::decode_single(insn)?,
+            0b10100 => Self::decode_pair(insn)?,
+            _ => return Err(InsnError::UnSupportedInsn),
+        };
+        decoded.check_writeback()?;
+        Ok(decoded)
+    }
+
+    /// Describes the single register access reported by a data abort with
+    /// ESR.ISV set. The syndrome does not describe the address, so the
+    /// access must be emulated with [`Self::emulate_at()`].
+    pub fn from_syndrome(
+        write: bool,
+        size: usize,
+        rt: u8,
+        sign_extend: bool,
+        sixty_four: bool,
+    ) -> Result<Self, InsnError> {
+        let size = match size {
+            1 => Bytes::One,
+            2 => Bytes::Two,
+            4 => Bytes::Four,
+            8 => Bytes::Eight,
+            _ => return Err(InsnError::InvalidDecode),
+        };
+        Ok(Self {
+            load: !write,
+            size,
+            sign_extend,
+            sixty_four,
+            rt,
+            rt2: None,
+            rn: REG_ZR_SP,
+            offset: A64Offset::Imm(0),
+            writeback: A64Writeback::None,
+        })
+    }
+
+    /// Load/store register: unsigned immediate, unscaled immediate,
+    /// immediate pre/post-indexed, unprivileged and register offset forms.
+    fn decode_single(insn: u32) -> Result<Self, InsnError> {
+        if field(insn, 26, 1) != 0 {
+            return Err(InsnError::UnSupportedInsn);
+        }
+        let size_log2 = field(insn, 30, 2);
+        let opc = field(insn, 22, 2);
+
+        let (offset, writeback) = if field(insn, 24, 1) != 0 {
+            let imm12 = field(insn, 10, 12) as i64;
+            (A64Offset::Imm(imm12 << size_log2), A64Writeback::None)
+        } else if field(insn, 21, 1) == 0 {
+            let imm9 = sign_extend_bits(field(insn, 12, 9) as u64, 9);
+            let writeback = match field(insn, 10, 2) {
+                0b01 => A64Writeback::Post,
+                0b11 => A64Writeback::Pre,
+                // LDUR/STUR and the unprivileged LDTR/STTR, which access
+                // MMIO the same way.
+                _ => A64Writeback::None,
+            };
+            (A64Offset::Imm(imm9), writeback)
+        } else if field(insn, 10, 2) == 0b10 {
+            let extend = match field(insn, 13, 3) {
+                0b010 => A64Extend::Uxtw,
+                0b011 => A64Extend::Lsl,
+                0b110 => A64Extend::Sxtw,
+                0b111 => A64Extend::Sxtx,
+                _ => return Err(InsnError::InvalidDecode),
+            };
+            let shift = if field(insn, 12, 1) != 0 {
+                size_log2 as u8
+            } else {
+                0
+            };
+            let rm = field(insn, 16, 5) as u8;
+            (A64Offset::Reg { rm, extend, shift }, A64Writeback::None)
+        } else {
+            // Atomic memory operations and pointer authenticated loads
+            return Err(InsnError::UnSupportedInsn);
+        };
+
+        let (load, sign_extend, sixty_four) = match (size_log2, opc) {
+            (_, 0b00) => (false, false, false),
+            (_, 0b01) => (true, false, size_log2 == 3),
+            // PRFM
+            (3, 0b10) => return Err(InsnError::UnSupportedInsn),
+            // LDRSB, LDRSH and LDRSW into an X register
+            (_, 0b10) => (true, true, true),
+            // LDRSB and LDRSH into a W register
+            (0 | 1, 0b11) => (true, true, false),
+            _ => return Err(InsnError::InvalidDecode),
+        };
+
+        Ok(Self {
+            load,
+            size: size_from_log2(size_log2),
+            sign_extend,
+            sixty_four,
+            rt: field(insn, 0, 5) as u8,
+            rt2: None,
+            rn: field(insn, 5, 5) as u8,
+            offset,
+            writeback,
+        })
+    }
+
+    /// Load/store register pair: no-allocate, post-indexed, signed offset
+    /// and pre-indexed forms.
+    fn decode_pair(insn: u32) -> Result<Self, InsnError> {
+        if field(insn, 26, 1) != 0 {
+            return Err(InsnError::UnSupportedInsn);
+        }
+        let load = field(insn, 22, 1) != 0;
+        let (size_log2, sign_extend) = match field(insn, 30, 2) {
+            0b00 => (2, false),
+            // LDPSW
+            0b01 if load => (2, true),
+            0b10 => (3, false),
+            // STGP and unallocated encodings
+            _ => return Err(InsnError::UnSupportedInsn),
+        };
+        let writeback = match field(insn, 23, 2) {
+            0b01 => A64Writeback::Post,
+            0b11 => A64Writeback::Pre,
+            _ => A64Writeback::None,
+        };
+        let imm7 = sign_extend_bits(field(insn, 15, 7) as u64, 7);
+
+        let rt = field(insn, 0, 5) as u8;
+        let rt2 = field(insn, 10, 5) as u8;
+        if load && rt == rt2 {
+            return Err(InsnError::InvalidDecode);
+        }
+
+        Ok(Self {
+            load,
+            size: size_from_log2(size_log2),
+            sign_extend,
+            sixty_four: size_log2 == 3 || sign_extend,
+            rt,
+            rt2: Some(rt2),
+            rn: field(insn, 5, 5) as u8,
+            offset: A64Offset::Imm(imm7 << size_log2),
+            writeback,
+        })
+    }
+
+    /// Rejects writeback to a base register which is also transferred,
+    /// which is constrained unpredictable.
+    fn check_writeback(&self) -> Result<(), InsnError> {
+        let overlaps = |rt: u8| rt == self.rn && rt != REG_ZR_SP;
+        if self.writeback != A64Writeback::None
+            && (overlaps(self.rt) || self.rt2.is_some_and(overlaps))
+        {
+            return Err(InsnError::InvalidDecode);
+        }
+        Ok(())
+    }
+
+    fn read_base<C: A64MachineCtx>(&self, ctx: &C) -> Result<u64, InsnError> {
+        if self.rn == REG_ZR_SP {
+            ctx.read_sp()
+        } else {
+            Ok(ctx.read_gpr(self.rn))
+        }
+    }
+
+    fn write_base<C: A64MachineCtx>(&self, ctx: &mut C, val: u64) -> Result<(), InsnError> {
+        if self.rn == REG_ZR_SP {
+            ctx.write_sp(val)
+        } else {
+            ctx.write_gpr(self.rn, val);
+            Ok(())
+        }
+    }
+
+    fn offset_value<C: A64MachineCtx>(&self, ctx: &C) -> u64 {
+        match self.offset {
+            A64Offset::Imm(imm) => imm as u64,
+            A64Offset::Reg { rm, extend, shift } => extend.apply(read_xzr(ctx, rm)) << shift,
+        }
+    }
+
+    /// Returns the virtual address of the first register access.
+    pub fn address<C: A64MachineCtx>(&self, ctx: &C) -> Result<u64, InsnError> {
+        let base = self.read_base(ctx)?;
+        Ok(match self.writeback {
+            A64Writeback::Post => base,
+            _ => base.wrapping_add(self.offset_value(ctx)),
+        })
+    }
+
+    /// Emulates the instruction, including the base register update. The
+    /// program counter is left to the caller.
+    pub fn emulate<C: A64MachineCtx>(&self, ctx: &mut C) -> Result<(), InsnError> {
+        let base = self.read_base(ctx)?;
+        let offset = self.offset_value(ctx);
+        let va = match self.writeback {
+            A64Writeback::Post => base,
+            _ => base.wrapping_add(offset),
+        };
+
+        self.emulate_at(ctx, va)?;
+
+        if self.writeback != A64Writeback::None {
+            self.write_base(ctx, base.wrapping_add(offset))?;
+        }
+        Ok(())
+    }
+
+    /// Performs the register accesses of the instruction at the virtual
+    /// address `va`, without updating the base register.
+    pub fn emulate_at<C: A64MachineCtx>(&self, ctx: &mut C, va: u64) -> Result<(), InsnError> {
+        let size = self.size as u64;
+        let regs = [Some(self.rt), self.rt2];
+        let accesses = regs
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .map(|(i, rt)| (rt, va.wrapping_add(i as u64 * size)));
+
+        if self.load {
+            // Complete all reads before writing any register, so that a
+            // failed access leaves the context unchanged.
+            let mut data = [(REG_ZR_SP, 0u64); 2];
+            let mut count = 0;
+            for (rt, va) in accesses {
+                let ipa = ctx.translate_addr(va, false)?;
+                // SAFETY: the address is decoded from the instruction and
+                // translated by the context.
+                let val = unsafe { ctx.handle_mmio_read(ipa, self.size)? };
+                data[count] = (
+                    rt,
+                    extend_load(val, self.size, self.sign_extend, self.sixty_four),
+                );
+                count += 1;
+            }
+            for (rt, val) in &data[..count] {
+                write_xzr(ctx, *rt, *val);
+            }
+        } else {
+            for (rt, va) in accesses {
+                let ipa = ctx.translate_addr(va, true)?;
+                let val = read_xzr(ctx, rt) & self.size.mask();
+                // SAFETY: as above.
+                unsafe { ctx.handle_mmio_write(ipa, self.size, val)? };
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads register `reg` of a data operand, where 31 is XZR.
+fn read_xzr<C: A64MachineCtx>(ctx: &C, reg: u8) -> u64 {
+    if reg == REG_ZR_SP {
+        0
+    } else {
+        ctx.read_gpr(reg)
+    }
+}
+
+/// Writes register `reg` of a data operand, where 31 is XZR.
+fn write_xzr<C: A64MachineCtx>(ctx: &mut C, reg: u8, val: u64) {
+    if reg != REG_ZR_SP {
+        ctx.write_gpr(reg, val);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MMIO_BASE: u64 = 0x1000;
+
+    #[derive(Debug)]
+    struct TestA64Ctx {
+        gprs: [u64; 31],
+        sp: u64,
+        mmio: [u8; 64],
+    }
+
+    impl A64MachineCtx for TestA64Ctx {
+        fn read_gpr(&self, reg: u8) -> u64 {
+            self.gprs[reg as usize]
+        }
+
+        fn write_gpr(&mut self, reg: u8, val: u64) {
+            self.gprs[reg as usize] = val;
+        }
+
+        fn read_sp(&self) -> Result<u64, InsnError> {
+            Ok(self.sp)
+        }
+
+        fn write_sp(&mut self, val: u64) -> Result<(), InsnError> {
+            self.sp = val;
+            Ok(())
+        }
+
+        fn translate_addr(&self, va: u64, _write: bool) -> Result<u64, InsnError> {
+            if (MMIO_BASE..MMIO_BASE + 64).contains(&va) {
+                Ok(va - MMIO_BASE)
+            } else {
+                Err(InsnError::TranslateLinearAddr)
+            }
+        }
+
+        unsafe fn handle_mmio_read(&self, ipa: u64, size: Bytes) -> Result<u64, InsnError> {
+            let mut bytes = [0u8; 8];
+            let ipa = ipa as usize;
+            let len = size as usize;
+            bytes[..len].copy_from_slice(&self.mmio[ipa..ipa + len]);
+            Ok(u64::from_le_bytes(bytes))
+        }
+
+        unsafe fn handle_mmio_write(
+            &mut self,
+            ipa: u64,
+            size: Bytes,
+            data: u64,
+        ) -> Result<(), InsnError> {
+            let ipa = ipa as usize;
+            let len = size as usize;
+            self.mmio[ipa..ipa + len].copy_from_slice(&data.to_le_bytes()[..len]);
+            Ok(())
+        }
+    }
+
+    fn ctx() -> TestA64Ctx {
+        let mut ctx = TestA64Ctx {
+            gprs: [0; 31],
+            sp: MMIO_BASE + 0x20,
+            mmio: [0; 64],
+        };
+        ctx.gprs[1] = MMIO_BASE;
+        for (i, b) in ctx.mmio.iter_mut().enumerate() {
+            *b = 0x80 | i as u8;
+        }
+        ctx
+    }
+
+    #[test]
+    fn ldr_unsigned_imm() {
+        // ldr w0, [x1, #8]
+        let insn = A64LoadStore::decode(0xb940_0820).unwrap();
+        assert!(insn.load);
+        assert_eq!(insn.size, Bytes::Four);
+        assert_eq!(insn.offset, A64Offset::Imm(8));
+        let mut ctx = ctx();
+        ctx.gprs[0] = u64::MAX;
+        insn.emulate(&mut ctx).unwrap();
+        assert_eq!(ctx.gprs[0], 0x8b8a_8988);
+
+        // str x2, [x1, #16]
+        let insn = A64LoadStore::decode(0xf900_0822).unwrap();
+        assert!(!insn.load);
+        ctx.gprs[2] = 0x1122_3344_5566_7788;
+        insn.emulate(&mut ctx).unwrap();
+        assert_eq!(&ctx.mmio[16..24], &0x1122_3344_5566_7788u64.to_le_bytes());
+    }
+
+    #[test]
+    fn unscaled_and_writeback() {
+        // ldurh w3, [x1, #-2] faults outside the window
+        let insn = A64LoadStore::decode(0x785f_e023).unwrap();
+        assert_eq!(insn.offset, A64Offset::Imm(-2));
+        assert!(insn.emulate(&mut ctx()).is_err());
+
+        // ldrb w3, [x1, #4]!
+        let insn = A64LoadStore::decode(0x3840_4c23).unwrap();
+        assert_eq!(insn.writeback, A64Writeback::Pre);
+        let mut ctx = ctx();
+        insn.emulate(&mut ctx).unwrap();
+        assert_eq!(ctx.gprs[3], 0x84);
+        assert_eq!(ctx.gprs[1], MMIO_BASE + 4);
+
+        // strh w3, [x1], #6
+        let insn = A64LoadStore::decode(0x7800_6423).unwrap();
+        assert_eq!(insn.writeback, A64Writeback::Post);
+        ctx.gprs[3] = 0xabcd;
+        insn.emulate(&mut ctx).unwrap();
+        assert_eq!(&ctx.mmio[4..6], &[0xcd, 0xab]);
+        assert_eq!(ctx.gprs[1], MMIO_BASE + 10);
+
+        // ldr x1, [x1, #8]! is unpredictable
+        assert!(A64LoadStore::decode(0xf840_8c21).is_err());
+    }
+
+    #[test]
+    fn sign_extending_loads() {
+        let mut ctx = ctx();
+        // ldrsb x4, [x1]
+        A64LoadStore::decode(0x3980_0024)
+            .unwrap()
+            .emulate(&mut ctx)
+            .unwrap();
+        assert_eq!(ctx.gprs[4], 0xffff_ffff_ffff_ff80);
+        // ldrsh w4, [x1, #2]
+        A64LoadStore::decode(0x79c0_0424)
+            .unwrap()
+            .emulate(&mut ctx)
+            .unwrap();
+        assert_eq!(ctx.gprs[4], 0xffff_8382);
+        // ldrsw x4, [x1, #4]
+        A64LoadStore::decode(0xb980_0424)
+            .unwrap()
+            .emulate(&mut ctx)
+            .unwrap();
+        assert_eq!(ctx.gprs[4], 0xffff_ffff_8786_8584);
+    }
+
+    #[test]
+    fn register_offset() {
+        // ldr x5, [x1, w2, sxtw #3]
+        let insn = A64LoadStore::decode(0xf862_d825).unwrap();
+        assert_eq!(
+            insn.offset,
+            A64Offset::Reg {
+                rm: 2,
+                extend: A64Extend::Sxtw,
+                shift: 3
+            }
+        );
+        let mut ctx = ctx();
+        ctx.gprs[1] = MMIO_BASE + 0x18;
+        ctx.gprs[2] = 0xffff_ffff;
+        insn.emulate(&mut ctx).unwrap();
+        assert_eq!(ctx.gprs[5], 0x9796_9594_9392_9190);
+    }
+
+    #[test]
+    fn pairs() {
+        // ldp w6, w7, [sp, #-8]
+        let insn = A64LoadStore::decode(0x297f_1fe6).unwrap();
+        assert_eq!(insn.rt2, Some(7));
+        assert_eq!(insn.rn, REG_ZR_SP);
+        let mut ctx = ctx();
+        insn.emulate(&mut ctx).unwrap();
+        assert_eq!(ctx.gprs[6], 0x9b9a_9998);
+        assert_eq!(ctx.gprs[7], 0x9f9e_9d9c);
+
+        // stp xzr, x8, [x1, #16]!
+        let insn = A64LoadStore::decode(0xa981_203f).unwrap();
+        ctx.gprs[8] = 0x42;
+        insn.emulate(&mut ctx).unwrap();
+        assert_eq!(&ctx.mmio[16..24], &[0; 8]);
+        assert_eq!(ctx.mmio[24], 0x42);
+        assert_eq!(ctx.gprs[1], MMIO_BASE + 16);
+
+        // ldpsw x9, x10, [x1], #8
+        let insn = A64LoadStore::decode(0x68c1_2829).unwrap();
+        assert!(insn.sign_extend && insn.sixty_four);
+        ctx.gprs[1] = MMIO_BASE + 0x38;
+        insn.emulate(&mut ctx).unwrap();
+        assert_eq!(ctx.gprs[9], 0xffff_ffff_bbba_b9b8);
+        assert_eq!(ctx.gprs[10], 0xffff_ffff_bfbe_bdbc);
+        assert_eq!(ctx.gprs[1], MMIO_BASE + 0x40);
+
+        // ldp x0, x0, [x1] is unpredictable
+        assert!(A64LoadStore::decode(0xa940_0020).is_err());
+    }
+
+    #[test]
+    fn unsupported() {
+        // ldr q0, [x1]
+        assert!(matches!(
+            A64LoadStore::decode(0x3dc0_0020),
+            Err(InsnError::UnSupportedInsn)
+        ));
+        // prfm pldl1keep, [x1]
+        assert!(matches!(
+            A64LoadStore::decode(0xf980_0020),
+            Err(InsnError::UnSupportedInsn)
+        ));
+        // add x0, x1, x2
+        assert!(matches!(
+            A64LoadStore::decode(0x8b02_0020),
+            Err(InsnError::UnSupportedInsn)
+        ));
+    }
+
+    #[test]
+    fn syndrome_access() {
+        let insn = A64LoadStore::from_syndrome(false, 2, 3, true, false).unwrap();
+        let mut ctx = ctx();
+        insn.emulate_at(&mut ctx, MMIO_BASE).unwrap();
+        assert_eq!(ctx.gprs[3], 0xffff_8180);
+    }
+}
@@ -4,10 +4,12 @@
 //
 // Author: Chuanxiao Dong <chuanxiao.dong@intel.com>
 
+pub mod aarch64;
 mod decode;
 mod insn;
 mod opcode;
 
+pub use aarch64::{A64LoadStore, A64MachineCtx, A64_INSN_SIZE};
 pub use decode::{DecodedInsnCtx, InsnMachineCtx, InsnMachineMem};
 #[cfg(any(test, fuzzing))]
 pub use insn::test_utils::TestCtx;
@@ -22,6 +22,7 @@ pub mod cpu;
 pub mod crypto;
 pub mod debug;
 pub mod error;
+pub mod fdt;
 pub mod fs;
 pub mod fw_cfg;
 pub mod greq;
@@ -40,13 +41,14 @@ pub mod sev;
 pub mod svsm_paging;
 pub mod syscall;
 pub mod task;
+pub mod tcg_log;
 // pub mod tdx;
 pub mod types;
 pub mod utils;
 #[cfg(feature = "virtio-drivers")]
 pub mod virtio;
 pub mod vmm;
-#[cfg(all(feature = "vtpm", not(test)))]
+#[cfg(feature = "vtpm")]
 pub mod vtpm;
 // pub mod stage2;
 pub mod svsm_arm64;
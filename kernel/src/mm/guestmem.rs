@@ -18,7 +18,6 @@ use alloc::string::String;
 use alloc::vec::Vec;
 use core::arch::asm;
 use core::ffi::c_char;
-use core::ptr;
 use core::mem::{size_of, MaybeUninit};
 use core::ptr::{with_exposed_provenance, with_exposed_provenance_mut};
 use syscall::PATH_MAX;
@@ -39,43 +38,32 @@ use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
 /// Any safety requirements for accessing raw pointers apply here as well.
 #[inline]
 pub unsafe fn read_u8(v: VirtAddr) -> Result<u8, SvsmError> {
-    /*
-    x86 版本使用内联 asm + .pushsection "__exception_table" 的方式把访存故障映
-    射到“回退标签”，从而在内核/裸机环境下把内存访问的异常转换成返回Err(SvsmError::Fault)
-    的行为。
-    let mut rcx: u64;
+    let mut err: u64;
     let mut val: u64;
 
     // SAFETY: Assembly dereferences the pointer, which is safe when the
     // function's safety requirements are fulfilled.
     unsafe {
-        asm!("1: movb ({0}), %al",
-             "   xorq %rcx, %rcx",
+        asm!("1: ldrb {val:w}, [{addr}]",
+             "   mov x2, #0",
              "2:",
              ".pushsection \"__exception_table\",\"a\"",
              ".balign 16",
              ".quad (1b)",
              ".quad (2b)",
              ".popsection",
-                in(reg) v.bits(),
-                out("x0") val,
-                out("x2") rcx,
-                /* options(att_syntax, nostack) */);
+                addr = in(reg) v.bits(),
+                val = out(reg) val,
+                out("x2") err,
+                options(nostack));
     }
 
     let ret: u8 = (val & 0xff) as u8;
-    if rcx == 0 {
+    if err == 0 {
         Ok(ret)
     } else {
         Err(SvsmError::Fault)
     }
-    */
-    unsafe{
-        let p = v.as_ptr::<u8>();
-        // SAFETY: caller ensures v is valid; we use volatile read to force memory access.
-        let val = ptr::read_volatile(p);
-        Ok(val)
-    }
 }
 
 /// Writes one byte at a virtual address.
@@ -91,38 +79,30 @@ pub unsafe fn read_u8(v: VirtAddr) -> Result<u8, SvsmError> {
 /// with the appropriate write permissions.
 #[inline]
 pub unsafe fn write_u8(v: VirtAddr, val: u8) -> Result<(), SvsmError> {
-    /*
-    let mut rcx: u64;
+    let mut err: u64;
 
     // SAFETY: Assembly writes to virtual address, safe when function's safety
     // requirements are fulfilled.
     unsafe {
-        asm!("1: movb %al, ({0})",
-             "   xorq %rcx, %rcx",
+        asm!("1: strb {val:w}, [{addr}]",
+             "   mov x2, #0",
              "2:",
              ".pushsection \"__exception_table\",\"a\"",
              ".balign 16",
              ".quad (1b)",
              ".quad (2b)",
              ".popsection",
-                in(reg) v.bits(),
-                in("x0") val as u64,
-                out("x2") rcx,
-                /* options(att_syntax, nostack) */);
+                addr = in(reg) v.bits(),
+                val = in(reg) u64::from(val),
+                out("x2") err,
+                options(nostack));
     }
 
-    if rcx == 0 {
+    if err == 0 {
         Ok(())
     } else {
         Err(SvsmError::Fault)
     }
-    */
-    unsafe{
-        let p = v.as_mut_ptr::<u8>();
-        // SAFETY: caller ensures v is valid for write; use volatile write.
-        ptr::write_volatile(p, val);
-        Ok(())
-    }
 }
 
 /// Read one word from a virtual address.
@@ -141,47 +121,32 @@ pub unsafe fn write_u8(v: VirtAddr, val: u8) -> Result<(), SvsmError> {
 #[expect(dead_code)]
 #[inline]
 unsafe fn read_u16(v: VirtAddr) -> Result<u16, SvsmError> {
-    /*
-    let mut rcx: u64;
+    let mut err: u64;
     let mut val: u64;
 
     // SAFETY: Assembly dereferences the pointer, which is safe when the
     // function's safety requirements are fulfilled.
     unsafe {
-        asm!("1: movw ({0}), {1}",
-             "   xorq %rcx, %rcx",
+        asm!("1: ldrh {val:w}, [{addr}]",
+             "   mov x2, #0",
              "2:",
              ".pushsection \"__exception_table\",\"a\"",
              ".balign 16",
              ".quad (1b)",
              ".quad (2b)",
              ".popsection",
-                in(reg) v.bits(),
-                out(reg) val,
-                out("x2") rcx,
-                /* options(att_syntax, nostack) */);
+                addr = in(reg) v.bits(),
+                val = out(reg) val,
+                out("x2") err,
+                options(nostack));
     }
 
     let ret: u16 = (val & 0xffff) as u16;
-    if rcx == 0 {
+    if err == 0 {
         Ok(ret)
     } else {
         Err(SvsmError::Fault)
     }
-    */
-    unsafe{
-        let p = v.as_ptr::<u16>();
-        // Use unaligned-safe byte read if address may be unaligned
-        let val = if (p as usize) & 1 == 0 {
-            ptr::read_volatile(p)
-        } else {
-            // handle unaligned by reading bytes
-            let b0 = ptr::read_volatile(v.as_ptr::<u8>());
-            let b1 = ptr::read_volatile(unsafe { v.as_ptr::<u8>().add(1) });
-           u16::from_le_bytes([b0, b1])
-        };
-        Ok(val)
-    }
 }
 
 /// Read one dword from a virtual address.
@@ -197,51 +162,34 @@ unsafe fn read_u16(v: VirtAddr) -> Result<u16, SvsmError> {
 /// # Safety
 ///
 /// Any safety requirements for accessing raw pointers apply here as well.
-#[expect(dead_code)]
 #[inline]
-unsafe fn read_u32(v: VirtAddr) -> Result<u32, SvsmError> {
-    /*
-    let mut rcx: u64;
+pub unsafe fn read_u32(v: VirtAddr) -> Result<u32, SvsmError> {
+    let mut err: u64;
     let mut val: u64;
 
     // SAFETY: Assembly dereferences the pointer, which is safe when the
     // function's safety requirements are fulfilled.
     unsafe {
-        asm!("1: movl ({0}), {1}",
-             "   xorq %rcx, %rcx",
+        asm!("1: ldr {val:w}, [{addr}]",
+             "   mov x2, #0",
              "2:",
              ".pushsection \"__exception_table\",\"a\"",
              ".balign 16",
              ".quad (1b)",
              ".quad (2b)",
              ".popsection",
-                in(reg) v.bits(),
-                out(reg) val,
-                out("x2") rcx,
-                /* options(att_syntax, nostack) */);
+                addr = in(reg) v.bits(),
+                val = out(reg) val,
+                out("x2") err,
+                options(nostack));
     }
 
     let ret: u32 = (val & 0xffffffff) as u32;
-    if rcx == 0 {
+    if err == 0 {
         Ok(ret)
     } else {
         Err(SvsmError::Fault)
     }
-    */
-    unsafe{
-        let p = v.as_ptr::<u32>();
-        let val = if (p as usize) & 3 == 0 {
-            ptr::read_volatile(p)
-        } else {
-            // unaligned: read byte-wise (le)
-            let mut bytes = [0u8; 4];
-            for i in 0..4 {
-                bytes[i] = ptr::read_volatile(unsafe { v.as_ptr::<u8>().add(i) });
-            }
-            u32::from_le_bytes(bytes)
-        };
-        Ok(val)
-    }
 }
 
 /// Read one qword from a virtual address.
@@ -257,51 +205,74 @@ unsafe fn read_u32(v: VirtAddr) -> Result<u32, SvsmError> {
 /// # Safety
 ///
 /// Any safety requirements for accessing raw pointers apply here as well.
-#[expect(dead_code)]
 #[inline]
-unsafe fn read_u64(v: VirtAddr) -> Result<u64, SvsmError> {
-    /*
-    let mut rcx: u64;
+pub unsafe fn read_u64(v: VirtAddr) -> Result<u64, SvsmError> {
+    let mut err: u64;
     let mut val: u64;
 
     // SAFETY: Assembly dereferences the pointer, which is safe when the
     // function's safety requirements are fulfilled.
     unsafe {
-        asm!("1: movq ({0}), {1}",
-             "   xorq %rcx, %rcx",
+        asm!("1: ldr {val}, [{addr}]",
+             "   mov x2, #0",
              "2:",
              ".pushsection \"__exception_table\",\"a\"",
              ".balign 16",
              ".quad (1b)",
              ".quad (2b)",
              ".popsection",
-                in(reg) v.bits(),
-                out(reg) val,
-                out("x2") rcx,
-                /* options(att_syntax, nostack) */);
+                addr = in(reg) v.bits(),
+                val = out(reg) val,
+                out("x2") err,
+                options(nostack));
     }
-    if rcx == 0 {
+
+    if err == 0 {
         Ok(val)
     } else {
         Err(SvsmError::Fault)
     }
-    */
-    unsafe{
-        let p = v.as_ptr::<u64>();
-        let val = if (p as usize) & 7 == 0 {
-            ptr::read_volatile(p)
-        } else {
-            // unaligned: read byte-wise (le)
-            let mut bytes = [0u8; 8];
-            for i in 0..8 {
-                bytes[i] = ptr::read_volatile(unsafe { v.as_ptr::<u8>().add(i) });
-            }
-            u64::from_le_bytes(bytes)
-        };
-        Ok(val)
-        }
+}
+
+/// Writes one qword at a virtual address with a single store.
+///
+/// # Safety
+///
+/// The caller must verify not to corrupt arbitrary memory, as this function
+/// doesn't make any checks in that regard.
+///
+/// # Returns
+///
+/// Returns an error if the specified address is not mapped or is not mapped
+/// with the appropriate write permissions.
+#[inline]
+pub unsafe fn write_u64(v: VirtAddr, val: u64) -> Result<(), SvsmError> {
+    let mut err: u64;
+
+    // SAFETY: Assembly writes to virtual address, safe when function's safety
+    // requirements are fulfilled.
+    unsafe {
+        asm!("1: str {val}, [{addr}]",
+             "   mov x2, #0",
+             "2:",
+             ".pushsection \"__exception_table\",\"a\"",
+             ".balign 16",
+             ".quad (1b)",
+             ".quad (2b)",
+             ".popsection",
+                addr = in(reg) v.bits(),
+                val = in(reg) val,
+                out("x2") err,
+                options(nostack));
     }
 
+    if err == 0 {
+        Ok(())
+    } else {
+        Err(SvsmError::Fault)
+    }
+}
+
 /// Copies `size` number of bytes from `src` to `dst`, catching any fault that
 /// might happen during the operation.
 ///
@@ -309,57 +280,36 @@ unsafe fn read_u64(v: VirtAddr) -> Result<u64, SvsmError> {
 ///
 /// The caller must make sure that writing to `dst` does not harm memory safety.
 #[inline]
-unsafe fn copy_bytes(src: *const u8, dst: *mut u8, mut size: usize) -> Result<(), SvsmError> {
-    /*
-    let mut rcx: u64;
+unsafe fn copy_bytes(src: *const u8, dst: *mut u8, size: usize) -> Result<(), SvsmError> {
+    let mut err: u64;
 
     // SAFETY: Safe as long as the function's safety requirements are met. Any
-    // fault that might happen is handled via the exception handlers.
+    // fault that might happen is handled via the exception handlers, which
+    // set x2 to !0 like a faulting `rep movsb` leaves a non-zero count.
     unsafe {
-        asm!("1:cld
-                rep movsb
+        asm!("1: cbz x2, 2f
+                 ldrb {tmp:w}, [x0], #1
+                 strb {tmp:w}, [x1], #1
+                 sub x2, x2, #1
+                 b 1b
               2:
              .pushsection \"__exception_table\",\"a\"
              .balign 16
              .quad (1b)
              .quad (2b)
              .popsection",
-                inout("x4") src.expose_provenance() => _,
-                inout("x3") dst.expose_provenance() => _,
-                inout("x2") size => rcx,
-                /* options(att_syntax, nostack) */);
+                inout("x0") src.expose_provenance() => _,
+                inout("x1") dst.expose_provenance() => _,
+                inout("x2") size => err,
+                tmp = out(reg) _,
+                options(nostack));
     }
 
-    if rcx == 0 {
+    if err == 0 {
         Ok(())
     } else {
         Err(SvsmError::Fault)
     }
-    */
-    // Use 64-bit block copies for speed, falling back to bytes.
-    unsafe{
-        let mut s = src;
-        let mut d = dst;
-
-        // Align to u64 for block copies when possible
-        while size >= 8 {
-            let v = ptr::read_volatile(s as *const u64);
-            ptr::write_volatile(d as *mut u64, v);
-            s = s.add(8);
-            d = d.add(8);
-            size -= 8;
-        }
-
-        while size > 0 {
-            let v = ptr::read_volatile(s);
-            ptr::write_volatile(d, v);
-            s = s.add(1);
-            d = d.add(1);
-            size -= 1;
-        }
-
-        Ok(())
-    }
 }
 
 /// Copies `src` to `dst`.
@@ -9,18 +9,25 @@ use core::ops::Deref;
 use core::ptr::NonNull;
 
 use crate::address::VirtAddr;
-use crate::cpu::flush_tlb_global_sync;
 use crate::cpu::mem::{unsafe_copy_bytes, write_bytes};
-use crate::cpu::percpu::this_cpu;
 use crate::error::SvsmError;
-use crate::mm::validate::{
-    valid_bitmap_clear_valid_4k, valid_bitmap_set_valid_4k, valid_bitmap_valid_addr,
-};
 use crate::mm::{virt_to_phys, PageBox};
-use crate::platform::{PageStateChangeOp, PageValidateOp, SVSM_PLATFORM};
 use crate::protocols::errors::SvsmReqError;
-use crate::types::{PageSize, PAGE_SIZE};
+use crate::types::PAGE_SIZE;
 use crate::utils::MemoryRegion;
+#[cfg(not(feature = "cca"))]
+use crate::{
+    cpu::flush_tlb_global_sync,
+    cpu::percpu::this_cpu,
+    mm::validate::{
+        valid_bitmap_clear_valid_4k, valid_bitmap_set_valid_4k, valid_bitmap_valid_addr,
+    },
+    platform::{PageStateChangeOp, PageValidateOp, SVSM_PLATFORM},
+    types::PageSize,
+};
+
+#[cfg(feature = "cca")]
+use crate::realm;
 
 use zerocopy::{FromBytes, FromZeros, Immutable, IntoBytes};
 
@@ -37,6 +44,7 @@ use zerocopy::{FromBytes, FromZeros, Immutable, IntoBytes};
 /// Notably any objects at `vaddr` must tolerate unsynchronized writes of any
 /// bit pattern.  In addition, the caller must take responsibility for
 /// returning a page to the private state if it is ever freed.
+#[cfg(not(feature = "cca"))]
 unsafe fn make_page_shared(vaddr: VirtAddr) -> Result<(), SvsmError> {
     // Revoke page validation before changing page state.
     // SAFETY: the caller verifies that the memory range is safe to convert.
@@ -79,6 +87,7 @@ unsafe fn make_page_shared(vaddr: VirtAddr) -> Result<(), SvsmError> {
 ///
 /// Converting the memory at `vaddr` must be safe within Rust's memory model.
 /// No outstanding references to the page may exist.
+#[cfg(not(feature = "cca"))]
 unsafe fn make_page_private(vaddr: VirtAddr) -> Result<(), SvsmError> {
     // Update the page tables to map the page as private.
     this_cpu().get_pgtable().set_encrypted_4k(vaddr)?;
@@ -107,6 +116,28 @@ unsafe fn make_page_private(vaddr: VirtAddr) -> Result<(), SvsmError> {
     Ok(())
 }
 
+/// Makes a virtual page shared through its unprotected realm alias.
+///
+/// # Safety
+///
+/// See the non-realm variant of this function.
+#[cfg(feature = "cca")]
+unsafe fn make_page_shared(vaddr: VirtAddr) -> Result<(), SvsmError> {
+    // SAFETY: the caller verifies that the memory range is safe to convert.
+    unsafe { realm::mem::share(MemoryRegion::new(virt_to_phys(vaddr), PAGE_SIZE)) }
+}
+
+/// Makes a virtual page private by moving it back to protected realm memory.
+///
+/// # Safety
+///
+/// See the non-realm variant of this function.
+#[cfg(feature = "cca")]
+unsafe fn make_page_private(vaddr: VirtAddr) -> Result<(), SvsmError> {
+    // SAFETY: the caller verifies that the memory range is safe to convert.
+    unsafe { realm::mem::unshare(MemoryRegion::new(virt_to_phys(vaddr), PAGE_SIZE)) }
+}
+
 /// SharedBox is a safe wrapper around memory pages shared with the host.
 pub struct SharedBox<T> {
     ptr: NonNull<T>,
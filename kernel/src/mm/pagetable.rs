@@ -5,7 +5,9 @@
 // Author: Joerg Roedel <jroedel@suse.de>
 
 use crate::address::{Address, PhysAddr, VirtAddr};
-use crate::cpu::control_regs::{write_cr3, CR0Flags, CR4Flags};
+#[cfg(not(feature = "cca"))]
+use crate::cpu::control_regs::write_cr3;
+use crate::cpu::control_regs::{CR0Flags, CR4Flags};
 use crate::cpu::efer::EFERFlags;
 use crate::cpu::flush_tlb_global_sync;
 use crate::cpu::idt::common::PageFaultError;
@@ -16,6 +18,8 @@ use crate::mm::{
     SVSM_PTE_BASE,
 };
 use crate::platform::SvsmPlatform;
+#[cfg(feature = "cca")]
+use crate::svsm_arm64::mm::mmu;
 use crate::types::{PageSize, PAGE_SIZE, PAGE_SIZE_1G, PAGE_SIZE_2M};
 use crate::utils::immut_after_init::{ImmutAfterInitCell, ImmutAfterInitResult};
 use crate::utils::MemoryRegion;
@@ -138,6 +142,7 @@ bitflags! {
         const PRESENT       = 1 << 0;
         const WRITABLE      = 1 << 1;
         const USER      = 1 << 2;
+        const NOCACHE       = 1 << 4;
         const ACCESSED      = 1 << 5;
         const DIRTY     = 1 << 6;
         const HUGE      = 1 << 7;
@@ -353,23 +358,44 @@ impl PTEntry {
     }
 
     /// Get the flags of the page table entry.
+    #[cfg(not(feature = "cca"))]
     pub fn flags(&self) -> PTEntryFlags {
         PTEntryFlags::from_bits_truncate(self.0.bits() as u64)
     }
 
+    /// Get the flags of the page table entry.
+    #[cfg(feature = "cca")]
+    pub fn flags(&self) -> PTEntryFlags {
+        mmu::decode(self.raw())
+    }
+
     /// Set the page table entry with the specified address and flags.
+    #[cfg(not(feature = "cca"))]
     pub fn set(&mut self, addr: PhysAddr, flags: PTEntryFlags) {
         let addr = addr.bits() as u64;
         assert_eq!(addr & !0x000f_ffff_ffff_f000, 0);
         self.0 = PhysAddr::from(addr | supported_flags(flags).bits());
     }
 
+    /// Set the page table entry with the specified address and flags.
+    #[cfg(feature = "cca")]
+    pub fn set(&mut self, addr: PhysAddr, flags: PTEntryFlags) {
+        self.0 = PhysAddr::from(mmu::encode(addr, supported_flags(flags)));
+    }
+
     /// Get the address from the page table entry, including the shared bit.
+    #[cfg(not(feature = "cca"))]
     pub fn page_frame(&self) -> PhysAddr {
         let addr = PhysAddr::from(self.0.bits() & 0x000f_ffff_ffff_f000);
         strip_confidentiality_bits(addr)
     }
 
+    /// Get the address from the page table entry, including the shared bit.
+    #[cfg(feature = "cca")]
+    pub fn page_frame(&self) -> PhysAddr {
+        strip_confidentiality_bits(mmu::desc_address(self.raw()))
+    }
+
     /// Get the address from the page table entry, excluding the C/shared bit.
     pub fn address(&self) -> PhysAddr {
         strip_shared_address_bits(self.page_frame())
@@ -526,6 +552,7 @@ impl PageTable {
     ///
     /// The caller must ensure to take other actions to make sure a memory safe
     /// execution state is warranted (e.g. changing the stack and register state)
+    #[cfg(not(feature = "cca"))]
     pub unsafe fn load(&self) {
         // SAFETY: demanded to the caller
         unsafe {
@@ -533,6 +560,20 @@ impl PageTable {
         }
     }
 
+    /// Load the current page table into TTBR0_EL1 and TTBR1_EL1.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure to take other actions to make sure a memory safe
+    /// execution state is warranted (e.g. changing the stack and register state)
+    #[cfg(feature = "cca")]
+    pub unsafe fn load(&self) {
+        // SAFETY: demanded to the caller
+        unsafe {
+            mmu::load_root(self.cr3_value());
+        }
+    }
+
     /// Get the CR3 register value for the current page table.
     pub fn cr3_value(&self) -> PhysAddr {
         let pgtable = VirtAddr::from(self as *const Self);
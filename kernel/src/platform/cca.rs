@@ -0,0 +1,260 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Platform support for running the SVSM in plane 0 of an Arm CCA realm.
+
+use super::capabilities::Caps;
+use super::{PageEncryptionMasks, PageStateChangeOp, PageValidateOp, SvsmPlatform};
+use crate::address::{Address, PhysAddr, VirtAddr};
+use crate::console::init_svsm_console;
+use crate::cpu::cpuid::CpuidResult;
+use crate::cpu::percpu::PerCpu;
+use crate::cpu::tlb::TlbFlushScope;
+use crate::error::SvsmError;
+use crate::hyperv;
+use crate::io::{IOPort, DEFAULT_IO_DRIVER};
+use crate::realm::mem::{mmio_vaddr, prot_ns_shared};
+use crate::realm::rsi::ipa_consts::{RSI_CHANGE_DESTROYED, RSI_NO_CHANGE_DESTROYED};
+use crate::realm::rsi::ripas::{RSI_RIPAS_EMPTY, RSI_RIPAS_RAM};
+use crate::realm::rsi::rsi_cmd::{rsi_set_memory_range, REALM_CONFIG};
+use crate::types::PageSize;
+use crate::utils::MemoryRegion;
+use syscall::GlobalFeatureFlags;
+
+use core::arch::asm;
+use core::mem::MaybeUninit;
+use core::ptr;
+
+#[cfg(test)]
+use bootlib::platform::SvsmPlatformType;
+
+/// Hyper-V status returned for hypercalls, which are not available in a
+/// realm.
+const HV_STATUS_INVALID_HYPERCALL_CODE: u64 = 2;
+
+#[derive(Clone, Copy, Debug)]
+pub struct CcaPlatform {}
+
+impl CcaPlatform {
+    pub fn new(_suppress_svsm_interrupts: bool) -> Self {
+        Self {}
+    }
+
+    /// Sets the RIPAS of `region` to `ripas`.
+    fn set_ripas(region: MemoryRegion<PhysAddr>, ripas: u64, flags: u64) -> Result<(), SvsmError> {
+        rsi_set_memory_range(region.start(), region.end(), ripas, flags)?;
+        Ok(())
+    }
+}
+
+impl SvsmPlatform for CcaPlatform {
+    #[cfg(test)]
+    fn platform_type(&self) -> SvsmPlatformType {
+        SvsmPlatformType::Cca
+    }
+
+    fn env_setup(&mut self, debug_serial_port: u64, _vtom: usize) -> Result<(), SvsmError> {
+        // The console writes straight to the UART MMIO registers, so it
+        // can be initialized immediately.
+        init_svsm_console(&DEFAULT_IO_DRIVER, debug_serial_port)
+    }
+
+    fn env_setup_late(&mut self, _debug_serial_port: u64) -> Result<(), SvsmError> {
+        Ok(())
+    }
+
+    fn env_setup_svsm(&self) -> Result<(), SvsmError> {
+        Ok(())
+    }
+
+    fn setup_percpu(&self, _cpu: &PerCpu) -> Result<(), SvsmError> {
+        Ok(())
+    }
+
+    fn setup_percpu_current(&self, _cpu: &PerCpu) -> Result<(), SvsmError> {
+        Ok(())
+    }
+
+    fn get_page_encryption_masks(&self) -> PageEncryptionMasks {
+        // The top IPA bit selects the unprotected alias, which halves the
+        // protected IPA space.
+        let ipa_bits = REALM_CONFIG.ipa_bits as u32;
+        PageEncryptionMasks {
+            private_pte_mask: 0,
+            shared_pte_mask: prot_ns_shared() as usize,
+            addr_mask_width: ipa_bits - 1,
+            phys_addr_sizes: ipa_bits,
+        }
+    }
+
+    fn determine_cet_support(&self) -> bool {
+        false
+    }
+
+    fn capabilities(&self) -> Caps {
+        // The realm's aux planes play the role of the lower VMPLs.
+        let vm_bitmap = (1u64 << (REALM_CONFIG.num_aux_planes + 1)) - 1;
+        Caps::new(vm_bitmap, GlobalFeatureFlags::PLATFORM_TYPE_CCA)
+    }
+
+    /// # Safety
+    /// Hypercalls may have side-effects that affect the integrity of the
+    /// system, and the caller must take responsibility for ensuring that the
+    /// hypercall operation is safe.
+    unsafe fn hypercall(
+        &self,
+        _input_control: hyperv::HvHypercallInput,
+        _hypercall_pages: &hyperv::HypercallPagesGuard<'_>,
+    ) -> hyperv::HvHypercallOutput {
+        hyperv::HvHypercallOutput::from(HV_STATUS_INVALID_HYPERCALL_CODE)
+    }
+
+    fn cpuid(&self, _eax: u32, _ecx: u32) -> Option<CpuidResult> {
+        None
+    }
+
+    unsafe fn write_host_msr(&self, _msr: u32, _value: u64) {}
+
+    fn get_io_port(&self) -> &'static dyn IOPort {
+        &DEFAULT_IO_DRIVER
+    }
+
+    fn page_state_change(
+        &self,
+        region: MemoryRegion<PhysAddr>,
+        _size: PageSize,
+        op: PageStateChangeOp,
+    ) -> Result<(), SvsmError> {
+        match op {
+            PageStateChangeOp::Private => {
+                Self::set_ripas(region, RSI_RIPAS_RAM, RSI_NO_CHANGE_DESTROYED)
+            }
+            PageStateChangeOp::Shared => {
+                Self::set_ripas(region, RSI_RIPAS_EMPTY, RSI_CHANGE_DESTROYED)
+            }
+            // Realm memory has no notion of large-page RMP entries.
+            PageStateChangeOp::Psmash | PageStateChangeOp::Unsmash => Ok(()),
+        }
+    }
+
+    fn validate_physical_page_range(
+        &self,
+        region: MemoryRegion<PhysAddr>,
+        op: PageValidateOp,
+    ) -> Result<(), SvsmError> {
+        match op {
+            PageValidateOp::Validate => {
+                Self::set_ripas(region, RSI_RIPAS_RAM, RSI_NO_CHANGE_DESTROYED)
+            }
+            PageValidateOp::Invalidate => {
+                Self::set_ripas(region, RSI_RIPAS_EMPTY, RSI_CHANGE_DESTROYED)
+            }
+        }
+    }
+
+    /// # Safety
+    /// The caller is required to ensure the safety of the validation operation
+    /// on this memory range.
+    unsafe fn validate_virtual_page_range(
+        &self,
+        region: MemoryRegion<VirtAddr>,
+        op: PageValidateOp,
+    ) -> Result<(), SvsmError> {
+        // The SVSM runs identity mapped in the realm.
+        let start = PhysAddr::from(region.start().bits());
+        self.validate_physical_page_range(MemoryRegion::new(start, region.len()), op)
+    }
+
+    fn flush_tlb(&self, _flush_scope: &TlbFlushScope) {
+        // SAFETY: invalidating stage-1 TLB entries has no memory safety
+        // implications.
+        unsafe {
+            asm!(
+                "dsb ishst",
+                "tlbi vmalle1is",
+                "dsb ish",
+                "isb",
+                options(nostack)
+            );
+        }
+    }
+
+    fn configure_alternate_injection(&mut self, alt_inj_requested: bool) -> Result<(), SvsmError> {
+        if alt_inj_requested {
+            return Err(SvsmError::NotSupported);
+        }
+        Ok(())
+    }
+
+    fn change_apic_registration_state(&self, _incr: bool) -> Result<bool, SvsmError> {
+        Err(SvsmError::NotSupported)
+    }
+
+    fn query_apic_registration_state(&self) -> bool {
+        false
+    }
+
+    fn use_interrupts(&self) -> bool {
+        true
+    }
+
+    fn is_external_interrupt(&self, _vector: usize) -> bool {
+        false
+    }
+
+    fn start_cpu(&self, _cpu: &PerCpu, _start_rip: u64) -> Result<(), SvsmError> {
+        // Secondary PEs have no x86 per-CPU area. They are started with
+        // PSCI CPU_ON by `svsm_arm64::cpu::smp::start_secondary_cpus()`.
+        Err(SvsmError::NotSupported)
+    }
+
+    fn start_svsm_request_loop(&self) -> bool {
+        true
+    }
+
+    unsafe fn mmio_write(&self, paddr: PhysAddr, data: &[u8]) -> Result<(), SvsmError> {
+        // The page is mapped through its unprotected alias, so that the host
+        // emulates the access.
+        let addr = mmio_vaddr(paddr)?.bits();
+        // SAFETY: the caller guarantees that `paddr` is a properly aligned
+        // address within a valid MMIO range.
+        unsafe {
+            match *data {
+                [b] => ptr::write_volatile(addr as *mut u8, b),
+                [b0, b1] => ptr::write_volatile(addr as *mut u16, u16::from_le_bytes([b0, b1])),
+                [b0, b1, b2, b3] => {
+                    ptr::write_volatile(addr as *mut u32, u32::from_le_bytes([b0, b1, b2, b3]))
+                }
+                [b0, b1, b2, b3, b4, b5, b6, b7] => ptr::write_volatile(
+                    addr as *mut u64,
+                    u64::from_le_bytes([b0, b1, b2, b3, b4, b5, b6, b7]),
+                ),
+                _ => return Err(SvsmError::InvalidBytes),
+            }
+        }
+        Ok(())
+    }
+
+    unsafe fn mmio_read(
+        &self,
+        paddr: PhysAddr,
+        data: &mut [MaybeUninit<u8>],
+    ) -> Result<(), SvsmError> {
+        // See `mmio_write()`.
+        let addr = mmio_vaddr(paddr)?.bits();
+        // SAFETY: the caller guarantees that `paddr` is a properly aligned
+        // address within a valid MMIO range.
+        let value: u64 = unsafe {
+            match data.len() {
+                1 => ptr::read_volatile(addr as *const u8).into(),
+                2 => ptr::read_volatile(addr as *const u16).into(),
+                4 => ptr::read_volatile(addr as *const u32).into(),
+                8 => ptr::read_volatile(addr as *const u64),
+                _ => return Err(SvsmError::InvalidBytes),
+            }
+        };
+        for (dst, src) in data.iter_mut().zip(value.to_le_bytes()) {
+            dst.write(src);
+        }
+        Ok(())
+    }
+}
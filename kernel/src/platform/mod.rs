@@ -4,6 +4,8 @@
 //
 // Author: Jon Lange <jlange@microsoft.com>
 
+#[cfg(feature = "cca")]
+pub mod cca;
 pub mod capabilities;
 pub mod guest_cpu;
 pub mod native;
@@ -14,6 +16,8 @@ mod snp_fw;
 pub use snp_fw::SevFWMetaData;
 
 use capabilities::Caps;
+#[cfg(feature = "cca")]
+use cca::CcaPlatform;
 use native::NativePlatform;
 use snp::SnpPlatform;
 // use tdp::TdpPlatform;
@@ -243,6 +247,8 @@ pub enum SvsmPlatformCell {
     Snp(SnpPlatform),
     // Tdp(TdpPlatform),
     Native(NativePlatform),
+    #[cfg(feature = "cca")]
+    Cca(CcaPlatform),
 }
 
 impl SvsmPlatformCell {
@@ -259,6 +265,12 @@ impl SvsmPlatformCell {
                 SvsmPlatformCell::Tdp(TdpPlatform::new(suppress_svsm_interrupts))
             }
                 */
+            #[cfg(feature = "cca")]
+            SvsmPlatformType::Cca => {
+                SvsmPlatformCell::Cca(CcaPlatform::new(suppress_svsm_interrupts))
+            }
+            #[cfg(not(feature = "cca"))]
+            SvsmPlatformType::Cca => panic!("CCA platform support is not enabled"),
         }
     }
 
@@ -276,6 +288,8 @@ impl SvsmPlatformCell {
             SvsmPlatformCell::Native(platform) => platform,
             SvsmPlatformCell::Snp(platform) => platform,
             // SvsmPlatformCell::Tdp(platform) => platform,
+            #[cfg(feature = "cca")]
+            SvsmPlatformCell::Cca(platform) => platform,
         }
     }
 
@@ -284,6 +298,8 @@ impl SvsmPlatformCell {
             SvsmPlatformCell::Native(platform) => platform,
             SvsmPlatformCell::Snp(platform) => platform,
             // SvsmPlatformCell::Tdp(platform) => platform,
+            #[cfg(feature = "cca")]
+            SvsmPlatformCell::Cca(platform) => platform,
         }
     }
 }
@@ -305,5 +321,9 @@ pub fn halt() {
         SvsmPlatformType::Native => NativePlatform::halt(),
         SvsmPlatformType::Snp => SnpPlatform::halt(),
         // SvsmPlatformType::Tdp => TdpPlatform::halt(),
+        #[cfg(feature = "cca")]
+        SvsmPlatformType::Cca => CcaPlatform::halt(),
+        #[cfg(not(feature = "cca"))]
+        SvsmPlatformType::Cca => NativePlatform::halt(),
     }
 }
@@ -11,19 +11,30 @@ extern crate alloc;
 use crate::address::{Address, PhysAddr};
 use crate::crypto::digest::{Algorithm, Sha512};
 use crate::error::{AttestError, SvsmError};
+#[cfg(not(feature = "cca"))]
 use crate::greq::{
     pld_report::{SnpReportRequest, SnpReportResponse},
     services::get_regular_report,
 };
 use crate::mm::guestmem::{copy_slice_to_guest, read_bytes_from_guest, read_from_guest};
 use crate::protocols::{errors::SvsmReqError, RequestParams};
+#[cfg(feature = "cca")]
+use crate::realm::measure::event_log;
+#[cfg(feature = "cca")]
+use crate::realm::rsi::rsi_cmd::rsi_attestation_token_vec;
+#[cfg(not(feature = "cca"))]
+use crate::utils::vec::try_to_vec;
 use crate::utils::MemoryRegion;
 #[cfg(all(feature = "vtpm", not(test)))]
-use crate::vtpm::vtpm_get_manifest;
+use crate::vtpm::{vtpm_get_ek_cert_evidence, vtpm_get_event_log, vtpm_get_manifest};
 
-use alloc::{boxed::Box, vec::Vec};
+#[cfg(not(feature = "cca"))]
+use alloc::boxed::Box;
+use alloc::vec::Vec;
 use uuid::{uuid, Uuid};
-use zerocopy::{FromBytes, FromZeros, Immutable, IntoBytes, KnownLayout};
+#[cfg(not(feature = "cca"))]
+use zerocopy::FromZeros;
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
 
 pub const ATTEST_PROTOCOL_VERSION_MIN: u32 = 1;
 pub const ATTEST_PROTOCOL_VERSION_MAX: u32 = 1;
@@ -36,6 +47,23 @@ const SVSM_ATTEST_SINGLE_SERVICE: u32 = 1;
 #[cfg(all(feature = "vtpm", not(test)))]
 const SVSM_ATTEST_VTPM_GUID: Uuid = uuid!("c476f1eb-0123-45a5-9641-b4e7dde5bfe3");
 
+// The manifest of this service is the TCG2 event log of the measurements extended into the vTPM
+// PCRs before the guest firmware started. The firmware installs it as a configuration table, so
+// that the guest can replay the PCRs.
+#[cfg(all(feature = "vtpm", not(test)))]
+const SVSM_ATTEST_VTPM_EVENT_LOG_GUID: Uuid = uuid!("8d3a6c52-41e7-4f0b-9c25-7be1f04a96d8");
+
+// The manifest of this service is the launch evidence binding the issuer of the vTPM EK
+// certificate, whose SHA-512 digest the certificate embeds. It is empty if the certificate was
+// not provisioned.
+#[cfg(all(feature = "vtpm", not(test)))]
+const SVSM_ATTEST_VTPM_EK_EVIDENCE_GUID: Uuid = uuid!("3710d84a-62a7-4b4d-a35b-c16871058161");
+
+// The manifest of this service is the event log of the aux plane images measured into the
+// realm extensible measurements.
+#[cfg(feature = "cca")]
+const SVSM_ATTEST_EVENT_LOG_GUID: Uuid = uuid!("f3a091f8-5813-4e00-9637-e8a98af2d46f");
+
 // Attest services operation structure, as defined in Table 11 of Secure VM Service Module for
 // SEV-SNP Guests 58019 Rev, 1.00 July 2023
 #[repr(C, packed)]
@@ -239,6 +267,7 @@ impl AttestSingleServiceOp {
     }
 }
 
+#[cfg(not(feature = "cca"))]
 fn get_attestation_report(nonce: &[u8]) -> Result<Box<SnpReportResponse>, SvsmReqError> {
     let mut resp = SnpReportResponse::new_box_zeroed()
         .map_err(|_| SvsmReqError::FatalError(SvsmError::Mem))?;
@@ -257,6 +286,21 @@ fn get_attestation_report(nonce: &[u8]) -> Result<Box<SnpReportResponse>, SvsmRe
     Ok(resp)
 }
 
+/// Returns the evidence binding the 64-byte `report_data`: the SEV-SNP
+/// attestation report with `report_data` as REPORT_DATA.
+#[cfg(not(feature = "cca"))]
+pub(crate) fn get_evidence(report_data: &[u8]) -> Result<Vec<u8>, SvsmReqError> {
+    let resp = get_attestation_report(report_data)?;
+    try_to_vec(resp.report.as_bytes()).map_err(|_| SvsmReqError::FatalError(SvsmError::Mem))
+}
+
+/// Returns the evidence binding the 64-byte `report_data`: the CBOR/COSE
+/// realm token with `report_data` as the realm challenge.
+#[cfg(feature = "cca")]
+pub(crate) fn get_evidence(report_data: &[u8]) -> Result<Vec<u8>, SvsmReqError> {
+    Ok(rsi_attestation_token_vec(report_data)?)
+}
+
 fn write_report_and_manifest(
     manifest: &[u8],
     params: &mut RequestParams,
@@ -326,10 +370,10 @@ fn attest_single_service(
     let nonce_and_manifest = [&nonce[..], manifest].concat();
     let hash = Sha512::digest(&nonce_and_manifest);
 
-    // Get attestation report from PSP with Sha512(nonce||manifest) as REPORT_DATA.
-    let resp = get_attestation_report(hash.as_slice())?;
+    // Get attestation evidence with Sha512(nonce||manifest) as REPORT_DATA.
+    let evidence = get_evidence(hash.as_slice())?;
 
-    write_report_and_manifest(manifest, params, &ops.op, resp.report.as_bytes())
+    write_report_and_manifest(manifest, params, &ops.op, &evidence)
 }
 
 #[cfg(all(feature = "vtpm", not(test)))]
@@ -354,6 +398,15 @@ fn attest_multiple_services(params: &mut RequestParams) -> Result<(), SvsmReqErr
 
     #[cfg(all(feature = "vtpm", not(test)))]
     services.push(SVSM_ATTEST_VTPM_GUID, vtpm_get_manifest()?);
+    #[cfg(all(feature = "vtpm", not(test)))]
+    services.push(SVSM_ATTEST_VTPM_EVENT_LOG_GUID, vtpm_get_event_log());
+    #[cfg(all(feature = "vtpm", not(test)))]
+    services.push(
+        SVSM_ATTEST_VTPM_EK_EVIDENCE_GUID,
+        vtpm_get_ek_cert_evidence(),
+    );
+    #[cfg(feature = "cca")]
+    services.push(SVSM_ATTEST_EVENT_LOG_GUID, event_log()?);
 
     let manifest = services.to_vec()?;
     let mut nonce_and_manifest = attest_op.get_nonce()?;
@@ -363,15 +416,10 @@ fn attest_multiple_services(params: &mut RequestParams) -> Result<(), SvsmReqErr
     // "Secure VM Service Module for SEV-SNP Guests 58019 Rev. 1.00".
     let hash = Sha512::digest(&nonce_and_manifest);
 
-    // Get attestation report from PSP with Sha512(nonce||manifest) as REPORT_DATA.
-    let resp = get_attestation_report(hash.as_slice())?;
+    // Get attestation evidence with Sha512(nonce||manifest) as REPORT_DATA.
+    let evidence = get_evidence(hash.as_slice())?;
 
-    write_report_and_manifest(
-        manifest.as_slice(),
-        params,
-        &attest_op,
-        resp.report.as_bytes(),
-    )
+    write_report_and_manifest(manifest.as_slice(), params, &attest_op, &evidence)
 }
 
 #[allow(clippy::needless_pass_by_ref_mut)]
@@ -390,6 +438,16 @@ fn attest_single_service_handler(params: &mut RequestParams) -> Result<(), SvsmR
     match attest_op.get_guid() {
         #[cfg(all(feature = "vtpm", not(test)))]
         SVSM_ATTEST_VTPM_GUID => attest_single_vtpm(params, &attest_op),
+        #[cfg(all(feature = "vtpm", not(test)))]
+        SVSM_ATTEST_VTPM_EVENT_LOG_GUID => {
+            attest_single_service(&vtpm_get_event_log(), params, &attest_op)
+        }
+        #[cfg(all(feature = "vtpm", not(test)))]
+        SVSM_ATTEST_VTPM_EK_EVIDENCE_GUID => {
+            attest_single_service(&vtpm_get_ek_cert_evidence(), params, &attest_op)
+        }
+        #[cfg(feature = "cca")]
+        SVSM_ATTEST_EVENT_LOG_GUID => attest_single_service(&event_log()?, params, &attest_op),
         _ => Err(SvsmReqError::unsupported_protocol()),
     }
 }
@@ -40,9 +40,28 @@ impl RequestParams {
         }
     }
 
+    /// Builds the parameters of a request made by an AArch64 guest, which
+    /// passes in x2, x3 and x4 what an x86 guest passes in RCX, RDX and R8.
+    pub fn from_gprs(gprs: &[u64]) -> Self {
+        RequestParams {
+            sev_features: 0,
+            rcx: gprs[2],
+            rdx: gprs[3],
+            r8: gprs[4],
+        }
+    }
+
+    #[cfg(not(feature = "cca"))]
     pub fn capture(&self, regs: &mut Vec<GuestRegister>) {
         regs.push(GuestRegister::X64Rcx(self.rcx));
         regs.push(GuestRegister::X64Rdx(self.rdx));
         regs.push(GuestRegister::X64R8(self.r8));
     }
+
+    #[cfg(feature = "cca")]
+    pub fn capture(&self, regs: &mut Vec<GuestRegister>) {
+        regs.push(GuestRegister::Aarch64X2(self.rcx));
+        regs.push(GuestRegister::Aarch64X3(self.rdx));
+        regs.push(GuestRegister::Aarch64X4(self.r8));
+    }
 }
@@ -13,10 +13,10 @@ use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
 
 use crate::{
     address::{Address, PhysAddr},
-    mm::guestmem::{copy_slice_to_guest, read_bytes_from_guest, read_from_guest},
+    mm::guestmem::{copy_slice_to_guest, read_bytes_from_guest, read_from_guest, write_to_guest},
     protocols::{errors::SvsmReqError, RequestParams},
     types::PAGE_SIZE,
-    vtpm::{vtpm_get_locked, TcgTpmSimulatorInterface, VtpmProtocolInterface},
+    vtpm::{vtpm_get_locked, TcgTpmSimulatorInterface, VtpmInterface, VtpmProtocolInterface},
 };
 
 /// vTPM platform commands (SVSM spec, section 8.1 - SVSM_VTPM_QUERY)
@@ -25,25 +25,50 @@ use crate::{
 /// Official TPM 2.0 Reference Implementation by Microsoft.
 ///
 /// `tpm-20-ref/TPMCmd/Simulator/include/TpmTcpProtocol.h`
+///
+/// [`Self::PowerOn`], [`Self::PowerOff`] and [`Self::Reset`] are not
+/// offered to the guest: they clear the PCRs, after which the guest could
+/// extend any boot history on top of the launch measurements. The SVSM
+/// powers on and starts the TPM itself, and a reset of the guest resets the
+/// whole realm.
 #[repr(u32)]
 #[derive(PartialEq, Copy, Clone, Debug)]
 pub enum TpmPlatformCommand {
+    PowerOn = 1,
+    PowerOff = 2,
     SendCommand = 8,
+    CancelOn = 9,
+    CancelOff = 10,
+    NvOn = 11,
+    NvOff = 12,
+    Reset = 17,
+}
+
+impl TpmPlatformCommand {
+    const ALL: &[Self] = &[
+        Self::PowerOn,
+        Self::PowerOff,
+        Self::SendCommand,
+        Self::CancelOn,
+        Self::CancelOff,
+        Self::NvOn,
+        Self::NvOff,
+        Self::Reset,
+    ];
 }
 
 impl TryFrom<u32> for TpmPlatformCommand {
     type Error = SvsmReqError;
 
     fn try_from(value: u32) -> Result<Self, Self::Error> {
-        let cmd = match value {
-            x if x == TpmPlatformCommand::SendCommand as u32 => TpmPlatformCommand::SendCommand,
-            other => {
-                log::warn!("Failed to convert {} to a TPM platform command", other);
-                return Err(SvsmReqError::invalid_parameter());
-            }
-        };
-
-        Ok(cmd)
+        TpmPlatformCommand::ALL
+            .iter()
+            .copied()
+            .find(|cmd| *cmd as u32 == value)
+            .ok_or_else(|| {
+                log::warn!("Failed to convert {} to a TPM platform command", value);
+                SvsmReqError::invalid_parameter()
+            })
     }
 }
 
@@ -112,8 +137,13 @@ impl TpmSendCommandRequest {
             .get(..length)
             .ok_or_else(SvsmReqError::invalid_parameter)?;
 
-        let vtpm = vtpm_get_locked();
+        let mut vtpm = vtpm_get_locked();
         let response = vtpm.send_tpm_command(tpm_cmd, self.locality)?;
+        // The command already ran, so a failure to persist its effect on
+        // the NV memory must not turn into a command failure.
+        if let Err(e) = vtpm.sync_nv() {
+            log::error!("vTPM: failed to save NV memory: {e:?}");
+        }
 
         Ok(response)
     }
@@ -179,6 +209,25 @@ fn tpm_send_command_request(buffer: &mut [u8]) -> Result<(), SvsmReqError> {
     Ok(())
 }
 
+/// Run a platform command which only signals an event to the vTPM.
+///
+/// The request holds nothing but the command, and the response holds no
+/// data.
+fn tpm_signal_request(cmd: TpmPlatformCommand) -> Result<(), SvsmReqError> {
+    let mut vtpm = vtpm_get_locked();
+    match cmd {
+        TpmPlatformCommand::PowerOn => vtpm.signal_poweron(false)?,
+        TpmPlatformCommand::PowerOff => vtpm.signal_poweroff()?,
+        TpmPlatformCommand::CancelOn => vtpm.signal_cancel_on()?,
+        TpmPlatformCommand::CancelOff => vtpm.signal_cancel_off()?,
+        TpmPlatformCommand::NvOn => vtpm.signal_nvon()?,
+        TpmPlatformCommand::NvOff => vtpm.signal_nvoff()?,
+        TpmPlatformCommand::Reset => vtpm.signal_reset()?,
+        TpmPlatformCommand::SendCommand => return Err(SvsmReqError::invalid_request()),
+    }
+    Ok(())
+}
+
 fn vtpm_command_request(params: &RequestParams) -> Result<(), SvsmReqError> {
     let paddr = PhysAddr::from(params.rcx);
 
@@ -207,6 +256,11 @@ fn vtpm_command_request(params: &RequestParams) -> Result<(), SvsmReqError> {
             tpm_send_command_request(&mut buffer[..])?;
             copy_slice_to_guest(&buffer[..], paddr)?;
         }
+        _ => {
+            tpm_signal_request(cmd)?;
+            // Response size
+            write_to_guest(&0u32, paddr)?;
+        }
     };
 
     Ok(())
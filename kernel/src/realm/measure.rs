@@ -0,0 +1,197 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Measured boot of aux planes.
+//!
+//! Every image the SVSM hands to an aux plane is hashed with the realm hash
+//! algorithm and extended into the realm extensible measurement (REM)
+//! [`PLANE_IMAGES_REM`]. Each extension is recorded in an event log in the
+//! TCG crypto agile format, with the REM index in place of the PCR index,
+//! so that a verifier can replay the log against the REM value reported in
+//! the realm token. The log is published at [`EVENT_LOG_PATH`] and is also
+//! served as a service manifest by the attest protocol.
+
+extern crate alloc;
+
+use crate::error::SvsmError;
+use crate::fs::{create_all, open_rw, truncate, write};
+use crate::locking::SpinLock;
+use crate::realm::rsi::hash_algo::{RSI_HASH_SHA_256, RSI_HASH_SHA_512};
+use crate::realm::rsi::rsi_cmd::{rsi_measurement_extend, REALM_CONFIG};
+use crate::tcg_log::{EventLog, EV_IPL, EV_NONHOST_INFO, EV_PLATFORM_CONFIG_FLAGS};
+use alloc::vec;
+use alloc::vec::Vec;
+use sha2::{Digest, Sha256, Sha512};
+
+/// REM which the SVSM extends with the images it hands to aux planes.
+pub const PLANE_IMAGES_REM: u64 = 1;
+
+/// Location of the event log in the SVSM file system.
+pub const EVENT_LOG_PATH: &str = "/realm/event_log";
+
+// TPM algorithm IDs
+const TPM_ALG_SHA256: u16 = 0x000b;
+const TPM_ALG_SHA512: u16 = 0x000d;
+
+/// Errors related to measuring aux plane images.
+#[derive(Clone, Copy, Debug)]
+pub enum MeasureError {
+    /// The realm uses a hash algorithm the SVSM does not implement.
+    HashAlgo(u64),
+    /// The vTPM failed to extend an image into its PCRs.
+    Vtpm,
+}
+
+impl From<MeasureError> for SvsmError {
+    fn from(err: MeasureError) -> Self {
+        Self::Measure(err)
+    }
+}
+
+/// Hash algorithm of the realm measurements.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashAlgo {
+    Sha256,
+    Sha512,
+}
+
+impl HashAlgo {
+    /// Returns the hash algorithm of the realm.
+    pub fn realm() -> Result<Self, MeasureError> {
+        match REALM_CONFIG.hash_algo {
+            RSI_HASH_SHA_256 => Ok(Self::Sha256),
+            RSI_HASH_SHA_512 => Ok(Self::Sha512),
+            algo => Err(MeasureError::HashAlgo(algo)),
+        }
+    }
+
+    /// Returns the TPM_ALG_ID of the algorithm.
+    pub const fn alg_id(self) -> u16 {
+        match self {
+            Self::Sha256 => TPM_ALG_SHA256,
+            Self::Sha512 => TPM_ALG_SHA512,
+        }
+    }
+
+    /// Returns the size of the digests in bytes.
+    pub const fn digest_size(self) -> usize {
+        match self {
+            Self::Sha256 => 32,
+            Self::Sha512 => 64,
+        }
+    }
+
+    /// Returns the bank of the event log, as a (TPM_ALG_ID, digest size)
+    /// pair.
+    fn banks(self) -> [(u16, u16); 1] {
+        [(self.alg_id(), self.digest_size() as u16)]
+    }
+
+    /// Hashes `data`.
+    pub fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Sha256 => Sha256::digest(data).to_vec(),
+            Self::Sha512 => Sha512::digest(data).to_vec(),
+        }
+    }
+}
+
+/// Kinds of images handed to aux planes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageKind {
+    Kernel,
+    DeviceTree,
+    Initrd,
+    VtpmNv,
+}
+
+impl ImageKind {
+    /// Returns the TCG event type recorded for images of this kind.
+    pub const fn event_type(self) -> u32 {
+        match self {
+            Self::Kernel | Self::Initrd => EV_IPL,
+            Self::DeviceTree => EV_PLATFORM_CONFIG_FLAGS,
+            Self::VtpmNv => EV_NONHOST_INFO,
+        }
+    }
+
+    /// Returns the event data recorded for images of this kind.
+    pub const fn description(self) -> &'static [u8] {
+        match self {
+            Self::Kernel => b"aux plane kernel\0",
+            Self::DeviceTree => b"aux plane device tree\0",
+            Self::Initrd => b"aux plane initrd\0",
+            Self::VtpmNv => b"vTPM NV state\0",
+        }
+    }
+}
+
+static EVENT_LOG: SpinLock<EventLog> = SpinLock::new(EventLog::new());
+
+/// Returns the serialized event log.
+pub fn event_log() -> Result<Vec<u8>, SvsmError> {
+    let algo = HashAlgo::realm()?;
+    Ok(EVENT_LOG.lock().to_vec(&algo.banks()))
+}
+
+fn publish_event_log(log: &[u8]) -> Result<(), SvsmError> {
+    let fh = open_rw(EVENT_LOG_PATH).or_else(|_| create_all(EVENT_LOG_PATH))?;
+    truncate(&fh, 0)?;
+    write(&fh, log)?;
+    Ok(())
+}
+
+/// Extends the digest of `data`, an image of `kind`, into
+/// [`PLANE_IMAGES_REM`] and records it in the event log.
+pub fn measure_image(kind: ImageKind, data: &[u8]) -> Result<(), SvsmError> {
+    let algo = HashAlgo::realm()?;
+    let digest = algo.digest(data);
+    rsi_measurement_extend(PLANE_IMAGES_REM, &digest)?;
+    log::info!(
+        "Measured {kind:?} ({} bytes) into REM {PLANE_IMAGES_REM}",
+        data.len()
+    );
+
+    let log = {
+        let mut event_log = EVENT_LOG.lock();
+        event_log.record(
+            PLANE_IMAGES_REM as u32,
+            kind.event_type(),
+            vec![(algo.alg_id(), digest)],
+            kind.description().to_vec(),
+        );
+        event_log.to_vec(&algo.banks())
+    };
+    publish_event_log(&log)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_event_layout() {
+        let algo = HashAlgo::Sha256;
+        let kind = ImageKind::Kernel;
+        let digest = algo.digest(b"kernel");
+        let mut log = EventLog::new();
+        log.record(
+            PLANE_IMAGES_REM as u32,
+            kind.event_type(),
+            vec![(algo.alg_id(), digest.clone())],
+            kind.description().to_vec(),
+        );
+        let bytes = log.to_vec(&algo.banks());
+
+        let spec_len = u32::from_le_bytes(bytes[28..32].try_into().unwrap()) as usize;
+        let spec_id = &bytes[32..32 + spec_len];
+        assert_eq!(&spec_id[24..28], &1u32.to_le_bytes());
+        assert_eq!(&spec_id[28..30], &TPM_ALG_SHA256.to_le_bytes());
+        assert_eq!(&spec_id[30..32], &32u16.to_le_bytes());
+
+        let event = &bytes[32 + spec_len..];
+        assert_eq!(&event[0..4], &1u32.to_le_bytes());
+        assert_eq!(&event[4..8], &EV_IPL.to_le_bytes());
+        assert_eq!(&event[14..46], digest.as_slice());
+        assert_eq!(&event[50..], kind.description());
+    }
+}
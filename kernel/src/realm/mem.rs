@@ -0,0 +1,249 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Sharing of realm memory with the host.
+//!
+//! The top bit of an IPA selects between the protected and the unprotected
+//! alias of the same granule. Memory is shared by setting its RIPAS to
+//! `EMPTY` and accessing it through the unprotected alias, which for the SVSM
+//! means remapping it with the shared mask of the [`PageTable`].
+//!
+//! [`PageTable`]: crate::mm::pagetable::PageTable
+
+use crate::address::{Address, PhysAddr, VirtAddr};
+use crate::error::SvsmError;
+use crate::fdt::DEVICE_TREE;
+use crate::locking::SpinLock;
+use crate::mm::pagetable::{PTEntryFlags, PageTable};
+use crate::mm::PageBox;
+use crate::realm::rsi::ipa_consts::{RSI_CHANGE_DESTROYED, RSI_NO_CHANGE_DESTROYED};
+use crate::realm::rsi::ripas::{RSI_RIPAS_EMPTY, RSI_RIPAS_RAM};
+use crate::realm::rsi::rsi_cmd::{rsi_set_memory_range, REALM_CONFIG};
+use crate::svsm_arm64::mm::mmu::{block_desc_2m, flush_tlb_range};
+use crate::types::{PageSize, PAGE_SIZE};
+use crate::utils::MemoryRegion;
+
+/// Page table of the SVSM, which all PEs share.
+static SVSM_PGTABLE: SpinLock<Option<PageBox<PageTable>>> = SpinLock::new(None);
+
+/// Loads `pgtable` on the current PE and keeps it as the page table of the
+/// SVSM, which the functions of this module update. Secondary PEs inherit
+/// it when they are started.
+///
+/// # Safety
+///
+/// `pgtable` must map all code and data in use, see [`PageTable::load()`].
+pub unsafe fn load_svsm_pgtable(pgtable: PageBox<PageTable>) -> Result<(), SvsmError> {
+    let mut svsm_pgtable = SVSM_PGTABLE.lock();
+    if svsm_pgtable.is_some() {
+        return Err(SvsmError::PlatformInit);
+    }
+    // SAFETY: demanded to the caller.
+    unsafe { pgtable.load() };
+    *svsm_pgtable = Some(pgtable);
+    Ok(())
+}
+
+/// Runs `f` on the page table of the SVSM, with the lock held.
+fn with_svsm_pgtable<R>(
+    f: impl FnOnce(&mut PageTable) -> Result<R, SvsmError>,
+) -> Result<R, SvsmError> {
+    let mut pgtable = SVSM_PGTABLE.lock();
+    f(pgtable.as_mut().ok_or(SvsmError::Mem)?)
+}
+
+/// Returns the IPA which `vaddr` maps to in the page table of the SVSM.
+/// Shared mappings return the protected alias.
+pub fn svsm_phys_addr(vaddr: VirtAddr) -> Result<PhysAddr, SvsmError> {
+    with_svsm_pgtable(|pgtable| pgtable.phys_addr(vaddr))
+}
+
+/// Returns the IPA bit which selects the unprotected alias of an IPA.
+pub fn prot_ns_shared() -> u64 {
+    1u64 << (REALM_CONFIG.ipa_bits - 1)
+}
+
+/// Returns the unprotected alias of `paddr`.
+pub fn unprotected_alias(paddr: PhysAddr) -> PhysAddr {
+    PhysAddr::from(u64::from(paddr) | prot_ns_shared())
+}
+
+/// Returns the protected alias of `paddr`.
+pub fn protected_alias(paddr: PhysAddr) -> PhysAddr {
+    PhysAddr::from(u64::from(paddr) & !prot_ns_shared())
+}
+
+/// Returns whether `paddr` lies in the unprotected half of the IPA space.
+pub fn is_unprotected(paddr: PhysAddr) -> bool {
+    u64::from(paddr) & prot_ns_shared() != 0
+}
+
+fn check_region(region: &MemoryRegion<PhysAddr>) -> Result<(), SvsmError> {
+    if !region.start().is_page_aligned() || !region.end().is_page_aligned() {
+        return Err(SvsmError::InvalidAddress);
+    }
+    if is_unprotected(region.end() - 1) {
+        return Err(SvsmError::InvalidAddress);
+    }
+    Ok(())
+}
+
+/// Switches the SVSM mapping of every page in `region` between the protected
+/// and the unprotected alias.
+fn remap(region: MemoryRegion<PhysAddr>, shared: bool) -> Result<(), SvsmError> {
+    // The SVSM runs identity mapped in the realm.
+    let vregion = MemoryRegion::new(VirtAddr::from(region.start().bits()), region.len());
+    let result = with_svsm_pgtable(|pgtable| {
+        for vaddr in vregion.iter_pages(PageSize::Regular) {
+            if shared {
+                pgtable.set_shared_4k(vaddr)?;
+            } else {
+                pgtable.set_encrypted_4k(vaddr)?;
+            }
+        }
+        Ok(())
+    });
+    // Pages switched before a failure must be flushed as well.
+    flush_tlb_range(vregion);
+    result
+}
+
+/// Shares `region` with the host.
+///
+/// The RIPAS of the region is set to `EMPTY` and the SVSM mapping is switched
+/// to the unprotected alias. The previous contents of the region are lost.
+///
+/// # Safety
+///
+/// Converting the memory in `region` must be safe within Rust's memory
+/// model. Any objects in it must tolerate unsynchronized writes of any bit
+/// pattern by the host.
+pub unsafe fn share(region: MemoryRegion<PhysAddr>) -> Result<(), SvsmError> {
+    check_region(&region)?;
+    rsi_set_memory_range(
+        region.start(),
+        region.end(),
+        RSI_RIPAS_EMPTY,
+        RSI_CHANGE_DESTROYED,
+    )?;
+    remap(region, true)
+}
+
+/// Takes `region` back from the host.
+///
+/// The SVSM mapping is switched to the protected alias and the RIPAS of the
+/// region is set to `RAM`. The contents of the region are zero afterwards.
+///
+/// # Safety
+///
+/// No outstanding references to the memory in `region` may exist.
+pub unsafe fn unshare(region: MemoryRegion<PhysAddr>) -> Result<(), SvsmError> {
+    check_region(&region)?;
+    remap(region, false)?;
+    rsi_set_memory_range(
+        region.start(),
+        region.end(),
+        RSI_RIPAS_RAM,
+        RSI_NO_CHANGE_DESTROYED,
+    )?;
+    Ok(())
+}
+
+/// Maps the emulated device MMIO `region` through its unprotected alias.
+///
+/// Device regions are never backed by realm RAM, so their RIPAS is left
+/// unchanged.
+pub fn map_mmio(region: MemoryRegion<PhysAddr>) -> Result<(), SvsmError> {
+    check_region(&region)?;
+    remap(region, true)
+}
+
+/// Maps the emulated device MMIO `region` into `pgtable` through its
+/// unprotected alias, at the virtual address equal to its IPA.
+///
+/// Unlike [`map_mmio`], the region need not be mapped already, which allows
+/// device registers to be set up in a page table before it is loaded.
+pub fn map_mmio_in(
+    pgtable: &mut PageTable,
+    region: MemoryRegion<PhysAddr>,
+) -> Result<(), SvsmError> {
+    check_region(&region)?;
+    let flags = PTEntryFlags::data() | PTEntryFlags::NOCACHE;
+    for paddr in region.iter_pages(PageSize::Regular) {
+        let vaddr = VirtAddr::from(paddr.bits());
+        pgtable.map_4k(vaddr, unprotected_alias(paddr), flags)?;
+    }
+    Ok(())
+}
+
+/// Returns the virtual address through which the SVSM accesses the emulated
+/// device MMIO at `paddr`, given as either alias.
+///
+/// The SVSM maps device MMIO at the virtual address equal to its protected
+/// IPA, like [`map_mmio_in()`]. Pages which are not mapped yet, e.g. those
+/// of devices an aux plane accesses, are mapped on demand. Realm RAM is
+/// never accessed this way.
+pub fn mmio_vaddr(paddr: PhysAddr) -> Result<VirtAddr, SvsmError> {
+    let ipa = protected_alias(paddr);
+    if DEVICE_TREE.memory.iter().any(|ram| ram.contains(ipa)) {
+        return Err(SvsmError::InvalidAddress);
+    }
+    let vaddr = VirtAddr::from(ipa.bits());
+    with_svsm_pgtable(|pgtable| {
+        if pgtable.phys_addr(vaddr).is_err() {
+            map_mmio_in(pgtable, page_region(ipa, 1))?;
+            flush_tlb_range(MemoryRegion::new(vaddr.page_align(), PAGE_SIZE));
+        }
+        Ok(vaddr)
+    })
+}
+
+/// Maps the emulated device MMIO at `paddr` through its unprotected alias in
+/// the boot page table, before the page table of the SVSM is loaded.
+///
+/// The boot page table maps the low IPA space with 2MB blocks, and no memory
+/// can be allocated this early to split them, so the whole block holding
+/// `paddr` is switched to the unprotected alias.
+///
+/// # Safety
+///
+/// The boot page table must be in use on the only running PE, and the block
+/// holding `paddr` must not hold anything but device MMIO.
+pub unsafe fn map_boot_mmio(paddr: PhysAddr) -> Result<(), SvsmError> {
+    if is_unprotected(paddr) {
+        return Err(SvsmError::InvalidAddress);
+    }
+    let vaddr = VirtAddr::from(paddr.bits());
+    let page = MemoryRegion::new(vaddr.page_align(), PAGE_SIZE);
+    // SAFETY: the boot page table is identity mapped and only walked by the
+    // current PE, as demanded to the caller.
+    let desc = unsafe { block_desc_2m(vaddr) }.ok_or(SvsmError::Mem)?;
+    // Break before make, as the output address changes.
+    let block = *desc;
+    *desc = 0;
+    flush_tlb_range(page);
+    *desc = block | prot_ns_shared();
+    flush_tlb_range(page);
+    Ok(())
+}
+
+/// Returns the page-aligned region of `len` bytes which starts at `addr`.
+pub fn page_region(addr: PhysAddr, len: usize) -> MemoryRegion<PhysAddr> {
+    let start = addr.page_align();
+    let end = (addr + len).page_align_up();
+    MemoryRegion::from_addresses(start, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_region_rounds_out() {
+        let r = page_region(PhysAddr::from(0x9000_0010u64), 8);
+        assert_eq!(r.start(), PhysAddr::from(0x9000_0000u64));
+        assert_eq!(r.len(), PAGE_SIZE);
+
+        let r = page_region(PhysAddr::from(0x9000_0ff8u64), 16);
+        assert_eq!(r.len(), 2 * PAGE_SIZE);
+    }
+}
@@ -1 +1,5 @@
-pub mod rsi;
\ No newline at end of file
+pub mod measure;
+pub mod mem;
+pub mod perm;
+pub mod plane;
+pub mod rsi;
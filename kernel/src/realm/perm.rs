@@ -0,0 +1,262 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Stage-2 permission overlays protecting plane-0 memory from aux planes.
+//!
+//! Every protected IPA of the realm carries a permission index, and plane 0
+//! sets, for each aux plane, the stage-2 permissions granted by an index.
+//! This is the CCA counterpart of RMPADJUST on SEV-SNP: memory owned by the
+//! SVSM is assigned [`PermOwner::Svsm`], which aux planes cannot access at
+//! all, while [`grant_plane_access()`] and [`revoke_plane_access()`] move
+//! memory between the SVSM and the aux planes, e.g. for memory deposit and
+//! withdrawal requests.
+//!
+//! Memory which was never assigned an index keeps index 0, whose
+//! permissions are set up by the RMM and give aux planes full access.
+
+use crate::address::{Address, PhysAddr};
+use crate::error::SvsmError;
+use crate::fdt::DEVICE_TREE;
+use crate::realm::mem::{is_unprotected, page_region};
+use crate::realm::plane::{PlaneError, PLANE_MAX_AUX_PLANES};
+use crate::realm::rsi::rsi_cmd::{rsi_mem_set_perm_value, rsi_set_perm_range, REALM_CONFIG};
+use crate::utils::immut_after_init::ImmutAfterInitCell;
+use crate::utils::MemoryRegion;
+use bootlib::kernel_launch::{KernelLaunchInfo, STAGE2_HEAP_END, STAGE2_HEAP_START};
+
+/// Stage-2 overlay permissions, encoded as in S2POR_EL1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u64)]
+pub enum S2Perm {
+    NoAccess = 0b0000,
+    Read = 0b1000,
+    ReadExec = 0b1011,
+    ReadWrite = 0b1100,
+    ReadWriteExec = 0b1111,
+}
+
+/// Owner of a range of realm memory, which selects its permission index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PermOwner {
+    /// Only accessible by plane 0.
+    Svsm,
+    /// Accessible by all aux planes.
+    AllPlanes,
+    /// Only accessible by the given aux plane, besides plane 0.
+    Plane(usize),
+}
+
+/// Permission index of memory owned by the SVSM.
+const PERM_INDEX_SVSM: u64 = 1;
+/// Permission index of memory granted to all aux planes.
+const PERM_INDEX_ALL_PLANES: u64 = 2;
+/// First permission index of memory private to one aux plane. Plane `n`
+/// uses index `PERM_INDEX_PLANE_BASE + n - 1`.
+const PERM_INDEX_PLANE_BASE: u64 = 3;
+
+impl PermOwner {
+    /// Returns all owners with a permission index.
+    fn all() -> impl Iterator<Item = Self> {
+        [Self::Svsm, Self::AllPlanes]
+            .into_iter()
+            .chain((1..=PLANE_MAX_AUX_PLANES).map(Self::Plane))
+    }
+
+    /// Returns the permission index of memory owned by `self`.
+    pub fn index(&self) -> Result<u64, SvsmError> {
+        match *self {
+            Self::Svsm => Ok(PERM_INDEX_SVSM),
+            Self::AllPlanes => Ok(PERM_INDEX_ALL_PLANES),
+            Self::Plane(plane) if (1..=PLANE_MAX_AUX_PLANES).contains(&plane) => {
+                Ok(PERM_INDEX_PLANE_BASE + plane as u64 - 1)
+            }
+            Self::Plane(plane) => Err(PlaneError::InvalidPlane(plane).into()),
+        }
+    }
+
+    /// Returns the permissions of aux plane `plane` for memory owned by
+    /// `self`.
+    pub fn perm_for(&self, plane: usize) -> S2Perm {
+        match *self {
+            Self::Svsm => S2Perm::NoAccess,
+            Self::AllPlanes => S2Perm::ReadWriteExec,
+            Self::Plane(owner) if owner == plane => S2Perm::ReadWriteExec,
+            Self::Plane(_) => S2Perm::NoAccess,
+        }
+    }
+}
+
+/// Sets the permissions of aux plane `plane` for memory with permission
+/// index `index` to `perm`.
+pub fn set_plane_perm(plane: usize, index: u64, perm: S2Perm) -> Result<(), SvsmError> {
+    rsi_mem_set_perm_value(plane as u64, index, perm as u64)?;
+    Ok(())
+}
+
+/// Assigns the memory in `region` to `owner`.
+pub fn set_owner(region: MemoryRegion<PhysAddr>, owner: PermOwner) -> Result<(), SvsmError> {
+    if !region.start().is_page_aligned()
+        || !region.end().is_page_aligned()
+        || is_unprotected(region.end() - 1)
+    {
+        return Err(SvsmError::InvalidAddress);
+    }
+    rsi_set_perm_range(region.start(), region.end(), owner.index()?)?;
+    Ok(())
+}
+
+/// Removes all access of aux planes to `region`.
+pub fn revoke_plane_access(region: MemoryRegion<PhysAddr>) -> Result<(), SvsmError> {
+    set_owner(region, PermOwner::Svsm)
+}
+
+/// Gives all aux planes full access to `region`.
+///
+/// # Safety
+///
+/// The caller is required to ensure that exposing `region` to the aux
+/// planes will not affect memory safety.
+pub unsafe fn grant_plane_access(region: MemoryRegion<PhysAddr>) -> Result<(), SvsmError> {
+    set_owner(region, PermOwner::AllPlanes)
+}
+
+/// Number of realm memory regions owned by the SVSM.
+const NUM_SVSM_REGIONS: usize = 5;
+
+/// Realm memory owned by the SVSM, set up by [`perm_init()`].
+static SVSM_REGIONS: ImmutAfterInitCell<[MemoryRegion<PhysAddr>; NUM_SVSM_REGIONS]> =
+    ImmutAfterInitCell::uninit();
+
+/// Returns the realm memory owned by the SVSM, rounded to pages:
+///
+/// - the SVSM image in `kernel`, which also holds the boot page tables and
+///   the boot stack,
+/// - the heap in `heap`,
+/// - the early heap, from which the page tables, the per-CPU areas, the
+///   stacks and boot data of the PEs and the vTPM state are allocated,
+/// - the ramfs archive in `fs`, which may be empty,
+/// - the DTB in `dtb`.
+fn svsm_regions(
+    kernel: MemoryRegion<PhysAddr>,
+    heap: MemoryRegion<PhysAddr>,
+    fs: MemoryRegion<PhysAddr>,
+    dtb: MemoryRegion<PhysAddr>,
+) -> [MemoryRegion<PhysAddr>; NUM_SVSM_REGIONS] {
+    let early_heap = MemoryRegion::from_addresses(
+        PhysAddr::from(u64::from(STAGE2_HEAP_START)),
+        PhysAddr::from(u64::from(STAGE2_HEAP_END)),
+    );
+    [kernel, heap, early_heap, fs, dtb].map(|region| page_region(region.start(), region.len()))
+}
+
+/// Returns the realm memory owned by the SVSM, which aux planes cannot
+/// access.
+///
+/// # Panics
+///
+/// Panics if called before [`perm_init()`].
+pub fn svsm_memory() -> &'static [MemoryRegion<PhysAddr>] {
+    &SVSM_REGIONS[..]
+}
+
+/// Returns whether `region` lies in one of the `ram` regions and does not
+/// overlap any of the `svsm` regions.
+pub fn owned_by_planes(
+    region: MemoryRegion<PhysAddr>,
+    ram: &[MemoryRegion<PhysAddr>],
+    svsm: &[MemoryRegion<PhysAddr>],
+) -> bool {
+    ram.iter().any(|ram| ram.contains_region(&region)) && !svsm.iter().any(|r| region.overlap(r))
+}
+
+/// Returns whether aux planes may access `region`, i.e. whether it lies in
+/// realm RAM and does not overlap the memory owned by the SVSM.
+///
+/// # Panics
+///
+/// Panics if called before [`perm_init()`].
+pub fn is_plane_memory(region: MemoryRegion<PhysAddr>) -> bool {
+    owned_by_planes(region, &DEVICE_TREE.memory, svsm_memory())
+}
+
+/// Sets up the permissions of all aux planes for each [`PermOwner`], and
+/// protects all memory owned by the SVSM, which also holds the vTPM state,
+/// from the aux planes.
+pub fn perm_init(launch_info: &KernelLaunchInfo) -> Result<(), SvsmError> {
+    let num_aux_planes = (REALM_CONFIG.num_aux_planes as usize).min(PLANE_MAX_AUX_PLANES);
+    for owner in PermOwner::all() {
+        let index = owner.index()?;
+        for plane in 1..=num_aux_planes {
+            set_plane_perm(plane, index, owner.perm_for(plane))?;
+        }
+    }
+
+    let regions = svsm_regions(
+        MemoryRegion::from_addresses(
+            PhysAddr::from(launch_info.kernel_region_phys_start),
+            PhysAddr::from(launch_info.kernel_region_phys_end),
+        ),
+        MemoryRegion::new(
+            PhysAddr::from(launch_info.heap_area_phys_start),
+            launch_info.heap_area_size as usize,
+        ),
+        MemoryRegion::from_addresses(
+            PhysAddr::from(launch_info.kernel_fs_start),
+            PhysAddr::from(launch_info.kernel_fs_end),
+        ),
+        DEVICE_TREE.dtb,
+    );
+    for region in regions.iter().filter(|region| !region.is_empty()) {
+        revoke_plane_access(*region)?;
+    }
+    SVSM_REGIONS
+        .init(regions)
+        .map_err(|_| SvsmError::PlatformInit)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn owner_indices() {
+        let mut indices = [0u64; PLANE_MAX_AUX_PLANES + 2];
+        assert_eq!(PermOwner::all().count(), indices.len());
+        for (index, owner) in indices.iter_mut().zip(PermOwner::all()) {
+            *index = owner.index().unwrap();
+        }
+        // Index 0 is left to the RMM and every owner has its own index.
+        assert!(indices.iter().all(|&i| i != 0));
+        assert!(indices.windows(2).all(|w| w[0] < w[1]));
+        assert!(PermOwner::Plane(0).index().is_err());
+        assert!(PermOwner::Plane(PLANE_MAX_AUX_PLANES + 1).index().is_err());
+    }
+
+    #[test]
+    fn owner_perms() {
+        assert_eq!(PermOwner::Svsm.perm_for(1), S2Perm::NoAccess);
+        assert_eq!(PermOwner::AllPlanes.perm_for(2), S2Perm::ReadWriteExec);
+        assert_eq!(PermOwner::Plane(1).perm_for(1), S2Perm::ReadWriteExec);
+        assert_eq!(PermOwner::Plane(1).perm_for(2), S2Perm::NoAccess);
+    }
+
+    #[test]
+    fn svsm_regions_cover_page_tables() {
+        let regions = svsm_regions(
+            MemoryRegion::new(PhysAddr::from(0x4008_0000u64), 0x40_0000),
+            MemoryRegion::new(PhysAddr::from(0x4048_0000u64), 0x4_0000),
+            MemoryRegion::new(PhysAddr::null(), 0),
+            MemoryRegion::new(PhysAddr::from(0x4800_0100u64), 0x2000),
+        );
+        // The page tables are allocated from the early heap.
+        let early_heap = MemoryRegion::from_addresses(
+            PhysAddr::from(u64::from(STAGE2_HEAP_START)),
+            PhysAddr::from(u64::from(STAGE2_HEAP_END)),
+        );
+        assert!(regions.iter().any(|r| r.contains_region(&early_heap)));
+        // A partially used page of the DTB is protected as a whole.
+        let dtb = MemoryRegion::new(PhysAddr::from(0x4800_0000u64), 0x3000);
+        assert!(regions.iter().any(|r| r.contains_region(&dtb)));
+        assert!(regions.iter().all(|r| r.start().is_page_aligned()));
+    }
+}
@@ -0,0 +1,391 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Decoding of aux-plane exits and dispatch to their handlers.
+//!
+//! [`PlaneExitReason::decode`] turns the raw syndrome the RMM reports in
+//! [`PlaneExit`] into a typed exit. An [`ExitDispatcher`] then routes it to
+//! the handler registered for its [`ExitClass`]. Exits without a handler are
+//! reported as [`PlaneError::UnhandledExit`] instead of stalling the SVSM.
+
+use super::{AuxPlaneContext, PlaneError};
+use crate::address::PhysAddr;
+use crate::error::SvsmError;
+use crate::realm::rsi::exit_reasons::{RSI_EXIT_HOST, RSI_EXIT_IRQ, RSI_EXIT_SYNC};
+use crate::realm::rsi::PlaneExit;
+use crate::svsm_arm64::cpu::esr::{
+    esr_ec, esr_iss, ESR_ELX_EC_DABT_LOW, ESR_ELX_EC_HVC64, ESR_ELX_EC_IABT_LOW, ESR_ELX_EC_SMC64,
+    ESR_ELX_EC_SYS64, ESR_ELX_EC_WFX,
+};
+
+// Data abort ISS fields
+const ISS_DABT_ISV: u64 = 1 << 24;
+const ISS_DABT_SAS_SHIFT: u64 = 22;
+const ISS_DABT_SSE: u64 = 1 << 21;
+const ISS_DABT_SRT_SHIFT: u64 = 16;
+const ISS_DABT_SF: u64 = 1 << 15;
+const ISS_DABT_WNR: u64 = 1 << 6;
+
+// Trapped MSR/MRS ISS fields
+const ISS_SYS_OP0_SHIFT: u64 = 20;
+const ISS_SYS_OP2_SHIFT: u64 = 17;
+const ISS_SYS_OP1_SHIFT: u64 = 14;
+const ISS_SYS_CRN_SHIFT: u64 = 10;
+const ISS_SYS_RT_SHIFT: u64 = 5;
+const ISS_SYS_CRM_SHIFT: u64 = 1;
+const ISS_SYS_READ: u64 = 1 << 0;
+
+// WFx ISS fields
+const ISS_WFX_TI_WFE: u64 = 1 << 0;
+
+const HPFAR_FIPA_MASK: u64 = !0xf;
+
+/// Number of SMCCC argument registers (x1..x7) captured for SMC and HVC
+/// exits.
+pub const SMCCC_NUM_ARGS: usize = 7;
+
+/// A system register, identified by its MSR/MRS encoding.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SysReg {
+    pub op0: u8,
+    pub op1: u8,
+    pub crn: u8,
+    pub crm: u8,
+    pub op2: u8,
+}
+
+impl SysReg {
+    pub const fn new(op0: u8, op1: u8, crn: u8, crm: u8, op2: u8) -> Self {
+        Self {
+            op0,
+            op1,
+            crn,
+            crm,
+            op2,
+        }
+    }
+
+    /// Returns the encoding used by `SMC_RSI_PLANE_SYSREG_READ/WRITE`.
+    pub const fn rsi_encoding(&self) -> u64 {
+        ((self.op0 as u64) << 14)
+            | ((self.op1 as u64) << 11)
+            | ((self.crn as u64) << 7)
+            | ((self.crm as u64) << 3)
+            | (self.op2 as u64)
+    }
+}
+
+/// Direction of a trapped system register access.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SysRegOp {
+    /// MRS: the register is read into `rt`.
+    Read,
+    /// MSR: the value of `rt` is written to the register.
+    Write,
+}
+
+/// A decoded aux-plane exit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaneExitReason {
+    /// A stage-2 data abort. `size` and `reg` are only known when the
+    /// syndrome is valid (ESR.ISV set).
+    DataAbort {
+        ipa: PhysAddr,
+        va: u64,
+        write: bool,
+        size: Option<usize>,
+        reg: Option<usize>,
+        sign_extend: bool,
+        sixty_four: bool,
+    },
+    /// A stage-2 instruction abort.
+    InstructionAbort { ipa: PhysAddr, va: u64 },
+    /// An SMC issued by the plane.
+    Smc {
+        fid: u64,
+        args: [u64; SMCCC_NUM_ARGS],
+    },
+    /// An HVC issued by the plane.
+    Hvc {
+        imm: u16,
+        fid: u64,
+        args: [u64; SMCCC_NUM_ARGS],
+    },
+    /// A trapped MSR or MRS.
+    SysReg {
+        reg: SysReg,
+        op: SysRegOp,
+        rt: usize,
+    },
+    /// A trapped WFI or WFE.
+    Wfx { wfe: bool },
+    /// The plane was interrupted by a physical or maintenance interrupt.
+    Irq { misr: u64 },
+    /// The plane issued RSI_HOST_CALL.
+    HostCall,
+    /// A synchronous exception with an exception class not decoded above.
+    Sync { ec: u64, esr: u64 },
+    /// An exit reason unknown to the SVSM.
+    Unknown { reason: u8 },
+}
+
+/// Coarse classification of [`PlaneExitReason`], used to look up handlers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitClass {
+    DataAbort = 0,
+    InstructionAbort,
+    Smc,
+    Hvc,
+    SysReg,
+    Wfx,
+    Irq,
+    HostCall,
+    Sync,
+    Unknown,
+}
+
+const EXIT_CLASSES: usize = ExitClass::Unknown as usize + 1;
+
+impl PlaneExitReason {
+    /// Decodes the exit reported by the RMM in `exit`.
+    pub fn decode(exit: &PlaneExit) -> Self {
+        match u64::from(exit.reason) {
+            RSI_EXIT_SYNC => Self::decode_sync(exit),
+            RSI_EXIT_IRQ => Self::Irq {
+                misr: exit.gicv3_misr,
+            },
+            RSI_EXIT_HOST => Self::HostCall,
+            _ => Self::Unknown {
+                reason: exit.reason,
+            },
+        }
+    }
+
+    fn fault_ipa(exit: &PlaneExit) -> PhysAddr {
+        PhysAddr::from(((exit.hpfar_el2 & HPFAR_FIPA_MASK) << 8) | (exit.far_el2 & 0xfff))
+    }
+
+    fn smccc_args(exit: &PlaneExit) -> [u64; SMCCC_NUM_ARGS] {
+        let mut args = [0u64; SMCCC_NUM_ARGS];
+        args.copy_from_slice(&exit.gprs[1..=SMCCC_NUM_ARGS]);
+        args
+    }
+
+    fn decode_sync(exit: &PlaneExit) -> Self {
+        let esr = exit.esr_el2;
+        let iss = esr_iss(esr);
+
+        match esr_ec(esr) {
+            ESR_ELX_EC_DABT_LOW => {
+                let isv = (iss & ISS_DABT_ISV) != 0;
+                Self::DataAbort {
+                    ipa: Self::fault_ipa(exit),
+                    va: exit.far_el2,
+                    write: (iss & ISS_DABT_WNR) != 0,
+                    size: isv.then(|| 1usize << ((iss >> ISS_DABT_SAS_SHIFT) & 0x3)),
+                    reg: isv.then(|| ((iss >> ISS_DABT_SRT_SHIFT) & 0x1f) as usize),
+                    sign_extend: isv && (iss & ISS_DABT_SSE) != 0,
+                    sixty_four: isv && (iss & ISS_DABT_SF) != 0,
+                }
+            }
+            ESR_ELX_EC_IABT_LOW => Self::InstructionAbort {
+                ipa: Self::fault_ipa(exit),
+                va: exit.far_el2,
+            },
+            ESR_ELX_EC_SMC64 => Self::Smc {
+                fid: exit.gprs[0],
+                args: Self::smccc_args(exit),
+            },
+            ESR_ELX_EC_HVC64 => Self::Hvc {
+                imm: (iss & 0xffff) as u16,
+                fid: exit.gprs[0],
+                args: Self::smccc_args(exit),
+            },
+            ESR_ELX_EC_SYS64 => Self::SysReg {
+                reg: SysReg::new(
+                    ((iss >> ISS_SYS_OP0_SHIFT) & 0x3) as u8,
+                    ((iss >> ISS_SYS_OP1_SHIFT) & 0x7) as u8,
+                    ((iss >> ISS_SYS_CRN_SHIFT) & 0xf) as u8,
+                    ((iss >> ISS_SYS_CRM_SHIFT) & 0xf) as u8,
+                    ((iss >> ISS_SYS_OP2_SHIFT) & 0x7) as u8,
+                ),
+                op: if (iss & ISS_SYS_READ) != 0 {
+                    SysRegOp::Read
+                } else {
+                    SysRegOp::Write
+                },
+                rt: ((iss >> ISS_SYS_RT_SHIFT) & 0x1f) as usize,
+            },
+            ESR_ELX_EC_WFX => Self::Wfx {
+                wfe: (iss & ISS_WFX_TI_WFE) != 0,
+            },
+            ec => Self::Sync { ec, esr },
+        }
+    }
+
+    /// Returns the class used to select a handler for this exit.
+    pub fn class(&self) -> ExitClass {
+        match self {
+            Self::DataAbort { .. } => ExitClass::DataAbort,
+            Self::InstructionAbort { .. } => ExitClass::InstructionAbort,
+            Self::Smc { .. } => ExitClass::Smc,
+            Self::Hvc { .. } => ExitClass::Hvc,
+            Self::SysReg { .. } => ExitClass::SysReg,
+            Self::Wfx { .. } => ExitClass::Wfx,
+            Self::Irq { .. } => ExitClass::Irq,
+            Self::HostCall => ExitClass::HostCall,
+            Self::Sync { .. } => ExitClass::Sync,
+            Self::Unknown { .. } => ExitClass::Unknown,
+        }
+    }
+}
+
+/// A handler for one class of aux-plane exits. On success the plane is
+/// resumed with the (possibly updated) context.
+pub type ExitHandler = fn(&mut AuxPlaneContext, &PlaneExitReason) -> Result<(), SvsmError>;
+
+/// Routes decoded exits to the handler registered for their class.
+#[derive(Clone, Copy, Debug)]
+pub struct ExitDispatcher {
+    handlers: [Option<ExitHandler>; EXIT_CLASSES],
+}
+
+impl Default for ExitDispatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExitDispatcher {
+    /// Creates a dispatcher without any registered handler.
+    pub const fn new() -> Self {
+        Self {
+            handlers: [None; EXIT_CLASSES],
+        }
+    }
+
+    /// Registers `handler` for `class`, replacing any previous handler.
+    pub fn register(&mut self, class: ExitClass, handler: ExitHandler) {
+        self.handlers[class as usize] = Some(handler);
+    }
+
+    /// Removes the handler for `class`.
+    pub fn unregister(&mut self, class: ExitClass) {
+        self.handlers[class as usize] = None;
+    }
+
+    /// Dispatches `exit` of `plane` to its handler.
+    pub fn dispatch(
+        &self,
+        plane: &mut AuxPlaneContext,
+        exit: &PlaneExitReason,
+    ) -> Result<(), SvsmError> {
+        match self.handlers[exit.class() as usize] {
+            Some(handler) => handler(plane, exit),
+            None => Err(PlaneError::UnhandledExit(*exit).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::Address;
+    use zerocopy::FromZeros;
+
+    fn sync_exit(esr: u64) -> PlaneExit {
+        let mut exit = PlaneExit::new_zeroed();
+        exit.reason = RSI_EXIT_SYNC as u8;
+        exit.esr_el2 = esr;
+        exit
+    }
+
+    #[test]
+    fn decode_data_abort() {
+        // DABT_LOW, ISV, 4 byte store from w3
+        let esr = (ESR_ELX_EC_DABT_LOW << 26) | ISS_DABT_ISV | (2 << 22) | (3 << 16) | ISS_DABT_WNR;
+        let mut exit = sync_exit(esr);
+        exit.hpfar_el2 = 0x0900_0000 >> 8;
+        exit.far_el2 = 0xffff_0000_0000_0018;
+
+        match PlaneExitReason::decode(&exit) {
+            PlaneExitReason::DataAbort {
+                ipa,
+                write,
+                size,
+                reg,
+                ..
+            } => {
+                assert_eq!(ipa.bits(), 0x0900_0018);
+                assert!(write);
+                assert_eq!(size, Some(4));
+                assert_eq!(reg, Some(3));
+            }
+            other => panic!("unexpected exit {other:?}"),
+        }
+    }
+
+    #[test]
+    fn decode_data_abort_without_syndrome() {
+        let exit = sync_exit(ESR_ELX_EC_DABT_LOW << 26);
+        match PlaneExitReason::decode(&exit) {
+            PlaneExitReason::DataAbort { size, reg, .. } => {
+                assert_eq!(size, None);
+                assert_eq!(reg, None);
+            }
+            other => panic!("unexpected exit {other:?}"),
+        }
+    }
+
+    #[test]
+    fn decode_sysreg() {
+        // MRS x2, ID_AA64PFR0_EL1 (3, 0, 0, 4, 0)
+        let iss = (3 << 20) | (4 << 1) | (2 << 5) | ISS_SYS_READ;
+        let exit = sync_exit((ESR_ELX_EC_SYS64 << 26) | iss);
+        assert_eq!(
+            PlaneExitReason::decode(&exit),
+            PlaneExitReason::SysReg {
+                reg: SysReg::new(3, 0, 0, 4, 0),
+                op: SysRegOp::Read,
+                rt: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn decode_smc() {
+        let mut exit = sync_exit(ESR_ELX_EC_SMC64 << 26);
+        exit.gprs[0] = 0x8400_0000;
+        exit.gprs[1] = 7;
+        match PlaneExitReason::decode(&exit) {
+            PlaneExitReason::Smc { fid, args } => {
+                assert_eq!(fid, 0x8400_0000);
+                assert_eq!(args[0], 7);
+            }
+            other => panic!("unexpected exit {other:?}"),
+        }
+    }
+
+    #[test]
+    fn unhandled_exit_is_error() {
+        let dispatcher = ExitDispatcher::new();
+        let mut plane = AuxPlaneContext::default();
+        let exit = PlaneExitReason::HostCall;
+        assert!(dispatcher.dispatch(&mut plane, &exit).is_err());
+    }
+
+    #[test]
+    fn registered_handler_is_called() {
+        fn skip(plane: &mut AuxPlaneContext, _: &PlaneExitReason) -> Result<(), SvsmError> {
+            plane.pc += 4;
+            Ok(())
+        }
+
+        let mut dispatcher = ExitDispatcher::new();
+        dispatcher.register(ExitClass::Wfx, skip);
+        let mut plane = AuxPlaneContext::default();
+        dispatcher
+            .dispatch(&mut plane, &PlaneExitReason::Wfx { wfe: false })
+            .unwrap();
+        assert_eq!(plane.pc, 4);
+    }
+}
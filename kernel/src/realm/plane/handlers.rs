@@ -0,0 +1,184 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Default handlers for aux-plane exits.
+
+use super::exit::{ExitClass, ExitDispatcher, PlaneExitReason, SMCCC_NUM_ARGS};
+use super::mmio::emulate_mmio;
+use super::psci::{handle_psci, is_psci_call};
+use super::svsm_call::{handle_svsm_call, svsm_call_protocol};
+use super::sysreg::emulate_sysreg;
+use super::walk::plane_set_leaf_bits;
+use super::{AuxPlaneContext, PlaneError, PlaneState};
+use crate::address::{Address, PhysAddr};
+use crate::error::SvsmError;
+use crate::realm::mem::{is_unprotected, prot_ns_shared};
+use crate::realm::perm::is_plane_memory;
+use crate::realm::rsi::fid::{SMC_RSI_ABI_VERSION, SMC_RSI_IPA_STATE_GET, SMC_RSI_IPA_STATE_SET};
+use crate::realm::rsi::ipa_consts::{RSI_ACCEPT, RSI_REJECT};
+use crate::realm::rsi::retcodes::{RSI_ERROR_INPUT, RSI_SUCCESS};
+use crate::realm::rsi::ripas::RSI_RIPAS_EMPTY;
+use crate::realm::rsi::rsi_cmd::{rsi_ipa_state_get, rsi_ipa_state_set};
+use crate::realm::rsi::RsiError;
+use crate::svsm_arm64::cpu::gicv3::gicv3_handle_irq;
+use crate::types::PAGE_SIZE;
+use crate::utils::MemoryRegion;
+
+/// Returns a dispatcher with the exit handlers the SVSM provides by default.
+pub fn default_dispatcher() -> ExitDispatcher {
+    let mut dispatcher = ExitDispatcher::new();
+    dispatcher.register(ExitClass::DataAbort, handle_data_abort);
+    dispatcher.register(ExitClass::Smc, handle_smc);
+    dispatcher.register(ExitClass::Hvc, handle_hvc);
+    dispatcher.register(ExitClass::SysReg, emulate_sysreg);
+    dispatcher.register(ExitClass::Wfx, handle_wfx);
+    dispatcher.register(ExitClass::Irq, handle_irq);
+    dispatcher
+}
+
+/// Handles an access through the protected alias of a page the plane has
+/// shared with the host, by switching the plane's mapping of the page to the
+/// unprotected alias. Accesses to unprotected IPAs are device MMIO, which is
+/// emulated.
+fn handle_data_abort(plane: &mut AuxPlaneContext, exit: &PlaneExitReason) -> Result<(), SvsmError> {
+    let PlaneExitReason::DataAbort { ipa, va, .. } = *exit else {
+        return Err(PlaneError::ExitNotEmulated(*exit).into());
+    };
+
+    if is_unprotected(ipa) {
+        return emulate_mmio(plane, exit);
+    }
+
+    let page = ipa.page_align();
+    let (_, ripas) = rsi_ipa_state_get(page, page + PAGE_SIZE)?;
+    if ripas != RSI_RIPAS_EMPTY {
+        return Err(PlaneError::ExitNotEmulated(*exit).into());
+    }
+
+    plane_set_leaf_bits(plane, va, prot_ns_shared())
+}
+
+/// Completes an RSI_IPA_STATE_SET call of an aux plane. Like the stage-2
+/// permissions set up by [`crate::realm::perm`], it only lets the plane
+/// change memory the aux planes own, never memory of the SVSM.
+fn handle_ipa_state_set(plane: &mut AuxPlaneContext, args: &[u64; SMCCC_NUM_ARGS]) {
+    let [base, top, ripas, flags, ..] = *args;
+    let (base, top) = (PhysAddr::from(base), PhysAddr::from(top));
+
+    let valid = base < top
+        && base.is_page_aligned()
+        && top.is_page_aligned()
+        && !is_unprotected(top - 1)
+        && is_plane_memory(MemoryRegion::from_addresses(base, top));
+    let ret = if valid {
+        match rsi_ipa_state_set(base, top, ripas, flags) {
+            Ok(next) => [RSI_SUCCESS, u64::from(next), RSI_ACCEPT],
+            Err(RsiError::Rejected) => [RSI_SUCCESS, u64::from(base), RSI_REJECT],
+            Err(e) => [e.status(), 0, 0],
+        }
+    } else {
+        [RSI_ERROR_INPUT, 0, 0]
+    };
+
+    plane.pc += 4;
+    plane.gprs[..3].copy_from_slice(&ret);
+}
+
+/// Completes an RSI_IPA_STATE_GET call of an aux plane.
+fn handle_ipa_state_get(plane: &mut AuxPlaneContext, args: &[u64; SMCCC_NUM_ARGS]) {
+    let (base, top) = (PhysAddr::from(args[0]), PhysAddr::from(args[1]));
+
+    let ret = if base < top && !is_unprotected(top - 1) {
+        match rsi_ipa_state_get(base, top) {
+            Ok((end, ripas)) => [RSI_SUCCESS, u64::from(end), ripas],
+            Err(e) => [e.status(), 0, 0],
+        }
+    } else {
+        [RSI_ERROR_INPUT, 0, 0]
+    };
+
+    plane.pc += 4;
+    plane.gprs[..3].copy_from_slice(&ret);
+}
+
+fn handle_smc(plane: &mut AuxPlaneContext, exit: &PlaneExitReason) -> Result<(), SvsmError> {
+    const NOT_SUPPORTED: u64 = u64::MAX;
+
+    let PlaneExitReason::Smc { fid, args } = *exit else {
+        return Err(PlaneError::ExitNotEmulated(*exit).into());
+    };
+
+    if let Some(protocol) = svsm_call_protocol(fid) {
+        // Skip the SMC first, so that a handler can redirect the plane.
+        plane.pc += 4;
+        handle_svsm_call(plane, protocol);
+        return Ok(());
+    }
+    if is_psci_call(fid) {
+        handle_psci(plane, fid, &args);
+        return Ok(());
+    }
+
+    // Memory the plane shares with the host is converted on its behalf.
+    match fid {
+        SMC_RSI_IPA_STATE_SET => {
+            handle_ipa_state_set(plane, &args);
+            return Ok(());
+        }
+        SMC_RSI_IPA_STATE_GET => {
+            handle_ipa_state_get(plane, &args);
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let ret = match fid {
+        // SMCCC_VERSION
+        0x8000_0000 => (1 << 16) | 2,
+        // SMCCC_ARCH_FEATURES
+        0x8000_0001 => NOT_SUPPORTED,
+        // TRNG
+        0x8400_0050 => NOT_SUPPORTED,
+        SMC_RSI_ABI_VERSION => NOT_SUPPORTED,
+        _ => return Err(PlaneError::ExitNotEmulated(*exit).into()),
+    };
+
+    plane.pc += 4;
+    plane.gprs[0] = ret;
+    plane.gprs[1..4].fill(0);
+    Ok(())
+}
+
+/// Handles SVSM calls issued with HVC. Unlike a trapped SMC, the exception
+/// is taken after the HVC instruction, so the PC is not advanced.
+fn handle_hvc(plane: &mut AuxPlaneContext, exit: &PlaneExitReason) -> Result<(), SvsmError> {
+    match *exit {
+        PlaneExitReason::Hvc { fid, .. } => match svsm_call_protocol(fid) {
+            Some(protocol) => {
+                handle_svsm_call(plane, protocol);
+                Ok(())
+            }
+            None => Err(PlaneError::ExitNotEmulated(*exit).into()),
+        },
+        _ => Err(PlaneError::ExitNotEmulated(*exit).into()),
+    }
+}
+
+/// Puts a plane executing WFI to sleep until it has an interrupt to take.
+/// WFE only yields the CPU to the other planes.
+fn handle_wfx(plane: &mut AuxPlaneContext, exit: &PlaneExitReason) -> Result<(), SvsmError> {
+    if let PlaneExitReason::Wfx { wfe: false } = *exit {
+        if !plane.gic.has_pending() {
+            plane.state = PlaneState::Blocked;
+        }
+    }
+    plane.pc += 4;
+    Ok(())
+}
+
+fn handle_irq(_plane: &mut AuxPlaneContext, _exit: &PlaneExitReason) -> Result<(), SvsmError> {
+    // Maintenance interrupts were already handled by `VGic::sync()` when the
+    // plane state was saved. Plane 0 runs with interrupts masked, so the
+    // physical interrupt which caused the exit is handled here.
+    gicv3_handle_irq();
+    Ok(())
+}
@@ -0,0 +1,583 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Launch descriptors of aux planes.
+//!
+//! How each aux plane is started is described in the device tree, with one
+//! node per plane:
+//!
+//! ```text
+//! planes {
+//!     #address-cells = <1>;
+//!     #size-cells = <0>;
+//!
+//!     plane@1 {
+//!         compatible = "svsm,aux-plane";
+//!         reg = <1>;                      // plane index
+//!         image = "/planes/Image";        // optional ramfs path of the kernel
+//!         load-addr = <0x0 0x60000000>;   // IPA of the kernel
+//!         entry = <0x0 0x60000000>;       // optional, defaults to load-addr
+//!         dtb-addr = <0x0 0x5fe00000>;    // optional IPA to copy the DTB to
+//!         initrd = "/planes/initrd";      // optional ramfs path of the initrd
+//!         regs = <0x0 0x0>;               // optional values of x1 onwards
+//!         pstate = <0x0 0x3c5>;           // optional initial PSTATE
+//!         trap-wfi;                       // optional trap flags
+//!         trap-wfe;
+//!         trap-hc;
+//!         gic-owner = <1>;                // optional, defaults to 1
+//!     };
+//! };
+//! ```
+//!
+//! Without `image`, the kernel is expected to have been placed at
+//! `load-addr` by the loader. An initrd read from the ramfs is copied to the
+//! region given by `linux,initrd-start` and `linux,initrd-end` in `/chosen`,
+//! so that the plane kernel finds it. x0 holds the DTB address, as expected
+//! by the arm64 Linux boot protocol.
+//!
+//! The DTB the SVSM was booted with is SVSM memory, so the plane gets a copy
+//! of it: at `dtb-addr`, or otherwise at the first 2MB boundary after the
+//! memory used by the kernel.
+//!
+//! Aux planes without a descriptor are not started. If the device tree has
+//! no descriptor at all, every aux plane starts at [`DEFAULT_PLANE_ENTRY`]
+//! with a copy of the DTB the SVSM was booted with.
+//!
+//! Every image is measured with [`measure_image`] before any plane runs,
+//! and extended into the vTPM PCRs as well when the vTPM is built.
+
+extern crate alloc;
+
+use super::{PlaneError, PSTATE_EL1H_MASKED};
+use crate::address::{Address, PhysAddr, VirtAddr};
+use crate::error::SvsmError;
+use crate::fdt::{Fdt, FdtNode, DEVICE_TREE};
+use crate::fs::open_read;
+use crate::realm::measure::{measure_image, ImageKind};
+use crate::realm::perm::{owned_by_planes, svsm_memory};
+use crate::realm::rsi::plane_enter_flags::{
+    PLANE_ENTER_FLAG_GIC_OWNER, PLANE_ENTER_FLAG_TRAP_HC, PLANE_ENTER_FLAG_TRAP_WFE,
+    PLANE_ENTER_FLAG_TRAP_WFI,
+};
+use crate::realm::rsi::PLANE_RUN_GPRS;
+use crate::utils::MemoryRegion;
+#[cfg(all(feature = "vtpm", not(test)))]
+use crate::{realm::measure::MeasureError, vtpm::vtpm_measure_image};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::slice;
+
+/// Kernel entry of the aux planes when the device tree has no launch
+/// descriptor.
+pub const DEFAULT_PLANE_ENTRY: u64 = 0x6000_0000;
+
+/// Alignment of the DTB copy placed after the kernel.
+const DTB_ALIGN: u64 = 0x20_0000;
+
+const PLANE_COMPATIBLE: &str = "svsm,aux-plane";
+
+// Offsets into the arm64 Linux image header
+const IMAGE_SIZE_OFFSET: usize = 16;
+const IMAGE_MAGIC_OFFSET: usize = 56;
+const IMAGE_HEADER_SIZE: usize = 64;
+const IMAGE_MAGIC: u32 = 0x644d_5241;
+
+/// Where the SVSM finds an image it hands to an aux plane.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImageSource {
+    /// The loader placed the image in realm memory.
+    InPlace,
+    /// The image is read from a file in the ramfs.
+    RamFs(String),
+}
+
+/// Describes how an aux plane is loaded and started.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlaneLaunchDesc {
+    pub index: usize,
+    pub kernel: ImageSource,
+    pub load_addr: PhysAddr,
+    pub entry: u64,
+    /// IPA to copy the DTB to, or `None` to copy it after the kernel.
+    pub dtb_addr: Option<PhysAddr>,
+    /// Source of the initrd described in `/chosen`, if there is one.
+    pub initrd: ImageSource,
+    /// Initial values of x0 onwards; x0 is the DTB address, set by
+    /// [`PlaneLoader::load()`] to the address of the copy.
+    pub gprs: [u64; PLANE_RUN_GPRS],
+    pub pstate: u64,
+    /// `SMC_RSI_PLANE_ENTER` flags.
+    pub flags: u64,
+}
+
+impl PlaneLaunchDesc {
+    /// Creates the descriptor of plane `index` running the kernel the
+    /// loader placed at `entry`, with the DTB at `fdt_addr`.
+    pub fn new(index: usize, entry: u64, fdt_addr: u64) -> Self {
+        let mut gprs = [0; PLANE_RUN_GPRS];
+        gprs[0] = fdt_addr;
+        Self {
+            index,
+            kernel: ImageSource::InPlace,
+            load_addr: PhysAddr::from(entry),
+            entry,
+            dtb_addr: None,
+            initrd: ImageSource::InPlace,
+            gprs,
+            pstate: PSTATE_EL1H_MASKED,
+            flags: PLANE_ENTER_FLAG_GIC_OWNER,
+        }
+    }
+
+    /// Parses the descriptor in `node`. `dtb` is the DTB the SVSM was
+    /// booted with.
+    fn from_node(node: &FdtNode<'_>, dtb: PhysAddr) -> Result<Self, PlaneError> {
+        let invalid = || {
+            log::error!("Invalid launch descriptor {}", node.name());
+            PlaneError::InvalidLaunchDesc
+        };
+        let index = node.reg().next().ok_or_else(invalid)?.start().bits();
+        let load_addr = node.property_u64("load-addr").ok_or_else(invalid)?;
+        let dtb_addr = node.property_u64("dtb-addr").map(PhysAddr::from);
+
+        let mut gprs = [0; PLANE_RUN_GPRS];
+        gprs[0] = u64::from(dtb_addr.unwrap_or(dtb));
+        let regs = node.property("regs").unwrap_or_default();
+        if regs.len() % 8 != 0 || regs.len() / 8 >= PLANE_RUN_GPRS {
+            return Err(invalid());
+        }
+        for (reg, value) in gprs[1..].iter_mut().zip(regs.chunks_exact(8)) {
+            *reg = u64::from_be_bytes(value.try_into().unwrap());
+        }
+
+        let mut flags = 0;
+        for (name, flag) in [
+            ("trap-wfi", PLANE_ENTER_FLAG_TRAP_WFI),
+            ("trap-wfe", PLANE_ENTER_FLAG_TRAP_WFE),
+            ("trap-hc", PLANE_ENTER_FLAG_TRAP_HC),
+        ] {
+            if node.property(name).is_some() {
+                flags |= flag;
+            }
+        }
+        if node.property_u32("gic-owner").unwrap_or(1) != 0 {
+            flags |= PLANE_ENTER_FLAG_GIC_OWNER;
+        }
+
+        let ramfs = |name| node.property_str(name).map(|path| path.to_string());
+        Ok(Self {
+            index: index as usize,
+            kernel: ramfs("image").map_or(ImageSource::InPlace, ImageSource::RamFs),
+            load_addr: PhysAddr::from(load_addr),
+            entry: node.property_u64("entry").unwrap_or(load_addr),
+            dtb_addr,
+            initrd: ramfs("initrd").map_or(ImageSource::InPlace, ImageSource::RamFs),
+            gprs,
+            pstate: node.property_u64("pstate").unwrap_or(PSTATE_EL1H_MASKED),
+            flags,
+        })
+    }
+
+    /// Returns the DTB address handed to the plane.
+    pub fn fdt_addr(&self) -> u64 {
+        self.gprs[0]
+    }
+}
+
+/// Parses the launch descriptors in `fdt`, or returns `None` if it has
+/// none. `dtb` is the address of the DTB the SVSM was booted with.
+pub fn parse_launch_descs(
+    fdt: &Fdt<'_>,
+    dtb: PhysAddr,
+) -> Result<Option<Vec<PlaneLaunchDesc>>, PlaneError> {
+    let mut descs = Vec::new();
+    for node in fdt.find_compatible(PLANE_COMPATIBLE) {
+        if node.is_enabled() {
+            descs.push(PlaneLaunchDesc::from_node(&node, dtb)?);
+        }
+    }
+    Ok((!descs.is_empty()).then_some(descs))
+}
+
+/// Returns the launch descriptors of the aux planes, falling back to the
+/// default layout if the device tree has none.
+pub fn launch_descs(num_aux_planes: usize) -> Result<Vec<PlaneLaunchDesc>, SvsmError> {
+    let dtb = DEVICE_TREE.dtb.start();
+    // SAFETY: the DTB the SVSM was booted with stays mapped and unmodified.
+    let fdt = unsafe { Fdt::from_addr(VirtAddr::from(dtb.bits()))? };
+    match parse_launch_descs(&fdt, dtb)? {
+        Some(descs) => Ok(descs),
+        None => Ok((1..=num_aux_planes)
+            .map(|index| PlaneLaunchDesc::new(index, DEFAULT_PLANE_ENTRY, u64::from(dtb)))
+            .collect()),
+    }
+}
+
+/// Returns the size of the arm64 Linux image whose header is `header`.
+fn kernel_image_size(header: &[u8]) -> Option<usize> {
+    let field = |offset: usize, len: usize| header.get(offset..offset + len);
+    let magic = u32::from_le_bytes(field(IMAGE_MAGIC_OFFSET, 4)?.try_into().ok()?);
+    let size = u64::from_le_bytes(field(IMAGE_SIZE_OFFSET, 8)?.try_into().ok()?);
+    (magic == IMAGE_MAGIC && size != 0).then_some(size as usize)
+}
+
+/// Checks that `region` lies in one of the `ram` regions and does not
+/// overlap any of the `reserved` regions.
+fn check_region(
+    index: usize,
+    region: MemoryRegion<PhysAddr>,
+    ram: &[MemoryRegion<PhysAddr>],
+    reserved: &[MemoryRegion<PhysAddr>],
+) -> Result<(), PlaneError> {
+    if !owned_by_planes(region, ram, reserved) {
+        return Err(PlaneError::LoadRegion(index));
+    }
+    Ok(())
+}
+
+/// Checks that `region` lies in realm RAM and does not overlap the memory
+/// owned by the SVSM: its image, heaps, page tables and the DTB it was
+/// booted with.
+fn check_load_region(index: usize, region: MemoryRegion<PhysAddr>) -> Result<(), PlaneError> {
+    check_region(index, region, &DEVICE_TREE.memory, svsm_memory())
+}
+
+/// Returns the memory used by the kernel loaded at `kernel` once it runs,
+/// which also covers the BSS of an arm64 Linux image with header `header`.
+fn kernel_footprint(kernel: MemoryRegion<PhysAddr>, header: &[u8]) -> MemoryRegion<PhysAddr> {
+    let size = kernel_image_size(header).unwrap_or(0).max(kernel.len());
+    MemoryRegion::new(kernel.start(), size)
+}
+
+/// Returns where the DTB of `len` bytes is copied to for the plane
+/// described by `desc`, whose kernel uses `kernel`.
+fn dtb_region(
+    desc: &PlaneLaunchDesc,
+    kernel: MemoryRegion<PhysAddr>,
+    len: usize,
+) -> MemoryRegion<PhysAddr> {
+    let addr = desc
+        .dtb_addr
+        .unwrap_or_else(|| PhysAddr::from(u64::from(kernel.end()).next_multiple_of(DTB_ALIGN)));
+    MemoryRegion::new(addr, len)
+}
+
+/// Returns the SVSM view of the realm memory in `region`.
+///
+/// # Safety
+///
+/// `region` must be realm RAM which is not modified while the returned
+/// slice is alive.
+unsafe fn region_bytes(region: MemoryRegion<PhysAddr>) -> &'static [u8] {
+    // The SVSM runs identity mapped in the realm.
+    let va = VirtAddr::from(region.start().bits());
+    // SAFETY: guaranteed by the caller.
+    unsafe { slice::from_raw_parts(va.as_ptr::<u8>(), region.len()) }
+}
+
+/// Copies `data` to the realm memory at `region`, which must be as large as
+/// `data`.
+///
+/// # Safety
+///
+/// `region` must be realm RAM which is neither used by the SVSM nor
+/// accessed by a running plane.
+unsafe fn copy_to_region(region: MemoryRegion<PhysAddr>, data: &[u8]) {
+    let va = VirtAddr::from(region.start().bits());
+    // SAFETY: guaranteed by the caller.
+    let dst = unsafe { slice::from_raw_parts_mut(va.as_mut_ptr::<u8>(), region.len()) };
+    dst.copy_from_slice(data);
+}
+
+/// Loads the images of the aux planes and measures them. Images shared by
+/// several planes are measured once.
+#[derive(Debug, Default)]
+pub struct PlaneLoader {
+    measured: Vec<(ImageKind, PhysAddr, usize)>,
+}
+
+impl PlaneLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Measures the image of `kind` at `region` unless it was measured
+    /// already.
+    ///
+    /// # Safety
+    ///
+    /// See [`region_bytes()`].
+    unsafe fn measure(
+        &mut self,
+        kind: ImageKind,
+        region: MemoryRegion<PhysAddr>,
+    ) -> Result<(), SvsmError> {
+        let key = (kind, region.start(), region.len());
+        if self.measured.contains(&key) {
+            return Ok(());
+        }
+        // SAFETY: guaranteed by the caller.
+        let data = unsafe { region_bytes(region) };
+        measure_image(kind, data)?;
+        #[cfg(all(feature = "vtpm", not(test)))]
+        vtpm_measure_image(kind, data).map_err(|_| MeasureError::Vtpm)?;
+        self.measured.push(key);
+        Ok(())
+    }
+
+    /// Reads the ramfs file at `path` into `region`, or into its start if
+    /// `region` is empty, and returns the region the file occupies.
+    fn load_file(
+        index: usize,
+        path: &str,
+        region: MemoryRegion<PhysAddr>,
+    ) -> Result<MemoryRegion<PhysAddr>, SvsmError> {
+        let fh = open_read(path)?;
+        let size = fh.size();
+        if !region.is_empty() && size > region.len() {
+            return Err(PlaneError::LoadRegion(index).into());
+        }
+        let target = MemoryRegion::new(region.start(), size);
+        check_load_region(index, target)?;
+
+        let mut data = Vec::new();
+        data.try_reserve_exact(size).map_err(|_| SvsmError::Mem)?;
+        data.resize(size, 0);
+        if fh.read(&mut data)? != size {
+            return Err(PlaneError::LoadRegion(index).into());
+        }
+        // SAFETY: the target region was checked to be realm RAM not owned by
+        // the SVSM, and no plane runs yet.
+        unsafe { copy_to_region(target, &data) };
+        Ok(target)
+    }
+
+    /// Loads and measures the kernel, DTB and initrd of the plane described
+    /// by `desc`, and sets its x0 to the DTB.
+    pub fn load(&mut self, desc: &mut PlaneLaunchDesc) -> Result<(), SvsmError> {
+        let index = desc.index;
+
+        let kernel = match &desc.kernel {
+            ImageSource::RamFs(path) => {
+                Self::load_file(index, path, MemoryRegion::new(desc.load_addr, 0))?
+            }
+            ImageSource::InPlace => {
+                let header = MemoryRegion::new(desc.load_addr, IMAGE_HEADER_SIZE);
+                check_load_region(index, header)?;
+                // SAFETY: the header was checked to be realm RAM, and no
+                // plane runs yet.
+                let size = kernel_image_size(unsafe { region_bytes(header) })
+                    .ok_or(PlaneError::KernelImage(index))?;
+                let kernel = MemoryRegion::new(desc.load_addr, size);
+                check_load_region(index, kernel)?;
+                kernel
+            }
+        };
+        // SAFETY: the kernel region was checked above.
+        unsafe { self.measure(ImageKind::Kernel, kernel)? };
+
+        let header = MemoryRegion::new(kernel.start(), IMAGE_HEADER_SIZE.min(kernel.len()));
+        // SAFETY: the header lies in the kernel region checked above.
+        let footprint = kernel_footprint(kernel, unsafe { region_bytes(header) });
+        let dtb = dtb_region(desc, footprint, DEVICE_TREE.dtb.len());
+        check_load_region(index, dtb)?;
+        if dtb.overlap(&footprint) || DEVICE_TREE.initrd.is_some_and(|r| dtb.overlap(&r)) {
+            return Err(PlaneError::LoadRegion(index).into());
+        }
+        // SAFETY: the DTB region was checked, and the source DTB stays in
+        // place for the lifetime of the SVSM.
+        unsafe { copy_to_region(dtb, region_bytes(DEVICE_TREE.dtb)) };
+        // SAFETY: the DTB was copied above.
+        unsafe { self.measure(ImageKind::DeviceTree, dtb)? };
+        desc.dtb_addr = Some(dtb.start());
+        desc.gprs[0] = u64::from(dtb.start());
+
+        let initrd = match (&desc.initrd, DEVICE_TREE.initrd) {
+            (ImageSource::RamFs(path), Some(region)) => Some(Self::load_file(index, path, region)?),
+            (ImageSource::RamFs(_), None) => return Err(PlaneError::LoadRegion(index).into()),
+            (ImageSource::InPlace, region) => region,
+        };
+        if let Some(initrd) = initrd {
+            // SAFETY: the initrd was placed by the loader or loaded above.
+            unsafe { self.measure(ImageKind::Initrd, initrd)? };
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fdt::tests::Builder;
+    use bootlib::kernel_launch::{STAGE2_HEAP_END, STAGE2_HEAP_START};
+
+    #[test]
+    fn image_header() {
+        let mut header = [0u8; IMAGE_HEADER_SIZE];
+        assert_eq!(kernel_image_size(&header), None);
+        header[IMAGE_SIZE_OFFSET..IMAGE_SIZE_OFFSET + 8].copy_from_slice(&0x20000u64.to_le_bytes());
+        header[IMAGE_MAGIC_OFFSET..IMAGE_MAGIC_OFFSET + 4].copy_from_slice(b"ARM\x64");
+        assert_eq!(kernel_image_size(&header), Some(0x20000));
+        assert_eq!(kernel_image_size(&header[..32]), None);
+    }
+
+    #[test]
+    fn parse_descriptors() {
+        let mut b = Builder::default();
+        b.begin("")
+            .prop_cells("#address-cells", &[2])
+            .prop_cells("#size-cells", &[2])
+            .begin("planes")
+            .prop_cells("#address-cells", &[1])
+            .prop_cells("#size-cells", &[0])
+            .begin("plane@1")
+            .prop("compatible", b"svsm,aux-plane\0")
+            .prop_cells("reg", &[1])
+            .prop("image", b"/planes/Image\0")
+            .prop_cells("load-addr", &[0, 0x6000_0000])
+            .prop_cells("entry", &[0, 0x6000_1000])
+            .prop_cells("regs", &[0, 7, 0, 8])
+            .prop("trap-wfi", b"")
+            .end()
+            .begin("plane@2")
+            .prop("compatible", b"svsm,aux-plane\0")
+            .prop_cells("reg", &[2])
+            .prop_cells("load-addr", &[0, 0x7000_0000])
+            .prop_cells("dtb-addr", &[0, 0x6fe0_0000])
+            .prop_cells("gic-owner", &[0])
+            .end()
+            .end()
+            .end();
+        let blob = b.finish();
+        let fdt = Fdt::new(&blob).unwrap();
+        let dtb = PhysAddr::from(0x4000_0000u64);
+        let descs = parse_launch_descs(&fdt, dtb).unwrap().unwrap();
+
+        assert_eq!(descs.len(), 2);
+        let plane1 = &descs[0];
+        assert_eq!(plane1.index, 1);
+        assert_eq!(
+            plane1.kernel,
+            ImageSource::RamFs("/planes/Image".to_string())
+        );
+        assert_eq!(plane1.entry, 0x6000_1000);
+        assert_eq!(plane1.gprs[..3], [0x4000_0000, 7, 8]);
+        assert_eq!(plane1.pstate, PSTATE_EL1H_MASKED);
+        assert_eq!(
+            plane1.flags,
+            PLANE_ENTER_FLAG_TRAP_WFI | PLANE_ENTER_FLAG_GIC_OWNER
+        );
+
+        let plane2 = &descs[1];
+        assert_eq!(plane2.kernel, ImageSource::InPlace);
+        assert_eq!(plane2.entry, 0x7000_0000);
+        assert_eq!(plane2.fdt_addr(), 0x6fe0_0000);
+        assert_eq!(plane2.flags, 0);
+        assert_eq!(plane2.initrd, ImageSource::InPlace);
+    }
+
+    #[test]
+    fn missing_load_addr() {
+        let mut b = Builder::default();
+        b.begin("")
+            .begin("plane@1")
+            .prop("compatible", b"svsm,aux-plane\0")
+            .prop_cells("reg", &[0, 1, 0, 0])
+            .end()
+            .end();
+        let blob = b.finish();
+        let fdt = Fdt::new(&blob).unwrap();
+        assert!(parse_launch_descs(&fdt, PhysAddr::null()).is_err());
+
+        let blob = Builder::default().begin("").end().finish();
+        let fdt = Fdt::new(&blob).unwrap();
+        assert_eq!(parse_launch_descs(&fdt, PhysAddr::null()).unwrap(), None);
+    }
+
+    #[test]
+    fn default_desc_dtb_copy() {
+        let boot_dtb = MemoryRegion::new(PhysAddr::from(0x4800_0000u64), 0x2000);
+        let desc = PlaneLaunchDesc::new(1, DEFAULT_PLANE_ENTRY, u64::from(boot_dtb.start()));
+        assert_eq!(desc.dtb_addr, None);
+
+        // The BSS of the kernel extends beyond the loaded image.
+        let mut header = [0u8; IMAGE_HEADER_SIZE];
+        header[IMAGE_SIZE_OFFSET..IMAGE_SIZE_OFFSET + 8]
+            .copy_from_slice(&0x1f0_0000u64.to_le_bytes());
+        header[IMAGE_MAGIC_OFFSET..IMAGE_MAGIC_OFFSET + 4].copy_from_slice(b"ARM\x64");
+        let image = MemoryRegion::new(desc.load_addr, 0x80_0000);
+        let kernel = kernel_footprint(image, &header);
+        assert_eq!(kernel.len(), 0x1f0_0000);
+
+        let dtb = dtb_region(&desc, kernel, boot_dtb.len());
+        assert_eq!(u64::from(dtb.start()), DEFAULT_PLANE_ENTRY + 0x200_0000);
+        assert!(!dtb.overlap(&kernel));
+        // The copy is accessible to the plane, unlike the boot DTB.
+        let ram = [region(RAM_START, RAM_SIZE)];
+        let reserved = [region(KERNEL_START, KERNEL_SIZE), boot_dtb];
+        assert!(check_region(1, dtb, &ram, &reserved).is_ok());
+        assert!(check_region(1, boot_dtb, &ram, &reserved).is_err());
+
+        let mut desc = desc;
+        desc.dtb_addr = Some(PhysAddr::from(0x5fe0_0000u64));
+        assert_eq!(
+            dtb_region(&desc, kernel, boot_dtb.len()).start(),
+            PhysAddr::from(0x5fe0_0000u64)
+        );
+    }
+
+    fn region(start: u64, len: usize) -> MemoryRegion<PhysAddr> {
+        MemoryRegion::new(PhysAddr::from(start), len)
+    }
+
+    const RAM_START: u64 = 0x4000_0000;
+    const RAM_SIZE: usize = 0x4000_0000;
+    const KERNEL_START: u64 = 0x4008_0000;
+    const KERNEL_SIZE: usize = 0x40_0000;
+    const HEAP_START: u64 = 0x4048_0000;
+    const HEAP_SIZE: usize = 0x4_0000;
+
+    fn check(start: u64, len: usize) -> Result<(), PlaneError> {
+        let ram = [region(RAM_START, RAM_SIZE)];
+        let early_heap = MemoryRegion::from_addresses(
+            PhysAddr::from(u64::from(STAGE2_HEAP_START)),
+            PhysAddr::from(u64::from(STAGE2_HEAP_END)),
+        );
+        let reserved = [
+            region(KERNEL_START, KERNEL_SIZE),
+            region(HEAP_START, HEAP_SIZE),
+            early_heap,
+        ];
+        check_region(1, region(start, len), &ram, &reserved)
+    }
+
+    #[test]
+    fn load_region_in_ram() {
+        assert!(check(0x6000_0000, 0x10000).is_ok());
+        assert!(check(HEAP_START + HEAP_SIZE as u64, 0x1000).is_ok());
+        assert!(check(RAM_START + RAM_SIZE as u64 - 0x1000, 0x2000).is_err());
+        assert!(check(0x1000_0000, 0x1000).is_err());
+    }
+
+    #[test]
+    fn load_region_overlaps_kernel() {
+        assert!(check(KERNEL_START - 0x1000, 0x2000).is_err());
+        assert!(check(KERNEL_START + 0x1000, 0x1000).is_err());
+        assert!(check(KERNEL_START - 0x1000, 0x1000).is_ok());
+    }
+
+    #[test]
+    fn load_region_overlaps_heap() {
+        assert!(check(HEAP_START + HEAP_SIZE as u64 - 0x1000, 0x2000).is_err());
+        assert!(check(HEAP_START + 0x1000, 0x1000).is_err());
+    }
+
+    #[test]
+    fn load_region_overlaps_early_heap() {
+        let start = u64::from(STAGE2_HEAP_START);
+        let end = u64::from(STAGE2_HEAP_END);
+        assert!(check(start - 0x1000, 0x2000).is_err());
+        assert!(check(end - 0x1000, 0x1000).is_err());
+        assert!(check(start - 0x1000, 0x1000).is_ok());
+    }
+}
@@ -0,0 +1,182 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Emulation of aux-plane accesses to device MMIO.
+//!
+//! An aux plane accessing an unprotected IPA which is not mapped for it
+//! exits to plane 0 with a data abort. When ESR.ISV is set, the syndrome
+//! describes the access and it is replayed from there. Otherwise the
+//! faulting instruction is fetched from the plane and decoded with
+//! [`A64LoadStore::decode()`]. Either way the access is forwarded to the
+//! host through the platform MMIO accessors. Accesses to the devices
+//! reserved for the SVSM are refused.
+
+use super::exit::{PlaneExitReason, SysReg};
+use super::walk::plane_va_to_ipa;
+use super::{AuxPlaneContext, PlaneError};
+use crate::address::{Address, PhysAddr, VirtAddr};
+use crate::error::SvsmError;
+use crate::fdt::DEVICE_TREE;
+use crate::insn_decode::{A64LoadStore, A64MachineCtx, InsnError, A64_INSN_SIZE};
+use crate::mm::guestmem::read_u32;
+use crate::platform::SVSM_PLATFORM;
+use crate::realm::mem::protected_alias;
+use crate::realm::perm::is_plane_memory;
+use crate::realm::rsi::rsi_cmd::{rsi_plane_sysreg_read, rsi_plane_sysreg_write};
+use crate::types::{Bytes, PAGE_SIZE};
+use crate::utils::MemoryRegion;
+use core::mem::MaybeUninit;
+
+const SYS_SP_EL0: SysReg = SysReg::new(3, 0, 4, 1, 0);
+const SYS_SP_EL1: SysReg = SysReg::new(3, 4, 4, 1, 0);
+
+// PSTATE fields
+const PSTATE_SP: u64 = 1 << 0;
+const PSTATE_EL_MASK: u64 = 0b1100;
+const PSTATE_EL1: u64 = 0b0100;
+
+/// The aux-plane state an MMIO access is emulated against.
+#[derive(Debug)]
+struct PlaneMmioCtx<'a> {
+    plane: &'a mut AuxPlaneContext,
+    fault_va: u64,
+    fault_ipa: PhysAddr,
+}
+
+impl PlaneMmioCtx<'_> {
+    /// Returns the stack pointer register selected by the PSTATE of the
+    /// plane.
+    fn sp_reg(&self) -> SysReg {
+        let pstate = self.plane.pstate;
+        if pstate & PSTATE_SP != 0 && pstate & PSTATE_EL_MASK == PSTATE_EL1 {
+            SYS_SP_EL1
+        } else {
+            SYS_SP_EL0
+        }
+    }
+}
+
+impl A64MachineCtx for PlaneMmioCtx<'_> {
+    fn read_gpr(&self, reg: u8) -> u64 {
+        self.plane.gprs[reg as usize]
+    }
+
+    fn write_gpr(&mut self, reg: u8, val: u64) {
+        self.plane.gprs[reg as usize] = val;
+    }
+
+    fn read_sp(&self) -> Result<u64, InsnError> {
+        rsi_plane_sysreg_read(self.plane.index, self.sp_reg().rsi_encoding())
+            .map_err(|_| InsnError::InvalidRegister)
+    }
+
+    fn write_sp(&mut self, val: u64) -> Result<(), InsnError> {
+        rsi_plane_sysreg_write(self.plane.index, self.sp_reg().rsi_encoding(), val)
+            .map_err(|_| InsnError::InvalidRegister)
+    }
+
+    /// Only the page of the faulting access is known to be device MMIO, so
+    /// accesses are restricted to it.
+    fn translate_addr(&self, va: u64, _write: bool) -> Result<u64, InsnError> {
+        let page_mask = !(PAGE_SIZE as u64 - 1);
+        if va & page_mask != self.fault_va & page_mask {
+            return Err(InsnError::TranslateLinearAddr);
+        }
+        Ok((u64::from(self.fault_ipa) & page_mask) | (va & !page_mask))
+    }
+
+    unsafe fn handle_mmio_read(&self, ipa: u64, size: Bytes) -> Result<u64, InsnError> {
+        let mut data = [MaybeUninit::<u8>::uninit(); 8];
+        let data = &mut data[..size as usize];
+        // SAFETY: the address lies on the faulting page, which the plane
+        // accessed as unprotected device memory.
+        unsafe { SVSM_PLATFORM.mmio_read(PhysAddr::from(ipa), data) }
+            .map_err(|_| InsnError::HandleMmioRead)?;
+        let mut bytes = [0u8; 8];
+        for (dst, src) in bytes.iter_mut().zip(data.iter()) {
+            // SAFETY: `mmio_read()` initialized all of `data`.
+            *dst = unsafe { src.assume_init() };
+        }
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    unsafe fn handle_mmio_write(
+        &mut self,
+        ipa: u64,
+        size: Bytes,
+        data: u64,
+    ) -> Result<(), InsnError> {
+        let bytes = data.to_le_bytes();
+        // SAFETY: see `handle_mmio_read()`.
+        unsafe { SVSM_PLATFORM.mmio_write(PhysAddr::from(ipa), &bytes[..size as usize]) }
+            .map_err(|_| InsnError::HandleMmioWrite)
+    }
+}
+
+/// Checks whether the page of `ipa` holds registers of a device reserved for
+/// the SVSM.
+fn is_svsm_device(ipa: PhysAddr) -> bool {
+    let page = MemoryRegion::new(protected_alias(ipa).page_align(), PAGE_SIZE);
+    DEVICE_TREE
+        .svsm_virtio_mmio
+        .iter()
+        .any(|region| region.overlap(&page))
+}
+
+/// Fetches the instruction at the program counter of `plane`.
+fn fetch_insn(plane: &AuxPlaneContext) -> Result<u32, SvsmError> {
+    if plane.pc % A64_INSN_SIZE as u64 != 0 {
+        return Err(InsnError::InsnPeek.into());
+    }
+    let ipa = plane_va_to_ipa(plane, plane.pc).map_err(|_| InsnError::InsnPeek)?;
+    if !is_plane_memory(MemoryRegion::new(ipa, A64_INSN_SIZE)) {
+        return Err(InsnError::InsnPeek.into());
+    }
+    // The SVSM runs identity mapped in the realm.
+    let vaddr = VirtAddr::from(u64::from(ipa));
+    // SAFETY: the instruction lies in aux-plane memory, which holds no SVSM
+    // data, and faults are caught.
+    unsafe { read_u32(vaddr) }.map_err(|_| InsnError::InsnPeek.into())
+}
+
+/// Emulates the MMIO access which caused the data abort `exit`, and moves
+/// the plane past the faulting instruction.
+pub fn emulate_mmio(plane: &mut AuxPlaneContext, exit: &PlaneExitReason) -> Result<(), SvsmError> {
+    let PlaneExitReason::DataAbort {
+        ipa,
+        va,
+        write,
+        size,
+        reg,
+        sign_extend,
+        sixty_four,
+    } = *exit
+    else {
+        return Err(PlaneError::ExitNotEmulated(*exit).into());
+    };
+    if is_svsm_device(ipa) {
+        return Err(PlaneError::ExitNotEmulated(*exit).into());
+    }
+
+    let mut ctx = PlaneMmioCtx {
+        plane,
+        fault_va: va,
+        fault_ipa: ipa,
+    };
+    match (size, reg) {
+        (Some(size), Some(reg)) => {
+            let access =
+                A64LoadStore::from_syndrome(write, size, reg as u8, sign_extend, sixty_four)?;
+            access.emulate_at(&mut ctx, va)?;
+        }
+        _ => {
+            let insn = fetch_insn(ctx.plane)?;
+            let access = A64LoadStore::decode(insn)?;
+            if access.load == write {
+                return Err(PlaneError::ExitNotEmulated(*exit).into());
+            }
+            access.emulate(&mut ctx)?;
+        }
+    }
+    ctx.plane.pc += A64_INSN_SIZE as u64;
+    Ok(())
+}
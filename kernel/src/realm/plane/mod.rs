@@ -0,0 +1,458 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Aux-plane scheduling for Arm CCA.
+//!
+//! The SVSM runs in plane 0 of the realm and multiplexes the aux planes
+//! (plane 1..=N) through `SMC_RSI_PLANE_ENTER`. Each aux plane has an
+//! [`AuxPlaneContext`] holding the register, GIC and timer state that is
+//! copied into the shared [`PlaneRun`] page before entering the plane and
+//! copied back out after it exits.
+//!
+//! Every PE of the realm runs its own [`PlaneScheduler`], with its own
+//! [`PlaneRun`] page, for the aux-plane vCPUs bound to that PE. The boot PE
+//! starts with vCPU 0 of every aux plane, while the vCPUs of the secondary
+//! PEs are turned on with PSCI CPU_ON.
+
+pub mod exit;
+mod handlers;
+pub mod launch;
+mod mmio;
+pub mod psci;
+pub mod svsm_call;
+pub mod sysreg;
+pub mod timer;
+pub mod vgic;
+mod walk;
+
+use crate::error::SvsmError;
+use crate::fdt::DEVICE_TREE;
+use crate::mm::PageBox;
+use crate::realm::rsi::rsi_cmd::{rsi_plane_enter, REALM_CONFIG};
+use crate::realm::rsi::{PlaneRun, PLANE_RUN_GPRS};
+use crate::svsm_arm64::cpu::gicv3::{current_mpidr, gicv3_handle_irq};
+use crate::svsm_arm64::cpu::percpu::{online_cpus, this_cpu};
+use crate::svsm_arm64::cpu::smp::run_on_cpu;
+use crate::svsm_arm64::cpu::timer::{timer_arm, timer_disarm, timer_init, wait_for_interrupt};
+use exit::{ExitDispatcher, PlaneExitReason, SysReg};
+use launch::{launch_descs, PlaneLaunchDesc, PlaneLoader, DEFAULT_PLANE_ENTRY};
+use psci::{any_vcpu_on, psci_init, take_pending_vcpu, vcpu_is_off, vcpu_off};
+use sysreg::ShadowRegs;
+use timer::{Counters, TimerState};
+use vgic::{num_lrs_from_vtr, VGic, Virq};
+
+/// Maximum number of aux planes supported by the RMM.
+pub const PLANE_MAX_AUX_PLANES: usize = 3;
+
+// AArch64 SPSR bits
+const PSR_MODE_EL1H: u64 = 0x0000_0005;
+const PSR_F_BIT: u64 = 0x0000_0040;
+const PSR_I_BIT: u64 = 0x0000_0080;
+const PSR_A_BIT: u64 = 0x0000_0100;
+const PSR_D_BIT: u64 = 0x0000_0200;
+
+/// PSTATE of a plane entering its kernel: EL1h with all exceptions masked.
+const PSTATE_EL1H_MASKED: u64 = PSR_MODE_EL1H | PSR_I_BIT | PSR_F_BIT | PSR_A_BIT | PSR_D_BIT;
+
+/// Errors related to running aux planes.
+#[derive(Clone, Copy, Debug)]
+pub enum PlaneError {
+    /// No handler is registered for this aux-plane exit.
+    UnhandledExit(PlaneExitReason),
+    /// A handler recognized the exit but could not emulate it.
+    ExitNotEmulated(PlaneExitReason),
+    /// The virtual interrupt queue of the plane is full.
+    VirqOverflow(u32),
+    /// The plane index does not refer to an aux plane of the realm.
+    InvalidPlane(usize),
+    /// A plane launch descriptor in the device tree is malformed.
+    InvalidLaunchDesc,
+    /// An image of the plane does not fit in realm RAM at its load address.
+    LoadRegion(usize),
+    /// The kernel of the plane does not start with an arm64 image header.
+    KernelImage(usize),
+    /// No shadow value is left for the given system register.
+    SysRegShadowOverflow(SysReg),
+}
+
+impl From<PlaneError> for SvsmError {
+    fn from(err: PlaneError) -> Self {
+        Self::Plane(err)
+    }
+}
+
+/// Lifecycle of an aux plane, as seen by the plane-0 scheduler.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PlaneState {
+    /// The context has not been initialized, or the vCPU is turned off.
+    #[default]
+    Idle,
+    /// The plane is runnable and waits to be entered.
+    Pending,
+    /// The plane is currently executing.
+    Active,
+    /// The plane executed WFI and waits for an interrupt.
+    Blocked,
+    /// The plane exited and its exit has not been handled yet.
+    Stopped,
+    /// The plane hit an exit that could not be handled and is not scheduled
+    /// anymore.
+    Abort,
+}
+
+/// Saved execution state of a single aux plane.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AuxPlaneContext {
+    pub state: PlaneState,
+    pub index: u64,
+
+    pub pc: u64,
+    pub gprs: [u64; PLANE_RUN_GPRS],
+    pub pstate: u64,
+    pub flags: u64,
+
+    pub gic: VGic,
+    pub timer: TimerState,
+    pub sysregs: ShadowRegs,
+}
+
+impl AuxPlaneContext {
+    /// Creates the context for plane `index`, which starts executing at
+    /// `entry` in EL1h with the DTB address in x0, as expected by the Linux
+    /// arm64 boot protocol. The plane may use `num_lrs` list registers.
+    pub fn new(index: u64, entry: u64, fdt_addr: u64, num_lrs: usize) -> Self {
+        let desc = PlaneLaunchDesc::new(index as usize, entry, fdt_addr);
+        Self::from_launch(&desc, num_lrs)
+    }
+
+    /// Creates the context for the plane described by `desc`. The plane may
+    /// use `num_lrs` list registers.
+    pub fn from_launch(desc: &PlaneLaunchDesc, num_lrs: usize) -> Self {
+        Self {
+            state: PlaneState::Pending,
+            index: desc.index as u64,
+            pc: desc.entry,
+            gprs: desc.gprs,
+            pstate: desc.pstate,
+            flags: desc.flags,
+            gic: VGic::new(num_lrs),
+            ..Default::default()
+        }
+    }
+
+    /// Restarts the plane at `entry` in EL1h with all exceptions masked and
+    /// `x0` as the only non-zero register, as on a PSCI warm boot. Shadowed
+    /// system registers return to their reset values.
+    pub fn warm_reset(&mut self, entry: u64, x0: u64) {
+        self.pc = entry;
+        self.gprs = [0; PLANE_RUN_GPRS];
+        self.gprs[0] = x0;
+        self.pstate = PSTATE_EL1H_MASKED;
+        self.sysregs.reset();
+    }
+
+    /// Copies this context into the enter half of `run`.
+    pub fn restore(&mut self, run: &mut PlaneRun) {
+        run.enter.pc = self.pc;
+        run.enter.flags = self.flags;
+        run.enter.spsr_el2 = self.pstate;
+        run.enter.gprs = self.gprs;
+
+        self.gic.flush(&mut run.enter);
+
+        self.state = PlaneState::Active;
+    }
+
+    /// Updates this context from the exit half of `run`.
+    pub fn save(&mut self, run: &PlaneRun) {
+        self.pc = run.exit.elr_el2;
+        self.pstate = run.exit.spsr_el2;
+        self.gprs = run.exit.gprs;
+
+        self.gic.sync(&run.exit);
+
+        self.timer.save(&run.exit);
+
+        self.state = PlaneState::Stopped;
+    }
+
+    /// Makes the PPIs of the timers which fired by `now` pending, and wakes
+    /// the plane up if it waits for an interrupt.
+    fn poll_timers(&mut self, now: &Counters) {
+        if let Err(e) = self.timer.inject(&mut self.gic, now) {
+            log::warn!(
+                "Plane {}: failed to inject timer interrupt: {e:?}",
+                self.index
+            );
+        }
+        if self.state == PlaneState::Blocked && self.gic.has_pending() {
+            self.state = PlaneState::Pending;
+        }
+    }
+}
+
+/// Schedules the aux-plane vCPUs of the current PE in a round-robin fashion
+/// from plane 0.
+#[derive(Debug)]
+pub struct PlaneScheduler {
+    run: PageBox<PlaneRun>,
+    planes: [AuxPlaneContext; PLANE_MAX_AUX_PLANES + 1],
+    num_aux_planes: usize,
+    current: usize,
+    dispatcher: ExitDispatcher,
+}
+
+impl PlaneScheduler {
+    /// Creates a scheduler starting the aux planes described by `descs`.
+    /// Aux planes without a descriptor stay idle.
+    pub fn new(descs: &[PlaneLaunchDesc]) -> Result<Self, SvsmError> {
+        let num_aux_planes = (REALM_CONFIG.num_aux_planes as usize).min(PLANE_MAX_AUX_PLANES);
+        let num_lrs = num_lrs_from_vtr(REALM_CONFIG.gicv3_vtr);
+        let mut planes = [AuxPlaneContext::default(); PLANE_MAX_AUX_PLANES + 1];
+
+        for desc in descs {
+            if desc.index == 0 || desc.index > num_aux_planes {
+                return Err(PlaneError::InvalidPlane(desc.index).into());
+            }
+            planes[desc.index] = AuxPlaneContext::from_launch(desc, num_lrs);
+        }
+
+        Ok(Self {
+            run: PageBox::try_new_zeroed()?,
+            planes,
+            num_aux_planes,
+            current: 0,
+            dispatcher: handlers::default_dispatcher(),
+        })
+    }
+
+    /// Gives access to the exit dispatcher, e.g. to register additional
+    /// exit handlers.
+    pub fn dispatcher_mut(&mut self) -> &mut ExitDispatcher {
+        &mut self.dispatcher
+    }
+
+    /// Returns the number of aux planes managed by this scheduler.
+    pub fn num_aux_planes(&self) -> usize {
+        self.num_aux_planes
+    }
+
+    /// Makes `virq` pending for aux plane `index`. It is delivered the next
+    /// time the plane is entered.
+    pub fn inject_irq(&mut self, index: usize, virq: Virq) -> Result<(), SvsmError> {
+        if index == 0 || index > self.num_aux_planes {
+            return Err(PlaneError::InvalidPlane(index).into());
+        }
+        let plane = &mut self.planes[index];
+        plane.gic.inject(virq)?;
+        if plane.state == PlaneState::Blocked {
+            plane.state = PlaneState::Pending;
+        }
+        Ok(())
+    }
+
+    /// Returns the next runnable plane after the current one, if any. Aux
+    /// plane vCPUs of this PE which were turned on with PSCI CPU_ON become
+    /// runnable here, and those turned off from another PE are dropped.
+    fn next_runnable(&mut self) -> Option<usize> {
+        let mpidr = current_mpidr();
+        let planes = self.planes.iter_mut().enumerate();
+        for (index, plane) in planes.skip(1).take(self.num_aux_planes) {
+            if plane.state == PlaneState::Idle {
+                if let Some(ctx) = take_pending_vcpu(mpidr, index) {
+                    *plane = ctx;
+                }
+            } else if vcpu_is_off(mpidr, index) {
+                *plane = AuxPlaneContext::default();
+            }
+        }
+
+        for _ in 0..self.num_aux_planes {
+            self.current = (self.current % self.num_aux_planes) + 1;
+            if self.planes[self.current].state == PlaneState::Pending {
+                return Some(self.current);
+            }
+        }
+        None
+    }
+
+    /// Enters the next runnable aux plane once and handles its exit.
+    ///
+    /// Returns `Ok(false)` when no aux plane is runnable anymore.
+    pub fn run_once(&mut self) -> Result<bool, SvsmError> {
+        let Some(index) = self.next_runnable() else {
+            return Ok(false);
+        };
+
+        let plane = &mut self.planes[index];
+        plane.restore(&mut self.run);
+        let entered = rsi_plane_enter(index as u64, &mut self.run);
+        plane.save(&self.run);
+        if let Err(e) = entered {
+            plane.state = PlaneState::Abort;
+            vcpu_off(index);
+            return Err(e.into());
+        }
+
+        let exit = PlaneExitReason::decode(&self.run.exit);
+        match self.dispatcher.dispatch(plane, &exit) {
+            Ok(()) => {
+                // Handlers may turn the plane off, e.g. for PSCI CPU_OFF.
+                if plane.state == PlaneState::Stopped {
+                    plane.state = PlaneState::Pending;
+                }
+                Ok(true)
+            }
+            Err(e) => {
+                plane.state = PlaneState::Abort;
+                vcpu_off(index);
+                Err(e)
+            }
+        }
+    }
+
+    /// Injects the timer interrupts which are due into the aux planes.
+    /// Returns the physical count at which the next timer of a plane waiting
+    /// for an interrupt fires.
+    fn poll_timers(&mut self) -> Option<u64> {
+        let now = Counters::read();
+        let planes = self.planes.iter_mut().skip(1).take(self.num_aux_planes);
+        planes
+            .filter(|plane| matches!(plane.state, PlaneState::Pending | PlaneState::Blocked))
+            .filter_map(|plane| {
+                plane.poll_timers(&now);
+                (plane.state == PlaneState::Blocked)
+                    .then(|| plane.timer.next_deadline(&now))
+                    .flatten()
+            })
+            .min()
+    }
+
+    fn has_blocked(&self) -> bool {
+        self.planes
+            .iter()
+            .any(|plane| plane.state == PlaneState::Blocked)
+    }
+
+    /// Runs the aux planes until no vCPU of the realm is on anymore. A plane
+    /// whose exit cannot be handled is stopped, while the remaining planes
+    /// keep running. When no plane of this PE is runnable, plane 0 sleeps
+    /// until the next timer deadline or any other interrupt, such as the
+    /// kick sent by PSCI CPU_ON. Plane 0 runs with interrupts masked, so
+    /// pending interrupts are handled explicitly after waking up.
+    pub fn run(&mut self) -> Result<(), SvsmError> {
+        loop {
+            let deadline = self.poll_timers();
+            match self.run_once() {
+                Ok(true) => {}
+                Ok(false) if self.has_blocked() || any_vcpu_on() => {
+                    if let Some(deadline) = deadline {
+                        timer_arm(deadline);
+                    }
+                    wait_for_interrupt();
+                    gicv3_handle_irq();
+                    timer_disarm();
+                }
+                Ok(false) => return Ok(()),
+                Err(e) => log::error!("Aborting plane {}: {e:?}", self.current),
+            }
+        }
+    }
+}
+
+/// Hands the CPU to the plane-N kernel at `entry`. Used when the realm has
+/// no aux planes, in which case the kernel runs in plane 0 itself.
+///
+/// # Safety
+///
+/// `entry` must point to a kernel image following the arm64 boot protocol.
+unsafe fn enter_kernel_directly(entry: u64, fdt_addr: u64) -> ! {
+    // SAFETY: the caller guarantees that `entry` is a valid kernel entry.
+    let entry: extern "C" fn(u64, u64, u64, u64) -> ! = unsafe { core::mem::transmute(entry) };
+    entry(fdt_addr, 0, 0, 0)
+}
+
+/// Schedules the aux-plane vCPUs of a secondary PE, which all start off.
+fn plane_secondary_main() {
+    let result = PlaneScheduler::new(&[]).and_then(|mut scheduler| scheduler.run());
+    if let Err(e) = result {
+        log::error!(
+            "Aux plane scheduler of CPU {} terminated: {e:?}",
+            this_cpu().cpu_index()
+        );
+    }
+}
+
+/// Loads and measures the aux planes described by the launch descriptors,
+/// and schedules them on all online PEs until no vCPU is on anymore.
+pub fn plane_main() -> Result<(), SvsmError> {
+    let num_aux_planes = (REALM_CONFIG.num_aux_planes as usize).min(PLANE_MAX_AUX_PLANES);
+    if num_aux_planes == 0 {
+        log::info!("No aux plane, entering kernel directly");
+        let fdt_addr = u64::from(DEVICE_TREE.dtb.start());
+        let mut desc = PlaneLaunchDesc::new(0, DEFAULT_PLANE_ENTRY, fdt_addr);
+        PlaneLoader::new().load(&mut desc)?;
+        // SAFETY: the kernel image was placed at `entry` by the loader.
+        unsafe { enter_kernel_directly(desc.entry, desc.fdt_addr()) };
+    }
+
+    let mut descs = launch_descs(num_aux_planes)?;
+    let mut loader = PlaneLoader::new();
+    for desc in &mut descs {
+        loader.load(desc)?;
+    }
+
+    let mut scheduler = PlaneScheduler::new(&descs)?;
+    psci_init(&DEVICE_TREE.cpus, scheduler.num_aux_planes());
+    timer_init()?;
+
+    let cpus = online_cpus();
+    for cpu in cpus.iter().filter(|cpu| cpu.mpidr() != current_mpidr()) {
+        run_on_cpu(cpu, plane_secondary_main)?;
+    }
+    log::info!(
+        "Initialized {} aux plane(s) on {} CPU(s)",
+        scheduler.num_aux_planes(),
+        cpus.len()
+    );
+    scheduler.run()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inject_vtimer_once() {
+        let mut run = PlaneRun::new_zeroed();
+        let mut ctx = AuxPlaneContext::new(1, 0x6000_0000, 0x4000_0000, 4);
+        ctx.timer.cntv_ctl = 1;
+        let now = Counters::default();
+        ctx.poll_timers(&now);
+        ctx.poll_timers(&now);
+        ctx.restore(&mut run);
+        let count = run
+            .enter
+            .gicv3_lrs
+            .iter()
+            .filter(|lr| (**lr & 0xffff_ffff) == 27)
+            .count();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn context_roundtrip() {
+        let mut run = PlaneRun::new_zeroed();
+        let mut ctx = AuxPlaneContext::new(1, 0x6000_0000, 0x4000_0000, 4);
+        ctx.restore(&mut run);
+        assert_eq!(ctx.state, PlaneState::Active);
+        assert_eq!(run.enter.pc, 0x6000_0000);
+        assert_eq!(run.enter.gprs[0], 0x4000_0000);
+
+        run.exit.elr_el2 = 0x6000_0004;
+        run.exit.gprs[1] = 42;
+        ctx.save(&run);
+        assert_eq!(ctx.state, PlaneState::Stopped);
+        assert_eq!(ctx.pc, 0x6000_0004);
+        assert_eq!(ctx.gprs[1], 42);
+    }
+}
@@ -0,0 +1,385 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! PSCI 1.1 server for aux planes.
+//!
+//! Each aux plane sees one vCPU per PE of the realm. The SVSM tracks the
+//! power state of every such vCPU in a global table. CPU_ON creates the
+//! context the vCPU starts with, which the scheduler on the target PE picks
+//! up through [`take_pending_vcpu`], and CPU_OFF drops it again, much like
+//! `core_create_vcpu` and `core_delete_vcpu` do for SNP guests.
+//! SYSTEM_OFF and SYSTEM_RESET affect the whole realm and are forwarded to
+//! the host.
+//!
+//! The vCPUs of a PE only run on that PE. Turning a vCPU on or off kicks the
+//! PEs whose schedulers need to notice it with an SGI.
+
+extern crate alloc;
+
+use super::exit::SMCCC_NUM_ARGS;
+use super::{AuxPlaneContext, PlaneState, PLANE_MAX_AUX_PLANES};
+use crate::locking::SpinLock;
+use crate::realm::rsi::rsi_cmd::rsi_host_call;
+use crate::realm::rsi::RsiHostCall;
+use crate::svsm_arm64::cpu::gicv3::{current_mpidr, MPIDR_AFF_MASK};
+use crate::svsm_arm64::cpu::smp::{kick_all, kick_cpu};
+use alloc::vec::Vec;
+use zerocopy::FromZeros;
+
+// PSCI function IDs. Functions taking addresses or MPIDRs are listed with
+// their SMC64 ID and also accepted with their SMC32 one.
+pub const PSCI_VERSION: u64 = 0x8400_0000;
+pub const PSCI_CPU_SUSPEND: u64 = 0xc400_0001;
+pub const PSCI_CPU_OFF: u64 = 0x8400_0002;
+pub const PSCI_CPU_ON: u64 = 0xc400_0003;
+pub const PSCI_AFFINITY_INFO: u64 = 0xc400_0004;
+pub const PSCI_MIGRATE_INFO_TYPE: u64 = 0x8400_0006;
+pub const PSCI_SYSTEM_OFF: u64 = 0x8400_0008;
+pub const PSCI_SYSTEM_RESET: u64 = 0x8400_0009;
+pub const PSCI_FEATURES: u64 = 0x8400_000a;
+
+const PSCI_FID_SMC64: u64 = 1 << 30;
+const PSCI_FN_MASK: u64 = 0x1f;
+
+const fn psci_fn(fid: u64) -> u64 {
+    fid & PSCI_FN_MASK
+}
+
+const PSCI_VERSION_1_1: u64 = (1 << 16) | 1;
+
+// PSCI return codes
+const PSCI_RET_SUCCESS: u64 = 0;
+const PSCI_RET_NOT_SUPPORTED: u64 = -1i64 as u64;
+const PSCI_RET_INVALID_PARAMETERS: u64 = -2i64 as u64;
+const PSCI_RET_ALREADY_ON: u64 = -4i64 as u64;
+const PSCI_RET_ON_PENDING: u64 = -5i64 as u64;
+const PSCI_RET_INTERNAL_FAILURE: u64 = -6i64 as u64;
+
+/// MIGRATE_INFO_TYPE: no Trusted OS, migration is not required.
+const PSCI_TOS_NOT_PRESENT_MP: u64 = 2;
+
+/// CPU_SUSPEND power_state bit selecting a powerdown state (original
+/// format).
+const PSCI_POWER_STATE_TYPE_POWERDOWN: u64 = 1 << 16;
+
+/// SMCCC_VERSION, which PSCI_FEATURES must report as implemented.
+const SMCCC_VERSION: u64 = 0x8000_0000;
+
+/// Host call immediate used to forward PSCI system calls to the host.
+const PSCI_HOST_CALL_IMM: u16 = 0;
+
+/// Power state of an aux-plane vCPU, with the values AFFINITY_INFO returns.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VcpuPower {
+    On = 0,
+    #[default]
+    Off = 1,
+    OnPending = 2,
+}
+
+/// The aux-plane vCPUs of one PE.
+#[derive(Debug)]
+struct PsciVcpu {
+    mpidr: u64,
+    power: [VcpuPower; PLANE_MAX_AUX_PLANES + 1],
+    /// Contexts created by CPU_ON which the PE has not picked up yet.
+    boot: [Option<AuxPlaneContext>; PLANE_MAX_AUX_PLANES + 1],
+}
+
+/// Power state of all aux-plane vCPUs, with one entry per PE.
+#[derive(Debug, Default)]
+struct PsciState {
+    vcpus: Vec<PsciVcpu>,
+}
+
+impl PsciState {
+    fn new(mpidrs: &[u64], boot_mpidr: u64, num_aux_planes: usize) -> Self {
+        let vcpus = mpidrs
+            .iter()
+            .map(|&mpidr| {
+                let mut power = [VcpuPower::Off; PLANE_MAX_AUX_PLANES + 1];
+                if mpidr == boot_mpidr {
+                    power[1..=num_aux_planes].fill(VcpuPower::On);
+                }
+                PsciVcpu {
+                    mpidr: mpidr & MPIDR_AFF_MASK,
+                    power,
+                    boot: [None; PLANE_MAX_AUX_PLANES + 1],
+                }
+            })
+            .collect();
+        Self { vcpus }
+    }
+
+    fn vcpu_mut(&mut self, mpidr: u64) -> Option<&mut PsciVcpu> {
+        self.vcpus
+            .iter_mut()
+            .find(|vcpu| vcpu.mpidr == mpidr & MPIDR_AFF_MASK)
+    }
+
+    fn cpu_on(
+        &mut self,
+        caller: &AuxPlaneContext,
+        target: u64,
+        entry: u64,
+        context_id: u64,
+    ) -> u64 {
+        let plane = caller.index as usize;
+        let Some(vcpu) = self.vcpu_mut(target) else {
+            return PSCI_RET_INVALID_PARAMETERS;
+        };
+        match vcpu.power[plane] {
+            VcpuPower::On => PSCI_RET_ALREADY_ON,
+            VcpuPower::OnPending => PSCI_RET_ON_PENDING,
+            VcpuPower::Off => {
+                let mut ctx =
+                    AuxPlaneContext::new(caller.index, entry, context_id, caller.gic.num_lrs());
+                ctx.flags = caller.flags;
+                vcpu.boot[plane] = Some(ctx);
+                vcpu.power[plane] = VcpuPower::OnPending;
+                PSCI_RET_SUCCESS
+            }
+        }
+    }
+
+    fn cpu_off(&mut self, plane: usize, mpidr: u64) {
+        if let Some(vcpu) = self.vcpu_mut(mpidr) {
+            vcpu.power[plane] = VcpuPower::Off;
+            vcpu.boot[plane] = None;
+        }
+    }
+
+    fn affinity_info(&mut self, plane: usize, target: u64) -> Option<VcpuPower> {
+        self.vcpu_mut(target).map(|vcpu| vcpu.power[plane])
+    }
+
+    fn any_on(&self) -> bool {
+        self.vcpus
+            .iter()
+            .any(|vcpu| vcpu.power.iter().any(|power| *power != VcpuPower::Off))
+    }
+
+    fn take_pending(&mut self, mpidr: u64, plane: usize) -> Option<AuxPlaneContext> {
+        let vcpu = self.vcpu_mut(mpidr)?;
+        let ctx = vcpu.boot[plane].take()?;
+        vcpu.power[plane] = VcpuPower::On;
+        Some(ctx)
+    }
+
+    fn system_off(&mut self, plane: usize) {
+        for vcpu in self.vcpus.iter_mut() {
+            vcpu.power[plane] = VcpuPower::Off;
+            vcpu.boot[plane] = None;
+        }
+    }
+}
+
+static PSCI_STATE: SpinLock<PsciState> = SpinLock::new(PsciState { vcpus: Vec::new() });
+
+/// Sets up the vCPU table for the PEs in `mpidrs`. The vCPUs of all aux
+/// planes are on for the current PE, which boots them, and off otherwise.
+pub fn psci_init(mpidrs: &[u64], num_aux_planes: usize) {
+    *PSCI_STATE.lock() = PsciState::new(mpidrs, current_mpidr(), num_aux_planes);
+}
+
+/// Returns the context of the vCPU of aux plane `plane` on the PE `mpidr`
+/// if another vCPU turned it on with CPU_ON, and marks the vCPU as on.
+pub fn take_pending_vcpu(mpidr: u64, plane: usize) -> Option<AuxPlaneContext> {
+    PSCI_STATE.lock().take_pending(mpidr, plane)
+}
+
+/// Returns whether the vCPU of aux plane `plane` on the PE `mpidr` was
+/// turned off, e.g. by SYSTEM_OFF on another PE.
+pub fn vcpu_is_off(mpidr: u64, plane: usize) -> bool {
+    PSCI_STATE.lock().affinity_info(plane, mpidr) == Some(VcpuPower::Off)
+}
+
+/// Returns whether any aux-plane vCPU of the realm is on or about to be
+/// turned on.
+pub fn any_vcpu_on() -> bool {
+    PSCI_STATE.lock().any_on()
+}
+
+/// Turns the vCPU of aux plane `plane` on the current PE off, and wakes up
+/// the other PEs, which may have been waiting for it.
+pub fn vcpu_off(plane: usize) {
+    PSCI_STATE.lock().cpu_off(plane, current_mpidr());
+    kick_others();
+}
+
+fn kick_others() {
+    if let Err(e) = kick_all() {
+        log::warn!("Failed to kick the other CPUs: {e:?}");
+    }
+}
+
+/// Returns whether `fid` is in the PSCI function ID range.
+pub fn is_psci_call(fid: u64) -> bool {
+    (fid & !(PSCI_FID_SMC64 | PSCI_FN_MASK)) == PSCI_VERSION
+}
+
+/// Forwards the system-wide PSCI call `fid` to the host.
+fn forward_to_host(fid: u64) -> u64 {
+    let mut call = RsiHostCall::new_zeroed();
+    call.imm = PSCI_HOST_CALL_IMM;
+    call.gprs[0] = fid;
+    match rsi_host_call(&mut call) {
+        Ok(()) => call.gprs[0],
+        Err(e) => {
+            log::error!("Failed to forward PSCI call {fid:#x} to the host: {e:?}");
+            PSCI_RET_INTERNAL_FAILURE
+        }
+    }
+}
+
+fn psci_features(fid: u64) -> u64 {
+    // All functions have feature flags of 0, which for CPU_SUSPEND means the
+    // original power_state format without OS-initiated mode.
+    const SUPPORTED: [u64; 9] = [
+        PSCI_VERSION,
+        PSCI_CPU_SUSPEND,
+        PSCI_CPU_OFF,
+        PSCI_CPU_ON,
+        PSCI_AFFINITY_INFO,
+        PSCI_MIGRATE_INFO_TYPE,
+        PSCI_SYSTEM_OFF,
+        PSCI_SYSTEM_RESET,
+        PSCI_FEATURES,
+    ];
+    let supported = fid == SMCCC_VERSION
+        || (is_psci_call(fid) && SUPPORTED.iter().any(|f| psci_fn(*f) == psci_fn(fid)));
+    if supported {
+        PSCI_RET_SUCCESS
+    } else {
+        PSCI_RET_NOT_SUPPORTED
+    }
+}
+
+/// Handles PSCI call `fid` of `plane`, which trapped with an SMC. Updates
+/// the plane context to reflect the outcome of the call.
+pub fn handle_psci(plane: &mut AuxPlaneContext, fid: u64, args: &[u64; SMCCC_NUM_ARGS]) {
+    const VERSION: u64 = psci_fn(PSCI_VERSION);
+    const CPU_SUSPEND: u64 = psci_fn(PSCI_CPU_SUSPEND);
+    const CPU_OFF: u64 = psci_fn(PSCI_CPU_OFF);
+    const CPU_ON: u64 = psci_fn(PSCI_CPU_ON);
+    const AFFINITY_INFO: u64 = psci_fn(PSCI_AFFINITY_INFO);
+    const MIGRATE_INFO_TYPE: u64 = psci_fn(PSCI_MIGRATE_INFO_TYPE);
+    const SYSTEM_OFF: u64 = psci_fn(PSCI_SYSTEM_OFF);
+    const SYSTEM_RESET: u64 = psci_fn(PSCI_SYSTEM_RESET);
+    const FEATURES: u64 = psci_fn(PSCI_FEATURES);
+
+    let index = plane.index as usize;
+    let ret = match psci_fn(fid) {
+        VERSION => PSCI_VERSION_1_1,
+        CPU_SUSPEND => {
+            if (args[0] & PSCI_POWER_STATE_TYPE_POWERDOWN) != 0 {
+                // Wake up right away from the powerdown state, at the entry
+                // point with the context ID in x0.
+                plane.warm_reset(args[1], args[2]);
+                return;
+            }
+            PSCI_RET_SUCCESS
+        }
+        CPU_OFF => {
+            vcpu_off(index);
+            plane.state = PlaneState::Idle;
+            return;
+        }
+        CPU_ON => {
+            let ret = PSCI_STATE.lock().cpu_on(plane, args[0], args[1], args[2]);
+            if ret == PSCI_RET_SUCCESS {
+                if let Err(e) = kick_cpu(args[0]) {
+                    log::warn!("Failed to kick CPU {:#x}: {e:?}", args[0]);
+                }
+            }
+            ret
+        }
+        AFFINITY_INFO => {
+            if args[1] != 0 {
+                PSCI_RET_INVALID_PARAMETERS
+            } else {
+                PSCI_STATE
+                    .lock()
+                    .affinity_info(index, args[0])
+                    .map_or(PSCI_RET_INVALID_PARAMETERS, |power| power as u64)
+            }
+        }
+        MIGRATE_INFO_TYPE => PSCI_TOS_NOT_PRESENT_MP,
+        SYSTEM_OFF | SYSTEM_RESET => {
+            // These calls do not return when the host completes them.
+            let ret = forward_to_host(fid);
+            log::error!("Host returned {ret:#x} from PSCI call {fid:#x}, stopping plane {index}");
+            PSCI_STATE.lock().system_off(index);
+            kick_others();
+            plane.state = PlaneState::Idle;
+            return;
+        }
+        FEATURES => psci_features(args[0]),
+        _ => PSCI_RET_NOT_SUPPORTED,
+    };
+
+    plane.pc += 4;
+    plane.gprs[0] = ret;
+    plane.gprs[1..4].fill(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> PsciState {
+        PsciState::new(&[0x0, 0x1, 0x100], 0x0, 1)
+    }
+
+    #[test]
+    fn fid_range() {
+        assert!(is_psci_call(PSCI_VERSION));
+        assert!(is_psci_call(PSCI_CPU_ON));
+        assert!(is_psci_call(0x8400_0003));
+        assert!(!is_psci_call(0x8000_0000));
+        assert!(!is_psci_call(0xc600_5300));
+    }
+
+    #[test]
+    fn cpu_on_lifecycle() {
+        let mut psci = state();
+        let caller = AuxPlaneContext::new(1, 0x6000_0000, 0, 4);
+
+        assert_eq!(psci.affinity_info(1, 0x0), Some(VcpuPower::On));
+        assert_eq!(psci.affinity_info(1, 0x1), Some(VcpuPower::Off));
+        assert_eq!(
+            psci.cpu_on(&caller, 0x0, 0x6000_1000, 0),
+            PSCI_RET_ALREADY_ON
+        );
+        assert_eq!(
+            psci.cpu_on(&caller, 0x2, 0x6000_1000, 0),
+            PSCI_RET_INVALID_PARAMETERS
+        );
+
+        assert_eq!(psci.cpu_on(&caller, 0x1, 0x6000_1000, 42), PSCI_RET_SUCCESS);
+        assert_eq!(psci.affinity_info(1, 0x1), Some(VcpuPower::OnPending));
+        assert_eq!(
+            psci.cpu_on(&caller, 0x1, 0x6000_1000, 42),
+            PSCI_RET_ON_PENDING
+        );
+
+        let ctx = psci.take_pending(0x1, 1).unwrap();
+        assert_eq!(ctx.pc, 0x6000_1000);
+        assert_eq!(ctx.gprs[0], 42);
+        assert_eq!(ctx.state, PlaneState::Pending);
+        assert_eq!(psci.affinity_info(1, 0x1), Some(VcpuPower::On));
+        assert!(psci.take_pending(0x1, 1).is_none());
+
+        psci.cpu_off(1, 0x1);
+        assert_eq!(psci.affinity_info(1, 0x1), Some(VcpuPower::Off));
+        assert!(psci.any_on());
+        psci.system_off(1);
+        assert!(!psci.any_on());
+    }
+
+    #[test]
+    fn features() {
+        assert_eq!(psci_features(PSCI_CPU_ON), PSCI_RET_SUCCESS);
+        assert_eq!(psci_features(SMCCC_VERSION), PSCI_RET_SUCCESS);
+        assert_eq!(psci_features(0x8400_0012), PSCI_RET_NOT_SUPPORTED);
+    }
+}
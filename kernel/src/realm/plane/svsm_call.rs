@@ -0,0 +1,119 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! SVSM protocol calls from aux planes.
+//!
+//! An aux plane issues SVSM requests with an SMC or HVC in the SMCCC vendor
+//! specific hypervisor service range. The function ID selects the protocol,
+//! the remaining argument registers carry the request:
+//!
+//! | Register | On entry                         | On return          |
+//! |----------|----------------------------------|--------------------|
+//! | x0       | `SVSM_SMCCC_FID_BASE + protocol` | result code        |
+//! | x1       | request number                   | preserved          |
+//! | x2..x4   | parameters (RCX, RDX, R8 on x86) | updated parameters |
+//! | x5..x7   | reserved, must be zero           | preserved          |
+//!
+//! This makes the existing protocol handlers usable by aux planes without
+//! changes. Requests with a non-zero reserved register fail with
+//! `SVSM_ERR_INVALID_PARAMETER`, so that the registers can be given a
+//! meaning later.
+
+use super::AuxPlaneContext;
+use crate::protocols::{errors::SvsmResultCode, RequestParams};
+use crate::realm::rsi::arm_smccc::{
+    call_val, ARM_SMCCC_FAST_CALL, ARM_SMCCC_OWNER_VENDOR_HYP, ARM_SMCCC_SMC_64,
+};
+use crate::requests::process_request;
+use crate::vmm::GuestRegister;
+
+/// First function ID of the SVSM protocol range. The function number is the
+/// SVSM protocol number.
+pub const SVSM_SMCCC_FID_BASE: u64 = call_val(
+    ARM_SMCCC_FAST_CALL,
+    ARM_SMCCC_SMC_64,
+    ARM_SMCCC_OWNER_VENDOR_HYP,
+    0x5300,
+);
+
+/// Number of function IDs reserved for SVSM protocols.
+pub const SVSM_SMCCC_NUM_FIDS: u64 = 0x100;
+
+/// Returns the SVSM protocol selected by `fid`, if it is in the SVSM range.
+pub fn svsm_call_protocol(fid: u64) -> Option<u32> {
+    let offset = fid.checked_sub(SVSM_SMCCC_FID_BASE)?;
+    (offset < SVSM_SMCCC_NUM_FIDS).then_some(offset as u32)
+}
+
+/// Returns whether the reserved registers x5..x7 of the SVSM call in `gprs`
+/// are zero.
+fn reserved_gprs_clear(gprs: &[u64]) -> bool {
+    gprs[5..=7].iter().all(|r| *r == 0)
+}
+
+/// Applies a register update produced by a protocol handler to `plane`.
+pub fn set_plane_register(plane: &mut AuxPlaneContext, reg: &GuestRegister) {
+    match *reg {
+        GuestRegister::Aarch64Pc(r) => plane.pc = r,
+        GuestRegister::Aarch64Pstate(r) => plane.pstate = r,
+        _ => match reg.aarch64_gpr() {
+            Some((index, r)) if index < plane.gprs.len() => plane.gprs[index] = r,
+            _ => log::warn!("Ignoring {reg:?} for plane {}", plane.index),
+        },
+    }
+}
+
+/// Handles the SVSM request of `protocol` that `plane` issued, and writes
+/// the results back to its registers.
+pub fn handle_svsm_call(plane: &mut AuxPlaneContext, protocol: u32) {
+    let request = plane.gprs[1] as u32;
+    if !reserved_gprs_clear(&plane.gprs) {
+        log::debug!(
+            "Plane {} issued protocol {protocol} request {request} with non-zero x5..x7",
+            plane.index
+        );
+        plane.gprs[0] = SvsmResultCode::INVALID_PARAMETER.into();
+        return;
+    }
+    let mut params = RequestParams::from_gprs(&plane.gprs);
+
+    for reg in process_request(protocol, request, &mut params) {
+        set_plane_register(plane, &reg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fid_range() {
+        assert_eq!(SVSM_SMCCC_FID_BASE, 0xc600_5300);
+        assert_eq!(svsm_call_protocol(0xc600_5300), Some(0));
+        assert_eq!(svsm_call_protocol(0xc600_5302), Some(2));
+        assert_eq!(svsm_call_protocol(0xc600_5400), None);
+        assert_eq!(svsm_call_protocol(0x8400_0000), None);
+    }
+
+    #[test]
+    fn reserved_registers() {
+        let mut gprs = [0xAAu64; 8];
+        gprs[5..].fill(0);
+        assert!(reserved_gprs_clear(&gprs));
+        for reg in 5..8 {
+            let mut gprs = gprs;
+            gprs[reg] = 1;
+            assert!(!reserved_gprs_clear(&gprs));
+        }
+    }
+
+    #[test]
+    fn plane_registers() {
+        let mut plane = AuxPlaneContext::default();
+        set_plane_register(&mut plane, &GuestRegister::Aarch64X0(7));
+        set_plane_register(&mut plane, &GuestRegister::Aarch64X4(9));
+        set_plane_register(&mut plane, &GuestRegister::Aarch64Pc(0x1000));
+        assert_eq!(plane.gprs[0], 7);
+        assert_eq!(plane.gprs[4], 9);
+        assert_eq!(plane.pc, 0x1000);
+    }
+}
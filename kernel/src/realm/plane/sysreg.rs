@@ -0,0 +1,318 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Emulation of system register accesses trapped from aux planes.
+//!
+//! Trapped MSR and MRS instructions, as well as trapped SYS instructions
+//! such as set/way cache maintenance, are looked up by their encoding and
+//! emulated according to a [`SysRegEmul`] policy. The SVSM provides
+//! defaults which hide the PMU and the debug features from aux planes.
+//! Further registers can be handled, or the defaults overridden, with
+//! [`register_sysreg()`].
+//!
+//! Registers emulated with [`SysRegEmul::Shadow`] keep a per-plane value in
+//! the [`ShadowRegs`] of the [`AuxPlaneContext`], which is never written to
+//! the hardware.
+
+extern crate alloc;
+
+use super::exit::{PlaneExitReason, SysReg, SysRegOp};
+use super::{AuxPlaneContext, PlaneError};
+use crate::error::SvsmError;
+use crate::locking::RWLock;
+use crate::realm::rsi::rsi_cmd::{rsi_plane_sysreg_read, rsi_plane_sysreg_write};
+use alloc::collections::btree_map::BTreeMap;
+
+/// Maximum number of shadowed system registers per plane.
+pub const SYSREG_MAX_SHADOW: usize = 16;
+
+/// Register number which encodes XZR in a trapped MSR or MRS.
+const REG_XZR: usize = 31;
+
+// Debug registers
+const SYS_MDSCR_EL1: SysReg = SysReg::new(2, 0, 0, 2, 2);
+const SYS_OSLAR_EL1: SysReg = SysReg::new(2, 0, 1, 0, 4);
+const SYS_OSLSR_EL1: SysReg = SysReg::new(2, 0, 1, 1, 4);
+const SYS_OSDLR_EL1: SysReg = SysReg::new(2, 0, 1, 3, 4);
+
+// OSLSR_EL1.OSLM: the OS Lock is implemented.
+const OSLSR_OSLM_IMPLEMENTED: u64 = 1 << 3;
+
+// PMU registers
+const SYS_PMCR_EL0: SysReg = SysReg::new(3, 3, 9, 12, 0);
+const SYS_PMCNTENSET_EL0: SysReg = SysReg::new(3, 3, 9, 12, 1);
+const SYS_PMCNTENCLR_EL0: SysReg = SysReg::new(3, 3, 9, 12, 2);
+const SYS_PMOVSCLR_EL0: SysReg = SysReg::new(3, 3, 9, 12, 3);
+const SYS_PMSELR_EL0: SysReg = SysReg::new(3, 3, 9, 12, 5);
+const SYS_PMCCNTR_EL0: SysReg = SysReg::new(3, 3, 9, 13, 0);
+const SYS_PMUSERENR_EL0: SysReg = SysReg::new(3, 3, 9, 14, 0);
+const SYS_PMINTENSET_EL1: SysReg = SysReg::new(3, 0, 9, 14, 1);
+const SYS_PMINTENCLR_EL1: SysReg = SysReg::new(3, 0, 9, 14, 2);
+const SYS_PMCCFILTR_EL0: SysReg = SysReg::new(3, 3, 14, 15, 7);
+
+// ID registers
+const SYS_ID_AA64DFR0_EL1: SysReg = SysReg::new(3, 0, 0, 5, 0);
+
+// ID_AA64DFR0_EL1.PMUVer and ID_AA64DFR0_EL1.PMSVer
+const ID_AA64DFR0_PMU_MASK: u64 = (0xf << 8) | (0xf << 32);
+
+// Set/way cache maintenance (DC ISW, DC CSW, DC CISW)
+const SYS_DC_ISW: SysReg = SysReg::new(1, 0, 7, 6, 2);
+const SYS_DC_CSW: SysReg = SysReg::new(1, 0, 7, 10, 2);
+const SYS_DC_CISW: SysReg = SysReg::new(1, 0, 7, 14, 2);
+
+/// Emulates a read of `reg` by `plane`.
+pub type SysRegReadFn = fn(&mut AuxPlaneContext, SysReg) -> Result<u64, SvsmError>;
+
+/// Emulates a write of the given value to `reg` by `plane`.
+pub type SysRegWriteFn = fn(&mut AuxPlaneContext, SysReg, u64) -> Result<(), SvsmError>;
+
+/// How a trapped system register access is emulated.
+#[derive(Clone, Copy, Debug)]
+pub enum SysRegEmul {
+    /// Reads as zero, writes are ignored.
+    RazWi,
+    /// Reads return the given value, writes are ignored.
+    Fixed(u64),
+    /// Reads and writes access a per-plane shadow value, which starts out
+    /// with the given reset value.
+    Shadow(u64),
+    /// Reads and writes access the register of the plane through
+    /// `SMC_RSI_PLANE_SYSREG_READ/WRITE`.
+    Passthrough,
+    /// Reads return the register of the plane with the bits in the given
+    /// mask cleared, writes are ignored. Used to hide features advertised
+    /// by ID registers.
+    Sanitize(u64),
+    /// Reads and writes are emulated by the given functions.
+    Handler {
+        read: SysRegReadFn,
+        write: SysRegWriteFn,
+    },
+}
+
+impl SysRegEmul {
+    fn read(&self, plane: &mut AuxPlaneContext, reg: SysReg) -> Result<u64, SvsmError> {
+        match *self {
+            Self::RazWi => Ok(0),
+            Self::Fixed(val) => Ok(val),
+            Self::Shadow(reset) => Ok(plane.sysregs.get(reg).unwrap_or(reset)),
+            Self::Passthrough => Ok(rsi_plane_sysreg_read(plane.index, reg.rsi_encoding())?),
+            Self::Sanitize(mask) => {
+                Ok(rsi_plane_sysreg_read(plane.index, reg.rsi_encoding())? & !mask)
+            }
+            Self::Handler { read, .. } => read(plane, reg),
+        }
+    }
+
+    fn write(&self, plane: &mut AuxPlaneContext, reg: SysReg, val: u64) -> Result<(), SvsmError> {
+        match *self {
+            Self::RazWi | Self::Fixed(_) | Self::Sanitize(_) => Ok(()),
+            Self::Shadow(_) => plane.sysregs.set(reg, val),
+            Self::Passthrough => Ok(rsi_plane_sysreg_write(
+                plane.index,
+                reg.rsi_encoding(),
+                val,
+            )?),
+            Self::Handler { write, .. } => write(plane, reg, val),
+        }
+    }
+}
+
+/// Per-plane values of the registers emulated with [`SysRegEmul::Shadow`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ShadowRegs {
+    regs: [(SysReg, u64); SYSREG_MAX_SHADOW],
+    len: usize,
+}
+
+impl ShadowRegs {
+    /// Returns the shadow value of `reg`, if it was written before.
+    pub fn get(&self, reg: SysReg) -> Option<u64> {
+        self.regs[..self.len]
+            .iter()
+            .find(|(r, _)| *r == reg)
+            .map(|(_, val)| *val)
+    }
+
+    /// Sets the shadow value of `reg` to `val`.
+    pub fn set(&mut self, reg: SysReg, val: u64) -> Result<(), SvsmError> {
+        if let Some(entry) = self.regs[..self.len].iter_mut().find(|(r, _)| *r == reg) {
+            entry.1 = val;
+        } else if self.len < SYSREG_MAX_SHADOW {
+            self.regs[self.len] = (reg, val);
+            self.len += 1;
+        } else {
+            return Err(PlaneError::SysRegShadowOverflow(reg).into());
+        }
+        Ok(())
+    }
+
+    /// Drops all shadow values, so that registers read their reset value.
+    pub fn reset(&mut self) {
+        self.len = 0;
+    }
+}
+
+/// Policies applied when no policy is registered for a register.
+const DEFAULT_SYSREGS: &[(SysReg, SysRegEmul)] = &[
+    (SYS_MDSCR_EL1, SysRegEmul::Shadow(0)),
+    (SYS_OSLAR_EL1, SysRegEmul::RazWi),
+    (SYS_OSLSR_EL1, SysRegEmul::Fixed(OSLSR_OSLM_IMPLEMENTED)),
+    (SYS_OSDLR_EL1, SysRegEmul::RazWi),
+    (SYS_PMCR_EL0, SysRegEmul::RazWi),
+    (SYS_PMCNTENSET_EL0, SysRegEmul::RazWi),
+    (SYS_PMCNTENCLR_EL0, SysRegEmul::RazWi),
+    (SYS_PMOVSCLR_EL0, SysRegEmul::RazWi),
+    (SYS_PMSELR_EL0, SysRegEmul::RazWi),
+    (SYS_PMCCNTR_EL0, SysRegEmul::RazWi),
+    (SYS_PMUSERENR_EL0, SysRegEmul::RazWi),
+    (SYS_PMINTENSET_EL1, SysRegEmul::RazWi),
+    (SYS_PMINTENCLR_EL1, SysRegEmul::RazWi),
+    (SYS_PMCCFILTR_EL0, SysRegEmul::RazWi),
+    (
+        SYS_ID_AA64DFR0_EL1,
+        SysRegEmul::Sanitize(ID_AA64DFR0_PMU_MASK),
+    ),
+    // Set/way operations are meaningless on virtual PEs, coherency of
+    // realm memory is maintained by the hardware.
+    (SYS_DC_ISW, SysRegEmul::RazWi),
+    (SYS_DC_CSW, SysRegEmul::RazWi),
+    (SYS_DC_CISW, SysRegEmul::RazWi),
+];
+
+static SYSREGS: RWLock<BTreeMap<SysReg, SysRegEmul>> = RWLock::new(BTreeMap::new());
+
+/// Emulates accesses to `reg` according to `emul`, replacing any previously
+/// registered or default policy.
+pub fn register_sysreg(reg: SysReg, emul: SysRegEmul) {
+    SYSREGS.lock_write().insert(reg, emul);
+}
+
+/// Removes the policy registered for `reg`, which falls back to its default
+/// policy, if any.
+pub fn unregister_sysreg(reg: SysReg) {
+    SYSREGS.lock_write().remove(&reg);
+}
+
+/// Returns the policy for accesses to `reg`.
+pub fn sysreg_emul(reg: SysReg) -> Option<SysRegEmul> {
+    if let Some(emul) = SYSREGS.lock_read().get(&reg) {
+        return Some(*emul);
+    }
+    DEFAULT_SYSREGS
+        .iter()
+        .find(|(r, _)| *r == reg)
+        .map(|(_, emul)| *emul)
+}
+
+/// Emulates the trapped system register access `exit` and moves the plane
+/// past the trapping instruction.
+pub fn emulate_sysreg(
+    plane: &mut AuxPlaneContext,
+    exit: &PlaneExitReason,
+) -> Result<(), SvsmError> {
+    let PlaneExitReason::SysReg { reg, op, rt } = *exit else {
+        return Err(PlaneError::ExitNotEmulated(*exit).into());
+    };
+    let Some(emul) = sysreg_emul(reg) else {
+        return Err(PlaneError::ExitNotEmulated(*exit).into());
+    };
+
+    match op {
+        SysRegOp::Read => {
+            let val = emul.read(plane, reg)?;
+            if rt != REG_XZR {
+                plane.gprs[rt] = val;
+            }
+        }
+        SysRegOp::Write => {
+            let val = if rt != REG_XZR { plane.gprs[rt] } else { 0 };
+            emul.write(plane, reg, val)?;
+        }
+    }
+    plane.pc += 4;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exit(reg: SysReg, op: SysRegOp, rt: usize) -> PlaneExitReason {
+        PlaneExitReason::SysReg { reg, op, rt }
+    }
+
+    #[test]
+    fn shadow() {
+        let mut plane = AuxPlaneContext::new(1, 0x1000, 0, 4);
+        plane.gprs[2] = 0x1234;
+        emulate_sysreg(&mut plane, &exit(SYS_MDSCR_EL1, SysRegOp::Read, 3)).unwrap();
+        assert_eq!(plane.gprs[3], 0);
+        emulate_sysreg(&mut plane, &exit(SYS_MDSCR_EL1, SysRegOp::Write, 2)).unwrap();
+        emulate_sysreg(&mut plane, &exit(SYS_MDSCR_EL1, SysRegOp::Read, 3)).unwrap();
+        assert_eq!(plane.gprs[3], 0x1234);
+        assert_eq!(plane.pc, 0x100c);
+
+        let other = AuxPlaneContext::new(2, 0x1000, 0, 4);
+        assert_eq!(other.sysregs.get(SYS_MDSCR_EL1), None);
+    }
+
+    #[test]
+    fn shadow_overflow() {
+        let mut regs = ShadowRegs::default();
+        for i in 0..SYSREG_MAX_SHADOW {
+            regs.set(SysReg::new(3, 0, 15, 0, i as u8), i as u64)
+                .unwrap();
+        }
+        assert!(regs.set(SysReg::new(3, 0, 15, 1, 0), 0).is_err());
+        regs.set(SysReg::new(3, 0, 15, 0, 0), 42).unwrap();
+        assert_eq!(regs.get(SysReg::new(3, 0, 15, 0, 0)), Some(42));
+        regs.reset();
+        assert_eq!(regs.get(SysReg::new(3, 0, 15, 0, 0)), None);
+    }
+
+    #[test]
+    fn raz_wi_and_fixed() {
+        let mut plane = AuxPlaneContext::new(1, 0x1000, 0, 4);
+        plane.gprs[0] = 0xffff;
+        emulate_sysreg(&mut plane, &exit(SYS_PMCR_EL0, SysRegOp::Read, 0)).unwrap();
+        assert_eq!(plane.gprs[0], 0);
+        emulate_sysreg(&mut plane, &exit(SYS_OSLSR_EL1, SysRegOp::Read, 1)).unwrap();
+        assert_eq!(plane.gprs[1], OSLSR_OSLM_IMPLEMENTED);
+        emulate_sysreg(&mut plane, &exit(SYS_DC_CISW, SysRegOp::Write, REG_XZR)).unwrap();
+        assert_eq!(plane.pc, 0x100c);
+    }
+
+    fn read_answer(_plane: &mut AuxPlaneContext, _reg: SysReg) -> Result<u64, SvsmError> {
+        Ok(42)
+    }
+
+    fn write_x0(plane: &mut AuxPlaneContext, _reg: SysReg, val: u64) -> Result<(), SvsmError> {
+        plane.gprs[0] = val;
+        Ok(())
+    }
+
+    #[test]
+    fn registered_handler() {
+        let reg = SysReg::new(3, 0, 15, 2, 0);
+        let mut plane = AuxPlaneContext::new(1, 0x1000, 0, 4);
+        assert!(emulate_sysreg(&mut plane, &exit(reg, SysRegOp::Read, 1)).is_err());
+        assert_eq!(plane.pc, 0x1000);
+
+        register_sysreg(
+            reg,
+            SysRegEmul::Handler {
+                read: read_answer,
+                write: write_x0,
+            },
+        );
+        emulate_sysreg(&mut plane, &exit(reg, SysRegOp::Read, 1)).unwrap();
+        assert_eq!(plane.gprs[1], 42);
+        plane.gprs[2] = 7;
+        emulate_sysreg(&mut plane, &exit(reg, SysRegOp::Write, 2)).unwrap();
+        assert_eq!(plane.gprs[0], 7);
+
+        unregister_sysreg(reg);
+        assert!(sysreg_emul(reg).is_none());
+    }
+}
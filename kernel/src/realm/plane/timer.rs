@@ -0,0 +1,176 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Generic timer emulation for aux planes.
+//!
+//! The RMM reports the EL1 physical and virtual timers of a plane in
+//! [`PlaneExit`]. [`TimerState`] keeps them while the plane is not running,
+//! makes the timer PPIs pending in the plane's [`VGic`] once they fire, and
+//! tells the scheduler when the next one is due, so that plane 0 can sleep
+//! until then.
+
+use super::vgic::{VGic, Virq};
+use super::PlaneError;
+use crate::realm::rsi::PlaneExit;
+use crate::svsm_arm64::cpu::timer::{read_cntpct, read_cntvct};
+
+/// PPI of the EL1 physical timer.
+pub const PTIMER_INTID: u32 = 30;
+/// PPI of the EL1 virtual timer.
+pub const VTIMER_INTID: u32 = 27;
+
+const TIMER_PRIORITY: u8 = 0xc0;
+
+// The timer PPIs are level-triggered, so they are resampled when the plane
+// deactivates them.
+const PTIMER_VIRQ: Virq = Virq::new(PTIMER_INTID, TIMER_PRIORITY).with_eoi_notify();
+const VTIMER_VIRQ: Virq = Virq::new(VTIMER_INTID, TIMER_PRIORITY).with_eoi_notify();
+
+// Generic timer control bits
+const CNTX_CTL_ENABLE: u64 = 1 << 0;
+const CNTX_CTL_IMASK: u64 = 1 << 1;
+
+/// Physical and virtual count, sampled at the same time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Counters {
+    pub phys: u64,
+    pub virt: u64,
+}
+
+impl Counters {
+    /// Samples the counters of the current PE.
+    pub fn read() -> Self {
+        Self {
+            phys: read_cntpct(),
+            virt: read_cntvct(),
+        }
+    }
+
+    /// Converts a virtual count into the corresponding physical count.
+    fn virt_to_phys(&self, count: u64) -> u64 {
+        count.wrapping_add(self.phys.wrapping_sub(self.virt))
+    }
+}
+
+const fn timer_armed(ctl: u64) -> bool {
+    (ctl & CNTX_CTL_ENABLE) != 0 && (ctl & CNTX_CTL_IMASK) == 0
+}
+
+/// Generic timer state of an aux plane.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TimerState {
+    pub cntp_ctl: u64,
+    pub cntp_cval: u64,
+    pub cntv_ctl: u64,
+    pub cntv_cval: u64,
+}
+
+impl TimerState {
+    /// Updates the timer state from `exit`.
+    pub fn save(&mut self, exit: &PlaneExit) {
+        self.cntp_ctl = exit.cntp_ctl;
+        self.cntp_cval = exit.cntp_cval;
+        self.cntv_ctl = exit.cntv_ctl;
+        self.cntv_cval = exit.cntv_cval;
+    }
+
+    fn ptimer_asserted(&self, now: &Counters) -> bool {
+        timer_armed(self.cntp_ctl) && now.phys >= self.cntp_cval
+    }
+
+    fn vtimer_asserted(&self, now: &Counters) -> bool {
+        timer_armed(self.cntv_ctl) && now.virt >= self.cntv_cval
+    }
+
+    /// Returns whether either the physical or the virtual timer is asserting
+    /// its interrupt at `now`.
+    pub fn pending(&self, now: &Counters) -> bool {
+        self.ptimer_asserted(now) || self.vtimer_asserted(now)
+    }
+
+    /// Makes the PPIs of the timers which fired by `now` pending in `gic`.
+    /// Returns whether any timer fired.
+    pub fn inject(&self, gic: &mut VGic, now: &Counters) -> Result<bool, PlaneError> {
+        let mut fired = false;
+        if self.ptimer_asserted(now) {
+            gic.inject(PTIMER_VIRQ)?;
+            fired = true;
+        }
+        if self.vtimer_asserted(now) {
+            gic.inject(VTIMER_VIRQ)?;
+            fired = true;
+        }
+        Ok(fired)
+    }
+
+    /// Returns the physical count at which the next armed timer fires, if
+    /// any timer is armed and has not fired by `now`.
+    pub fn next_deadline(&self, now: &Counters) -> Option<u64> {
+        let ptimer =
+            (timer_armed(self.cntp_ctl) && !self.ptimer_asserted(now)).then_some(self.cntp_cval);
+        let vtimer = (timer_armed(self.cntv_ctl) && !self.vtimer_asserted(now))
+            .then(|| now.virt_to_phys(self.cntv_cval));
+        match (ptimer, vtimer) {
+            (Some(p), Some(v)) => Some(p.min(v)),
+            (p, v) => p.or(v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::realm::plane::vgic::VirqState;
+
+    const NOW: Counters = Counters {
+        phys: 1000,
+        virt: 400,
+    };
+
+    #[test]
+    fn timer_pending() {
+        let mut timer = TimerState::default();
+        assert!(!timer.pending(&NOW));
+        timer.cntv_ctl = CNTX_CTL_ENABLE;
+        timer.cntv_cval = 400;
+        assert!(timer.pending(&NOW));
+        timer.cntv_ctl |= CNTX_CTL_IMASK;
+        assert!(!timer.pending(&NOW));
+    }
+
+    #[test]
+    fn deadlines() {
+        let mut timer = TimerState::default();
+        assert_eq!(timer.next_deadline(&NOW), None);
+
+        // Virtual deadlines are converted to the physical count.
+        timer.cntv_ctl = CNTX_CTL_ENABLE;
+        timer.cntv_cval = 500;
+        assert_eq!(timer.next_deadline(&NOW), Some(1100));
+
+        timer.cntp_ctl = CNTX_CTL_ENABLE;
+        timer.cntp_cval = 1050;
+        assert_eq!(timer.next_deadline(&NOW), Some(1050));
+
+        // A timer which already fired has no deadline anymore.
+        timer.cntp_cval = 900;
+        assert_eq!(timer.next_deadline(&NOW), Some(1100));
+    }
+
+    #[test]
+    fn inject_ppis() {
+        let mut gic = VGic::new(4);
+        let mut timer = TimerState {
+            cntp_ctl: CNTX_CTL_ENABLE,
+            cntp_cval: 900,
+            cntv_ctl: CNTX_CTL_ENABLE,
+            cntv_cval: 500,
+        };
+        assert!(timer.inject(&mut gic, &NOW).unwrap());
+        assert_eq!(gic.state(PTIMER_INTID), Some(VirqState::Queued));
+        assert_eq!(gic.state(VTIMER_INTID), None);
+
+        timer.cntp_ctl |= CNTX_CTL_IMASK;
+        let mut gic = VGic::new(4);
+        assert!(!timer.inject(&mut gic, &NOW).unwrap());
+    }
+}
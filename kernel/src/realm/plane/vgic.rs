@@ -0,0 +1,397 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Virtual GICv3 CPU interface of an aux plane.
+//!
+//! Plane 0 owns the list registers of the aux planes it enters with
+//! `PLANE_ENTER_FLAG_GIC_OWNER`. A [`VGic`] keeps the virtual interrupts
+//! which are pending for one plane in priority order, loads the most urgent
+//! ones into the list registers of [`PlaneEnter`] before the plane runs, and
+//! folds the list register state reported in [`PlaneExit`] back in after it
+//! exits. Interrupts which do not fit into the list registers stay queued
+//! and a maintenance interrupt is requested to refill the list registers
+//! as soon as the plane has taken the ones already loaded.
+
+use super::PlaneError;
+use crate::realm::rsi::{PlaneEnter, PlaneExit, PLANE_GIC_NUM_LRS};
+
+// ICH_LR<n>_EL2 fields
+const ICH_LR_VINTID_MASK: u64 = 0xffff_ffff;
+const ICH_LR_EOI: u64 = 1 << 41;
+const ICH_LR_PRIORITY_SHIFT: u64 = 48;
+const ICH_LR_PRIORITY_MASK: u64 = 0xff << ICH_LR_PRIORITY_SHIFT;
+const ICH_LR_GROUP1: u64 = 1 << 60;
+const ICH_LR_STATE_SHIFT: u64 = 62;
+const ICH_LR_STATE: u64 = 3 << ICH_LR_STATE_SHIFT;
+const ICH_LR_PENDING: u64 = 1 << ICH_LR_STATE_SHIFT;
+const ICH_LR_ACTIVE: u64 = 2 << ICH_LR_STATE_SHIFT;
+
+// ICH_HCR_EL2 fields
+const ICH_HCR_EN: u64 = 1 << 0;
+const ICH_HCR_UIE: u64 = 1 << 1;
+const ICH_HCR_NPIE: u64 = 1 << 3;
+const ICH_HCR_VSGIEOICOUNT: u64 = 1 << 8;
+const ICH_HCR_DVIM: u64 = 1 << 15;
+const ICH_HCR_EOICOUNT_MASK: u64 = 0x1f << 27;
+
+// ICH_MISR_EL2 fields
+const ICH_MISR_EOI: u64 = 1 << 0;
+const ICH_MISR_U: u64 = 1 << 1;
+const ICH_MISR_NP: u64 = 1 << 3;
+
+// ICH_VTR_EL2 fields
+const ICH_VTR_LISTREGS_MASK: u64 = 0x1f;
+
+/// Maximum number of virtual interrupts waiting for a list register.
+pub const VIRQ_QUEUE_LEN: usize = 32;
+
+/// Returns the number of list registers usable for aux planes, given the
+/// value of ICH_VTR_EL2 reported in the realm configuration.
+pub fn num_lrs_from_vtr(vtr: u64) -> usize {
+    (((vtr & ICH_VTR_LISTREGS_MASK) + 1) as usize).min(PLANE_GIC_NUM_LRS)
+}
+
+/// A virtual group 1 interrupt to deliver to an aux plane.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Virq {
+    pub intid: u32,
+    /// Lower values are more urgent, as in the GIC.
+    pub priority: u8,
+    /// Report the deactivation of the interrupt by the plane through
+    /// [`VGic::completed`].
+    pub notify_eoi: bool,
+}
+
+impl Virq {
+    pub const fn new(intid: u32, priority: u8) -> Self {
+        Self {
+            intid,
+            priority,
+            notify_eoi: false,
+        }
+    }
+
+    /// Requests an EOI maintenance interrupt once the plane deactivates the
+    /// interrupt, e.g. to resample a level-triggered source.
+    pub const fn with_eoi_notify(mut self) -> Self {
+        self.notify_eoi = true;
+        self
+    }
+
+    fn lr_value(&self) -> u64 {
+        let mut lr = ICH_LR_PENDING
+            | ICH_LR_GROUP1
+            | (u64::from(self.priority) << ICH_LR_PRIORITY_SHIFT)
+            | u64::from(self.intid);
+        if self.notify_eoi {
+            lr |= ICH_LR_EOI;
+        }
+        lr
+    }
+
+    fn from_lr(lr: u64) -> Self {
+        Self {
+            intid: (lr & ICH_LR_VINTID_MASK) as u32,
+            priority: ((lr & ICH_LR_PRIORITY_MASK) >> ICH_LR_PRIORITY_SHIFT) as u8,
+            notify_eoi: (lr & ICH_LR_EOI) != 0,
+        }
+    }
+}
+
+/// Where a virtual interrupt currently is on its way to the plane.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VirqState {
+    /// Waiting for a free list register.
+    Queued,
+    /// Loaded in a list register but not acknowledged by the plane yet.
+    Pending,
+    /// Acknowledged but not deactivated by the plane.
+    Active,
+    /// Acknowledged, and asserted again before the plane deactivated it.
+    PendingActive,
+}
+
+/// Virtual GIC CPU interface state of an aux plane.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VGic {
+    hcr: u64,
+    lrs: [u64; PLANE_GIC_NUM_LRS],
+    misr: u64,
+    vmcr: u64,
+    num_lrs: usize,
+
+    queue: [Virq; VIRQ_QUEUE_LEN],
+    queued: usize,
+
+    completed: [u32; PLANE_GIC_NUM_LRS],
+    num_completed: usize,
+}
+
+impl VGic {
+    /// Creates the virtual CPU interface of a plane which may use the first
+    /// `num_lrs` list registers.
+    pub fn new(num_lrs: usize) -> Self {
+        Self {
+            hcr: ICH_HCR_EN | ICH_HCR_VSGIEOICOUNT | ICH_HCR_DVIM,
+            num_lrs: num_lrs.min(PLANE_GIC_NUM_LRS),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the number of list registers the plane may use.
+    pub fn num_lrs(&self) -> usize {
+        self.num_lrs
+    }
+
+    /// Returns the maintenance interrupt status reported by the last exit.
+    pub fn misr(&self) -> u64 {
+        self.misr
+    }
+
+    /// Returns the saved virtual machine control register of the plane.
+    pub fn vmcr(&self) -> u64 {
+        self.vmcr
+    }
+
+    /// Returns the INTIDs which were deactivated by the plane during its
+    /// last run and asked for EOI notification.
+    pub fn completed(&self) -> &[u32] {
+        &self.completed[..self.num_completed]
+    }
+
+    /// Returns whether any interrupt waits to be acknowledged by the plane.
+    pub fn has_pending(&self) -> bool {
+        self.queued > 0
+            || self.lrs[..self.num_lrs]
+                .iter()
+                .any(|lr| (lr & ICH_LR_PENDING) != 0)
+    }
+
+    fn find_lr(&self, intid: u32) -> Option<usize> {
+        self.lrs[..self.num_lrs].iter().position(|lr| {
+            (lr & ICH_LR_STATE) != 0 && (lr & ICH_LR_VINTID_MASK) == u64::from(intid)
+        })
+    }
+
+    /// Returns where `intid` is on its way to the plane, or `None` if it is
+    /// neither queued nor in a list register.
+    pub fn state(&self, intid: u32) -> Option<VirqState> {
+        if let Some(i) = self.find_lr(intid) {
+            return Some(match self.lrs[i] & ICH_LR_STATE {
+                ICH_LR_PENDING => VirqState::Pending,
+                ICH_LR_ACTIVE => VirqState::Active,
+                _ => VirqState::PendingActive,
+            });
+        }
+        self.queue[..self.queued]
+            .iter()
+            .any(|v| v.intid == intid)
+            .then_some(VirqState::Queued)
+    }
+
+    /// Makes `virq` pending for the plane. An interrupt which is already
+    /// pending is not queued a second time, while one which is active
+    /// becomes pending and active. Interrupts asking for EOI notification
+    /// are treated as level-triggered and are not made pending while they
+    /// are active: their source is resampled once the plane deactivates
+    /// them.
+    pub fn inject(&mut self, virq: Virq) -> Result<(), PlaneError> {
+        if let Some(i) = self.find_lr(virq.intid) {
+            if !virq.notify_eoi {
+                self.lrs[i] |= ICH_LR_PENDING;
+            }
+            return Ok(());
+        }
+        if self.queue[..self.queued]
+            .iter()
+            .any(|v| v.intid == virq.intid)
+        {
+            return Ok(());
+        }
+        self.enqueue(virq)
+    }
+
+    /// Inserts `virq` behind all queued interrupts of the same or a more
+    /// urgent priority.
+    fn enqueue(&mut self, virq: Virq) -> Result<(), PlaneError> {
+        if self.queued == VIRQ_QUEUE_LEN {
+            return Err(PlaneError::VirqOverflow(virq.intid));
+        }
+        let pos = self.queue[..self.queued]
+            .iter()
+            .position(|v| v.priority > virq.priority)
+            .unwrap_or(self.queued);
+        self.queue.copy_within(pos..self.queued, pos + 1);
+        self.queue[pos] = virq;
+        self.queued += 1;
+        Ok(())
+    }
+
+    fn dequeue(&mut self) -> Virq {
+        let virq = self.queue[0];
+        self.queue.copy_within(1..self.queued, 0);
+        self.queued -= 1;
+        virq
+    }
+
+    /// Returns the list register holding the least urgent interrupt which
+    /// the plane has not acknowledged yet, if it is less urgent than
+    /// `priority`.
+    fn preemptible_lr(&self, priority: u8) -> Option<usize> {
+        self.lrs[..self.num_lrs]
+            .iter()
+            .enumerate()
+            .filter(|(_, lr)| (**lr & ICH_LR_STATE) == ICH_LR_PENDING)
+            .map(|(i, lr)| (i, Virq::from_lr(*lr).priority))
+            .filter(|(_, p)| *p > priority)
+            .max_by_key(|(_, p)| *p)
+            .map(|(i, _)| i)
+    }
+
+    /// Loads the most urgent queued interrupts into the list registers and
+    /// copies the interface state into `enter`.
+    pub fn flush(&mut self, enter: &mut PlaneEnter) {
+        while self.queued > 0 {
+            let priority = self.queue[0].priority;
+            let free = self.lrs[..self.num_lrs]
+                .iter()
+                .position(|lr| (lr & ICH_LR_STATE) == 0);
+            let Some(slot) = free.or_else(|| self.preemptible_lr(priority)) else {
+                break;
+            };
+            let virq = self.dequeue();
+            if (self.lrs[slot] & ICH_LR_STATE) != 0 {
+                // A slot was freed by the dequeue above, so this cannot fail.
+                let _ = self.enqueue(Virq::from_lr(self.lrs[slot]));
+            }
+            self.lrs[slot] = virq.lr_value();
+        }
+
+        // Ask for a maintenance exit once the plane has taken all pending
+        // interrupts, so the remaining ones get a list register.
+        self.hcr &= !(ICH_HCR_UIE | ICH_HCR_NPIE | ICH_HCR_EOICOUNT_MASK);
+        if self.queued > 0 {
+            self.hcr |= ICH_HCR_NPIE;
+        }
+
+        enter.gicv3_hcr = self.hcr;
+        enter.gicv3_lrs = self.lrs;
+    }
+
+    /// Updates the interface state from `exit` and handles the maintenance
+    /// conditions reported in its MISR.
+    pub fn sync(&mut self, exit: &PlaneExit) {
+        self.hcr = exit.gicv3_hcr;
+        self.misr = exit.gicv3_misr;
+        self.vmcr = exit.gicv3_vmcr;
+        self.lrs[..self.num_lrs].copy_from_slice(&exit.gicv3_lrs[..self.num_lrs]);
+
+        self.num_completed = 0;
+        for lr in self.lrs[..self.num_lrs].iter_mut() {
+            if (*lr & ICH_LR_STATE) != 0 {
+                continue;
+            }
+            if (self.misr & ICH_MISR_EOI) != 0 && (*lr & ICH_LR_EOI) != 0 {
+                self.completed[self.num_completed] = (*lr & ICH_LR_VINTID_MASK) as u32;
+                self.num_completed += 1;
+            }
+            *lr = 0;
+        }
+
+        // Underflow and no-pending conditions only ask for more interrupts,
+        // which the next flush loads from the queue.
+        if (self.misr & (ICH_MISR_U | ICH_MISR_NP)) != 0 && self.queued == 0 {
+            self.hcr &= !(ICH_HCR_UIE | ICH_HCR_NPIE);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::realm::rsi::PlaneRun;
+
+    #[test]
+    fn vtr_list_registers() {
+        assert_eq!(num_lrs_from_vtr(0x9000_0003), 4);
+        assert_eq!(num_lrs_from_vtr(0x1f), PLANE_GIC_NUM_LRS);
+    }
+
+    #[test]
+    fn lr_encoding() {
+        let virq = Virq::new(27, 0xc0).with_eoi_notify();
+        assert_eq!(virq.lr_value(), 0x50c0_0200_0000_001b);
+        assert_eq!(Virq::from_lr(virq.lr_value()), virq);
+    }
+
+    #[test]
+    fn inject_coalesces() {
+        let mut vgic = VGic::new(4);
+        let mut run = PlaneRun::new_zeroed();
+        vgic.inject(Virq::new(27, 0xa0)).unwrap();
+        vgic.inject(Virq::new(27, 0xa0)).unwrap();
+        assert_eq!(vgic.state(27), Some(VirqState::Queued));
+        vgic.flush(&mut run.enter);
+        vgic.inject(Virq::new(27, 0xa0)).unwrap();
+        let loaded = run.enter.gicv3_lrs.iter().filter(|lr| **lr != 0).count();
+        assert_eq!(loaded, 1);
+        assert_eq!(vgic.state(27), Some(VirqState::Pending));
+    }
+
+    #[test]
+    fn priority_order_and_preemption() {
+        let mut vgic = VGic::new(2);
+        let mut run = PlaneRun::new_zeroed();
+        for (intid, priority) in [(40, 0xa0), (41, 0x80), (42, 0xc0)] {
+            vgic.inject(Virq::new(intid, priority)).unwrap();
+        }
+        vgic.flush(&mut run.enter);
+        assert_eq!(vgic.state(41), Some(VirqState::Pending));
+        assert_eq!(vgic.state(40), Some(VirqState::Pending));
+        assert_eq!(vgic.state(42), Some(VirqState::Queued));
+        assert_ne!(run.enter.gicv3_hcr & ICH_HCR_NPIE, 0);
+
+        // A more urgent interrupt evicts the least urgent pending one.
+        vgic.inject(Virq::new(1, 0x00)).unwrap();
+        vgic.flush(&mut run.enter);
+        assert_eq!(vgic.state(1), Some(VirqState::Pending));
+        assert_eq!(vgic.state(41), Some(VirqState::Pending));
+        assert_eq!(vgic.state(40), Some(VirqState::Queued));
+    }
+
+    #[test]
+    fn maintenance_refill() {
+        let mut vgic = VGic::new(1);
+        let mut run = PlaneRun::new_zeroed();
+        vgic.inject(Virq::new(27, 0xa0).with_eoi_notify()).unwrap();
+        vgic.inject(Virq::new(48, 0xa0)).unwrap();
+        vgic.flush(&mut run.enter);
+
+        // The plane acknowledged INTID 27: it stays in its list register.
+        run.exit.gicv3_lrs = run.enter.gicv3_lrs;
+        run.exit.gicv3_lrs[0] = (run.exit.gicv3_lrs[0] & !ICH_LR_STATE) | ICH_LR_ACTIVE;
+        run.exit.gicv3_misr = ICH_MISR_NP;
+        vgic.sync(&run.exit);
+        assert_eq!(vgic.state(27), Some(VirqState::Active));
+        vgic.flush(&mut run.enter);
+        assert_eq!(vgic.state(48), Some(VirqState::Queued));
+
+        // The plane deactivated INTID 27, which frees the list register.
+        run.exit.gicv3_lrs[0] &= !ICH_LR_STATE;
+        run.exit.gicv3_misr = ICH_MISR_EOI | ICH_MISR_NP;
+        vgic.sync(&run.exit);
+        assert_eq!(vgic.completed(), &[27]);
+        assert_eq!(vgic.state(27), None);
+        vgic.flush(&mut run.enter);
+        assert_eq!(vgic.state(48), Some(VirqState::Pending));
+        assert_eq!(run.enter.gicv3_hcr & ICH_HCR_NPIE, 0);
+    }
+
+    #[test]
+    fn queue_overflow() {
+        let mut vgic = VGic::new(1);
+        for intid in 0..VIRQ_QUEUE_LEN as u32 {
+            vgic.inject(Virq::new(intid, 0xa0)).unwrap();
+        }
+        assert!(vgic.inject(Virq::new(100, 0xa0)).is_err());
+    }
+}
@@ -0,0 +1,351 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Walks of the stage-1 translation tables of aux planes.
+//!
+//! The tables belong to the plane, which may have built them to steer the
+//! SVSM anywhere. The translation regime is therefore taken from the
+//! plane's TCR_EL1, every descriptor is checked, and the tables are only
+//! accessed where they lie in memory the aux planes own, through the
+//! fault-catching accessors of [`crate::mm::guestmem`].
+
+use super::exit::SysReg;
+use super::AuxPlaneContext;
+use crate::address::{PhysAddr, VirtAddr};
+use crate::error::SvsmError;
+use crate::mm::guestmem::{read_u64, write_u64};
+use crate::realm::perm::is_plane_memory;
+use crate::realm::rsi::rsi_cmd::rsi_plane_sysreg_read;
+use crate::svsm_arm64::mm::mmu::{DESC_TABLE, DESC_VALID};
+use crate::utils::MemoryRegion;
+
+const SYS_SCTLR_EL1: SysReg = SysReg::new(3, 0, 1, 0, 0);
+const SYS_TTBR0_EL1: SysReg = SysReg::new(3, 0, 2, 0, 0);
+const SYS_TTBR1_EL1: SysReg = SysReg::new(3, 0, 2, 0, 1);
+const SYS_TCR_EL1: SysReg = SysReg::new(3, 0, 2, 0, 2);
+
+// SCTLR_EL1 fields
+const SCTLR_M: u64 = 1 << 0;
+
+// TCR_EL1 fields
+const TCR_T0SZ_SHIFT: u64 = 0;
+const TCR_EPD0: u64 = 1 << 7;
+const TCR_TG0_SHIFT: u64 = 14;
+const TCR_T1SZ_SHIFT: u64 = 16;
+const TCR_EPD1: u64 = 1 << 23;
+const TCR_TG1_SHIFT: u64 = 30;
+const TCR_TBI0: u64 = 1 << 37;
+const TCR_TBI1: u64 = 1 << 38;
+const TCR_DS: u64 = 1 << 59;
+const TCR_TXSZ_MASK: u64 = 0x3f;
+const TCR_TG_MASK: u64 = 0b11;
+
+/// Bits [47:1] of TTBRn_EL1 hold the table base address.
+const TTBR_BADDR_MASK: u64 = 0x0000_ffff_ffff_fffe;
+/// Bits [47:12] of a descriptor hold the output address.
+const DESC_OA_MASK: u64 = 0x0000_ffff_ffff_f000;
+const DESC_SIZE: u64 = 8;
+
+/// Translation regime of an aux plane for one half of its VA space.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Regime {
+    /// Base address of the table of the first level.
+    base: u64,
+    /// log2 of the translation granule.
+    granule_shift: u32,
+    /// Number of VA bits translated by the tables.
+    va_bits: u32,
+    /// Whether the tables use the 52-bit descriptor format of TCR_EL1.DS.
+    ds: bool,
+}
+
+impl Regime {
+    /// Returns the regime translating `va`, from the TCR_EL1 and TTBRn_EL1
+    /// values of the plane.
+    fn new(tcr: u64, ttbr0: u64, ttbr1: u64, va: u64) -> Result<Self, SvsmError> {
+        let upper = va & (1 << 55) != 0;
+        let (ttbr, txsz, tg, disabled, tbi) = if upper {
+            let granule = match (tcr >> TCR_TG1_SHIFT) & TCR_TG_MASK {
+                0b01 => 14,
+                0b10 => 12,
+                0b11 => 16,
+                _ => return Err(SvsmError::InvalidAddress),
+            };
+            let t1sz = (tcr >> TCR_T1SZ_SHIFT) & TCR_TXSZ_MASK;
+            (ttbr1, t1sz, granule, tcr & TCR_EPD1, tcr & TCR_TBI1)
+        } else {
+            let granule = match (tcr >> TCR_TG0_SHIFT) & TCR_TG_MASK {
+                0b00 => 12,
+                0b01 => 16,
+                0b10 => 14,
+                _ => return Err(SvsmError::InvalidAddress),
+            };
+            let t0sz = (tcr >> TCR_T0SZ_SHIFT) & TCR_TXSZ_MASK;
+            (ttbr0, t0sz, granule, tcr & TCR_EPD0, tcr & TCR_TBI0)
+        };
+        let va_bits = 64 - txsz as u32;
+        if disabled != 0 || !(16..=52).contains(&va_bits) || va_bits <= tg {
+            return Err(SvsmError::InvalidAddress);
+        }
+
+        // The bits above the translated ones must all match bit 55, except
+        // for the top byte if it is ignored.
+        let top_bits = if tbi != 0 { 56 } else { 64 };
+        let high = (va & ((1u128 << top_bits) - 1) as u64) >> va_bits;
+        let expected = if upper {
+            (1u64 << (top_bits - va_bits)) - 1
+        } else {
+            0
+        };
+        if high != expected {
+            return Err(SvsmError::InvalidAddress);
+        }
+
+        let ds = tcr & TCR_DS != 0 && tg != 16;
+        let mut base = ttbr & TTBR_BADDR_MASK;
+        if ds || tg == 16 {
+            // Bits [5:2] hold bits [51:48] of 52-bit table addresses.
+            base = (base & !0x3f) | (((ttbr >> 2) & 0xf) << 48);
+        }
+        Ok(Self {
+            base: base & !(DESC_SIZE - 1),
+            granule_shift: tg,
+            va_bits,
+            ds,
+        })
+    }
+
+    fn bits_per_level(&self) -> u32 {
+        self.granule_shift - 3
+    }
+
+    /// Returns the level at which the walk starts, between -1 and 3.
+    fn start_level(&self) -> i32 {
+        let levels = (self.va_bits - self.granule_shift).div_ceil(self.bits_per_level());
+        4 - levels as i32
+    }
+
+    /// Returns the log2 of the size mapped by a descriptor at `level`.
+    fn level_shift(&self, level: i32) -> u32 {
+        self.granule_shift + (3 - level) as u32 * self.bits_per_level()
+    }
+
+    /// Returns whether block descriptors are allowed at `level`.
+    fn block_allowed(&self, level: i32) -> bool {
+        match self.granule_shift {
+            12 => level == 1 || level == 2 || (self.ds && level == 0),
+            14 => level == 2 || (self.ds && level == 1),
+            _ => level == 2,
+        }
+    }
+
+    /// Returns the output address of `desc` for a mapping of `shift` bits.
+    fn output_addr(&self, desc: u64, shift: u32) -> u64 {
+        let low_mask = !((1u64 << shift) - 1);
+        if self.ds {
+            // Bits [9:8] hold bits [51:50] of the output address.
+            (desc & 0x0003_ffff_ffff_f000 & low_mask) | (((desc >> 8) & 0b11) << 50)
+        } else if self.granule_shift == 16 {
+            // Bits [15:12] hold bits [51:48] of the output address.
+            (desc & DESC_OA_MASK & low_mask) | (((desc >> 12) & 0xf) << 48)
+        } else {
+            desc & DESC_OA_MASK & low_mask
+        }
+    }
+}
+
+/// Leaf descriptor found by a walk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Leaf {
+    /// IPA of the descriptor.
+    addr: PhysAddr,
+    /// Value of the descriptor.
+    desc: u64,
+    /// log2 of the size the descriptor maps.
+    shift: u32,
+}
+
+impl Leaf {
+    /// Returns the IPA `va` translates to.
+    fn translate(&self, regime: &Regime, va: u64) -> PhysAddr {
+        let offset_mask = (1u64 << self.shift) - 1;
+        PhysAddr::from(regime.output_addr(self.desc, self.shift) | (va & offset_mask))
+    }
+}
+
+/// Walks the tables of `regime` for `va`, reading each descriptor with
+/// `read`, and returns the leaf descriptor mapping `va`.
+fn walk<F>(regime: &Regime, va: u64, mut read: F) -> Result<Leaf, SvsmError>
+where
+    F: FnMut(PhysAddr) -> Result<u64, SvsmError>,
+{
+    let mut table = regime.base;
+    let mut level = regime.start_level();
+    loop {
+        let shift = regime.level_shift(level);
+        let index_bits = regime.bits_per_level().min(regime.va_bits - shift);
+        let index = (va >> shift) & ((1 << index_bits) - 1);
+        let addr = PhysAddr::from(table + index * DESC_SIZE);
+        let desc = read(addr)?;
+
+        if desc & DESC_VALID == 0 {
+            return Err(SvsmError::InvalidAddress);
+        }
+        let is_table = desc & DESC_TABLE != 0;
+        if level == 3 {
+            // At the last level, the table bit denotes a page.
+            if !is_table {
+                return Err(SvsmError::InvalidAddress);
+            }
+            return Ok(Leaf { addr, desc, shift });
+        }
+        if !is_table {
+            if !regime.block_allowed(level) {
+                return Err(SvsmError::InvalidAddress);
+            }
+            return Ok(Leaf { addr, desc, shift });
+        }
+        table = regime.output_addr(desc, regime.granule_shift);
+        level += 1;
+    }
+}
+
+/// Returns the IPA of `addr` as seen by the SVSM, if it holds a descriptor
+/// in memory the aux planes own.
+fn desc_vaddr(addr: PhysAddr) -> Result<VirtAddr, SvsmError> {
+    if !is_plane_memory(MemoryRegion::new(addr, DESC_SIZE as usize)) {
+        return Err(SvsmError::InvalidAddress);
+    }
+    // The SVSM runs identity mapped in the realm.
+    Ok(VirtAddr::from(u64::from(addr)))
+}
+
+/// Reads the descriptor at `addr` in memory owned by the aux planes.
+fn read_desc(addr: PhysAddr) -> Result<u64, SvsmError> {
+    let vaddr = desc_vaddr(addr)?;
+    // SAFETY: the descriptor lies in aux-plane memory, which holds no SVSM
+    // data, and faults are caught.
+    unsafe { read_u64(vaddr) }
+}
+
+/// Returns the regime of `plane` for `va`, or `None` if its MMU is off.
+fn plane_regime(plane: &AuxPlaneContext, va: u64) -> Result<Option<Regime>, SvsmError> {
+    let read = |reg: SysReg| rsi_plane_sysreg_read(plane.index, reg.rsi_encoding());
+    if read(SYS_SCTLR_EL1)? & SCTLR_M == 0 {
+        return Ok(None);
+    }
+    let tcr = read(SYS_TCR_EL1)?;
+    let ttbr0 = read(SYS_TTBR0_EL1)?;
+    let ttbr1 = read(SYS_TTBR1_EL1)?;
+    Regime::new(tcr, ttbr0, ttbr1, va).map(Some)
+}
+
+/// Translates the virtual address `va` of the aux plane to an IPA.
+pub fn plane_va_to_ipa(plane: &AuxPlaneContext, va: u64) -> Result<PhysAddr, SvsmError> {
+    match plane_regime(plane, va)? {
+        Some(regime) => Ok(walk(&regime, va, read_desc)?.translate(&regime, va)),
+        None => Ok(PhysAddr::from(va)),
+    }
+}
+
+/// Sets `bits` in the leaf descriptor mapping the virtual address `va` of
+/// the aux plane.
+pub fn plane_set_leaf_bits(plane: &AuxPlaneContext, va: u64, bits: u64) -> Result<(), SvsmError> {
+    let regime = plane_regime(plane, va)?.ok_or(SvsmError::InvalidAddress)?;
+    let leaf = walk(&regime, va, read_desc)?;
+    let vaddr = desc_vaddr(leaf.addr)?;
+    // SAFETY: the descriptor lies in aux-plane memory, which holds no SVSM
+    // data, and faults are caught.
+    unsafe { write_u64(vaddr, leaf.desc | bits) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // TCR_EL1 of Linux with 4KB pages and 48-bit VAs in both halves.
+    const TCR_4K_48: u64 =
+        (16 << TCR_T0SZ_SHIFT) | (16 << TCR_T1SZ_SHIFT) | (0b10 << TCR_TG1_SHIFT);
+    const TABLE: u64 = DESC_VALID | DESC_TABLE;
+    const PAGE: u64 = DESC_VALID | DESC_TABLE;
+    const BLOCK: u64 = DESC_VALID;
+
+    /// Walks tables made of the (address, descriptor) pairs in `mem`.
+    fn walk_mem(regime: &Regime, va: u64, mem: &[(u64, u64)]) -> Result<Leaf, SvsmError> {
+        walk(regime, va, |addr| {
+            mem.iter()
+                .find(|(desc_addr, _)| *desc_addr == u64::from(addr))
+                .map(|(_, desc)| *desc)
+                .ok_or(SvsmError::InvalidAddress)
+        })
+    }
+
+    #[test]
+    fn regime_4k_48() {
+        let regime = Regime::new(TCR_4K_48, 0x1000, 0x8_0001, 0xffff_8000_0000_0000).unwrap();
+        assert_eq!(regime.base, 0x8_0000);
+        assert_eq!(regime.start_level(), 0);
+        let regime = Regime::new(TCR_4K_48, 0x1000, 0x8_0001, 0x4000).unwrap();
+        assert_eq!(regime.base, 0x1000);
+        // Neither a lower nor an upper address.
+        assert!(Regime::new(TCR_4K_48, 0x1000, 0x2000, 0x0001_0000_0000_0000).is_err());
+        assert!(Regime::new(TCR_4K_48 | TCR_EPD0, 0x1000, 0x2000, 0x4000).is_err());
+    }
+
+    #[test]
+    fn regime_levels() {
+        // 4KB granule, 39-bit VAs: 3 levels.
+        let tcr = 25 << TCR_T0SZ_SHIFT;
+        assert_eq!(Regime::new(tcr, 0, 0, 0).unwrap().start_level(), 1);
+        // 64KB granule, 42-bit VAs: 2 levels.
+        let tcr = (22 << TCR_T0SZ_SHIFT) | (0b01 << TCR_TG0_SHIFT);
+        assert_eq!(Regime::new(tcr, 0, 0, 0).unwrap().start_level(), 2);
+        // 4KB granule, 52-bit VAs: 5 levels.
+        let tcr = (12 << TCR_T0SZ_SHIFT) | TCR_DS;
+        assert_eq!(Regime::new(tcr, 0, 0, 0).unwrap().start_level(), -1);
+    }
+
+    #[test]
+    fn walk_page() {
+        let regime = Regime::new(TCR_4K_48, 0x1000, 0, 0).unwrap();
+        let va = 0x0000_0080_4020_1234;
+        let tables = [
+            (0x1008, 0x2000 | TABLE),
+            (0x2008, 0x3000 | TABLE),
+            (0x3008, 0x4000 | TABLE),
+            (0x4008, 0x4567_8000 | PAGE),
+        ];
+        let leaf = walk_mem(&regime, va, &tables).unwrap();
+        assert_eq!(u64::from(leaf.addr), 0x4008);
+        assert_eq!(leaf.shift, 12);
+        assert_eq!(u64::from(leaf.translate(&regime, va)), 0x4567_8234);
+    }
+
+    #[test]
+    fn walk_block() {
+        let regime = Regime::new(TCR_4K_48, 0x1000, 0, 0).unwrap();
+        let va = 0x4020_1234;
+        let tables = [(0x1000, 0x2000 | TABLE), (0x2008, 0x8000_0000 | BLOCK)];
+        let leaf = walk_mem(&regime, va, &tables).unwrap();
+        assert_eq!(leaf.shift, 30);
+        assert_eq!(u64::from(leaf.translate(&regime, va)), 0x8020_1234);
+    }
+
+    #[test]
+    fn walk_invalid() {
+        let regime = Regime::new(TCR_4K_48, 0x1000, 0, 0).unwrap();
+        // Invalid descriptor at level 0.
+        assert!(walk_mem(&regime, 0, &[(0x1000, 0x2000)]).is_err());
+        // Blocks are not allowed at level 0.
+        assert!(walk_mem(&regime, 0, &[(0x1000, 0x8000_0000 | BLOCK)]).is_err());
+        // Reserved descriptor at level 3.
+        let tables = [
+            (0x1000, 0x2000 | TABLE),
+            (0x2000, 0x3000 | TABLE),
+            (0x3000, 0x4000 | TABLE),
+            (0x4000, 0x5000 | BLOCK),
+        ];
+        assert!(walk_mem(&regime, 0, &tables).is_err());
+        // A descriptor which cannot be read ends the walk.
+        assert!(walk_mem(&regime, 0, &[(0x1000, 0x2000 | TABLE)]).is_err());
+    }
+}
@@ -4,6 +4,7 @@ pub mod rsi_cmd;
 pub mod smccc;
 
 use core::mem::size_of;
+use zerocopy::FromZeros;
 
 pub mod retcodes {
     /// RSI return codes
@@ -14,6 +15,59 @@ pub mod retcodes {
     pub const RSI_ERROR_UNKNOWN: u64 = 4;
 }
 
+/// Errors reported by RSI calls.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RsiError {
+    /// RSI_ERROR_INPUT: an input value was invalid.
+    Input,
+    /// RSI_ERROR_STATE: the call is not valid in the current realm state.
+    State,
+    /// RSI_INCOMPLETE returned where the call was expected to complete.
+    Incomplete,
+    /// RSI_ERROR_UNKNOWN: the call failed for an unspecified reason.
+    Unknown,
+    /// A status code not defined by the RSI specification.
+    Status(u64),
+    /// The host rejected an IPA state change.
+    Rejected,
+    /// The RMM does not implement a compatible RSI ABI version.
+    AbiVersion { lower: u64, higher: u64 },
+    /// A buffer passed to an RSI call was too small.
+    BufferTooSmall,
+}
+
+impl RsiError {
+    /// Converts an RSI status code into a `Result`.
+    pub fn check(status: u64) -> Result<(), Self> {
+        match status {
+            retcodes::RSI_SUCCESS => Ok(()),
+            retcodes::RSI_ERROR_INPUT => Err(Self::Input),
+            retcodes::RSI_ERROR_STATE => Err(Self::State),
+            retcodes::RSI_INCOMPLETE => Err(Self::Incomplete),
+            retcodes::RSI_ERROR_UNKNOWN => Err(Self::Unknown),
+            other => Err(Self::Status(other)),
+        }
+    }
+
+    /// Returns the RSI status code to report for this error, e.g. when
+    /// completing an RSI call on behalf of an aux plane.
+    pub fn status(&self) -> u64 {
+        match *self {
+            Self::Input => retcodes::RSI_ERROR_INPUT,
+            Self::State => retcodes::RSI_ERROR_STATE,
+            Self::Incomplete => retcodes::RSI_INCOMPLETE,
+            Self::Status(status) => status,
+            _ => retcodes::RSI_ERROR_UNKNOWN,
+        }
+    }
+}
+
+impl From<RsiError> for crate::error::SvsmError {
+    fn from(err: RsiError) -> Self {
+        Self::Rsi(err)
+    }
+}
+
 /// Helper to build SMC call FIDs similar to ARM_SMCCC_CALL_VAL macro.
 /// The exact shift values depend on the SMCCC implementation in the target
 /// environment. The values below follow the typical Linux kernel layout:
@@ -30,6 +84,7 @@ pub mod arm_smccc {
     pub const ARM_SMCCC_STD_CALL: u64 = 0;
     pub const ARM_SMCCC_SMC_64: u64 = 1; // indicates 64-bit SMC convention
     pub const ARM_SMCCC_OWNER_STANDARD: u64 = 0;
+    pub const ARM_SMCCC_OWNER_VENDOR_HYP: u64 = 6;
 
     /// Build call value (similar to ARM_SMCCC_CALL_VAL macro)
     pub const fn call_val(typ: u64, cc: u64, owner: u64, func_num: u64) -> u64 {
@@ -79,6 +134,9 @@ pub mod fid {
     pub const SMC_RSI_IPA_STATE_SET: u64 = arm_smccc::fid(0x197);
     pub const SMC_RSI_IPA_STATE_GET: u64 = arm_smccc::fid(0x198);
     pub const SMC_RSI_HOST_CALL: u64 = arm_smccc::fid(0x199);
+    pub const SMC_RSI_MEM_GET_PERM_VALUE: u64 = arm_smccc::fid(0x1A0);
+    pub const SMC_RSI_MEM_SET_PERM_INDEX: u64 = arm_smccc::fid(0x1A1);
+    pub const SMC_RSI_MEM_SET_PERM_VALUE: u64 = arm_smccc::fid(0x1A2);
     pub const SMC_RSI_PLANE_ENTER: u64 = arm_smccc::fid(0x1A3);
     pub const SMC_RSI_PLANE_SYSREG_READ: u64 = arm_smccc::fid(0x1AE);
     pub const SMC_RSI_PLANE_SYSREG_WRITE: u64 = arm_smccc::fid(0x1AF);
@@ -93,6 +151,20 @@ pub mod ipa_consts {
     pub const RSI_REJECT: u64 = 1;
 }
 
+/// Realm IPA state (RIPAS) values
+pub mod ripas {
+    pub const RSI_RIPAS_EMPTY: u64 = 0;
+    pub const RSI_RIPAS_RAM: u64 = 1;
+    pub const RSI_RIPAS_DESTROYED: u64 = 2;
+    pub const RSI_RIPAS_DEV: u64 = 3;
+}
+
+/// Realm hash algorithms
+pub mod hash_algo {
+    pub const RSI_HASH_SHA_256: u64 = 0;
+    pub const RSI_HASH_SHA_512: u64 = 1;
+}
+
 /// Plane / GIC constants
 pub const PLANE_RUN_GPRS: usize = 31;
 pub const PLANE_GIC_NUM_LRS: usize = 16;
@@ -209,7 +281,7 @@ impl Default for RealmConfig {
 /// We'll build paddings to place fields at the intended offsets.
 #[repr(C)]
 #[repr(align(0x800))]
-#[derive(Debug)]
+#[derive(Debug, FromZeros)]
 pub struct PlaneEnter {
     // 0x000
     pub flags: u64,
@@ -234,7 +306,7 @@ pub struct PlaneEnter {
 
 #[repr(C)]
 #[repr(align(0x800))]
-#[derive(Debug)]
+#[derive(Debug, FromZeros)]
 pub struct PlaneExit {
     // 0x000
     pub reason: u8,
@@ -272,13 +344,28 @@ pub struct PlaneExit {
 
 #[repr(C)]
 #[repr(align(0x1000))]
-#[derive(Debug)]
+#[derive(Debug, FromZeros)]
 pub struct PlaneRun {
     pub enter: PlaneEnter,
     pub exit: PlaneExit,
     // pad to 0x1000 if necessary - but PlaneEnter (0x800) + PlaneExit (0x800) = 0x1000
 }
 
+/// Maximum size in bytes of a measurement or challenge exchanged with RSI.
+pub const RSI_MEASUREMENT_MAX_SIZE: usize = 64;
+
+/// Realm host call data structure, passed to `SMC_RSI_HOST_CALL`.
+#[repr(C)]
+#[repr(align(0x100))]
+#[derive(Debug, FromZeros)]
+pub struct RsiHostCall {
+    // 0x000
+    pub imm: u16,
+    _pad0: [u8; 6],
+    // 0x008
+    pub gprs: [u64; PLANE_RUN_GPRS],
+}
+
 // RSI exit reasons
 pub mod exit_reasons {
     pub const RSI_EXIT_SYNC: u64 = 0;
@@ -302,6 +389,18 @@ mod tests {
         assert_eq!(size_of::<PlaneEnter>(), 0x800);
         assert_eq!(size_of::<PlaneExit>(), 0x800);
         assert_eq!(size_of::<PlaneRun>(), 0x1000);
+        assert_eq!(size_of::<RsiHostCall>(), 0x100);
+    }
+
+    #[test]
+    fn status_codes() {
+        assert_eq!(RsiError::check(retcodes::RSI_SUCCESS), Ok(()));
+        assert_eq!(RsiError::check(retcodes::RSI_ERROR_INPUT), Err(RsiError::Input));
+        assert_eq!(RsiError::check(retcodes::RSI_INCOMPLETE), Err(RsiError::Incomplete));
+        assert_eq!(RsiError::check(42), Err(RsiError::Status(42)));
+        assert_eq!(RsiError::State.status(), retcodes::RSI_ERROR_STATE);
+        assert_eq!(RsiError::Status(42).status(), 42);
+        assert_eq!(RsiError::Rejected.status(), retcodes::RSI_ERROR_UNKNOWN);
     }
 
     #[test]
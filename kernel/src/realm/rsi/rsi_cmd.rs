@@ -1,44 +1,399 @@
-use crate::realm::rsi::{RealmConfig};
-use crate::realm::rsi::smccc::{ArmSmcccRes, arm_smccc_smc};
+extern crate alloc;
+
+use crate::address::{Address, PhysAddr, VirtAddr};
+use crate::error::SvsmError;
+use crate::realm::rsi::abi::{get_major, get_minor, RSI_ABI_VERSION, RSI_ABI_VERSION_MAJOR};
 use crate::realm::rsi::fid::*;
+use crate::realm::rsi::ipa_consts::RSI_ACCEPT;
 use crate::realm::rsi::retcodes::*;
-use crate::error::SvsmError;
+use crate::realm::rsi::smccc::{arm_smccc_1_2_smc, ArmSmccc12Regs, ARM_SMCCC_1_2_NUM_REGS};
+use crate::realm::rsi::{PlaneRun, RealmConfig, RsiError, RsiHostCall, RSI_MEASUREMENT_MAX_SIZE};
+use crate::types::PAGE_SIZE;
 use crate::utils::immut_after_init::ImmutAfterInitCell;
+use crate::utils::vec::vec_sized;
+use alloc::vec::Vec;
 
 pub static REALM_CONFIG: ImmutAfterInitCell<RealmConfig> = ImmutAfterInitCell::uninit();
 
-pub fn rsi_realm_config() -> RealmConfig {
-    let mut res = ArmSmcccRes {
-        a0: 0,
-        a1: 0,
-        a2: 0,
-        a3: 0,
+/// Number of 64-bit registers holding a measurement or challenge.
+const MEASUREMENT_REGS: usize = RSI_MEASUREMENT_MAX_SIZE / 8;
+
+/// Returns the IPA of SVSM memory at `ptr`. The SVSM runs identity mapped
+/// in the realm.
+fn ipa_of<T>(ptr: *const T) -> PhysAddr {
+    PhysAddr::from(VirtAddr::from(ptr).bits())
+}
+
+/// Issues RSI call `fid` with `args` in x1 onwards and returns x0..x17.
+///
+/// # Safety
+///
+/// Any memory whose address is passed in `args` must be valid for the
+/// accesses the RMM performs for `fid`.
+unsafe fn rsi_call(fid: u64, args: &[u64]) -> [u64; ARM_SMCCC_1_2_NUM_REGS] {
+    let mut regs = ArmSmccc12Regs::default();
+    regs.x[0] = fid;
+    regs.x[1..=args.len()].copy_from_slice(args);
+    // SAFETY: the caller guarantees that memory referenced by `args` is valid.
+    unsafe { arm_smccc_1_2_smc(&regs).x }
+}
+
+/// Packs up to 64 bytes of `data` into little-endian register values.
+fn bytes_to_regs(data: &[u8]) -> [u64; MEASUREMENT_REGS] {
+    let mut regs = [0u64; MEASUREMENT_REGS];
+    for (reg, chunk) in regs.iter_mut().zip(data.chunks(8)) {
+        let mut bytes = [0u8; 8];
+        bytes[..chunk.len()].copy_from_slice(chunk);
+        *reg = u64::from_le_bytes(bytes);
+    }
+    regs
+}
+
+/// Unpacks little-endian register values into bytes.
+fn regs_to_bytes(regs: &[u64]) -> [u8; RSI_MEASUREMENT_MAX_SIZE] {
+    let mut data = [0u8; RSI_MEASUREMENT_MAX_SIZE];
+    for (chunk, reg) in data.chunks_mut(8).zip(regs) {
+        chunk.copy_from_slice(&reg.to_le_bytes());
+    }
+    data
+}
+
+/// Requests RSI ABI version `req`. Returns the lowest and highest ABI
+/// versions implemented by the RMM.
+pub fn rsi_abi_version(req: u64) -> Result<(u64, u64), RsiError> {
+    // SAFETY: the call takes no memory arguments.
+    let ret = unsafe { rsi_call(SMC_RSI_ABI_VERSION, &[req]) };
+    match RsiError::check(ret[0]) {
+        Ok(()) => Ok((ret[1], ret[2])),
+        Err(RsiError::Input) => Err(RsiError::AbiVersion {
+            lower: ret[1],
+            higher: ret[2],
+        }),
+        Err(e) => Err(e),
+    }
+}
+
+/// Negotiates the RSI ABI version with the RMM. Fails if the RMM does not
+/// implement the major version the SVSM was written against.
+pub fn rsi_abi_handshake() -> Result<(), SvsmError> {
+    let (lower, higher) = rsi_abi_version(RSI_ABI_VERSION)?;
+    if get_major(lower) != RSI_ABI_VERSION_MAJOR {
+        return Err(RsiError::AbiVersion { lower, higher }.into());
+    }
+    log::info!(
+        "RSI ABI version {}.{} (RMM supports up to {}.{})",
+        get_major(lower),
+        get_minor(lower),
+        get_major(higher),
+        get_minor(higher)
+    );
+    Ok(())
+}
+
+/// Reads feature register `index`.
+pub fn rsi_features(index: u64) -> Result<u64, RsiError> {
+    // SAFETY: the call takes no memory arguments.
+    let ret = unsafe { rsi_call(SMC_RSI_FEATURES, &[index]) };
+    RsiError::check(ret[0])?;
+    Ok(ret[1])
+}
+
+/// Reads realm measurement `index` (0 is the RIM, 1..=4 the REMs).
+pub fn rsi_measurement_read(index: u64) -> Result<[u8; RSI_MEASUREMENT_MAX_SIZE], RsiError> {
+    // SAFETY: the call takes no memory arguments.
+    let ret = unsafe { rsi_call(SMC_RSI_MEASUREMENT_READ, &[index]) };
+    RsiError::check(ret[0])?;
+    Ok(regs_to_bytes(&ret[1..=MEASUREMENT_REGS]))
+}
+
+/// Extends realm extensible measurement `index` with `value`, which must
+/// not be longer than 64 bytes.
+pub fn rsi_measurement_extend(index: u64, value: &[u8]) -> Result<(), RsiError> {
+    if value.len() > RSI_MEASUREMENT_MAX_SIZE {
+        return Err(RsiError::Input);
+    }
+
+    let mut args = [0u64; 2 + MEASUREMENT_REGS];
+    args[0] = index;
+    args[1] = value.len() as u64;
+    args[2..].copy_from_slice(&bytes_to_regs(value));
+
+    // SAFETY: the call takes no memory arguments.
+    let ret = unsafe { rsi_call(SMC_RSI_MEASUREMENT_EXTEND, &args) };
+    RsiError::check(ret[0])
+}
+
+/// Starts an attestation token request for `challenge`. Returns an upper
+/// bound of the token size.
+pub fn rsi_attestation_token_init(
+    challenge: &[u8; RSI_MEASUREMENT_MAX_SIZE],
+) -> Result<usize, RsiError> {
+    // SAFETY: the call takes no memory arguments.
+    let ret = unsafe { rsi_call(SMC_RSI_ATTESTATION_TOKEN_INIT, &bytes_to_regs(challenge)) };
+    RsiError::check(ret[0])?;
+    Ok(ret[1] as usize)
+}
+
+/// Continues the current attestation token request, writing up to `size`
+/// bytes at `offset` into the granule at `granule`.
+///
+/// Returns the number of bytes written and whether the token is complete.
+///
+/// # Safety
+///
+/// `granule` must be the IPA of a granule-aligned page owned by the caller,
+/// and `offset + size` must not exceed the granule size.
+pub unsafe fn rsi_attestation_token_continue(
+    granule: PhysAddr,
+    offset: usize,
+    size: usize,
+) -> Result<(usize, bool), RsiError> {
+    // SAFETY: the caller guarantees that the granule may be written.
+    let ret = unsafe {
+        rsi_call(
+            SMC_RSI_ATTESTATION_TOKEN_CONTINUE,
+            &[u64::from(granule), offset as u64, size as u64],
+        )
     };
+    match ret[0] {
+        RSI_SUCCESS => Ok((ret[1] as usize, true)),
+        RSI_INCOMPLETE => Ok((ret[1] as usize, false)),
+        status => Err(RsiError::check(status).unwrap_err()),
+    }
+}
 
-    let mut config = RealmConfig::default();
+/// Reads the token of the current attestation token request into `buf`.
+/// Returns the token length.
+fn read_attestation_token(buf: &mut [u8]) -> Result<usize, RsiError> {
+    let mut len = 0;
 
-    unsafe {
-        // call SMC
-        arm_smccc_smc(
-            SMC_RSI_REALM_CONFIG,                   // FID
-            &mut config as *mut RealmConfig as u64, // arg1 = RealmConfig 地址
-            0, 0, 0, 0, 0, 0,
-            &mut res as *mut ArmSmcccRes,
-            core::ptr::null_mut(),
-        );
+    while len < buf.len() {
+        let va = VirtAddr::from(buf[len..].as_mut_ptr());
+        let granule = ipa_of(va.page_align().as_ptr::<u8>());
+        let offset = va.page_offset();
+        let size = (PAGE_SIZE - offset).min(buf.len() - len);
+
+        // SAFETY: the range [offset, offset + size) of the granule is backed
+        // by `buf`, which we borrow mutably.
+        let (written, done) = unsafe { rsi_attestation_token_continue(granule, offset, size)? };
+        len += written;
+        if done {
+            return Ok(len);
+        }
     }
 
-    // 可以检查 res.a0 判断是否成功
-    if res.a0 != RSI_SUCCESS {
-        panic!("Failed to read RealmConfig via SMC: {}", res.a0);
+    Err(RsiError::BufferTooSmall)
+}
+
+/// Retrieves the realm attestation token for `challenge` into `buf`.
+/// Returns the token length.
+pub fn rsi_attestation_token(
+    challenge: &[u8; RSI_MEASUREMENT_MAX_SIZE],
+    buf: &mut [u8],
+) -> Result<usize, RsiError> {
+    let max_size = rsi_attestation_token_init(challenge)?;
+    log::debug!("Realm token size is at most {max_size} bytes");
+    read_attestation_token(buf)
+}
+
+/// Retrieves the realm attestation token for `challenge` into a buffer
+/// sized after the upper bound reported by the RMM. A challenge shorter
+/// than 64 bytes is zero-padded.
+pub fn rsi_attestation_token_vec(challenge: &[u8]) -> Result<Vec<u8>, SvsmError> {
+    if challenge.len() > RSI_MEASUREMENT_MAX_SIZE {
+        return Err(RsiError::Input.into());
     }
+    let mut padded = [0u8; RSI_MEASUREMENT_MAX_SIZE];
+    padded[..challenge.len()].copy_from_slice(challenge);
 
-    config
+    let max_size = rsi_attestation_token_init(&padded)?;
+    let mut token: Vec<u8> = vec_sized(max_size).map_err(|_| SvsmError::Mem)?;
+    let len = read_attestation_token(&mut token)?;
+    token.truncate(len);
+    Ok(token)
+}
+
+/// Changes the RIPAS of `[base, top)` to `ripas`. The RMM may process only
+/// part of the range; the returned address is the first one not changed.
+pub fn rsi_ipa_state_set(
+    base: PhysAddr,
+    top: PhysAddr,
+    ripas: u64,
+    flags: u64,
+) -> Result<PhysAddr, RsiError> {
+    // SAFETY: the call takes no memory arguments.
+    let ret = unsafe {
+        rsi_call(
+            SMC_RSI_IPA_STATE_SET,
+            &[u64::from(base), u64::from(top), ripas, flags],
+        )
+    };
+    RsiError::check(ret[0])?;
+    if ret[2] != RSI_ACCEPT {
+        return Err(RsiError::Rejected);
+    }
+    Ok(PhysAddr::from(ret[1]))
+}
+
+/// Returns the RIPAS of the region starting at `base`, together with the
+/// end of the region within `[base, top)` sharing that RIPAS.
+pub fn rsi_ipa_state_get(base: PhysAddr, top: PhysAddr) -> Result<(PhysAddr, u64), RsiError> {
+    // SAFETY: the call takes no memory arguments.
+    let ret = unsafe { rsi_call(SMC_RSI_IPA_STATE_GET, &[u64::from(base), u64::from(top)]) };
+    RsiError::check(ret[0])?;
+    Ok((PhysAddr::from(ret[1]), ret[2]))
+}
+
+/// Changes the RIPAS of `[start, end)` to `state`, looping until the RMM
+/// has processed the whole range.
+pub fn rsi_set_memory_range(
+    mut start: PhysAddr,
+    end: PhysAddr,
+    state: u64,
+    flags: u64,
+) -> Result<(), RsiError> {
+    while start != end {
+        let top = rsi_ipa_state_set(start, end, state, flags)?;
+        if top <= start || top > end {
+            return Err(RsiError::Unknown);
+        }
+        start = top;
+    }
+    Ok(())
+}
+
+/// Returns the stage-2 permissions of aux plane `plane_idx` for memory with
+/// permission index `perm_idx`.
+pub fn rsi_mem_get_perm_value(plane_idx: u64, perm_idx: u64) -> Result<u64, RsiError> {
+    // SAFETY: the call takes no memory arguments.
+    let ret = unsafe { rsi_call(SMC_RSI_MEM_GET_PERM_VALUE, &[plane_idx, perm_idx]) };
+    RsiError::check(ret[0])?;
+    Ok(ret[1])
+}
+
+/// Sets the stage-2 permissions of aux plane `plane_idx` for memory with
+/// permission index `perm_idx` to `value`.
+pub fn rsi_mem_set_perm_value(plane_idx: u64, perm_idx: u64, value: u64) -> Result<(), RsiError> {
+    // SAFETY: the call takes no memory arguments.
+    let ret = unsafe { rsi_call(SMC_RSI_MEM_SET_PERM_VALUE, &[plane_idx, perm_idx, value]) };
+    RsiError::check(ret[0])
+}
+
+/// Assigns permission index `perm_idx` to `[base, top)`. The RMM may
+/// process only part of the range; the returned address is the first one
+/// not changed, and the returned cookie must be passed to the call which
+/// continues from there.
+pub fn rsi_mem_set_perm_index(
+    base: PhysAddr,
+    top: PhysAddr,
+    perm_idx: u64,
+    cookie: u64,
+) -> Result<(PhysAddr, u64), RsiError> {
+    // SAFETY: the call takes no memory arguments.
+    let ret = unsafe {
+        rsi_call(
+            SMC_RSI_MEM_SET_PERM_INDEX,
+            &[u64::from(base), u64::from(top), perm_idx, cookie],
+        )
+    };
+    RsiError::check(ret[0])?;
+    if ret[2] != RSI_ACCEPT {
+        return Err(RsiError::Rejected);
+    }
+    Ok((PhysAddr::from(ret[1]), ret[3]))
+}
+
+/// Assigns permission index `perm_idx` to `[start, end)`, looping until the
+/// RMM has processed the whole range.
+pub fn rsi_set_perm_range(
+    mut start: PhysAddr,
+    end: PhysAddr,
+    perm_idx: u64,
+) -> Result<(), RsiError> {
+    let mut cookie = 0;
+    while start != end {
+        let (top, next_cookie) = rsi_mem_set_perm_index(start, end, perm_idx, cookie)?;
+        if top <= start || top > end {
+            return Err(RsiError::Unknown);
+        }
+        start = top;
+        cookie = next_cookie;
+    }
+    Ok(())
+}
+
+/// Issues a host call with the immediate and registers in `call`. On
+/// return, `call.gprs` holds the values provided by the host.
+pub fn rsi_host_call(call: &mut RsiHostCall) -> Result<(), RsiError> {
+    let pa = ipa_of(call as *mut RsiHostCall);
+    // SAFETY: the RMM only accesses the RsiHostCall we borrow mutably.
+    let ret = unsafe { rsi_call(SMC_RSI_HOST_CALL, &[u64::from(pa)]) };
+    RsiError::check(ret[0])
+}
+
+/// Enters aux plane `plane_idx` using the enter/exit state in `run`. On
+/// return, `run.exit` describes why the plane exited.
+pub fn rsi_plane_enter(plane_idx: u64, run: &mut PlaneRun) -> Result<(), RsiError> {
+    let run_pa = ipa_of(run as *mut PlaneRun);
+    // SAFETY: the RMM only accesses the PlaneRun page we borrow mutably.
+    let ret = unsafe { rsi_call(SMC_RSI_PLANE_ENTER, &[plane_idx, u64::from(run_pa)]) };
+    RsiError::check(ret[0])
+}
+
+/// Reads the system register encoded as `sysreg` from aux plane `plane_idx`.
+pub fn rsi_plane_sysreg_read(plane_idx: u64, sysreg: u64) -> Result<u64, RsiError> {
+    // SAFETY: the call takes no memory arguments.
+    let ret = unsafe { rsi_call(SMC_RSI_PLANE_SYSREG_READ, &[plane_idx, sysreg]) };
+    RsiError::check(ret[0])?;
+    Ok(ret[1])
+}
+
+/// Writes `value` to the system register encoded as `sysreg` of aux plane
+/// `plane_idx`.
+pub fn rsi_plane_sysreg_write(plane_idx: u64, sysreg: u64, value: u64) -> Result<(), RsiError> {
+    // SAFETY: the call takes no memory arguments.
+    let ret = unsafe { rsi_call(SMC_RSI_PLANE_SYSREG_WRITE, &[plane_idx, sysreg, value, 0]) };
+    RsiError::check(ret[0])
+}
+
+/// Reads the realm configuration.
+pub fn rsi_realm_config() -> Result<RealmConfig, RsiError> {
+    let mut config = RealmConfig::default();
+    let pa = ipa_of(&mut config as *mut RealmConfig);
+
+    // SAFETY: the RMM writes the configuration into `config`, which is a
+    // page-sized and page-aligned RealmConfig we own.
+    let ret = unsafe { rsi_call(SMC_RSI_REALM_CONFIG, &[u64::from(pa)]) };
+    RsiError::check(ret[0])?;
+
+    Ok(config)
 }
 
 // This function can only call once
 pub fn init_realm_config() -> Result<(), SvsmError> {
-    let cfg = rsi_realm_config();
-    REALM_CONFIG.init(cfg);
-    Ok(())
-}
\ No newline at end of file
+    let cfg = rsi_realm_config()?;
+    REALM_CONFIG.init(cfg).map_err(|_| SvsmError::PlatformInit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measurement_register_packing() {
+        let mut value = [0u8; RSI_MEASUREMENT_MAX_SIZE];
+        for (i, b) in value.iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        let regs = bytes_to_regs(&value);
+        assert_eq!(regs[0], 0x0706_0504_0302_0100);
+        assert_eq!(regs_to_bytes(&regs), value);
+    }
+
+    #[test]
+    fn short_measurement_is_zero_padded() {
+        let regs = bytes_to_regs(&[0xaa; 12]);
+        assert_eq!(regs[1], 0x0000_0000_aaaa_aaaa);
+        assert!(regs[2..].iter().all(|r| *r == 0));
+    }
+}
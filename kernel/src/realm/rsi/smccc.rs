@@ -1,7 +1,7 @@
 use core::arch::asm;
 
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct ArmSmcccRes {
     pub a0: u64, // offset 0
     pub a1: u64, // offset 8
@@ -81,3 +81,56 @@ pub unsafe fn arm_smccc_smc(
         );
     }
 }
+
+/// Number of registers (x0..x17) passed and returned by an SMCCC v1.2 call.
+pub const ARM_SMCCC_1_2_NUM_REGS: usize = 18;
+
+/// Register file of an SMCCC v1.2 call, which passes arguments and results
+/// in x0..x17.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ArmSmccc12Regs {
+    pub x: [u64; ARM_SMCCC_1_2_NUM_REGS],
+}
+
+/// Issues an SMC with x0..x17 taken from `args` and returns x0..x17 as left
+/// by the callee.
+///
+/// # Safety
+///
+/// Any memory whose address is passed in `args` must be valid for the
+/// accesses the called function performs on it.
+#[inline(always)]
+pub unsafe fn arm_smccc_1_2_smc(args: &ArmSmccc12Regs) -> ArmSmccc12Regs {
+    let mut res = *args;
+    let x = &mut res.x;
+
+    // SAFETY: the caller guarantees the validity of memory referenced by
+    // the arguments. All argument/result registers are declared.
+    unsafe {
+        asm!(
+            "smc #0",
+            inout("x0") x[0],
+            inout("x1") x[1],
+            inout("x2") x[2],
+            inout("x3") x[3],
+            inout("x4") x[4],
+            inout("x5") x[5],
+            inout("x6") x[6],
+            inout("x7") x[7],
+            inout("x8") x[8],
+            inout("x9") x[9],
+            inout("x10") x[10],
+            inout("x11") x[11],
+            inout("x12") x[12],
+            inout("x13") x[13],
+            inout("x14") x[14],
+            inout("x15") x[15],
+            inout("x16") x[16],
+            inout("x17") x[17],
+            options(nostack)
+        );
+    }
+
+    res
+}
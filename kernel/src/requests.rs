@@ -21,12 +21,10 @@ use crate::protocols::{
     RequestParams, SVSM_APIC_PROTOCOL, SVSM_ATTEST_PROTOCOL, SVSM_CORE_PROTOCOL,
 };
 
-use alloc::vec::Vec;
+#[cfg(feature = "cca")]
+use crate::realm::plane::plane_main;
 
-extern "C" {
-    fn plane_main_svsm(kernel_entry: u64, kernel_fdt_addr: u64);
-    fn context_main_loop_svsm();
-}
+use alloc::vec::Vec;
 
 /// The SVSM Calling Area (CAA)
 #[repr(C, packed)]
@@ -149,20 +147,19 @@ pub extern "C" fn request_loop_main(cpu_index: usize) {
     }
     */
 
-    let kernel_entry: u64 = 0x60000000;
-    let kernel_fdt_addr = 0x40000000;
-
-    loop {
-        unsafe {
-            log::info!("Intialized plane context");
-            plane_main_svsm(kernel_entry, kernel_fdt_addr);
-            log::info!("Enter plane");
-            context_main_loop_svsm();
-        }
+    #[cfg(feature = "cca")]
+    if let Err(e) = plane_main() {
+        log::error!("Aux plane scheduler terminated: {e:?}");
     }
 }
 
-fn process_request(protocol: u32, request: u32, params: &mut RequestParams) -> Vec<GuestRegister> {
+/// Handles `request` of `protocol` and returns the guest registers holding
+/// the result code and the updated parameters.
+pub fn process_request(
+    protocol: u32,
+    request: u32,
+    params: &mut RequestParams,
+) -> Vec<GuestRegister> {
     let rax: Option<u64> = match request_loop_once(params, protocol, request) {
         Ok(()) => Some(SvsmResultCode::SUCCESS.into()),
         Err(SvsmReqError::RequestError(code)) => {
@@ -185,7 +182,10 @@ fn process_request(protocol: u32, request: u32, params: &mut RequestParams) -> V
     // Generate vector of registers to update.
     let mut guest_regs = Vec::<GuestRegister>::new();
     if let Some(val) = rax {
+        #[cfg(not(feature = "cca"))]
         guest_regs.push(GuestRegister::X64Rax(val));
+        #[cfg(feature = "cca")]
+        guest_regs.push(GuestRegister::Aarch64X0(val));
     }
 
     params.capture(&mut guest_regs);
@@ -34,8 +34,7 @@ _setup_pagetable:
     // Set TTBRs
     ldr     x1, =LD_TTBR0_BASE
     msr     ttbr0_el1, x1 // L0 base address TTBR0
-    ldr     x2, =LD_TTBR1_BASE
-    msr     ttbr1_el1, x2 // L0 base address TTBR1
+    msr     ttbr1_el1, x1 // same root for the high half, as on x86
 
     //
     //  4KB granule，VA 48-bit：
@@ -59,15 +58,15 @@ _setup_pagetable:
     str     x14, [x13]           // L2_1[0] = 0
 
     // L0[0] -> pointer to L1 (table descriptor: low bits = 0b11)
-    // entry = (L1_base & ~0xfff) | 0b11
-    orr     x14, x11, #3
+    // entry = (L1_base & ~0xfff) | TABLE_ATTR
+    ldr     x15, =TABLE_ATTR
+    orr     x14, x11, x15
     str     x14, [x10]           // L0[0] = &L1 | table-bit(3)
 
     // L1[0] -> pointer to L2_0; L1[1] -> pointer to L2_1 (table descriptors)
-    mov     x15, #0x23
     orr     x14, x12, x15
     str     x14, [x11]           // L1[0] = &L2_0 | table-bit
-    orr     x14, x13, #3
+    orr     x14, x13, x15
     str     x14, [x11, #8]       // L1[1] = &L2_1 | table-bit
 
     //
@@ -119,7 +118,8 @@ _setup_pagetable:
     mov     x17, #PGTABLE_LVL3_IDX_PTE_SELFMAP    // x17 = 493
     lsl     x17, x17, #3                          // x17 *= 8 -> 3944
     add     x15, x10, x17                         // x15 = x10 + offset
-    orr     x16, x10, #3                          // table descriptor
+    ldr     x16, =TABLE_ATTR
+    orr     x16, x10, x16                         // table descriptor
     str     x16, [x15]                            // L0[493] = L0 | table-bit
 
 /*
@@ -205,6 +205,10 @@ system_off:
 // INDX  | b100    << 2  | Attribute index in MAIR_ELn，see MAIR_EL1_VALUE
 // ENTRY | b01     << 0  | Block entry
 
+.equ TABLE_ATTR, 0x403 // -----------------------------------------------------
+// AF    | b1      << 10 | Access Flag, used when the self-map reads a table as a page
+// ENTRY | b11     << 0  | Table entry
+
 .equ TWO_MB, 0x00200000
 .equ ONE_GB, 0x40000000
 .equ PGTABLE_LVL3_IDX_PTE_SELFMAP, 493
@@ -35,6 +35,7 @@ use svsm::cpu::sse::sse_init;
 use svsm::debug::gdbstub::svsm_gdbstub::{debug_break, gdbstub_start};
 // use svsm::debug::stacktrace::print_stack;
 use svsm::enable_shadow_stacks;
+use svsm::fdt::{console_base, init_device_tree, Fdt, DEVICE_TREE};
 use svsm::fs::{initialize_fs, opendir, populate_ram_fs};
 use svsm::hyperv::hyperv_setup;
 use svsm::igvm_params::IgvmParams;
@@ -52,20 +53,56 @@ use svsm::platform;
 use svsm::platform::{init_capabilities, init_platform_type, SvsmPlatformCell, SVSM_PLATFORM};
 use svsm::requests::request_loop_main;
 use svsm::sev::secrets_page_mut;
-use svsm::svsm_paging::{init_page_table, init_page_table_arm, invalidate_early_boot_memory};
+use svsm::svsm_paging::{init_page_table, invalidate_early_boot_memory};
+#[cfg(feature = "cca")]
+use svsm::svsm_paging::init_page_table_arm;
 use svsm::task::schedule_init;
 use svsm::task::{exec_user, start_kernel_task};
 use svsm::types::PAGE_SIZE;
 use svsm::utils::{immut_after_init::ImmutAfterInitCell, zero_mem_region, MemoryRegion};
 #[cfg(all(feature = "vtpm", not(test)))]
-use svsm::vtpm::vtpm_init;
+use alloc::vec::Vec;
+#[cfg(all(feature = "vtpm", not(test)))]
+use svsm::vtpm::{measure::FwImage, vtpm_init};
+#[cfg(all(
+    feature = "vtpm",
+    feature = "attest",
+    feature = "virtio-drivers",
+    not(test)
+))]
+use alloc::boxed::Box;
+#[cfg(all(
+    feature = "vtpm",
+    feature = "attest",
+    feature = "virtio-drivers",
+    not(test)
+))]
+use svsm::block::{api::BlockDriver, virtio_blk::VirtIOBlkDriver};
+#[cfg(all(
+    feature = "vtpm",
+    feature = "attest",
+    feature = "virtio-drivers",
+    not(test)
+))]
+use svsm::vtpm::{vtpm_attach_nv_storage, vtpm_unseal_nv_storage};
+#[cfg(all(
+    feature = "vtpm",
+    feature = "attest",
+    feature = "virtio-drivers",
+    not(test),
+    not(feature = "cca")
+))]
+use svsm::fw_cfg::FwCfg;
 
 use svsm::mm::validate::{init_valid_bitmap_ptr, migrate_valid_bitmap};
 
 use alloc::string::String;
 use release::COCONUT_VERSION;
 
-use svsm::svsm_arm64::cpu::gicv3::{gicv3_init, gicv3_enable_irq};
+#[cfg(feature = "cca")]
+use svsm::svsm_arm64::cpu::gicv3::gicv3_init;
+#[cfg(feature = "cca")]
+use svsm::svsm_arm64::cpu::smp::{smp_init, start_secondary_cpus};
 
 // use svsm::stage2::stage2_main;
 
@@ -73,11 +110,17 @@ use svsm::svsm_arm64::cpu::gicv3::{gicv3_init, gicv3_enable_irq};
 use kbs_types::Tee;
 
 #[cfg(feature = "cca")]
-use svsm::realm::rsi::rsi_cmd::{init_realm_config};
+use svsm::realm::mem::load_svsm_pgtable;
+#[cfg(feature = "cca")]
+use svsm::realm::perm::perm_init;
+#[cfg(feature = "cca")]
+use svsm::realm::rsi::rsi_cmd::{init_realm_config, rsi_abi_handshake};
 #[cfg(feature = "cca")]
 use svsm::svsm_arm64::cpu::gicv3::{init_mmio_gic};
 #[cfg(feature = "cca")]
 use svsm::console::{init_mmio_uart};
+#[cfg(feature = "cca")]
+use svsm::svsm_arm64::mm::mmu::init_translation_regs;
 
 extern "C" {
     static bsp_stack: u8;
@@ -87,6 +130,7 @@ extern "C" {
 extern "C" {
     static kernel_region_phys_start: u64;
     static kernel_region_phys_end: u64;
+    static kernel_text_end: u64;
 }
 
 /*
@@ -161,6 +205,72 @@ fn setup_svsm_early_allocator(heap_start: u64, heap_end: u64) {
     root_mem_init(pstart, vstart, nr_pages);
 }
 
+/// Returns the first virtio block device reserved for the SVSM, which holds
+/// the vTPM NV state. On CCA these are the devices with the SVSM-only
+/// compatible that no aux-plane transport overlaps. Elsewhere the devices
+/// from fw_cfg are not described to the guest.
+#[cfg(all(
+    feature = "vtpm",
+    feature = "attest",
+    feature = "virtio-drivers",
+    not(test)
+))]
+fn vtpm_nv_device() -> Option<Box<dyn BlockDriver + Send>> {
+    #[cfg(feature = "cca")]
+    let addresses = DEVICE_TREE
+        .svsm_virtio_mmio
+        .iter()
+        .filter(|region| !DEVICE_TREE.virtio_mmio.iter().any(|r| r.overlap(region)))
+        .map(|region| region.start())
+        .collect::<Vec<_>>();
+    #[cfg(not(feature = "cca"))]
+    let addresses = FwCfg::new(SVSM_PLATFORM.get_io_port())
+        .get_virtio_mmio_addresses()
+        .unwrap_or_default()
+        .into_iter()
+        .map(PhysAddr::from)
+        .collect::<Vec<_>>();
+
+    addresses.into_iter().find_map(|addr| {
+        let dev = VirtIOBlkDriver::new(addr).ok()?;
+        log::info!("vTPM: NV state stored on virtio-blk at {addr:#x}");
+        Some(Box::new(dev) as Box<dyn BlockDriver + Send>)
+    })
+}
+
+/// Returns the images prepared for the guest firmware by `prepare_fw()`,
+/// which are measured into the vTPM PCRs.
+#[cfg(all(feature = "vtpm", not(test)))]
+fn vtpm_fw_images(config: &SvsmConfig<'_>) -> Vec<FwImage> {
+    if !config.should_launch_fw() {
+        return Vec::new();
+    }
+    let images = config
+        .get_fw_regions(&new_kernel_region(&LAUNCH_INFO))
+        .into_iter()
+        .map(FwImage::Code);
+    // A realm has no CPUID page.
+    #[cfg(not(feature = "cca"))]
+    let images = images.chain(
+        config
+            .get_fw_metadata()
+            .and_then(|meta| meta.cpuid_page)
+            .map(|page| {
+                let region = MemoryRegion::new(page, PAGE_SIZE);
+                FwImage::Table(region, b"SEV-SNP CPUID table\0")
+            }),
+    );
+    images.collect()
+}
+
+/// Returns the base of the console UART described by the DTB at `fdt_addr`.
+fn fdt_console_base(fdt_addr: u64) -> PhysAddr {
+    // SAFETY: the loader passes the address of a valid DTB, and the SVSM runs
+    // identity mapped.
+    let fdt = unsafe { Fdt::from_addr(VirtAddr::from(fdt_addr)) }.expect("Invalid device tree");
+    console_base(&fdt).expect("No PL011 console in device tree")
+}
+
 #[no_mangle]
 extern "C" fn svsm_start(/* li: &KernelLaunchInfo, vb_addr: usize */fdt_addr: u64) -> ! {
     let kernel_start = unsafe { &kernel_region_phys_start as *const u64 as u64 };
@@ -183,9 +293,12 @@ extern "C" fn svsm_start(/* li: &KernelLaunchInfo, vb_addr: usize */fdt_addr: u6
         secrets_page: 0,
         stage2_igvm_params_phys_addr: 0,
         vtom: 0,
-        debug_serial_port: 0x0900_0000,
+        debug_serial_port: fdt_console_base(fdt_addr).into(),
         use_alternate_injection: false,
         suppress_svsm_interrupts: true,
+        #[cfg(feature = "cca")]
+        platform_type: SvsmPlatformType::Cca,
+        #[cfg(not(feature = "cca"))]
         platform_type: SvsmPlatformType::Native,
         kernel_region_virt_start: kernel_start,
         stage2_igvm_params_size: 0,
@@ -209,6 +322,9 @@ extern "C" fn svsm_start(/* li: &KernelLaunchInfo, vb_addr: usize */fdt_addr: u6
     // 初始化堆
     setup_svsm_early_allocator(STAGE2_HEAP_START.into(), STAGE2_HEAP_END.into());
 
+    // SAFETY: the DTB was already validated above and stays in place.
+    unsafe { init_device_tree(PhysAddr::from(fdt_addr)) }.expect("Failed to parse device tree");
+
     let kernel_base: PhysAddr = kernel_start.into();
     let kernel_size: usize = 4 * 1024 * 1024;
 
@@ -300,14 +416,26 @@ extern "C" fn svsm_start(/* li: &KernelLaunchInfo, vb_addr: usize */fdt_addr: u6
     paging_init(platform, false).expect("Failed to initialize paging");
     // let init_pgtable =
     //     init_page_table(&launch_info, &kernel_elf).expect("Could not initialize the page table");
-    // let init_pgtable =
-    //     init_page_table_arm(&launch_info).expect("Could not initialize the page table");
+    #[cfg(feature = "cca")]
+    let init_pgtable = {
+        let text_end = unsafe { &kernel_text_end as *const u64 as u64 };
+        let kernel_text =
+            MemoryRegion::from_addresses(PhysAddr::from(kernel_start), PhysAddr::from(text_end));
+        let mut pgtable = init_page_table_arm(&launch_info, kernel_text)
+            .expect("Could not initialize the page table");
+        init_mmio_gic(&mut pgtable).expect("Cannot map gic in unprotected IPA");
+        pgtable
+    };
     // SAFETY: we are initializing the state, including stack and registers
-    /*
+    #[cfg(feature = "cca")]
     unsafe {
-        init_pgtable.load();
+        init_translation_regs();
+        load_svsm_pgtable(init_pgtable).expect("SVSM page table already loaded");
     }
-    */
+    #[cfg(feature = "cca")]
+    gicv3_init().expect("Failed to initialize the GIC");
+    #[cfg(feature = "cca")]
+    smp_init(&DEVICE_TREE.cpus).expect("Failed to setup BSP per-cpu area");
 
     // SAFETY: this is the first CPU, so there can be no other dependencies
     // on multi-threaded access to the per-cpu areas.
@@ -406,7 +534,7 @@ pub extern "C" fn svsm_main(cpu_index: usize, fdt_addr: u64) {
         panic!("Launch VTOM does not match VTOM from IGVM parameters");
     }
 
-    let config = SvsmConfig::new(*SVSM_PLATFORM, igvm_params);
+    let config = SvsmConfig::new(*SVSM_PLATFORM, igvm_params).with_device_tree(&DEVICE_TREE);
 
     init_memory_map(&config, &LAUNCH_INFO).expect("Failed to init guest memory map");
 
@@ -418,6 +546,10 @@ pub extern "C" fn svsm_main(cpu_index: usize, fdt_addr: u64) {
     // let cpus = config.load_cpu_info().expect("Failed to load ACPI tables");
 
     // start_secondary_cpus(&**SVSM_PLATFORM, &cpus);
+    #[cfg(feature = "cca")]
+    start_secondary_cpus(&DEVICE_TREE.cpus).expect("Failed to start secondary CPUs");
+    #[cfg(feature = "cca")]
+    perm_init(&LAUNCH_INFO).expect("Failed to protect SVSM memory from aux planes");
 
     // Make ro_after_init section read-only
     // make_ro_after_init().expect("Failed to make ro_after_init region read-only");
@@ -429,17 +561,53 @@ pub extern "C" fn svsm_main(cpu_index: usize, fdt_addr: u64) {
         panic!("Failed to prepare guest FW: {e:#?}");
     }
 
-    #[cfg(feature = "attest")]
-    {
-        let mut proxy = AttestationDriver::try_from(Tee::Snp).unwrap();
-        let _data = proxy.attest().unwrap();
+    // The vTPM NV state is sealed with the secret released by attestation.
+    // The counter of the stored image is bound into the evidence, so that
+    // the attestation server can refuse to release the secret for an older
+    // image.
+    #[cfg(all(
+        feature = "vtpm",
+        feature = "attest",
+        feature = "virtio-drivers",
+        not(test)
+    ))]
+    let nv_counter = match vtpm_nv_device() {
+        Some(dev) => vtpm_attach_nv_storage(dev).expect("Failed to attach vTPM NV storage"),
+        None => {
+            log::warn!("vTPM: no storage device found, NV state will not persist");
+            0
+        }
+    };
 
-        // Nothing to do with data at the moment, simply print a success message.
+    #[cfg(feature = "attest")]
+    #[allow(unused_variables)]
+    let attest_secret = {
+        #[cfg(feature = "cca")]
+        let tee = Tee::Cca;
+        #[cfg(not(feature = "cca"))]
+        let tee = Tee::Snp;
+
+        let mut proxy = AttestationDriver::try_from(tee).unwrap();
+        #[cfg(all(
+            feature = "vtpm",
+            feature = "attest",
+            feature = "virtio-drivers",
+            not(test)
+        ))]
+        proxy.bind_nv_counter(nv_counter);
+        let secret = proxy.attest().unwrap();
         log::info!("attestation successful");
-    }
+        secret
+    };
 
     #[cfg(all(feature = "vtpm", not(test)))]
-    vtpm_init().expect("vTPM failed to initialize");
+    {
+        // The released secret seals the vTPM NV state, so it only persists
+        // across boots of an SVSM which passed attestation.
+        #[cfg(all(feature = "attest", feature = "virtio-drivers"))]
+        vtpm_unseal_nv_storage(&attest_secret).expect("Failed to unseal vTPM NV storage");
+        vtpm_init(&vtpm_fw_images(&config)).expect("vTPM failed to initialize");
+    }
 
     // virt_log_usage();
 
@@ -484,27 +652,20 @@ pub extern "C" fn svsm_main(cpu_index: usize, fdt_addr: u64) {
 pub extern "C" fn not_main(fdt_addr: u64) {
     #[cfg(feature = "cca")]
     {
+        // Refuse to run on an RMM implementing an incompatible RSI ABI
+        rsi_abi_handshake().expect("Incompatible RSI ABI version");
         // 第一步调用rsi_realm_config获取配置信息
         init_realm_config().expect("REALM_CONFIG already initialized");
         // 然后根据配置信息中的ipa_width来修改页表项
-        // init_mmio_gic().expect("Cannot map gic in unprotected IPA");
-        init_mmio_uart().expect("Cannot map uart in unprotected IPA");;
+        // SAFETY: the boot page table from start.s is in use and no other PE
+        // runs yet.
+        unsafe { init_mmio_uart(fdt_console_base(fdt_addr)) }
+            .expect("Cannot map uart in unprotected IPA");
     }
 
     // ...
 
     /*
-    unsafe {
-        // Initialize GICv3
-        gicv3_init();
-        
-        // Example: enable a plausible interrupt ID for testing.
-        // Choose an interrupt ID that QEMU generates (for virt machine,
-        // Example UART0 is typically at ID 33 in some setups; you may adjust).
-        // We enable ID 33 here as a demo; if no source asserts it, you won't see IRQ.
-        gicv3_enable_irq(33);
-    }
-
     // Initialize MMU
 
 
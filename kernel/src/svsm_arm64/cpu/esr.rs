@@ -0,0 +1,117 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Exception Syndrome Register (ESR_ELx) field definitions.
+//!
+//! The layout is shared between ESR_EL1, which the SVSM reads for its own
+//! exceptions, and ESR_EL2, which the RMM reports for aux-plane exits.
+
+pub const ESR_ELX_EC_UNKNOWN: u64 = 0x00;
+pub const ESR_ELX_EC_WFX: u64 = 0x01;
+pub const ESR_ELX_EC_FP_ASIMD: u64 = 0x07;
+pub const ESR_ELX_EC_ILL: u64 = 0x0e;
+pub const ESR_ELX_EC_SVC64: u64 = 0x15;
+pub const ESR_ELX_EC_HVC64: u64 = 0x16;
+pub const ESR_ELX_EC_SMC64: u64 = 0x17;
+pub const ESR_ELX_EC_SYS64: u64 = 0x18;
+pub const ESR_ELX_EC_IABT_LOW: u64 = 0x20;
+pub const ESR_ELX_EC_IABT_CUR: u64 = 0x21;
+pub const ESR_ELX_EC_PC_ALIGN: u64 = 0x22;
+pub const ESR_ELX_EC_DABT_LOW: u64 = 0x24;
+pub const ESR_ELX_EC_DABT_CUR: u64 = 0x25;
+pub const ESR_ELX_EC_SP_ALIGN: u64 = 0x26;
+pub const ESR_ELX_EC_SERROR: u64 = 0x2f;
+pub const ESR_ELX_EC_BRK64: u64 = 0x3c;
+
+pub const ESR_ELX_EC_SHIFT: u64 = 26;
+pub const ESR_ELX_EC_MASK: u64 = 0x3f << ESR_ELX_EC_SHIFT;
+pub const ESR_ELX_ISS_MASK: u64 = 0x01ff_ffff;
+
+/// Fault status code of an instruction or data abort.
+pub const ESR_ELX_ISS_FSC_MASK: u64 = 0x3f;
+/// Data abort caused by a write.
+pub const ESR_ELX_ISS_WNR: u64 = 1 << 6;
+/// FAR_ELx does not hold a valid address for an abort.
+pub const ESR_ELX_ISS_FNV: u64 = 1 << 10;
+
+/// Returns the exception class of `esr`.
+#[inline]
+pub const fn esr_ec(esr: u64) -> u64 {
+    (esr & ESR_ELX_EC_MASK) >> ESR_ELX_EC_SHIFT
+}
+
+/// Returns the instruction specific syndrome of `esr`.
+#[inline]
+pub const fn esr_iss(esr: u64) -> u64 {
+    esr & ESR_ELX_ISS_MASK
+}
+
+/// Returns the fault status code of the abort described by `esr`.
+#[inline]
+pub const fn esr_fsc(esr: u64) -> u64 {
+    esr & ESR_ELX_ISS_FSC_MASK
+}
+
+/// Returns whether `esr` describes an instruction or data abort.
+#[inline]
+pub const fn esr_is_abort(esr: u64) -> bool {
+    matches!(
+        esr_ec(esr),
+        ESR_ELX_EC_IABT_LOW | ESR_ELX_EC_IABT_CUR | ESR_ELX_EC_DABT_LOW | ESR_ELX_EC_DABT_CUR
+    )
+}
+
+/// Returns a human readable name for the exception class `ec`.
+pub fn esr_ec_name(ec: u64) -> &'static str {
+    match ec {
+        ESR_ELX_EC_UNKNOWN => "Unknown",
+        ESR_ELX_EC_WFX => "WFI/WFE",
+        ESR_ELX_EC_FP_ASIMD => "FP/SIMD access",
+        ESR_ELX_EC_ILL => "Illegal execution state",
+        ESR_ELX_EC_SVC64 => "SVC",
+        ESR_ELX_EC_HVC64 => "HVC",
+        ESR_ELX_EC_SMC64 => "SMC",
+        ESR_ELX_EC_SYS64 => "MSR/MRS",
+        ESR_ELX_EC_IABT_LOW => "Instruction abort (lower EL)",
+        ESR_ELX_EC_IABT_CUR => "Instruction abort",
+        ESR_ELX_EC_PC_ALIGN => "PC alignment",
+        ESR_ELX_EC_DABT_LOW => "Data abort (lower EL)",
+        ESR_ELX_EC_DABT_CUR => "Data abort",
+        ESR_ELX_EC_SP_ALIGN => "SP alignment",
+        ESR_ELX_EC_SERROR => "SError",
+        ESR_ELX_EC_BRK64 => "BRK",
+        _ => "Reserved",
+    }
+}
+
+/// Returns a human readable name for the abort fault status code `fsc`.
+pub fn esr_fsc_name(fsc: u64) -> &'static str {
+    match fsc {
+        0x00..=0x03 => "Address size fault",
+        0x04..=0x07 => "Translation fault",
+        0x09..=0x0b => "Access flag fault",
+        0x0d..=0x0f => "Permission fault",
+        0x10 => "Synchronous external abort",
+        0x21 => "Alignment fault",
+        0x30 => "TLB conflict abort",
+        _ => "Other fault",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_data_abort() {
+        // Data abort at the current EL, write, level 3 translation fault.
+        let esr = (ESR_ELX_EC_DABT_CUR << ESR_ELX_EC_SHIFT) | ESR_ELX_ISS_WNR | 0x07;
+        assert_eq!(esr_ec(esr), ESR_ELX_EC_DABT_CUR);
+        assert!(esr_is_abort(esr));
+        assert_eq!(esr_fsc(esr), 0x07);
+        assert_eq!(esr_fsc_name(esr_fsc(esr)), "Translation fault");
+        assert_eq!(esr_ec_name(esr_ec(esr)), "Data abort");
+
+        let svc = ESR_ELX_EC_SVC64 << ESR_ELX_EC_SHIFT;
+        assert!(!esr_is_abort(svc));
+    }
+}
@@ -1,30 +1,209 @@
-use core::arch::global_asm;
+// SPDX-License-Identifier: MIT OR Apache-2.0
 
-const EL1_SP0_SYNC: &'static str = "EL1_SP0_SYNC";
+//! EL1 exception handling.
+//!
+//! `exceptions.s` provides the 16-entry vector table installed in VBAR_EL1.
+//! Every entry saves the interrupted state as an [`ExceptionCtx`] on the
+//! stack and calls the Rust handler of the same name, which dispatches on
+//! the [`ExceptionSource`] and [`ExceptionKind`] of the vector.
+
+use super::esr::{
+    esr_ec, esr_ec_name, esr_fsc, esr_fsc_name, esr_is_abort, ESR_ELX_EC_DABT_CUR, ESR_ELX_ISS_FNV,
+};
+use super::gicv3::gicv3_handle_irq;
+use crate::address::VirtAddr;
+use crate::cpu::extable::search_exception_table;
+use core::arch::{asm, global_asm};
 
 global_asm!(include_str!("exceptions.s"));
 
+/// General purpose register which is set to `!0` when a fault is recovered
+/// through the exception table, like `%rcx` on x86.
+pub const EXTABLE_ERR_REG: usize = 2;
+
+/// Register state saved on exception entry.
 #[repr(C)]
 #[derive(Debug)]
 pub struct ExceptionCtx {
-    regs: [u64; 30],
-    elr_el1: u64,
-    spsr_el1: u64,
-    lr: u64,
+    /// x0 to x29
+    pub regs: [u64; 30],
+    pub elr_el1: u64,
+    pub spsr_el1: u64,
+    /// x30
+    pub lr: u64,
+    pub sp_el0: u64,
+}
+
+/// The exception level and stack an exception was taken from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExceptionSource {
+    /// EL1 running on SP_EL0.
+    CurrentSp0,
+    /// EL1 running on SP_EL1.
+    CurrentSpx,
+    /// EL0 in AArch64 state.
+    Lower64,
+    /// EL0 in AArch32 state.
+    Lower32,
 }
 
-// print exception data
-fn catch(ctx: &mut ExceptionCtx, name: &str) {
-    
-    log::info!("Exception: {}   ELR_EL1: 0x{:016x}", name, ctx.elr_el1);
+impl ExceptionSource {
+    fn is_current_el(self) -> bool {
+        matches!(self, Self::CurrentSp0 | Self::CurrentSpx)
+    }
+}
 
-    // enter endless loop
-    loop {
+/// The type of an exception.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExceptionKind {
+    Sync,
+    Irq,
+    Fiq,
+    SError,
+}
 
+/// Syndrome information of a synchronous exception.
+#[derive(Clone, Copy, Debug)]
+pub struct Syndrome {
+    pub esr: u64,
+    /// The faulting virtual address, for aborts which report a valid one.
+    pub far: Option<VirtAddr>,
+}
+
+impl Syndrome {
+    /// Reads ESR_EL1 and FAR_EL1 for the exception currently being handled.
+    pub fn read() -> Self {
+        let esr: u64;
+        let far: u64;
+        // SAFETY: reading the syndrome registers has no side effects.
+        unsafe {
+            asm!("mrs {}, esr_el1", out(reg) esr, options(nomem, nostack));
+            asm!("mrs {}, far_el1", out(reg) far, options(nomem, nostack));
+        }
+        let far_valid = esr_is_abort(esr) && esr & ESR_ELX_ISS_FNV == 0;
+        Self {
+            esr,
+            far: far_valid.then(|| VirtAddr::from(far)),
+        }
+    }
+
+    pub fn ec(&self) -> u64 {
+        esr_ec(self.esr)
     }
 }
 
-#[no_mangle]
-unsafe extern "C" fn el1_sp0_sync(ctx: &mut ExceptionCtx) {
-    catch(ctx, EL1_SP0_SYNC);
-}
\ No newline at end of file
+/// Resumes execution at the fixup address if the exception at the ELR of
+/// `ctx` is covered by the exception table.
+///
+/// # Returns
+///
+/// `true` if the exception was recovered, `false` otherwise.
+pub fn handle_exception_table(ctx: &mut ExceptionCtx) -> bool {
+    let pc = VirtAddr::from(ctx.elr_el1);
+    match search_exception_table(pc) {
+        Some(fixup) => {
+            ctx.regs[EXTABLE_ERR_REG] = !0;
+            ctx.elr_el1 = u64::from(fixup);
+            true
+        }
+        None => false,
+    }
+}
+
+fn handle_sync(ctx: &mut ExceptionCtx, source: ExceptionSource) {
+    let syndrome = Syndrome::read();
+
+    // Data aborts in the SVSM itself can be recovered when they hit one of
+    // the guarded memory accessors.
+    if source.is_current_el() && syndrome.ec() == ESR_ELX_EC_DABT_CUR && handle_exception_table(ctx)
+    {
+        return;
+    }
+
+    unhandled(ctx, source, ExceptionKind::Sync, &syndrome);
+}
+
+fn handle_irq(_ctx: &mut ExceptionCtx, _source: ExceptionSource) {
+    gicv3_handle_irq();
+}
+
+fn unhandled(
+    ctx: &ExceptionCtx,
+    source: ExceptionSource,
+    kind: ExceptionKind,
+    syndrome: &Syndrome,
+) -> ! {
+    let ec = syndrome.ec();
+    let fault = if esr_is_abort(syndrome.esr) {
+        esr_fsc_name(esr_fsc(syndrome.esr))
+    } else {
+        ""
+    };
+    log::error!(
+        "Unhandled {:?} exception from {:?}: {} {}",
+        kind,
+        source,
+        esr_ec_name(ec),
+        fault
+    );
+    log::error!(
+        "  ESR_EL1: {:#018x}  FAR_EL1: {:#018x}",
+        syndrome.esr,
+        syndrome.far.map_or(0, u64::from)
+    );
+    log::error!(
+        "  ELR_EL1: {:#018x}  SPSR_EL1: {:#018x}  LR: {:#018x}",
+        ctx.elr_el1,
+        ctx.spsr_el1,
+        ctx.lr
+    );
+    for (i, pair) in ctx.regs.chunks(2).enumerate() {
+        log::error!(
+            "  x{:<2}: {:#018x}  x{:<2}: {:#018x}",
+            2 * i,
+            pair[0],
+            2 * i + 1,
+            pair[1]
+        );
+    }
+    panic!("Unhandled {kind:?} exception at {:#018x}", ctx.elr_el1);
+}
+
+fn handle_exception(ctx: &mut ExceptionCtx, source: ExceptionSource, kind: ExceptionKind) {
+    match (source, kind) {
+        (ExceptionSource::Lower32, _) => {
+            unhandled(ctx, source, kind, &Syndrome::read());
+        }
+        (_, ExceptionKind::Sync) => handle_sync(ctx, source),
+        (_, ExceptionKind::Irq) => handle_irq(ctx, source),
+        (_, ExceptionKind::Fiq | ExceptionKind::SError) => {
+            unhandled(ctx, source, kind, &Syndrome::read());
+        }
+    }
+}
+
+macro_rules! exception_vector {
+    ($name:ident, $source:ident, $kind:ident) => {
+        #[no_mangle]
+        extern "C" fn $name(ctx: &mut ExceptionCtx) {
+            handle_exception(ctx, ExceptionSource::$source, ExceptionKind::$kind);
+        }
+    };
+}
+
+exception_vector!(el1_sp0_sync, CurrentSp0, Sync);
+exception_vector!(el1_sp0_irq, CurrentSp0, Irq);
+exception_vector!(el1_sp0_fiq, CurrentSp0, Fiq);
+exception_vector!(el1_sp0_serror, CurrentSp0, SError);
+exception_vector!(el1_spx_sync, CurrentSpx, Sync);
+exception_vector!(el1_spx_irq, CurrentSpx, Irq);
+exception_vector!(el1_spx_fiq, CurrentSpx, Fiq);
+exception_vector!(el1_spx_serror, CurrentSpx, SError);
+exception_vector!(el0_64_sync, Lower64, Sync);
+exception_vector!(el0_64_irq, Lower64, Irq);
+exception_vector!(el0_64_fiq, Lower64, Fiq);
+exception_vector!(el0_64_serror, Lower64, SError);
+exception_vector!(el0_32_sync, Lower32, Sync);
+exception_vector!(el0_32_irq, Lower32, Irq);
+exception_vector!(el0_32_fiq, Lower32, Fiq);
+exception_vector!(el0_32_serror, Lower32, SError);
@@ -1,8 +1,16 @@
-
 .section .text.exceptions
+
+// Layout of struct ExceptionCtx:
+//   x0-x29     at 0
+//   ELR_EL1    at 240
+//   SPSR_EL1   at 248
+//   x30        at 256
+//   SP_EL0     at 264
+.equ CONTEXT_SIZE, 272
+
 .macro EXCEPTION_VECTOR handler
 
-  sub sp, sp, #264
+  sub sp, sp, #CONTEXT_SIZE
 
   // store general purpose registers
   stp x0, x1, [sp, #16 * 0]
@@ -26,8 +34,9 @@
   mrs x1, spsr_el1
   stp x0, x1, [sp, #16 * 15]
 
-  // store link register which is x30
-  str x30, [sp, #16 * 16]
+  // store link register which is x30 and the EL0 stack pointer
+  mrs x0, sp_el0
+  stp x30, x0, [sp, #16 * 16]
   mov x0, sp
 
   // call exception handler
@@ -38,8 +47,9 @@
 .endm
 
 .exit_exception:
-  // restore link register
-  ldr x30, [sp, #16 * 16]
+  // restore link register and the EL0 stack pointer
+  ldp x30, x0, [sp, #16 * 16]
+  msr sp_el0, x0
 
   // restore exception link register and saved processor state register
   ldp x0, x1, [sp, #16 * 15]
@@ -64,62 +74,65 @@
   ldp x0, x1, [sp, #16 * 0]
 
   // restore stack pointer
-  add sp, sp, #264
+  add sp, sp, #CONTEXT_SIZE
   eret
 
 .section .text.exceptions_vector_table
 // Export a symbol for the Rust code to use.
 .globl exception_vector_table
+.balign 2048
 exception_vector_table:
 
+// Current EL with SP_EL0
 .org 0x0000
-    EXCEPTION_VECTOR el1_sp0_sync // 
+    EXCEPTION_VECTOR el1_sp0_sync
 
 .org 0x0080
-    EXCEPTION_VECTOR el1_sp0_sync // el1_sp0_irq
+    EXCEPTION_VECTOR el1_sp0_irq
 
 .org 0x0100
-    EXCEPTION_VECTOR el1_sp0_sync // el1_sp0_fiq
+    EXCEPTION_VECTOR el1_sp0_fiq
 
 .org 0x0180
-    EXCEPTION_VECTOR el1_sp0_sync // el1_sp0_error
+    EXCEPTION_VECTOR el1_sp0_serror
 
+// Current EL with SP_ELx
 .org 0x0200
-    EXCEPTION_VECTOR el1_sp0_sync // el1_sync
+    EXCEPTION_VECTOR el1_spx_sync
 
 .org 0x0280
-    EXCEPTION_VECTOR el1_sp0_sync // el1_irq
+    EXCEPTION_VECTOR el1_spx_irq
 
 .org 0x0300
-    EXCEPTION_VECTOR el1_sp0_sync // el1_fiq
+    EXCEPTION_VECTOR el1_spx_fiq
 
 .org 0x0380
-    EXCEPTION_VECTOR el1_sp0_sync // el1_error
+    EXCEPTION_VECTOR el1_spx_serror
 
+// Lower EL using AArch64
 .org 0x0400
-    EXCEPTION_VECTOR el1_sp0_sync // el0_sync
+    EXCEPTION_VECTOR el0_64_sync
 
 .org 0x0480
-    EXCEPTION_VECTOR el1_sp0_sync // el0_irq
+    EXCEPTION_VECTOR el0_64_irq
 
 .org 0x0500
-    EXCEPTION_VECTOR el1_sp0_sync // el0_fiq
+    EXCEPTION_VECTOR el0_64_fiq
 
 .org 0x0580
-    EXCEPTION_VECTOR el1_sp0_sync // el0_error
+    EXCEPTION_VECTOR el0_64_serror
 
+// Lower EL using AArch32
 .org 0x0600
-    EXCEPTION_VECTOR el1_sp0_sync // el0_32_sync
+    EXCEPTION_VECTOR el0_32_sync
 
 .org 0x0680
-    EXCEPTION_VECTOR el1_sp0_sync // el0_32_irq
+    EXCEPTION_VECTOR el0_32_irq
 
 .org 0x0700
-    EXCEPTION_VECTOR el1_sp0_sync // el0_32_fiq
+    EXCEPTION_VECTOR el0_32_fiq
 
 .org 0x0780
-    EXCEPTION_VECTOR el1_sp0_sync // el0_32_error
+    EXCEPTION_VECTOR el0_32_serror
 
 .org 0x0800
-
-.equ CONTEXT_SIZE, 264
\ No newline at end of file
@@ -1,73 +1,517 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
 
+//! GICv3 interrupt controller driver.
+//!
+//! SPIs are configured in the distributor and routed to a PE by affinity.
+//! SGIs and PPIs are banked per PE and configured in the PE's redistributor
+//! frame, which is located by walking the redistributor region from the
+//! device tree. Interrupts are acknowledged and completed through the ICC
+//! system register interface and dispatched to the [`IrqHandler`] registered
+//! for their INTID.
+
+extern crate alloc;
+
+use crate::address::Address;
+use crate::error::{GicError, SvsmError};
+use crate::fdt::DEVICE_TREE;
+use crate::locking::RWLockIrqSafe;
+#[cfg(feature = "cca")]
+use crate::mm::pagetable::PageTable;
+#[cfg(feature = "cca")]
+use crate::realm::mem::map_mmio_in;
+use alloc::collections::btree_map::BTreeMap;
 use core::arch::asm;
+use core::ptr;
 
-use crate::error::SvsmError;
-// use core::arch::global_asm;
+/// Number of software generated interrupts.
+pub const GIC_NUM_SGIS: u32 = 16;
+/// First private peripheral interrupt.
+pub const GIC_PPI_BASE: u32 = 16;
+/// First shared peripheral interrupt.
+pub const GIC_SPI_BASE: u32 = 32;
+/// INTIDs from here on are special and never delivered to a handler.
+pub const GIC_SPECIAL_INTID_BASE: u32 = 1020;
+/// INTID returned by ICC_IAR1_EL1 when no interrupt is pending.
+pub const GIC_INTID_SPURIOUS: u32 = 1023;
 
-/// These base addresses match QEMU 'virt' defaults for GICv3.
-/// If your platform differs, replace with values from its DTB.
-const GICD_BASE: usize = 0x0800_0000;
-const GICR_BASE: usize = 0x080A_0000; // base of redistributor for CPU0
+/// Priority assigned to interrupts by default. Lower values are more
+/// urgent.
+pub const GIC_PRIORITY_DEFAULT: u8 = 0xa0;
 
 // GICD offsets
 const GICD_CTLR: usize = 0x0000;
-const GICD_ISENABLER: usize = 0x0100;
+const GICD_TYPER: usize = 0x0004;
+const GICD_IROUTER: usize = 0x6000;
+
+// Offsets shared between the distributor and the SGI frame of a
+// redistributor
+const GICX_IGROUPR: usize = 0x0080;
+const GICX_ISENABLER: usize = 0x0100;
+const GICX_ICENABLER: usize = 0x0180;
+const GICX_ICPENDR: usize = 0x0280;
+const GICX_IPRIORITYR: usize = 0x0400;
+const GICX_ICFGR: usize = 0x0c00;
+
+const GICD_CTLR_ENABLE_GRP1: u32 = 1 << 1;
+const GICD_CTLR_ARE: u32 = 1 << 4;
+const GICD_CTLR_RWP: u32 = 1 << 31;
+const GICD_TYPER_IT_LINES_MASK: u32 = 0x1f;
 
 // GICR offsets (within redistributor)
+const GICR_CTLR: usize = 0x0000;
+const GICR_TYPER: usize = 0x0008;
 const GICR_WAKER: usize = 0x0014;
+/// Offset of the SGI and PPI frame from the start of a redistributor.
+const GICR_SGI_OFFSET: usize = 0x1_0000;
+
+const GICR_CTLR_RWP: u32 = 1 << 3;
+const GICR_TYPER_VLPIS: u64 = 1 << 1;
+const GICR_TYPER_LAST: u64 = 1 << 4;
+const GICR_TYPER_AFF_SHIFT: u64 = 32;
+const GICR_WAKER_PROCESSOR_SLEEP: u32 = 1 << 1;
+const GICR_WAKER_CHILDREN_ASLEEP: u32 = 1 << 2;
+
+/// Size of a redistributor with RD and SGI frames.
+const GICR_FRAME_SIZE: usize = 0x2_0000;
+/// Size of a redistributor which additionally has VLPI frames.
+const GICR_FRAME_SIZE_VLPI: usize = 0x4_0000;
+
+// ICC_SGI1R_EL1 fields
+const ICC_SGI1R_AFF1_SHIFT: u64 = 16;
+const ICC_SGI1R_INTID_SHIFT: u64 = 24;
+const ICC_SGI1R_AFF2_SHIFT: u64 = 32;
+const ICC_SGI1R_IRM: u64 = 1 << 40;
+const ICC_SGI1R_RS_SHIFT: u64 = 44;
+const ICC_SGI1R_AFF3_SHIFT: u64 = 48;
+
+const ICC_SRE_SRE: u64 = 1 << 0;
+
+/// Affinity fields (Aff3, Aff2, Aff1 and Aff0) of MPIDR_EL1.
+pub const MPIDR_AFF_MASK: u64 = 0xff_00ff_ffff;
+
+/// A handler for an interrupt delivered through the GIC.
+pub trait IrqHandler: Sync {
+    /// Called in IRQ context when interrupt `intid` was acknowledged. The
+    /// interrupt is completed when the handler returns.
+    fn handle_irq(&self, intid: u32);
+}
+
+/// Handlers indexed by INTID.
+static IRQ_HANDLERS: RWLockIrqSafe<BTreeMap<u32, &'static dyn IrqHandler>> =
+    RWLockIrqSafe::new(BTreeMap::new());
+
+/// The PEs an SGI is sent to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SgiTarget {
+    /// The PE with the given MPIDR.
+    Cpu(u64),
+    /// All PEs except the sending one.
+    AllButSelf,
+}
 
 fn mmio_write32(addr: usize, v: u32) {
-    unsafe{core::ptr::write_volatile(addr as *mut u32, v);}
+    // SAFETY: `addr` lies in a GIC register frame from the device tree,
+    // which is mapped as device memory.
+    unsafe { ptr::write_volatile(addr as *mut u32, v) }
 }
 
 fn mmio_read32(addr: usize) -> u32 {
-    unsafe{core::ptr::read_volatile(addr as *const u32)}
+    // SAFETY: see mmio_write32().
+    unsafe { ptr::read_volatile(addr as *const u32) }
 }
 
-/// Initialize minimal GICv3 for CPU0
-pub fn gicv3_init() {
-    // 1) Enable System Register Access (ICC_SRE_EL1.SRE = 1)
-    let mut sre: u64;
-    unsafe{
-        asm!("mrs {0}, ICC_SRE_EL1", out(reg) sre);
-        sre |= 1;
-        asm!("msr ICC_SRE_EL1, {0}", in(reg) sre);
-        asm!("isb");
+fn mmio_write64(addr: usize, v: u64) {
+    // SAFETY: see mmio_write32().
+    unsafe { ptr::write_volatile(addr as *mut u64, v) }
+}
 
-        // 2) Wake up Redistributor (clear ProcessorSleep)
-        let waker = GICR_BASE + GICR_WAKER;
-        let mut w = mmio_read32(waker);
-        w &= !(1 << 1); // clear ProcessorSleep
-        mmio_write32(waker, w);
-        // wait for ChildrenAsleep == 0
-        while (mmio_read32(waker) & (1 << 2)) != 0 {}
+fn mmio_read64(addr: usize) -> u64 {
+    // SAFETY: see mmio_write32().
+    unsafe { ptr::read_volatile(addr as *const u64) }
+}
 
-        // 3) Enable Distributor (EnableGrp1, bit0)
-        mmio_write32(GICD_BASE + GICD_CTLR, 1);
+fn mmio_write8(addr: usize, v: u8) {
+    // SAFETY: see mmio_write32().
+    unsafe { ptr::write_volatile(addr as *mut u8, v) }
+}
+
+/// Returns the distributor base from the device tree.
+fn gicd_base() -> usize {
+    DEVICE_TREE
+        .gicd
+        .expect("No GICv3 distributor in device tree")
+        .start()
+        .bits()
+}
 
-        // 4) Enable Group1 interrupts at CPU interface
-        asm!("msr ICC_IGRPEN1_EL1, {0}", in(reg) 1u64);
-        asm!("isb");
+/// Returns the MPIDR_EL1 of the current PE.
+pub fn current_mpidr() -> u64 {
+    let mpidr: u64;
+    // SAFETY: reading MPIDR_EL1 has no side effects.
+    unsafe {
+        asm!("mrs {}, mpidr_el1", out(reg) mpidr, options(nomem, nostack));
+    }
+    mpidr & MPIDR_AFF_MASK
+}
 
-        // 5) Set priority mask to allow all priorities (0xff)
-        asm!("msr ICC_PMR_EL1, {0}", in(reg) 0xffu64);
-        asm!("isb");
+/// Packs the affinity fields of `mpidr` into the Aff3.Aff2.Aff1.Aff0 layout
+/// used by GICR_TYPER.
+const fn mpidr_to_affinity(mpidr: u64) -> u64 {
+    ((mpidr >> 8) & 0xff00_0000) | (mpidr & 0x00ff_ffff)
+}
 
-        // Clear Binary Point register (no preemption priority)
-        asm!("msr ICC_BPR1_EL1, {0}", in(reg) 0u64);
+/// Walks redistributor frames starting at `start` until `end` and returns the
+/// one whose GICR_TYPER, as returned by `read_typer`, matches `mpidr`.
+fn walk_redistributors(
+    start: usize,
+    end: usize,
+    mpidr: u64,
+    read_typer: impl Fn(usize) -> u64,
+) -> Option<usize> {
+    let affinity = mpidr_to_affinity(mpidr);
+    let mut frame = start;
+    while frame < end {
+        let typer = read_typer(frame + GICR_TYPER);
+        if typer >> GICR_TYPER_AFF_SHIFT == affinity {
+            return Some(frame);
+        }
+        if typer & GICR_TYPER_LAST != 0 {
+            break;
+        }
+        frame += if typer & GICR_TYPER_VLPIS != 0 {
+            GICR_FRAME_SIZE_VLPI
+        } else {
+            GICR_FRAME_SIZE
+        };
     }
+    None
+}
 
-    // Done
+/// Returns the base of the redistributor of the PE with MPIDR `mpidr`.
+pub fn find_redistributor(mpidr: u64) -> Result<usize, GicError> {
+    let region = DEVICE_TREE.gicr.ok_or(GicError::NoRedistributor)?;
+    walk_redistributors(
+        region.start().bits(),
+        region.end().bits(),
+        mpidr,
+        mmio_read64,
+    )
+    .ok_or(GicError::NoRedistributor)
 }
 
-/// Enable a global interrupt ID (SPI/PPI) in distributor
-pub fn gicv3_enable_irq(irq: u32) {
-    let reg = GICD_BASE + GICD_ISENABLER + ((irq as usize / 32) * 4);
-    let bit = 1u32 << (irq % 32);
-    mmio_write32(reg, bit);
+/// Returns the base of the register frame holding the configuration of
+/// `intid`: the SGI frame of the current PE's redistributor for SGIs and
+/// PPIs, the distributor otherwise.
+fn config_base(intid: u32) -> usize {
+    if intid < GIC_SPI_BASE {
+        find_redistributor(current_mpidr()).expect("No redistributor for this PE") + GICR_SGI_OFFSET
+    } else {
+        gicd_base()
+    }
 }
 
-#[cfg(feature = "cca")]
-pub fn init_mmio_gic() -> Result<(), SvsmError> {
+fn check_intid(intid: u32) -> Result<(), GicError> {
+    if intid < GIC_SPECIAL_INTID_BASE {
+        Ok(())
+    } else {
+        Err(GicError::InvalidIntId)
+    }
+}
+
+/// Returns the offset of the 32-bit register containing the bit for
+/// `intid` and the mask of that bit.
+const fn bit_reg(intid: u32) -> (usize, u32) {
+    ((intid as usize / 32) * 4, 1 << (intid % 32))
+}
+
+fn wait_for_rwp(reg: usize, rwp: u32) {
+    while mmio_read32(reg) & rwp != 0 {
+        core::hint::spin_loop();
+    }
+}
+
+/// Enables interrupt `intid`.
+pub fn gicv3_enable_irq(intid: u32) {
+    let (off, bit) = bit_reg(intid);
+    mmio_write32(config_base(intid) + GICX_ISENABLER + off, bit);
+}
+
+/// Disables interrupt `intid`.
+pub fn gicv3_disable_irq(intid: u32) {
+    let (off, bit) = bit_reg(intid);
+    mmio_write32(config_base(intid) + GICX_ICENABLER + off, bit);
+}
+
+/// Sets the priority of interrupt `intid`.
+pub fn gicv3_set_priority(intid: u32, priority: u8) {
+    mmio_write8(
+        config_base(intid) + GICX_IPRIORITYR + intid as usize,
+        priority,
+    );
+}
+
+/// Assigns interrupt `intid` to group 1 when `group1` is set, to group 0
+/// otherwise.
+pub fn gicv3_set_group(intid: u32, group1: bool) {
+    let (off, bit) = bit_reg(intid);
+    let reg = config_base(intid) + GICX_IGROUPR + off;
+    let val = mmio_read32(reg);
+    mmio_write32(reg, if group1 { val | bit } else { val & !bit });
+}
+
+/// Configures interrupt `intid` as edge-triggered when `edge` is set, as
+/// level-sensitive otherwise. SGIs are always edge-triggered.
+pub fn gicv3_set_trigger(intid: u32, edge: bool) {
+    if intid < GIC_NUM_SGIS {
+        return;
+    }
+    let reg = config_base(intid) + GICX_ICFGR + (intid as usize / 16) * 4;
+    let bit = 2u32 << ((intid % 16) * 2);
+    let val = mmio_read32(reg);
+    mmio_write32(reg, if edge { val | bit } else { val & !bit });
+}
+
+/// Routes SPI `intid` to the PE with MPIDR `mpidr`.
+pub fn gicv3_route_spi(intid: u32, mpidr: u64) -> Result<(), GicError> {
+    if !(GIC_SPI_BASE..GIC_SPECIAL_INTID_BASE).contains(&intid) {
+        return Err(GicError::InvalidIntId);
+    }
+    mmio_write64(
+        gicd_base() + GICD_IROUTER + intid as usize * 8,
+        mpidr & MPIDR_AFF_MASK,
+    );
+    Ok(())
+}
+
+/// Configures interrupt `intid` as a group 1 interrupt with `priority`,
+/// routes it to the current PE if it is an SPI, and enables it.
+pub fn gicv3_configure_irq(intid: u32, priority: u8) -> Result<(), GicError> {
+    check_intid(intid)?;
+    gicv3_set_group(intid, true);
+    gicv3_set_priority(intid, priority);
+    if intid >= GIC_SPI_BASE {
+        gicv3_route_spi(intid, current_mpidr())?;
+    }
+    gicv3_enable_irq(intid);
     Ok(())
 }
+
+/// Returns the number of INTIDs supported by the distributor.
+fn gicd_num_intids() -> u32 {
+    let lines = mmio_read32(gicd_base() + GICD_TYPER) & GICD_TYPER_IT_LINES_MASK;
+    (32 * (lines + 1)).min(GIC_SPECIAL_INTID_BASE)
+}
+
+/// Initializes the distributor: all SPIs are disabled, put into group 1
+/// with the default priority and routed to the current PE.
+fn gicv3_init_distributor() {
+    let gicd = gicd_base();
+    mmio_write32(gicd + GICD_CTLR, 0);
+    wait_for_rwp(gicd + GICD_CTLR, GICD_CTLR_RWP);
+
+    let num_intids = gicd_num_intids();
+    for intid in (GIC_SPI_BASE..num_intids).step_by(32) {
+        let off = (intid as usize / 32) * 4;
+        mmio_write32(gicd + GICX_ICENABLER + off, !0);
+        mmio_write32(gicd + GICX_ICPENDR + off, !0);
+        mmio_write32(gicd + GICX_IGROUPR + off, !0);
+    }
+    wait_for_rwp(gicd + GICD_CTLR, GICD_CTLR_RWP);
+
+    let mpidr = current_mpidr();
+    for intid in GIC_SPI_BASE..num_intids {
+        mmio_write8(
+            gicd + GICX_IPRIORITYR + intid as usize,
+            GIC_PRIORITY_DEFAULT,
+        );
+        mmio_write64(gicd + GICD_IROUTER + intid as usize * 8, mpidr);
+    }
+
+    mmio_write32(gicd + GICD_CTLR, GICD_CTLR_ARE | GICD_CTLR_ENABLE_GRP1);
+    wait_for_rwp(gicd + GICD_CTLR, GICD_CTLR_RWP);
+}
+
+/// Wakes up the redistributor of the current PE and puts its SGIs and PPIs
+/// into group 1 with the default priority. All PPIs are disabled, all SGIs
+/// are enabled.
+fn gicv3_init_redistributor() -> Result<(), GicError> {
+    let gicr = find_redistributor(current_mpidr())?;
+
+    let waker = gicr + GICR_WAKER;
+    mmio_write32(waker, mmio_read32(waker) & !GICR_WAKER_PROCESSOR_SLEEP);
+    while mmio_read32(waker) & GICR_WAKER_CHILDREN_ASLEEP != 0 {
+        core::hint::spin_loop();
+    }
+
+    let sgi = gicr + GICR_SGI_OFFSET;
+    mmio_write32(sgi + GICX_ICENABLER, !0);
+    wait_for_rwp(gicr + GICR_CTLR, GICR_CTLR_RWP);
+    mmio_write32(sgi + GICX_IGROUPR, !0);
+    for intid in 0..GIC_SPI_BASE {
+        mmio_write8(sgi + GICX_IPRIORITYR + intid as usize, GIC_PRIORITY_DEFAULT);
+    }
+    mmio_write32(sgi + GICX_ISENABLER, (1 << GIC_NUM_SGIS) - 1);
+
+    Ok(())
+}
+
+/// Enables the system register interface of the current PE and unmasks
+/// group 1 interrupts of all priorities.
+fn gicv3_init_cpu_interface() {
+    // SAFETY: configuring the GIC CPU interface has no memory safety
+    // implications.
+    unsafe {
+        let mut sre: u64;
+        asm!("mrs {}, ICC_SRE_EL1", out(reg) sre, options(nomem, nostack));
+        sre |= ICC_SRE_SRE;
+        asm!("msr ICC_SRE_EL1, {}", "isb", in(reg) sre, options(nomem, nostack));
+
+        asm!("msr ICC_PMR_EL1, {}", in(reg) 0xffu64, options(nomem, nostack));
+        asm!("msr ICC_BPR1_EL1, {}", in(reg) 0u64, options(nomem, nostack));
+        asm!("msr ICC_IGRPEN1_EL1, {}", "isb", in(reg) 1u64, options(nomem, nostack));
+    }
+}
+
+/// Initializes the GIC on an application processor: its redistributor and
+/// its CPU interface.
+pub fn gicv3_init_cpu() -> Result<(), SvsmError> {
+    gicv3_init_redistributor()?;
+    gicv3_init_cpu_interface();
+    Ok(())
+}
+
+/// Initializes the GIC on the boot processor: the distributor, which is
+/// shared by all PEs, and the redistributor and CPU interface of the boot
+/// processor.
+pub fn gicv3_init() -> Result<(), SvsmError> {
+    gicv3_init_distributor();
+    gicv3_init_cpu()
+}
+
+/// Registers `handler` for interrupt `intid`.
+pub fn register_irq_handler(intid: u32, handler: &'static dyn IrqHandler) -> Result<(), SvsmError> {
+    check_intid(intid)?;
+    let mut handlers = IRQ_HANDLERS.lock_write();
+    if handlers.contains_key(&intid) {
+        return Err(GicError::AlreadyRegistered.into());
+    }
+    handlers.insert(intid, handler);
+    Ok(())
+}
+
+/// Removes the handler registered for interrupt `intid`.
+pub fn unregister_irq_handler(intid: u32) {
+    IRQ_HANDLERS.lock_write().remove(&intid);
+}
+
+fn read_iar1() -> u32 {
+    let intid: u64;
+    // SAFETY: acknowledging an interrupt has no memory safety implications.
+    unsafe {
+        asm!("mrs {}, ICC_IAR1_EL1", out(reg) intid, options(nomem, nostack));
+    }
+    intid as u32
+}
+
+fn write_eoir1(intid: u32) {
+    // SAFETY: completing an interrupt has no memory safety implications.
+    unsafe {
+        asm!("msr ICC_EOIR1_EL1, {}", in(reg) u64::from(intid), options(nomem, nostack));
+    }
+}
+
+/// Acknowledges all pending group 1 interrupts, dispatches each to its
+/// registered [`IrqHandler`] and completes it.
+pub fn gicv3_handle_irq() {
+    loop {
+        let intid = read_iar1();
+        if intid >= GIC_SPECIAL_INTID_BASE {
+            break;
+        }
+
+        let handler = IRQ_HANDLERS.lock_read().get(&intid).copied();
+        match handler {
+            Some(handler) => handler.handle_irq(intid),
+            None => log::warn!("Spurious interrupt {intid} without handler"),
+        }
+
+        write_eoir1(intid);
+    }
+}
+
+/// Returns the ICC_SGI1R_EL1 value which sends SGI `sgi` to `target`.
+fn sgi1r_value(sgi: u32, target: SgiTarget) -> u64 {
+    let intid = u64::from(sgi) << ICC_SGI1R_INTID_SHIFT;
+    match target {
+        SgiTarget::AllButSelf => intid | ICC_SGI1R_IRM,
+        SgiTarget::Cpu(mpidr) => {
+            let aff0 = mpidr & 0xff;
+            let aff1 = (mpidr >> 8) & 0xff;
+            let aff2 = (mpidr >> 16) & 0xff;
+            let aff3 = (mpidr >> 32) & 0xff;
+            intid
+                | (1 << (aff0 % 16))
+                | ((aff0 / 16) << ICC_SGI1R_RS_SHIFT)
+                | (aff1 << ICC_SGI1R_AFF1_SHIFT)
+                | (aff2 << ICC_SGI1R_AFF2_SHIFT)
+                | (aff3 << ICC_SGI1R_AFF3_SHIFT)
+        }
+    }
+}
+
+/// Sends the group 1 SGI `sgi` to `target`.
+pub fn gicv3_send_sgi(sgi: u32, target: SgiTarget) -> Result<(), GicError> {
+    if sgi >= GIC_NUM_SGIS {
+        return Err(GicError::InvalidIntId);
+    }
+    let val = sgi1r_value(sgi, target);
+    // SAFETY: sending an SGI has no memory safety implications.
+    unsafe {
+        asm!("dsb ishst", "msr ICC_SGI1R_EL1, {}", "isb", in(reg) val, options(nostack));
+    }
+    Ok(())
+}
+
+/// Maps the distributor and redistributor registers into `pgtable` through
+/// their unprotected alias, so that accesses are emulated by the host.
+#[cfg(feature = "cca")]
+pub fn init_mmio_gic(pgtable: &mut PageTable) -> Result<(), SvsmError> {
+    let gicd = DEVICE_TREE.gicd.ok_or(SvsmError::PlatformInit)?;
+    let gicr = DEVICE_TREE.gicr.ok_or(SvsmError::PlatformInit)?;
+    map_mmio_in(pgtable, gicd)?;
+    map_mmio_in(pgtable, gicr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redistributor_walk() {
+        // Three PEs, the second of which has VLPI frames.
+        let typer = |addr: usize| -> u64 {
+            match addr - GICR_TYPER {
+                0x0 => 0,
+                0x2_0000 => (1 << GICR_TYPER_AFF_SHIFT) | GICR_TYPER_VLPIS,
+                0x6_0000 => (0x0100_0000 << GICR_TYPER_AFF_SHIFT) | GICR_TYPER_LAST,
+                a => panic!("Read of GICR_TYPER at {a:#x}"),
+            }
+        };
+        let end = 0x10_0000;
+        assert_eq!(walk_redistributors(0, end, 0, typer), Some(0));
+        assert_eq!(walk_redistributors(0, end, 1, typer), Some(0x2_0000));
+        assert_eq!(walk_redistributors(0, end, 1 << 32, typer), Some(0x6_0000));
+        assert_eq!(walk_redistributors(0, end, 2, typer), None);
+    }
+
+    #[test]
+    fn sgi_encoding() {
+        assert_eq!(sgi1r_value(1, SgiTarget::AllButSelf), (1 << 24) | (1 << 40));
+        assert_eq!(sgi1r_value(3, SgiTarget::Cpu(0)), (3 << 24) | 1);
+        let mpidr = (2 << 32) | (5 << 16) | (4 << 8) | 17;
+        assert_eq!(
+            sgi1r_value(0, SgiTarget::Cpu(mpidr)),
+            (2 << 48) | (5 << 32) | (1 << 44) | (4 << 16) | (1 << 1)
+        );
+    }
+}
@@ -1,2 +1,7 @@
+pub mod esr;
 pub mod gicv3;
-pub mod exceptions;
\ No newline at end of file
+pub mod exceptions;
+pub mod percpu;
+#[cfg(feature = "cca")]
+pub mod smp;
+pub mod timer;
@@ -0,0 +1,151 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Per-CPU areas of the arm64 SVSM.
+//!
+//! The x86 per-CPU area lives at a fixed virtual address and carries the
+//! GDT, TSS and APIC state of a CPU, none of which exists on arm64. Here
+//! every PE owns a page-sized [`PerCpu`] whose address is kept in
+//! TPIDR_EL1. All areas are also registered in a global table, so that
+//! other PEs can look them up by MPIDR and queue work on them.
+
+extern crate alloc;
+
+use super::gicv3::MPIDR_AFF_MASK;
+use crate::error::SvsmError;
+use crate::locking::{RWLock, SpinLock};
+use crate::mm::PageBox;
+use alloc::collections::vec_deque::VecDeque;
+use alloc::vec::Vec;
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Work queued on a PE with [`PerCpu::push_call()`].
+pub type CpuCall = fn();
+
+/// State of a single PE.
+#[derive(Debug)]
+pub struct PerCpu {
+    cpu_index: usize,
+    mpidr: u64,
+    online: AtomicBool,
+    calls: SpinLock<VecDeque<CpuCall>>,
+}
+
+impl PerCpu {
+    const fn new(cpu_index: usize, mpidr: u64) -> Self {
+        Self {
+            cpu_index,
+            mpidr,
+            online: AtomicBool::new(false),
+            calls: SpinLock::new(VecDeque::new()),
+        }
+    }
+
+    /// Returns the index of the PE in the `/cpus` node of the device tree.
+    pub fn cpu_index(&self) -> usize {
+        self.cpu_index
+    }
+
+    /// Returns the affinity fields of the MPIDR of the PE.
+    pub fn mpidr(&self) -> u64 {
+        self.mpidr
+    }
+
+    /// Returns whether the PE finished its initialization and accepts work.
+    pub fn is_online(&self) -> bool {
+        self.online.load(Ordering::Acquire)
+    }
+
+    /// Marks the PE as ready to accept work.
+    pub fn set_online(&self) {
+        self.online.store(true, Ordering::Release);
+    }
+
+    /// Queues `call` to be run by the PE. The caller is responsible for
+    /// waking the PE up.
+    pub fn push_call(&self, call: CpuCall) {
+        self.calls.lock().push_back(call);
+    }
+
+    /// Takes the oldest call queued on the PE.
+    pub fn take_call(&self) -> Option<CpuCall> {
+        self.calls.lock().pop_front()
+    }
+
+    /// Makes this area the one returned by [`this_cpu()`] on the current
+    /// PE.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called on the PE this area was created for.
+    pub unsafe fn load(&'static self) {
+        let addr = self as *const Self as u64;
+        // SAFETY: TPIDR_EL1 is reserved for the per-CPU area, and the
+        // caller guarantees that it is set on the right PE.
+        unsafe {
+            asm!("msr tpidr_el1, {}", in(reg) addr, options(nomem, nostack));
+        }
+    }
+}
+
+static PERCPU_AREAS: RWLock<Vec<&'static PerCpu>> = RWLock::new(Vec::new());
+
+/// Allocates the per-CPU area of the PE with index `cpu_index` and MPIDR
+/// `mpidr`, and registers it in the global table.
+pub fn percpu_create(cpu_index: usize, mpidr: u64) -> Result<&'static PerCpu, SvsmError> {
+    let area = PerCpu::new(cpu_index, mpidr & MPIDR_AFF_MASK);
+    let area = PageBox::leak(PageBox::try_new(area)?);
+    PERCPU_AREAS.lock_write().push(area);
+    Ok(area)
+}
+
+/// Returns the per-CPU area of the current PE.
+pub fn this_cpu() -> &'static PerCpu {
+    let addr: u64;
+    // SAFETY: reading TPIDR_EL1 has no side effects.
+    unsafe {
+        asm!("mrs {}, tpidr_el1", out(reg) addr, options(nomem, nostack));
+    }
+    assert_ne!(addr, 0, "No per-CPU area loaded");
+    // SAFETY: TPIDR_EL1 is only set by `PerCpu::load()`, which requires a
+    // leaked and thus 'static area.
+    unsafe { &*(addr as *const PerCpu) }
+}
+
+/// Returns the per-CPU area of the PE with MPIDR `mpidr`.
+pub fn cpu_by_mpidr(mpidr: u64) -> Option<&'static PerCpu> {
+    PERCPU_AREAS
+        .lock_read()
+        .iter()
+        .find(|cpu| cpu.mpidr == mpidr & MPIDR_AFF_MASK)
+        .copied()
+}
+
+/// Returns the per-CPU areas of all PEs which are online.
+pub fn online_cpus() -> Vec<&'static PerCpu> {
+    PERCPU_AREAS
+        .lock_read()
+        .iter()
+        .filter(|cpu| cpu.is_online())
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nop() {}
+
+    #[test]
+    fn call_queue() {
+        let cpu = PerCpu::new(1, 0x100);
+        assert!(!cpu.is_online());
+        assert!(cpu.take_call().is_none());
+        cpu.push_call(nop);
+        assert!(cpu.take_call().is_some());
+        assert!(cpu.take_call().is_none());
+        cpu.set_online();
+        assert!(cpu.is_online());
+    }
+}
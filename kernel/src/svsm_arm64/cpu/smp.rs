@@ -0,0 +1,274 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Secondary PE bring-up and inter-processor calls for Arm CCA.
+//!
+//! The boot PE starts every other PE of the realm with PSCI CPU_ON, which
+//! the RMM forwards to the host. A secondary PE enters at `secondary_entry`
+//! in `smp.s` with the MMU off and its [`SecondaryBoot`] block in x0. It
+//! takes over the translation, exception and FP configuration of the boot
+//! PE, switches to its own stack and initializes its redistributor, CPU
+//! interface and timer before it reports itself online.
+//!
+//! Work is handed to a PE with [`run_on_cpu()`], which queues a function in
+//! the [`PerCpu`] area of the PE and sends it [`SGI_KICK`]. The SVSM runs
+//! with interrupts masked, so the SGI only wakes the PE up from WFI. The PE
+//! then acknowledges it through [`gicv3_handle_irq()`] and runs the queued
+//! work from [`cpu_idle_loop()`].
+
+use super::gicv3::{
+    current_mpidr, gicv3_handle_irq, gicv3_init_cpu, gicv3_send_sgi, register_irq_handler,
+    IrqHandler, SgiTarget, MPIDR_AFF_MASK,
+};
+use super::percpu::{percpu_create, this_cpu, CpuCall, PerCpu};
+use super::timer::{read_cntfrq, read_cntpct, timer_init_cpu, wait_for_interrupt};
+use crate::address::Address;
+use crate::error::SvsmError;
+use crate::mm::address_space::STACK_SIZE;
+use crate::mm::PageBox;
+use crate::realm::plane::psci::PSCI_CPU_ON;
+use crate::realm::rsi::smccc::{arm_smccc_1_2_smc, ArmSmccc12Regs};
+use core::arch::{asm, global_asm};
+use core::mem::{offset_of, size_of};
+
+global_asm!(
+    include_str!("smp.s"),
+    BOOT_MAIR = const offset_of!(SecondaryBoot, mair),
+    BOOT_TCR = const offset_of!(SecondaryBoot, tcr),
+    BOOT_TTBR = const offset_of!(SecondaryBoot, ttbr),
+    BOOT_SCTLR = const offset_of!(SecondaryBoot, sctlr),
+    BOOT_VBAR = const offset_of!(SecondaryBoot, vbar),
+    BOOT_CPACR = const offset_of!(SecondaryBoot, cpacr),
+    BOOT_STACK = const offset_of!(SecondaryBoot, stack_top),
+);
+
+extern "C" {
+    fn secondary_entry();
+}
+
+/// SGI which wakes a PE up to look for queued work.
+pub const SGI_KICK: u32 = 0;
+
+/// Time a secondary PE gets to come online, in milliseconds.
+const CPU_ON_TIMEOUT_MS: u64 = 1000;
+
+/// Errors related to starting secondary PEs.
+#[derive(Clone, Copy, Debug)]
+pub enum SmpError {
+    /// PSCI CPU_ON failed with the given PSCI return code.
+    CpuOn(i64),
+    /// The PE with the given MPIDR did not come online in time.
+    Timeout(u64),
+}
+
+impl From<SmpError> for SvsmError {
+    fn from(err: SmpError) -> Self {
+        Self::Smp(err)
+    }
+}
+
+/// State a secondary PE is started with. It is read with the MMU off, so
+/// it must be cleaned to the point of coherency before CPU_ON.
+#[repr(C)]
+#[derive(Debug)]
+struct SecondaryBoot {
+    mair: u64,
+    tcr: u64,
+    ttbr: u64,
+    sctlr: u64,
+    vbar: u64,
+    cpacr: u64,
+    stack_top: u64,
+    percpu: &'static PerCpu,
+}
+
+impl SecondaryBoot {
+    /// Captures the system registers of the current PE, which the new PE
+    /// takes over.
+    fn new(percpu: &'static PerCpu, stack_top: u64) -> Self {
+        let (mair, tcr, ttbr, sctlr, vbar, cpacr): (u64, u64, u64, u64, u64, u64);
+        // SAFETY: reading system registers has no side effects.
+        unsafe {
+            asm!(
+                "mrs {mair}, mair_el1",
+                "mrs {tcr}, tcr_el1",
+                "mrs {ttbr}, ttbr0_el1",
+                "mrs {sctlr}, sctlr_el1",
+                "mrs {vbar}, vbar_el1",
+                "mrs {cpacr}, cpacr_el1",
+                mair = out(reg) mair,
+                tcr = out(reg) tcr,
+                ttbr = out(reg) ttbr,
+                sctlr = out(reg) sctlr,
+                vbar = out(reg) vbar,
+                cpacr = out(reg) cpacr,
+                options(nomem, nostack),
+            );
+        }
+        Self {
+            mair,
+            tcr,
+            ttbr,
+            sctlr,
+            vbar,
+            cpacr,
+            stack_top,
+            percpu,
+        }
+    }
+}
+
+/// Cleans the data cache lines covering `len` bytes at `addr` to the point
+/// of coherency.
+fn clean_dcache_range(addr: usize, len: usize) {
+    let ctr: u64;
+    // SAFETY: reading CTR_EL0 has no side effects.
+    unsafe {
+        asm!("mrs {}, ctr_el0", out(reg) ctr, options(nomem, nostack));
+    }
+    let line = 4usize << ((ctr >> 16) & 0xf);
+    for line_addr in (addr & !(line - 1)..addr + len).step_by(line) {
+        // SAFETY: cleaning a cache line does not change memory contents.
+        unsafe {
+            asm!("dc cvac, {}", in(reg) line_addr, options(nostack));
+        }
+    }
+    // SAFETY: barriers have no memory safety implications.
+    unsafe {
+        asm!("dsb sy", options(nostack));
+    }
+}
+
+/// Asks the host through the RMM to start the PE `mpidr` at `entry` with
+/// `context_id` in x0.
+fn psci_cpu_on(mpidr: u64, entry: u64, context_id: u64) -> Result<(), SmpError> {
+    let mut regs = ArmSmccc12Regs::default();
+    regs.x[..4].copy_from_slice(&[PSCI_CPU_ON, mpidr, entry, context_id]);
+    // SAFETY: CPU_ON does not access memory of the calling PE.
+    let ret = unsafe { arm_smccc_1_2_smc(&regs) }.x[0];
+    match ret {
+        0 => Ok(()),
+        ret => Err(SmpError::CpuOn(ret as i64)),
+    }
+}
+
+#[derive(Debug)]
+struct Kick;
+
+impl IrqHandler for Kick {
+    fn handle_irq(&self, _intid: u32) {
+        // Waking the PE up is all the SGI is for.
+    }
+}
+
+static KICK: Kick = Kick;
+
+/// Wakes the PE `mpidr` up, so that it runs its queued work or re-checks
+/// the state it waits for.
+pub fn kick_cpu(mpidr: u64) -> Result<(), SvsmError> {
+    if mpidr & MPIDR_AFF_MASK != current_mpidr() {
+        gicv3_send_sgi(SGI_KICK, SgiTarget::Cpu(mpidr))?;
+    }
+    Ok(())
+}
+
+/// Wakes up all PEs except the current one.
+pub fn kick_all() -> Result<(), SvsmError> {
+    gicv3_send_sgi(SGI_KICK, SgiTarget::AllButSelf)?;
+    Ok(())
+}
+
+/// Queues `call` on `cpu` and wakes it up to run it.
+pub fn run_on_cpu(cpu: &PerCpu, call: CpuCall) -> Result<(), SvsmError> {
+    cpu.push_call(call);
+    kick_cpu(cpu.mpidr())
+}
+
+/// Runs the calls queued on the current PE, and sleeps until it is kicked
+/// when there are none.
+pub fn cpu_idle_loop() -> ! {
+    let cpu = this_cpu();
+    loop {
+        while let Some(call) = cpu.take_call() {
+            call();
+        }
+        // A kick sent after the queue was found empty stays pending, so WFI
+        // returns right away.
+        wait_for_interrupt();
+        gicv3_handle_irq();
+    }
+}
+
+/// Sets up the per-CPU area of the boot PE, whose position in `mpidrs`
+/// gives its CPU index, and registers the handler of [`SGI_KICK`].
+pub fn smp_init(mpidrs: &[u64]) -> Result<(), SvsmError> {
+    let mpidr = current_mpidr();
+    let cpu_index = mpidrs
+        .iter()
+        .position(|m| m & MPIDR_AFF_MASK == mpidr)
+        .unwrap_or(0);
+    let bsp = percpu_create(cpu_index, mpidr)?;
+    // SAFETY: this is the area of the boot PE.
+    unsafe { bsp.load() };
+    bsp.set_online();
+    register_irq_handler(SGI_KICK, &KICK)
+}
+
+fn wait_online(cpu: &PerCpu) -> Result<(), SmpError> {
+    let timeout = read_cntfrq() * CPU_ON_TIMEOUT_MS / 1000;
+    let start = read_cntpct();
+    while !cpu.is_online() {
+        if read_cntpct().wrapping_sub(start) > timeout {
+            return Err(SmpError::Timeout(cpu.mpidr()));
+        }
+        core::hint::spin_loop();
+    }
+    Ok(())
+}
+
+fn start_cpu(cpu_index: usize, mpidr: u64) -> Result<(), SvsmError> {
+    let cpu = percpu_create(cpu_index, mpidr)?;
+    let stack = PageBox::leak(PageBox::<[u8; STACK_SIZE]>::try_new_zeroed()?);
+    let stack_top = stack.as_mut_ptr_range().end as u64;
+
+    let boot = PageBox::try_new(SecondaryBoot::new(cpu, stack_top))?;
+    let boot_addr = boot.vaddr().bits();
+    clean_dcache_range(boot_addr, size_of::<SecondaryBoot>());
+
+    // The SVSM is identity mapped, so virtual addresses are IPAs.
+    let entry = secondary_entry as usize as u64;
+    psci_cpu_on(mpidr, entry, boot_addr as u64)?;
+    // The PE reads `boot` whenever it starts running, which may be after
+    // the wait below timed out, so it is never freed.
+    PageBox::leak(boot);
+    wait_online(cpu)?;
+    log::info!("Started CPU {cpu_index} (MPIDR {mpidr:#x})");
+    Ok(())
+}
+
+/// Starts all PEs in `mpidrs` except the current one.
+pub fn start_secondary_cpus(mpidrs: &[u64]) -> Result<(), SvsmError> {
+    let bsp = current_mpidr();
+    for (cpu_index, &mpidr) in mpidrs.iter().enumerate() {
+        if mpidr & MPIDR_AFF_MASK != bsp {
+            start_cpu(cpu_index, mpidr)?;
+        }
+    }
+    Ok(())
+}
+
+fn secondary_init() -> Result<(), SvsmError> {
+    gicv3_init_cpu()?;
+    timer_init_cpu()
+}
+
+#[no_mangle]
+extern "C" fn secondary_main(boot: &SecondaryBoot) -> ! {
+    let cpu = boot.percpu;
+    // SAFETY: the boot PE created this area for the current PE.
+    unsafe { cpu.load() };
+    if let Err(e) = secondary_init() {
+        panic!("Failed to initialize CPU {}: {e:?}", cpu.cpu_index());
+    }
+    cpu.set_online();
+    cpu_idle_loop()
+}
@@ -0,0 +1,34 @@
+.section .text
+
+// Entry point of secondary PEs started with PSCI CPU_ON.
+//
+// The PE enters at EL1 with the MMU and caches off and a pointer to its
+// struct SecondaryBoot in x0. The SVSM is identity mapped, so the pointer
+// and the addresses of the code and the stack stay valid once the MMU is
+// enabled with the translation table of the boot PE.
+.globl secondary_entry
+secondary_entry:
+    ldr     x1, [x0, #{BOOT_VBAR}]
+    msr     vbar_el1, x1
+    ldr     x1, [x0, #{BOOT_CPACR}]
+    msr     cpacr_el1, x1
+    ldr     x1, [x0, #{BOOT_MAIR}]
+    msr     mair_el1, x1
+    ldr     x1, [x0, #{BOOT_TCR}]
+    msr     tcr_el1, x1
+    ldr     x1, [x0, #{BOOT_TTBR}]
+    msr     ttbr0_el1, x1
+    msr     ttbr1_el1, x1
+    isb
+    tlbi    vmalle1
+    dsb     nsh
+
+    ldr     x1, [x0, #{BOOT_SCTLR}]
+    msr     sctlr_el1, x1
+    isb
+
+    ldr     x1, [x0, #{BOOT_STACK}]
+    mov     sp, x1
+    mov     x29, xzr
+    mov     x30, xzr
+    b       secondary_main
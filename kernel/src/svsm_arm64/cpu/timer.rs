@@ -0,0 +1,115 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! EL1 physical timer of the SVSM.
+//!
+//! The SVSM only uses the timer to wake itself up from WFI when the next
+//! timer deadline of an aux plane is due. The interrupt handler masks the
+//! timer again, so that it does not keep firing until it is re-armed.
+
+use super::gicv3::{gicv3_configure_irq, register_irq_handler, IrqHandler};
+use crate::error::SvsmError;
+use core::arch::asm;
+
+/// PPI of the EL1 physical timer.
+pub const CNTP_PPI: u32 = 30;
+
+const CNTP_PRIORITY: u8 = 0x80;
+
+// CNTP_CTL_EL0 bits
+const CNTP_CTL_ENABLE: u64 = 1 << 0;
+const CNTP_CTL_IMASK: u64 = 1 << 1;
+
+/// Returns the physical count.
+pub fn read_cntpct() -> u64 {
+    let count: u64;
+    // SAFETY: reading the counter has no side effects. The ISB keeps the
+    // read from being speculated ahead of earlier instructions.
+    unsafe {
+        asm!("isb", "mrs {}, cntpct_el0", out(reg) count, options(nomem, nostack));
+    }
+    count
+}
+
+/// Returns the virtual count.
+pub fn read_cntvct() -> u64 {
+    let count: u64;
+    // SAFETY: as above.
+    unsafe {
+        asm!("isb", "mrs {}, cntvct_el0", out(reg) count, options(nomem, nostack));
+    }
+    count
+}
+
+/// Returns the frequency of the system counter in Hz.
+pub fn read_cntfrq() -> u64 {
+    let freq: u64;
+    // SAFETY: reading CNTFRQ_EL0 has no side effects.
+    unsafe {
+        asm!("mrs {}, cntfrq_el0", out(reg) freq, options(nomem, nostack));
+    }
+    freq
+}
+
+/// Arms the timer to fire once the physical count reaches `deadline`.
+pub fn timer_arm(deadline: u64) {
+    // SAFETY: programming the EL1 physical timer only affects the interrupt
+    // it raises, which is handled by `TimerWakeup`.
+    unsafe {
+        asm!(
+            "msr cntp_cval_el0, {cval}",
+            "msr cntp_ctl_el0, {ctl}",
+            "isb",
+            cval = in(reg) deadline,
+            ctl = in(reg) CNTP_CTL_ENABLE,
+            options(nomem, nostack)
+        );
+    }
+}
+
+/// Stops the timer from raising its interrupt.
+pub fn timer_disarm() {
+    // SAFETY: see `timer_arm()`.
+    unsafe {
+        asm!(
+            "msr cntp_ctl_el0, {ctl}",
+            "isb",
+            ctl = in(reg) CNTP_CTL_IMASK,
+            options(nomem, nostack)
+        );
+    }
+}
+
+/// Waits until an interrupt becomes pending. This also returns when
+/// interrupts are masked, in which case the interrupt stays pending.
+pub fn wait_for_interrupt() {
+    // SAFETY: WFI only suspends execution.
+    unsafe {
+        asm!("dsb sy", "wfi", options(nomem, nostack));
+    }
+}
+
+#[derive(Debug)]
+struct TimerWakeup;
+
+impl IrqHandler for TimerWakeup {
+    fn handle_irq(&self, _intid: u32) {
+        timer_disarm();
+    }
+}
+
+static TIMER_WAKEUP: TimerWakeup = TimerWakeup;
+
+/// Registers the timer interrupt handler, which is shared by all PEs, and
+/// enables the timer interrupt on the current PE.
+pub fn timer_init() -> Result<(), SvsmError> {
+    register_irq_handler(CNTP_PPI, &TIMER_WAKEUP)?;
+    timer_init_cpu()
+}
+
+/// Enables the timer interrupt on the current PE. The timer itself stays
+/// disarmed until [`timer_arm()`] is called.
+pub fn timer_init_cpu() -> Result<(), SvsmError> {
+    timer_disarm();
+    gicv3_configure_irq(CNTP_PPI, CNTP_PRIORITY)?;
+    Ok(())
+}
@@ -0,0 +1,383 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! ARMv8-A stage-1 translation table format for the 4KB granule.
+//!
+//! The generic page table code in [`crate::mm::pagetable`] works with the
+//! architecture neutral [`PTEntryFlags`]. This module translates them to and
+//! from VMSAv8-64 descriptors and programs the EL1 translation registers.
+//!
+//! A 48-bit VA with a 4KB granule uses the same four 9-bit table indices as
+//! x86 4-level paging, so the table walking code is shared. The same root is
+//! installed in both TTBR0_EL1 and TTBR1_EL1, which gives the SVSM a single
+//! address space spanning the low and the high half like on x86.
+
+use crate::address::{Address, PhysAddr, VirtAddr};
+use crate::mm::pagetable::PTEntryFlags;
+use crate::types::{PageSize, PAGE_SHIFT};
+use crate::utils::MemoryRegion;
+use core::arch::asm;
+
+/// Descriptor is valid.
+pub const DESC_VALID: u64 = 1 << 0;
+/// Table descriptor at levels 0-2, page descriptor at level 3. Block
+/// descriptors have this bit clear.
+pub const DESC_TABLE: u64 = 1 << 1;
+/// Shift of the AttrIndx field, which selects an attribute in MAIR_EL1.
+pub const DESC_ATTR_INDX_SHIFT: u64 = 2;
+/// Mask of the AttrIndx field.
+pub const DESC_ATTR_INDX_MASK: u64 = 7 << DESC_ATTR_INDX_SHIFT;
+/// AP[1]: the mapping is accessible from EL0.
+pub const DESC_AP_EL0: u64 = 1 << 6;
+/// AP[2]: the mapping is read-only.
+pub const DESC_AP_RO: u64 = 1 << 7;
+/// Inner shareable.
+pub const DESC_SH_INNER: u64 = 3 << 8;
+/// Access flag.
+pub const DESC_AF: u64 = 1 << 10;
+/// Not global: the translation is tagged with the current ASID.
+pub const DESC_NG: u64 = 1 << 11;
+/// Privileged execute never.
+pub const DESC_PXN: u64 = 1 << 53;
+/// Unprivileged execute never.
+pub const DESC_UXN: u64 = 1 << 54;
+/// Software defined bit used to track the dirty state.
+pub const DESC_SW_DIRTY: u64 = 1 << 55;
+/// Privileged execute never for everything mapped through a table.
+pub const DESC_PXN_TABLE: u64 = 1 << 59;
+/// Unprivileged execute never for everything mapped through a table.
+pub const DESC_UXN_TABLE: u64 = 1 << 60;
+/// No EL0 access to anything mapped through a table.
+pub const DESC_AP_TABLE_NO_EL0: u64 = 1 << 61;
+/// No write access to anything mapped through a table.
+pub const DESC_AP_TABLE_RO: u64 = 1 << 62;
+
+/// Output address bits of a descriptor for a 48-bit physical address space.
+pub const DESC_ADDR_MASK: u64 = 0x0000_ffff_ffff_f000;
+
+/// MAIR_EL1 attribute index for Device-nGnRnE memory.
+pub const MAIR_IDX_DEVICE_NGNRNE: u64 = 0;
+/// MAIR_EL1 attribute index for Device-nGnRE memory.
+pub const MAIR_IDX_DEVICE_NGNRE: u64 = 1;
+/// MAIR_EL1 attribute index for Device-GRE memory.
+pub const MAIR_IDX_DEVICE_GRE: u64 = 2;
+/// MAIR_EL1 attribute index for Normal non-cacheable memory.
+pub const MAIR_IDX_NORMAL_NC: u64 = 3;
+/// MAIR_EL1 attribute index for Normal write-back cacheable memory.
+pub const MAIR_IDX_NORMAL: u64 = 4;
+
+/// Memory attributes, indexed by the `MAIR_IDX_*` constants. The layout
+/// matches the one programmed by the boot code.
+pub const MAIR_EL1_VALUE: u64 = (0x00 << (8 * MAIR_IDX_DEVICE_NGNRNE))
+    | (0x04 << (8 * MAIR_IDX_DEVICE_NGNRE))
+    | (0x0c << (8 * MAIR_IDX_DEVICE_GRE))
+    | (0x44 << (8 * MAIR_IDX_NORMAL_NC))
+    | (0xff << (8 * MAIR_IDX_NORMAL));
+
+/// Size offset of both halves of the address space (48-bit VAs).
+const TCR_TXSZ: u64 = 64 - 48;
+const TCR_T0SZ_SHIFT: u64 = 0;
+const TCR_T1SZ_SHIFT: u64 = 16;
+/// Table walks are Normal, inner/outer write-back write-allocate and inner
+/// shareable for both halves.
+const TCR_IRGN0_WBWA: u64 = 1 << 8;
+const TCR_ORGN0_WBWA: u64 = 1 << 10;
+const TCR_SH0_INNER: u64 = 3 << 12;
+const TCR_TG0_4K: u64 = 0 << 14;
+const TCR_IRGN1_WBWA: u64 = 1 << 24;
+const TCR_ORGN1_WBWA: u64 = 1 << 26;
+const TCR_SH1_INNER: u64 = 3 << 28;
+const TCR_TG1_4K: u64 = 2 << 30;
+const TCR_IPS_SHIFT: u64 = 32;
+
+/// Largest supported ID_AA64MMFR0_EL1.PARange encoding (48 bits).
+const PARANGE_48: u64 = 5;
+
+/// Converts an address and a set of [`PTEntryFlags`] into a descriptor.
+///
+/// The leaf attributes are also encoded in table descriptors, where the
+/// hardware ignores them. This keeps entries of the recursive self-map,
+/// which interprets table descriptors as page descriptors, accessible. The
+/// restrictions of table entries are additionally propagated to the
+/// hierarchical APTable/XNTable bits.
+pub fn encode(addr: PhysAddr, flags: PTEntryFlags) -> u64 {
+    let addr = u64::from(addr);
+    assert_eq!(addr & !DESC_ADDR_MASK, 0);
+
+    if !flags.contains(PTEntryFlags::PRESENT) {
+        return addr;
+    }
+
+    let mut desc = addr | DESC_VALID;
+    if !flags.contains(PTEntryFlags::HUGE) {
+        desc |= DESC_TABLE;
+    }
+
+    if flags.contains(PTEntryFlags::NOCACHE) {
+        desc |= MAIR_IDX_DEVICE_NGNRE << DESC_ATTR_INDX_SHIFT;
+    } else {
+        desc |= (MAIR_IDX_NORMAL << DESC_ATTR_INDX_SHIFT) | DESC_SH_INNER;
+    }
+
+    if !flags.contains(PTEntryFlags::WRITABLE) {
+        desc |= DESC_AP_RO | DESC_AP_TABLE_RO;
+    }
+
+    if flags.contains(PTEntryFlags::USER) {
+        // The SVSM never executes code which is mapped for EL0.
+        desc |= DESC_AP_EL0 | DESC_PXN;
+        if flags.contains(PTEntryFlags::NX) {
+            desc |= DESC_UXN;
+        }
+    } else {
+        desc |= DESC_AP_TABLE_NO_EL0 | DESC_UXN;
+        if flags.contains(PTEntryFlags::NX) {
+            desc |= DESC_PXN;
+        }
+    }
+
+    if flags.contains(PTEntryFlags::NX) {
+        desc |= DESC_PXN_TABLE | DESC_UXN_TABLE;
+    }
+
+    if flags.contains(PTEntryFlags::ACCESSED) {
+        desc |= DESC_AF;
+    }
+    if flags.contains(PTEntryFlags::DIRTY) {
+        desc |= DESC_SW_DIRTY;
+    }
+    if !flags.contains(PTEntryFlags::GLOBAL) {
+        desc |= DESC_NG;
+    }
+
+    desc
+}
+
+/// Converts the attributes of a descriptor back into [`PTEntryFlags`].
+pub fn decode(desc: u64) -> PTEntryFlags {
+    let mut flags = PTEntryFlags::empty();
+
+    if desc & DESC_VALID == 0 {
+        return flags;
+    }
+
+    flags |= PTEntryFlags::PRESENT;
+    if desc & DESC_TABLE == 0 {
+        flags |= PTEntryFlags::HUGE;
+    }
+    if (desc & DESC_ATTR_INDX_MASK) >> DESC_ATTR_INDX_SHIFT == MAIR_IDX_DEVICE_NGNRE {
+        flags |= PTEntryFlags::NOCACHE;
+    }
+    if desc & DESC_AP_RO == 0 {
+        flags |= PTEntryFlags::WRITABLE;
+    }
+
+    let xn = if desc & DESC_AP_EL0 != 0 {
+        flags |= PTEntryFlags::USER;
+        desc & DESC_UXN
+    } else {
+        desc & DESC_PXN
+    };
+    if xn != 0 {
+        flags |= PTEntryFlags::NX;
+    }
+
+    if desc & DESC_AF != 0 {
+        flags |= PTEntryFlags::ACCESSED;
+    }
+    if desc & DESC_SW_DIRTY != 0 {
+        flags |= PTEntryFlags::DIRTY;
+    }
+    if desc & DESC_NG == 0 {
+        flags |= PTEntryFlags::GLOBAL;
+    }
+
+    flags
+}
+
+/// Returns the output address of a descriptor.
+pub fn desc_address(desc: u64) -> PhysAddr {
+    PhysAddr::from(desc & DESC_ADDR_MASK)
+}
+
+/// Returns the TCR_EL1 value for 48-bit VAs in both halves with a 4KB
+/// granule and the intermediate physical address size given by `parange`.
+pub const fn tcr_value(parange: u64) -> u64 {
+    let ips = if parange > PARANGE_48 {
+        PARANGE_48
+    } else {
+        parange
+    };
+
+    (TCR_TXSZ << TCR_T0SZ_SHIFT)
+        | TCR_IRGN0_WBWA
+        | TCR_ORGN0_WBWA
+        | TCR_SH0_INNER
+        | TCR_TG0_4K
+        | (TCR_TXSZ << TCR_T1SZ_SHIFT)
+        | TCR_IRGN1_WBWA
+        | TCR_ORGN1_WBWA
+        | TCR_SH1_INNER
+        | TCR_TG1_4K
+        | (ips << TCR_IPS_SHIFT)
+}
+
+/// Programs MAIR_EL1 and TCR_EL1 for the translation table format produced
+/// by [`encode`].
+///
+/// # Safety
+///
+/// The caller must ensure that the translation tables in use are compatible
+/// with the new configuration, or that the MMU is disabled.
+pub unsafe fn init_translation_regs() {
+    let mmfr0: u64;
+    // SAFETY: reading the ID register has no side effects.
+    unsafe {
+        asm!("mrs {}, id_aa64mmfr0_el1", out(reg) mmfr0, options(nomem, nostack));
+    }
+    let tcr = tcr_value(mmfr0 & 0xf);
+
+    // SAFETY: demanded to the caller.
+    unsafe {
+        asm!(
+            "msr mair_el1, {mair}",
+            "msr tcr_el1, {tcr}",
+            "isb",
+            mair = in(reg) MAIR_EL1_VALUE,
+            tcr = in(reg) tcr,
+            options(nostack),
+        );
+    }
+}
+
+/// Installs the translation table rooted at `root` for both halves of the
+/// address space and invalidates all stage-1 TLB entries.
+///
+/// # Safety
+///
+/// The caller must ensure that `root` points to a valid translation table
+/// which maps all code and data in use.
+pub unsafe fn load_root(root: PhysAddr) {
+    let root = root.bits() as u64;
+    // SAFETY: demanded to the caller.
+    unsafe {
+        asm!(
+            "dsb ishst",
+            "msr ttbr0_el1, {root}",
+            "msr ttbr1_el1, {root}",
+            "isb",
+            "tlbi vmalle1is",
+            "dsb ish",
+            "isb",
+            root = in(reg) root,
+            options(nostack),
+        );
+    }
+}
+
+/// Invalidates the stage-1 TLB entries of every page in `region` on all PEs
+/// of the inner shareable domain, after a change of their last-level
+/// descriptors.
+pub fn flush_tlb_range(region: MemoryRegion<VirtAddr>) {
+    // SAFETY: the barrier makes the descriptor updates visible to the table
+    // walks, and has no memory safety implications.
+    unsafe { asm!("dsb ishst", options(nostack)) };
+    for vaddr in region.iter_pages(PageSize::Regular) {
+        // TLBI takes VA[55:12] in bits [43:0]. Entries of the SVSM are
+        // global, so the ASID field is left zero.
+        let page = (vaddr.bits() >> PAGE_SHIFT) as u64 & ((1 << 44) - 1);
+        // SAFETY: invalidating TLB entries has no memory safety
+        // implications.
+        unsafe { asm!("tlbi vale1is, {}", in(reg) page, options(nostack)) };
+    }
+    // SAFETY: waiting for the invalidations to complete has no memory
+    // safety implications.
+    unsafe { asm!("dsb ish", "isb", options(nostack)) };
+}
+
+/// Returns the level 2 block descriptor which maps `vaddr` in the
+/// translation table installed in TTBR0_EL1, or `None` if `vaddr` is not
+/// mapped by a 2MB block.
+///
+/// # Safety
+///
+/// The translation table must be identity mapped, and the caller must
+/// ensure that the descriptor is not accessed concurrently.
+pub unsafe fn block_desc_2m(vaddr: VirtAddr) -> Option<&'static mut u64> {
+    let ttbr0: u64;
+    // SAFETY: reading TTBR0_EL1 has no side effects.
+    unsafe { asm!("mrs {}, ttbr0_el1", out(reg) ttbr0, options(nomem, nostack)) };
+
+    let va = vaddr.bits() as u64;
+    let mut table = desc_address(ttbr0);
+    for shift in [39, 30, 21] {
+        let index = ((va >> shift) & 0x1ff) as usize;
+        // SAFETY: `table` is an identity mapped translation table, as
+        // demanded to the caller.
+        let desc = unsafe { &mut *(table.bits() as *mut u64).add(index) };
+        match (shift, *desc & (DESC_VALID | DESC_TABLE)) {
+            (21, DESC_VALID) => return Some(desc),
+            (39 | 30, d) if d == DESC_VALID | DESC_TABLE => table = desc_address(*desc),
+            _ => return None,
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_kernel_flags() {
+        let addr = PhysAddr::from(0x4008_0000u64);
+
+        let text = encode(addr, PTEntryFlags::exec());
+        assert_eq!(text & DESC_ADDR_MASK, 0x4008_0000);
+        assert_ne!(text & DESC_TABLE, 0);
+        assert_ne!(text & DESC_AP_RO, 0);
+        assert_eq!(text & (DESC_PXN | DESC_NG | DESC_AP_EL0), 0);
+        assert_ne!(text & (DESC_UXN | DESC_AF), 0);
+
+        let data = encode(addr, PTEntryFlags::data() | PTEntryFlags::HUGE);
+        assert_eq!(data & DESC_TABLE, 0);
+        assert_eq!(data & DESC_AP_RO, 0);
+        assert_eq!(data & (DESC_PXN | DESC_UXN), DESC_PXN | DESC_UXN);
+        assert_eq!(
+            (data & DESC_ATTR_INDX_MASK) >> DESC_ATTR_INDX_SHIFT,
+            MAIR_IDX_NORMAL
+        );
+
+        let user = encode(addr, PTEntryFlags::task_exec() | PTEntryFlags::USER);
+        assert_ne!(user & (DESC_AP_EL0 | DESC_PXN | DESC_NG), 0);
+        assert_eq!(user & DESC_UXN, 0);
+
+        assert_eq!(encode(addr, PTEntryFlags::empty()), 0x4008_0000);
+    }
+
+    #[test]
+    fn decode_roundtrip() {
+        let addr = PhysAddr::from(0x8_0000_0000u64);
+        for flags in [
+            PTEntryFlags::exec(),
+            PTEntryFlags::data(),
+            PTEntryFlags::data_ro(),
+            PTEntryFlags::data() | PTEntryFlags::HUGE,
+            PTEntryFlags::data() | PTEntryFlags::NOCACHE,
+            PTEntryFlags::task_exec() | PTEntryFlags::USER,
+            PTEntryFlags::task_data() | PTEntryFlags::USER,
+            PTEntryFlags::task_data_ro() | PTEntryFlags::USER,
+        ] {
+            let desc = encode(addr, flags);
+            assert_eq!(desc_address(desc), addr);
+            assert_eq!(decode(desc).bits(), flags.bits());
+        }
+        assert!(decode(0x4008_0000).is_empty());
+    }
+
+    #[test]
+    fn tcr_ips_is_clamped() {
+        assert_eq!(tcr_value(2) >> TCR_IPS_SHIFT, 2);
+        assert_eq!(tcr_value(6) >> TCR_IPS_SHIFT, PARANGE_48);
+        assert_eq!(tcr_value(0) & 0x3f, 16);
+    }
+}
@@ -1,2 +1,3 @@
 pub mod uart_console;
-pub mod cpu;
\ No newline at end of file
+pub mod cpu;
+pub mod mm;
@@ -7,14 +7,20 @@
 use crate::address::{Address, PhysAddr, VirtAddr};
 use crate::config::SvsmConfig;
 use crate::error::SvsmError;
+#[cfg(feature = "cca")]
+use crate::fdt::DEVICE_TREE;
 use crate::igvm_params::IgvmParams;
 use crate::mm::global_memory::init_global_ranges;
 use crate::mm::pagetable::{PTEntryFlags, PageTable};
 use crate::mm::PageBox;
 use crate::platform::{PageStateChangeOp, PageValidateOp, SvsmPlatform};
+#[cfg(feature = "cca")]
+use crate::realm::mem::{map_mmio_in, page_region};
 use crate::types::PageSize;
 use crate::utils::{page_align_up, MemoryRegion};
 use bootlib::kernel_launch::{KernelLaunchInfo, LOWMEM_END};
+#[cfg(feature = "cca")]
+use bootlib::kernel_launch::{STAGE2_HEAP_END, STAGE2_HEAP_START};
 
 struct IgvmParamInfo<'a> {
     virt_addr: VirtAddr,
@@ -95,17 +101,56 @@ pub fn init_page_table(
     Ok(pgtable)
 }
 
+/// Builds the page table for the SVSM kernel in plane 0 of a realm.
+///
+/// The SVSM runs identity mapped, so every region is mapped at the virtual
+/// address equal to its IPA. `kernel_text` is the part of the kernel region
+/// which holds code; the remainder of the kernel region is mapped as data.
+#[cfg(feature = "cca")]
 pub fn init_page_table_arm(
     launch_info: &KernelLaunchInfo,
+    kernel_text: MemoryRegion<PhysAddr>,
 ) -> Result<PageBox<PageTable>, SvsmError> {
     let mut pgtable = PageTable::allocate_new()?;
-    Ok(pgtable)
 
-    /*
-    let mut phys = PhysAddr::from(launch_info.kernel_region_phys_start);
+    let identity = |region: MemoryRegion<PhysAddr>| {
+        MemoryRegion::new(VirtAddr::from(region.start().bits()), region.len())
+    };
 
-    // TODO：需要一个for循环将内核段映射到虚拟地址空间的上半部分
+    // Map the kernel image: code read-only and executable, everything else
+    // (data, read-only data, bss, boot page tables and stack) as data.
+    let kernel_region = MemoryRegion::from_addresses(
+        PhysAddr::from(launch_info.kernel_region_phys_start),
+        PhysAddr::from(launch_info.kernel_region_phys_end).page_align_up(),
+    );
+    let kernel_data = MemoryRegion::from_addresses(kernel_text.end(), kernel_region.end());
+    pgtable
+        .map_region(
+            identity(kernel_text),
+            kernel_text.start(),
+            PTEntryFlags::exec(),
+        )
+        .expect("Failed to map kernel text");
+    pgtable
+        .map_region(
+            identity(kernel_data),
+            kernel_data.start(),
+            PTEntryFlags::data(),
+        )
+        .expect("Failed to map kernel data");
 
+    // Map the early heap, which holds the page table pages themselves.
+    let early_heap = MemoryRegion::from_addresses(
+        PhysAddr::from(u64::from(STAGE2_HEAP_START)),
+        PhysAddr::from(u64::from(STAGE2_HEAP_END)),
+    );
+    pgtable
+        .map_region(
+            identity(early_heap),
+            early_heap.start(),
+            PTEntryFlags::data(),
+        )
+        .expect("Failed to map early heap");
 
     // Map subsequent heap area.
     let heap_vregion = MemoryRegion::new(
@@ -120,10 +165,63 @@ pub fn init_page_table_arm(
         )
         .expect("Failed to map heap");
 
+    // Map the device tree, which is handed on to the guest.
+    let dtb = page_region(DEVICE_TREE.dtb.start(), DEVICE_TREE.dtb.len());
+    pgtable
+        .map_region(identity(dtb), dtb.start(), PTEntryFlags::data_ro())
+        .expect("Failed to map device tree");
+
+    // Map the rest of realm RAM as data, which the SVSM accesses to load
+    // and measure the images of the aux planes, to emulate their
+    // instructions and to walk their page tables.
+    let heap = page_region(
+        PhysAddr::from(launch_info.heap_area_phys_start),
+        launch_info.heap_area_size as usize,
+    );
+    let mut mapped = [kernel_region, early_heap, heap, dtb];
+    mapped.sort_unstable_by_key(|region| region.start());
+    for ram in DEVICE_TREE.memory.iter() {
+        let ram = MemoryRegion::from_addresses(ram.start().page_align_up(), ram.end().page_align());
+        for_each_gap(ram, &mapped, |gap| {
+            pgtable.map_region(identity(gap), gap.start(), PTEntryFlags::data())
+        })
+        .expect("Failed to map realm RAM");
+    }
+
+    // Map the console UART and the virtio devices through their
+    // unprotected alias. The GIC is mapped by `init_mmio_gic()`.
+    let uart = page_region(PhysAddr::from(launch_info.debug_serial_port), 1);
+    map_mmio_in(&mut pgtable, uart).expect("Failed to map console");
+    let virtio = DEVICE_TREE.virtio_mmio.iter();
+    for region in virtio.chain(DEVICE_TREE.svsm_virtio_mmio.iter()) {
+        let region = page_region(region.start(), region.len());
+        map_mmio_in(&mut pgtable, region).expect("Failed to map virtio device");
+    }
+
     init_global_ranges();
 
     Ok(pgtable)
-    */
+}
+
+/// Calls `f` for every part of `region` which does not overlap any of
+/// `holes`, which must be sorted by their start address.
+#[cfg(feature = "cca")]
+fn for_each_gap(
+    region: MemoryRegion<PhysAddr>,
+    holes: &[MemoryRegion<PhysAddr>],
+    mut f: impl FnMut(MemoryRegion<PhysAddr>) -> Result<(), SvsmError>,
+) -> Result<(), SvsmError> {
+    let mut start = region.start();
+    for hole in holes.iter().filter(|hole| hole.overlap(&region)) {
+        if start < hole.start() {
+            f(MemoryRegion::from_addresses(start, hole.start()))?;
+        }
+        start = start.max(hole.end());
+    }
+    if start < region.end() {
+        f(MemoryRegion::from_addresses(start, region.end()))?;
+    }
+    Ok(())
 }
 
 fn invalidate_boot_memory_region(
@@ -199,3 +297,46 @@ pub fn invalidate_early_boot_memory(
 
     Ok(())
 }
+
+#[cfg(all(test, feature = "cca"))]
+mod tests {
+    extern crate alloc;
+
+    use super::*;
+    use alloc::vec::Vec;
+
+    fn region(start: u64, end: u64) -> MemoryRegion<PhysAddr> {
+        MemoryRegion::from_addresses(PhysAddr::from(start), PhysAddr::from(end))
+    }
+
+    fn gaps(ram: MemoryRegion<PhysAddr>, holes: &[MemoryRegion<PhysAddr>]) -> Vec<(u64, u64)> {
+        let mut gaps = Vec::new();
+        for_each_gap(ram, holes, |gap| {
+            gaps.push((u64::from(gap.start()), u64::from(gap.end())));
+            Ok(())
+        })
+        .unwrap();
+        gaps
+    }
+
+    #[test]
+    fn ram_gaps() {
+        let ram = region(0x4000_0000, 0x8000_0000);
+        let holes = [
+            region(0x4001_0000, 0x4003_0000),
+            region(0x4008_0000, 0x4048_0000),
+            region(0x4048_0000, 0x404c_0000),
+            region(0x8000_0000, 0x8000_1000),
+        ];
+        assert_eq!(
+            gaps(ram, &holes),
+            [
+                (0x4000_0000, 0x4001_0000),
+                (0x4003_0000, 0x4008_0000),
+                (0x404c_0000, 0x8000_0000),
+            ]
+        );
+        assert_eq!(gaps(ram, &[]), [(0x4000_0000, 0x8000_0000)]);
+        assert!(gaps(region(0x4001_0000, 0x4002_0000), &holes).is_empty());
+    }
+}
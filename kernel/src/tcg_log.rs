@@ -0,0 +1,169 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) Coconut-SVSM authors
+//
+
+//! TCG2 event logs in the crypto agile format.
+//!
+//! The log starts with a `TCG_PCR_EVENT` carrying the Spec ID event, which
+//! lists the hash algorithms of the log, followed by a `TCG_PCR_EVENT2` for
+//! each measurement. It is used both for the vTPM PCRs and, with the REM
+//! index in place of the PCR index, for the realm extensible measurements.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+// TCG event types
+pub const EV_NO_ACTION: u32 = 0x0000_0003;
+pub const EV_S_CRTM_CONTENTS: u32 = 0x0000_0007;
+pub const EV_PLATFORM_CONFIG_FLAGS: u32 = 0x0000_000a;
+pub const EV_IPL: u32 = 0x0000_000d;
+pub const EV_NONHOST_INFO: u32 = 0x0000_0011;
+pub const EV_EFI_PLATFORM_FIRMWARE_BLOB: u32 = 0x8000_0008;
+
+const SPEC_ID_SIGNATURE: &[u8; 16] = b"Spec ID Event03\0";
+const SHA1_DIGEST_SIZE: usize = 20;
+
+/// A measurement recorded in an [`EventLog`].
+#[derive(Clone, Debug)]
+pub struct LogEvent {
+    /// Index of the PCR or REM the digests were extended into.
+    pub index: u32,
+    pub event_type: u32,
+    /// One (TPM_ALG_ID, digest) pair per bank.
+    pub digests: Vec<(u16, Vec<u8>)>,
+    pub data: Vec<u8>,
+}
+
+/// Log of the measurements extended into a set of PCRs or REMs.
+#[derive(Debug, Default)]
+pub struct EventLog {
+    events: Vec<LogEvent>,
+}
+
+impl EventLog {
+    pub const fn new() -> Self {
+        Self { events: Vec::new() }
+    }
+
+    /// Records that `digests`, one (TPM_ALG_ID, digest) pair per bank, of
+    /// an event of `event_type` with `data` were extended into `index`.
+    pub fn record(
+        &mut self,
+        index: u32,
+        event_type: u32,
+        digests: Vec<(u16, Vec<u8>)>,
+        data: Vec<u8>,
+    ) {
+        self.events.push(LogEvent {
+            index,
+            event_type,
+            digests,
+            data,
+        });
+    }
+
+    /// Returns the recorded events, oldest first.
+    pub fn events(&self) -> &[LogEvent] {
+        &self.events
+    }
+
+    /// Serializes the log. The Spec ID event lists `banks`, one
+    /// (TPM_ALG_ID, digest size) pair per hash algorithm, which must match
+    /// the digests of every recorded event.
+    pub fn to_vec(&self, banks: &[(u16, u16)]) -> Vec<u8> {
+        let mut spec_id = Vec::new();
+        spec_id.extend_from_slice(SPEC_ID_SIGNATURE);
+        spec_id.extend_from_slice(&0u32.to_le_bytes()); // platformClass
+        spec_id.extend_from_slice(&[0, 2, 0, 2]); // version 2.0, errata 0, 64-bit UINTN
+        spec_id.extend_from_slice(&(banks.len() as u32).to_le_bytes());
+        for (alg, size) in banks {
+            spec_id.extend_from_slice(&alg.to_le_bytes());
+            spec_id.extend_from_slice(&size.to_le_bytes());
+        }
+        spec_id.push(0); // vendorInfoSize
+
+        let mut log = Vec::new();
+        log.extend_from_slice(&0u32.to_le_bytes());
+        log.extend_from_slice(&EV_NO_ACTION.to_le_bytes());
+        log.extend_from_slice(&[0; SHA1_DIGEST_SIZE]);
+        log.extend_from_slice(&(spec_id.len() as u32).to_le_bytes());
+        log.extend_from_slice(&spec_id);
+
+        for event in &self.events {
+            log.extend_from_slice(&event.index.to_le_bytes());
+            log.extend_from_slice(&event.event_type.to_le_bytes());
+            log.extend_from_slice(&(event.digests.len() as u32).to_le_bytes());
+            for (alg, digest) in &event.digests {
+                log.extend_from_slice(&alg.to_le_bytes());
+                log.extend_from_slice(digest);
+            }
+            log.extend_from_slice(&(event.data.len() as u32).to_le_bytes());
+            log.extend_from_slice(&event.data);
+        }
+
+        log
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    const TPM_ALG_SHA256: u16 = 0x000b;
+    const TPM_ALG_SHA384: u16 = 0x000c;
+
+    #[test]
+    fn event_log_layout() {
+        let mut log = EventLog::new();
+        let sha256 = vec![0xaa; 32];
+        let sha384 = vec![0xbb; 48];
+        log.record(
+            4,
+            EV_IPL,
+            vec![
+                (TPM_ALG_SHA256, sha256.clone()),
+                (TPM_ALG_SHA384, sha384.clone()),
+            ],
+            b"kernel\0".to_vec(),
+        );
+        let bytes = log.to_vec(&[(TPM_ALG_SHA256, 32), (TPM_ALG_SHA384, 48)]);
+
+        // Header event: pcrIndex, eventType, SHA-1 digest, eventSize.
+        assert_eq!(&bytes[0..4], &0u32.to_le_bytes());
+        assert_eq!(&bytes[4..8], &EV_NO_ACTION.to_le_bytes());
+        assert_eq!(&bytes[8..28], &[0; SHA1_DIGEST_SIZE]);
+        let spec_len = u32::from_le_bytes(bytes[28..32].try_into().unwrap()) as usize;
+        let spec_id = &bytes[32..32 + spec_len];
+        assert_eq!(&spec_id[..16], SPEC_ID_SIGNATURE);
+        assert_eq!(&spec_id[24..28], &2u32.to_le_bytes());
+        assert_eq!(&spec_id[28..30], &TPM_ALG_SHA256.to_le_bytes());
+        assert_eq!(&spec_id[30..32], &32u16.to_le_bytes());
+        assert_eq!(&spec_id[32..34], &TPM_ALG_SHA384.to_le_bytes());
+        assert_eq!(&spec_id[34..36], &48u16.to_le_bytes());
+        assert_eq!(spec_id[36], 0);
+        assert_eq!(spec_len, 37);
+
+        let event = &bytes[32 + spec_len..];
+        assert_eq!(&event[0..4], &4u32.to_le_bytes());
+        assert_eq!(&event[4..8], &EV_IPL.to_le_bytes());
+        assert_eq!(&event[8..12], &2u32.to_le_bytes());
+        assert_eq!(&event[12..14], &TPM_ALG_SHA256.to_le_bytes());
+        assert_eq!(&event[14..46], sha256.as_slice());
+        assert_eq!(&event[46..48], &TPM_ALG_SHA384.to_le_bytes());
+        assert_eq!(&event[48..96], sha384.as_slice());
+        let data_len = u32::from_le_bytes(event[96..100].try_into().unwrap()) as usize;
+        assert_eq!(&event[100..100 + data_len], b"kernel\0");
+        assert_eq!(event.len(), 100 + data_len);
+    }
+
+    #[test]
+    fn empty_event_log() {
+        let bytes = EventLog::new().to_vec(&[(TPM_ALG_SHA256, 32)]);
+        let spec_len = u32::from_le_bytes(bytes[28..32].try_into().unwrap()) as usize;
+        assert_eq!(bytes.len(), 32 + spec_len);
+        assert_eq!(&bytes[32 + 24..32 + 28], &1u32.to_le_bytes());
+    }
+}
@@ -18,11 +18,16 @@ use super::error::*;
 use crate::address::PhysAddr;
 use crate::error::SvsmError;
 use crate::locking::SpinLock;
+#[cfg(not(feature = "cca"))]
 use crate::mm::global_memory::{map_global_range_4k_shared, GlobalRangeGuard};
+#[cfg(not(feature = "cca"))]
 use crate::mm::pagetable::PTEntryFlags;
+#[cfg(feature = "cca")]
+use crate::realm::mem::mmio_vaddr;
 
 pub struct VirtIOBlkDevice {
     pub device: SpinLock<VirtIOBlk<SvsmHal, MmioTransport<SvsmHal>>>,
+    #[cfg(not(feature = "cca"))]
     _mmio_space: GlobalRangeGuard,
 }
 
@@ -36,10 +41,16 @@ impl VirtIOBlkDevice {
     pub fn new(mmio_base: PhysAddr) -> Result<Box<Self>, SvsmError> {
         virtio_init();
 
+        #[cfg(not(feature = "cca"))]
         let mem = map_global_range_4k_shared(mmio_base, PAGE_SIZE, PTEntryFlags::data())?;
+        #[cfg(not(feature = "cca"))]
+        let vaddr = mem.addr();
+        // A realm maps device MMIO in the SVSM page table itself.
+        #[cfg(feature = "cca")]
+        let vaddr = mmio_vaddr(mmio_base)?;
 
-        // Not expected to fail, because mem exists.
-        let header = NonNull::new(mem.addr().as_mut_ptr()).unwrap();
+        // Not expected to fail, because the mapping exists.
+        let header = NonNull::new(vaddr.as_mut_ptr()).unwrap();
 
         // SAFETY: `header` is the MMIO config area; we have to trust the content is valid.
         let transport = unsafe {
@@ -59,6 +70,7 @@ impl VirtIOBlkDevice {
 
         Ok(Box::new(VirtIOBlkDevice {
             device: SpinLock::new(blk),
+            #[cfg(not(feature = "cca"))]
             _mmio_space: mem,
         }))
     }
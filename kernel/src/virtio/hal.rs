@@ -16,10 +16,18 @@ use zerocopy::{FromBytes, Immutable, IntoBytes};
 
 use crate::{
     address::{PhysAddr, VirtAddr},
-    cpu::percpu::this_cpu,
     mm::{page_visibility::*, *},
 };
 
+#[cfg(not(feature = "cca"))]
+use crate::cpu::percpu::this_cpu;
+// In a realm the device accesses shared pages through their unprotected
+// alias.
+#[cfg(feature = "cca")]
+use crate::realm::mem::{
+    protected_alias as dma_to_phys, svsm_phys_addr, unprotected_alias as dma_addr,
+};
+
 struct PageStore {
     pages: Vec<(PhysAddr, SharedBox<[u8; PAGE_SIZE]>)>,
 }
@@ -44,6 +52,31 @@ impl PageStore {
 
 static SHARED_MEM: SpinLock<OnceCell<PageStore>> = SpinLock::new(OnceCell::new());
 
+/// Returns the address through which the device accesses the shared page at
+/// `pa`.
+#[cfg(not(feature = "cca"))]
+fn dma_addr(pa: PhysAddr) -> PhysAddr {
+    pa
+}
+
+/// Inverse of [`dma_addr`].
+#[cfg(not(feature = "cca"))]
+fn dma_to_phys(addr: PhysAddr) -> PhysAddr {
+    addr
+}
+
+/// Returns the physical address of the MMIO register mapped at `vaddr`.
+#[cfg(not(feature = "cca"))]
+fn mmio_phys_addr(vaddr: VirtAddr) -> PhysAddr {
+    this_cpu().get_pgtable().phys_addr(vaddr).unwrap()
+}
+
+/// Returns the IPA of the MMIO register mapped at `vaddr`.
+#[cfg(feature = "cca")]
+fn mmio_phys_addr(vaddr: VirtAddr) -> PhysAddr {
+    svsm_phys_addr(vaddr).unwrap()
+}
+
 pub fn virtio_init() {
     SHARED_MEM.lock().get_or_init(PageStore::new);
 }
@@ -71,7 +104,7 @@ unsafe impl virtio_drivers::Hal for SvsmHal {
         assert!(pages == 1);
 
         let shared_page = SharedBox::<[u8; PAGE_SIZE]>::try_new_zeroed().unwrap();
-        let pa = virt_to_phys(shared_page.addr());
+        let pa = dma_addr(virt_to_phys(shared_page.addr()));
         let p = NonNull::<u8>::new(shared_page.addr().as_mut_ptr()).unwrap();
 
         SHARED_MEM.lock().get_mut().unwrap().push(pa, shared_page);
@@ -141,7 +174,7 @@ unsafe impl virtio_drivers::Hal for SvsmHal {
             }
         }
 
-        let pa = virt_to_phys(shared_page.addr());
+        let pa = dma_addr(virt_to_phys(shared_page.addr()));
         SHARED_MEM.lock().get_mut().unwrap().push(pa, shared_page);
 
         // return pa of shared page
@@ -166,7 +199,7 @@ unsafe impl virtio_drivers::Hal for SvsmHal {
         assert!(buffer.len() <= PAGE_SIZE);
 
         if let Some(shared_page) = SHARED_MEM.lock().get_mut().unwrap().pop(paddr.into()) {
-            let vaddr = phys_to_virt(paddr.into());
+            let vaddr = phys_to_virt(dma_to_phys(paddr.into()));
             let va_from_shared = shared_page.addr();
             assert!(vaddr == va_from_shared);
 
@@ -198,10 +231,7 @@ unsafe impl virtio_drivers::Hal for SvsmHal {
     ///
     /// `src` must be properly aligned and reside at a readable memory address.
     unsafe fn mmio_read<T: FromBytes + Immutable>(src: &T) -> T {
-        let paddr = this_cpu()
-            .get_pgtable()
-            .phys_addr(VirtAddr::from(addr_of!(*src)))
-            .unwrap();
+        let paddr = mmio_phys_addr(VirtAddr::from(addr_of!(*src)));
 
         let mut b = MaybeUninit::<T>::uninit();
         // SAFETY: We are trusting the caller (the virtio driver) to ensure `src` is a valid MMIO
@@ -229,10 +259,7 @@ unsafe impl virtio_drivers::Hal for SvsmHal {
     ///
     /// `dst` must be properly aligned and reside at a writable memory address.
     unsafe fn mmio_write<T: IntoBytes + Immutable>(dst: &mut T, v: T) {
-        let paddr = this_cpu()
-            .get_pgtable()
-            .phys_addr(VirtAddr::from(addr_of!(*dst)))
-            .unwrap();
+        let paddr = mmio_phys_addr(VirtAddr::from(addr_of!(*dst)));
 
         // SAFETY: We are trusting the caller (the virtio driver) to ensure validity of `paddr` and alignment of data.
         unsafe {
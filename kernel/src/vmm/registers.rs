@@ -24,6 +24,80 @@ pub enum GuestRegister {
     X64R13(u64),
     X64R14(u64),
     X64R15(u64),
+    Aarch64X0(u64),
+    Aarch64X1(u64),
+    Aarch64X2(u64),
+    Aarch64X3(u64),
+    Aarch64X4(u64),
+    Aarch64X5(u64),
+    Aarch64X6(u64),
+    Aarch64X7(u64),
+    Aarch64X8(u64),
+    Aarch64X9(u64),
+    Aarch64X10(u64),
+    Aarch64X11(u64),
+    Aarch64X12(u64),
+    Aarch64X13(u64),
+    Aarch64X14(u64),
+    Aarch64X15(u64),
+    Aarch64X16(u64),
+    Aarch64X17(u64),
+    Aarch64X18(u64),
+    Aarch64X19(u64),
+    Aarch64X20(u64),
+    Aarch64X21(u64),
+    Aarch64X22(u64),
+    Aarch64X23(u64),
+    Aarch64X24(u64),
+    Aarch64X25(u64),
+    Aarch64X26(u64),
+    Aarch64X27(u64),
+    Aarch64X28(u64),
+    Aarch64X29(u64),
+    Aarch64X30(u64),
+    Aarch64Pc(u64),
+    Aarch64Pstate(u64),
+}
+
+impl GuestRegister {
+    /// Returns the index and value of an AArch64 general purpose register.
+    pub fn aarch64_gpr(&self) -> Option<(usize, u64)> {
+        let gpr = match *self {
+            GuestRegister::Aarch64X0(r) => (0, r),
+            GuestRegister::Aarch64X1(r) => (1, r),
+            GuestRegister::Aarch64X2(r) => (2, r),
+            GuestRegister::Aarch64X3(r) => (3, r),
+            GuestRegister::Aarch64X4(r) => (4, r),
+            GuestRegister::Aarch64X5(r) => (5, r),
+            GuestRegister::Aarch64X6(r) => (6, r),
+            GuestRegister::Aarch64X7(r) => (7, r),
+            GuestRegister::Aarch64X8(r) => (8, r),
+            GuestRegister::Aarch64X9(r) => (9, r),
+            GuestRegister::Aarch64X10(r) => (10, r),
+            GuestRegister::Aarch64X11(r) => (11, r),
+            GuestRegister::Aarch64X12(r) => (12, r),
+            GuestRegister::Aarch64X13(r) => (13, r),
+            GuestRegister::Aarch64X14(r) => (14, r),
+            GuestRegister::Aarch64X15(r) => (15, r),
+            GuestRegister::Aarch64X16(r) => (16, r),
+            GuestRegister::Aarch64X17(r) => (17, r),
+            GuestRegister::Aarch64X18(r) => (18, r),
+            GuestRegister::Aarch64X19(r) => (19, r),
+            GuestRegister::Aarch64X20(r) => (20, r),
+            GuestRegister::Aarch64X21(r) => (21, r),
+            GuestRegister::Aarch64X22(r) => (22, r),
+            GuestRegister::Aarch64X23(r) => (23, r),
+            GuestRegister::Aarch64X24(r) => (24, r),
+            GuestRegister::Aarch64X25(r) => (25, r),
+            GuestRegister::Aarch64X26(r) => (26, r),
+            GuestRegister::Aarch64X27(r) => (27, r),
+            GuestRegister::Aarch64X28(r) => (28, r),
+            GuestRegister::Aarch64X29(r) => (29, r),
+            GuestRegister::Aarch64X30(r) => (30, r),
+            _ => return None,
+        };
+        Some(gpr)
+    }
 }
 
 pub fn set_guest_register(vmsa: &mut VMSA, reg: &GuestRegister) {
@@ -44,5 +118,6 @@ pub fn set_guest_register(vmsa: &mut VMSA, reg: &GuestRegister) {
         GuestRegister::X64R13(r) => vmsa.r13 = *r,
         GuestRegister::X64R14(r) => vmsa.r14 = *r,
         GuestRegister::X64R15(r) => vmsa.r15 = *r,
+        _ => log::warn!("Ignoring {reg:?} for an x86 guest"),
     }
 }
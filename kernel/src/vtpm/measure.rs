@@ -0,0 +1,275 @@
+// SPDX-License-Identifier: MIT
+//
+// Copyright (c) Coconut-SVSM authors
+//
+
+//! Measurements of the SVSM launch into the vTPM PCRs.
+//!
+//! Before the guest firmware runs, the SVSM extends PCR 0 with the launch
+//! measurement of the platform, i.e. the MEASUREMENT of the SEV-SNP
+//! attestation report or the realm initial measurement (RIM) on CCA, and
+//! with the firmware images it prepared for the guest. Tables describing
+//! the platform to the firmware are extended into PCR 1. On CCA, the images
+//! handed to the aux plane are extended as they are measured into the REMs:
+//! the kernel into PCR 4, the device tree into PCR 1 and the initrd into
+//! PCR 9.
+//!
+//! The TPM computes the digests itself, with a PCR event or an event
+//! sequence, so that every allocated PCR bank is extended. Each extension is
+//! recorded in a TCG2 event log in the crypto agile format, which the guest
+//! firmware retrieves through the attest protocol and installs as a
+//! configuration table, so that the guest can replay the PCRs.
+//!
+//! The SVSM starts the TPM itself, so that TPM2_Startup of the guest fails
+//! with TPM_RC_INITIALIZE and leaves the PCRs untouched, and the guest
+//! cannot power the TPM off or reset it. Should the TPM restart all the
+//! same, the SVSM extends the recorded digests again before
+//! accepting any guest command. The images are not measured again, as the
+//! guest may have modified them since.
+
+extern crate alloc;
+
+use crate::address::PhysAddr;
+use crate::error::SvsmError;
+#[cfg(not(feature = "cca"))]
+use crate::greq::{pld_report::SnpReportResponse, services::get_regular_report};
+use crate::locking::SpinLock;
+use crate::mm::PerCPUPageMappingGuard;
+use crate::protocols::errors::SvsmReqError;
+#[cfg(feature = "cca")]
+use crate::realm::{
+    measure::{HashAlgo, ImageKind},
+    rsi::rsi_cmd::rsi_measurement_read,
+};
+use crate::tcg_log::{
+    EventLog, EV_EFI_PLATFORM_FIRMWARE_BLOB, EV_PLATFORM_CONFIG_FLAGS, EV_S_CRTM_CONTENTS,
+};
+use crate::types::{PageSize, PAGE_SIZE};
+use crate::utils::MemoryRegion;
+use crate::vtpm::tcgtpm::tss::{self, AuthSession, TPM_ALG_NULL};
+use crate::vtpm::TcgTpmSimulatorInterface;
+use alloc::vec::Vec;
+use core::slice;
+#[cfg(not(feature = "cca"))]
+use zerocopy::{FromZeros, IntoBytes};
+
+/// PCR extended with the launch measurement and the firmware code.
+const PCR_FW_CODE: u32 = 0;
+/// PCR extended with the tables describing the platform.
+const PCR_FW_CONFIG: u32 = 1;
+/// PCR extended with the kernel of the aux plane.
+#[cfg(feature = "cca")]
+const PCR_BOOT_CODE: u32 = 4;
+/// PCR extended with the initrd of the aux plane.
+#[cfg(feature = "cca")]
+const PCR_KERNEL_DATA: u32 = 9;
+
+/// An image prepared for the guest firmware.
+#[derive(Clone, Copy, Debug)]
+pub enum FwImage {
+    /// Firmware code, measured into PCR 0.
+    Code(MemoryRegion<PhysAddr>),
+    /// A table describing the platform, measured into PCR 1 with a
+    /// NUL-terminated description.
+    Table(MemoryRegion<PhysAddr>, &'static [u8]),
+}
+
+impl FwImage {
+    fn region(&self) -> MemoryRegion<PhysAddr> {
+        match *self {
+            Self::Code(region) | Self::Table(region, _) => region,
+        }
+    }
+
+    fn pcr(&self) -> u32 {
+        match self {
+            Self::Code(_) => PCR_FW_CODE,
+            Self::Table(..) => PCR_FW_CONFIG,
+        }
+    }
+
+    fn event_type(&self) -> u32 {
+        match self {
+            Self::Code(_) => EV_EFI_PLATFORM_FIRMWARE_BLOB,
+            Self::Table(..) => EV_PLATFORM_CONFIG_FLAGS,
+        }
+    }
+
+    /// Returns the event data: a UEFI_PLATFORM_FIRMWARE_BLOB for code, and
+    /// the description for tables.
+    fn event_data(&self) -> Vec<u8> {
+        match *self {
+            Self::Code(region) => {
+                let mut blob = Vec::new();
+                blob.extend_from_slice(&u64::from(region.start()).to_le_bytes());
+                blob.extend_from_slice(&(region.len() as u64).to_le_bytes());
+                blob
+            }
+            Self::Table(_, description) => description.to_vec(),
+        }
+    }
+}
+
+/// Extends the digests recorded in `log` into the PCRs of `vtpm` again.
+fn replay<T: TcgTpmSimulatorInterface>(vtpm: &T, log: &EventLog) -> Result<(), SvsmReqError> {
+    let auth = AuthSession::empty_password();
+    for event in log.events() {
+        let digests: Vec<(u16, &[u8])> = event
+            .digests
+            .iter()
+            .map(|(alg, digest)| (*alg, digest.as_slice()))
+            .collect();
+        tss::pcr_extend(vtpm, event.index, &auth, &digests)?;
+    }
+    Ok(())
+}
+
+static EVENT_LOG: SpinLock<EventLog> = SpinLock::new(EventLog::new());
+
+/// Returns the serialized event log, whose Spec ID event lists the banks
+/// of the first recorded event.
+pub fn event_log() -> Vec<u8> {
+    let log = EVENT_LOG.lock();
+    let banks: Vec<(u16, u16)> = log
+        .events()
+        .first()
+        .map(|event| {
+            event
+                .digests
+                .iter()
+                .map(|(alg, digest)| (*alg, digest.len() as u16))
+                .collect()
+        })
+        .unwrap_or_default();
+    log.to_vec(&banks)
+}
+
+/// Returns the MEASUREMENT of a VMPL0 attestation report.
+#[cfg(not(feature = "cca"))]
+fn launch_measurement() -> Result<Vec<u8>, SvsmReqError> {
+    let mut resp = SnpReportResponse::new_box_zeroed()
+        .map_err(|_| SvsmReqError::FatalError(SvsmError::Mem))?;
+    // The zeroed buffer is a request for a VMPL0 report with zero
+    // REPORT_DATA.
+    get_regular_report(resp.as_mut_bytes())?;
+    resp.validate()?;
+    Ok(resp.measurement().to_vec())
+}
+
+/// Returns the realm initial measurement.
+#[cfg(feature = "cca")]
+fn launch_measurement() -> Result<Vec<u8>, SvsmReqError> {
+    let size = HashAlgo::realm().map_err(SvsmError::from)?.digest_size();
+    let rim = rsi_measurement_read(0).map_err(SvsmError::from)?;
+    Ok(rim[..size].to_vec())
+}
+
+/// Adds the contents of `region`, which holds guest memory not used by the
+/// SVSM, to the sequence `sequence`.
+fn sequence_update_region<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    sequence: u32,
+    auth: &AuthSession,
+    region: MemoryRegion<PhysAddr>,
+) -> Result<(), SvsmReqError> {
+    for paddr in region.iter_pages(PageSize::Regular) {
+        let guard = PerCPUPageMappingGuard::create_4k(paddr)?;
+        // SAFETY: the guard maps a full page of guest memory, which the
+        // guest cannot modify, as none of its vCPUs runs yet.
+        let page = unsafe { slice::from_raw_parts(guard.virt_addr().as_ptr::<u8>(), PAGE_SIZE) };
+        tss::sequence_update(vtpm, sequence, auth, page)?;
+    }
+    Ok(())
+}
+
+/// Extends the digests of the data added by `update` to an event sequence
+/// into `pcr`, and returns them.
+fn event_sequence<T, F>(vtpm: &T, pcr: u32, update: F) -> Result<Vec<(u16, Vec<u8>)>, SvsmReqError>
+where
+    T: TcgTpmSimulatorInterface,
+    F: FnOnce(u32, &AuthSession) -> Result<(), SvsmReqError>,
+{
+    let auth = AuthSession::empty_password();
+    let sequence = tss::hash_sequence_start(vtpm, &[], TPM_ALG_NULL)?;
+    let result = update(sequence, &auth).and_then(|()| {
+        tss::event_sequence_complete(vtpm, pcr, &auth, sequence, &auth, &[])
+            .map_err(SvsmReqError::from)
+    });
+    if result.is_err() {
+        // The TPM only flushes the sequence when it completes.
+        tss::flush_context(vtpm, sequence)?;
+    }
+    result
+}
+
+/// Extends the digests of `image` into its PCR and records them.
+fn measure_fw_image<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    log: &mut EventLog,
+    image: &FwImage,
+) -> Result<(), SvsmReqError> {
+    let digests = event_sequence(vtpm, image.pcr(), |sequence, auth| {
+        sequence_update_region(vtpm, sequence, auth, image.region())
+    })?;
+    log.record(image.pcr(), image.event_type(), digests, image.event_data());
+    Ok(())
+}
+
+/// Extends the launch measurement and the digests of `fw_images` into the
+/// PCRs of `vtpm`, which must be started, and records them in the event
+/// log.
+pub fn measure_launch<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    fw_images: &[FwImage],
+) -> Result<(), SvsmReqError> {
+    // Only keep a complete log, which [`replay_launch()`] can extend again.
+    let mut log = EventLog::new();
+    let auth = AuthSession::empty_password();
+
+    let measurement = launch_measurement()?;
+    let digests = tss::pcr_event(vtpm, PCR_FW_CODE, &auth, &measurement)?;
+    log.record(PCR_FW_CODE, EV_S_CRTM_CONTENTS, digests, measurement);
+
+    for image in fw_images {
+        measure_fw_image(vtpm, &mut log, image)?;
+    }
+    *EVENT_LOG.lock() = log;
+
+    log::info!(
+        "VTPM: measured the launch and {} firmware image(s) into the PCRs",
+        fw_images.len()
+    );
+    Ok(())
+}
+
+/// Extends the digests recorded in the event log, if any, into the
+/// PCRs of `vtpm`, which must have been restarted.
+pub fn replay_launch<T: TcgTpmSimulatorInterface>(vtpm: &T) -> Result<(), SvsmReqError> {
+    replay(vtpm, &EVENT_LOG.lock())
+}
+
+/// Extends the digests of `data`, an aux plane image of `kind`, into the
+/// PCRs of `vtpm` and records them in the event log, so that they are
+/// extended again with the launch measurements if the TPM restarts.
+#[cfg(feature = "cca")]
+pub fn measure_plane_image<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    kind: ImageKind,
+    data: &[u8],
+) -> Result<(), SvsmReqError> {
+    let pcr = match kind {
+        ImageKind::Kernel => PCR_BOOT_CODE,
+        ImageKind::DeviceTree => PCR_FW_CONFIG,
+        ImageKind::Initrd => PCR_KERNEL_DATA,
+        // Not an image, only bound to the realm token.
+        ImageKind::VtpmNv => return Ok(()),
+    };
+    let digests = event_sequence(vtpm, pcr, |sequence, auth| {
+        tss::sequence_update(vtpm, sequence, auth, data).map_err(SvsmReqError::from)
+    })?;
+    EVENT_LOG
+        .lock()
+        .record(pcr, kind.event_type(), digests, kind.description().to_vec());
+    log::info!("VTPM: measured {kind:?} into PCR {pcr}");
+    Ok(())
+}
@@ -6,7 +6,15 @@
 
 //! This crate defines the Virtual TPM interfaces and shows what
 //! TPM backends are supported
-
+//!
+//! Unit tests are not linked against the TPM library, so only the parts
+//! which do not drive the TPM instance are built for them.
+
+/// Measurements of the SVSM launch into the PCRs
+#[cfg(not(test))]
+pub mod measure;
+/// Sealed storage of the TPM NV memory
+pub mod nvstore;
 /// TPM 2.0 Reference Implementation
 pub mod tcgtpm;
 
@@ -14,9 +22,22 @@ extern crate alloc;
 
 use alloc::vec::Vec;
 
-use crate::vtpm::tcgtpm::TcgTpm as Vtpm;
-use crate::{locking::LockGuard, protocols::vtpm::TpmPlatformCommand};
-use crate::{locking::SpinLock, protocols::errors::SvsmReqError};
+use crate::vtpm::tcgtpm::tss::TpmRc;
+use crate::{protocols::errors::SvsmReqError, protocols::vtpm::TpmPlatformCommand};
+
+#[cfg(not(test))]
+use crate::{
+    block::api::BlockDriver,
+    locking::{LockGuard, SpinLock},
+    vtpm::measure::{measure_launch, FwImage},
+    vtpm::nvstore::NvStore,
+    vtpm::tcgtpm::{TcgTpm as Vtpm, NV_MEMORY_SIZE},
+};
+#[cfg(not(test))]
+use alloc::boxed::Box;
+
+#[cfg(all(feature = "cca", not(test)))]
+use crate::realm::measure::{measure_image, ImageKind};
 
 /// Basic services required to perform the VTPM Protocol
 pub trait VtpmProtocolInterface {
@@ -57,12 +78,33 @@ pub trait TcgTpmSimulatorInterface: VtpmProtocolInterface {
     /// the NV may not always be available. This function indicates that NV
     /// is available.
     fn signal_nvon(&self) -> Result<(), SvsmReqError>;
+
+    /// Power-off the TPM. Its volatile state is lost, and it has to be
+    /// powered on again before it can run commands.
+    fn signal_poweroff(&mut self) -> Result<(), SvsmReqError>;
+
+    /// Reset the TPM without powering it off. Like on power-on, the TPM is
+    /// started again.
+    fn signal_reset(&mut self) -> Result<(), SvsmReqError>;
+
+    /// Indicate that the NV memory is no longer available.
+    fn signal_nvoff(&self) -> Result<(), SvsmReqError>;
+
+    /// Request the cancellation of the TPM command being run. The request
+    /// stays pending until [`Self::signal_cancel_off()`] is called.
+    fn signal_cancel_on(&self) -> Result<(), SvsmReqError>;
+
+    /// Withdraw the request to cancel TPM commands.
+    fn signal_cancel_off(&self) -> Result<(), SvsmReqError>;
 }
 
 #[derive(Debug)]
 pub enum SvsmVTpmError {
     ReqError(SvsmReqError),
-    CommandError(u32),
+    /// The TPM failed the command with a response code
+    CommandError(TpmRc),
+    /// The TPM response does not match the command
+    MalformedResponse,
 }
 
 impl From<SvsmReqError> for SvsmVTpmError {
@@ -75,7 +117,9 @@ impl From<SvsmVTpmError> for SvsmReqError {
     fn from(err: SvsmVTpmError) -> Self {
         match err {
             SvsmVTpmError::ReqError(e) => e,
-            SvsmVTpmError::CommandError(_) => SvsmReqError::invalid_request(),
+            SvsmVTpmError::CommandError(_) | SvsmVTpmError::MalformedResponse => {
+                SvsmReqError::invalid_request()
+            }
         }
     }
 }
@@ -93,27 +137,90 @@ pub trait VtpmInterface: TcgTpmSimulatorInterface {
     /// that the EK public key does not exist.
     /// Needs mutability to cache the key.
     fn get_ekpub(&mut self) -> Result<Vec<u8>, SvsmReqError>;
+
+    /// Saves the NV memory of the TPM to its persistent storage, if it has
+    /// any and the NV memory changed since it was last saved.
+    fn sync_nv(&mut self) -> Result<(), SvsmReqError>;
 }
 
+#[cfg(not(test))]
 static VTPM: SpinLock<Vtpm> = SpinLock::new(Vtpm::new());
 
 /// Initialize the TPM by calling the init() implementation of the
-/// [`VtpmInterface`]
-pub fn vtpm_init() -> Result<(), SvsmReqError> {
+/// [`VtpmInterface`], and extend its PCRs with the launch measurement and
+/// with `fw_images`, the images prepared for the guest firmware.
+#[cfg(not(test))]
+pub fn vtpm_init(fw_images: &[FwImage]) -> Result<(), SvsmReqError> {
     let mut vtpm = VTPM.lock();
     if vtpm.is_powered_on() {
         return Ok(());
     }
     vtpm.init()?;
-    Ok(())
+    measure_launch(&*vtpm, fw_images)
+}
+
+/// Keep the NV memory of the TPM on `dev`. It must be called before
+/// attestation, otherwise the TPM state is lost at every boot.
+///
+/// # Returns
+///
+/// The counter of the newest NV image on `dev`, which the caller binds into
+/// the attestation evidence. On CCA it is also measured into the REM of the
+/// SVSM, which is part of the realm token.
+#[cfg(not(test))]
+pub fn vtpm_attach_nv_storage(dev: Box<dyn BlockDriver + Send>) -> Result<u64, SvsmReqError> {
+    let store = NvStore::new(dev, NV_MEMORY_SIZE)?;
+    let counter = store.stored_counter();
+    #[cfg(feature = "cca")]
+    measure_image(ImageKind::VtpmNv, &counter.to_le_bytes())?;
+    VTPM.lock().attach_nv_store(store)?;
+    Ok(counter)
+}
+
+/// Unseal the NV memory attached with [`vtpm_attach_nv_storage()`] with a
+/// key derived from `secret`, which the attestation server released. It
+/// must be called before [`vtpm_init()`].
+#[cfg(not(test))]
+pub fn vtpm_unseal_nv_storage(secret: &[u8]) -> Result<(), SvsmReqError> {
+    VTPM.lock().set_nv_secret(secret)
 }
 
+#[cfg(not(test))]
 pub fn vtpm_get_locked<'a>() -> LockGuard<'a, Vtpm> {
     VTPM.lock()
 }
 
+/// Extend `data`, an image of `kind` handed to the aux plane, into the PCRs
+/// of the TPM, which [`vtpm_init()`] must have started.
+#[cfg(all(feature = "cca", not(test)))]
+pub fn vtpm_measure_image(kind: ImageKind, data: &[u8]) -> Result<(), SvsmReqError> {
+    let vtpm = VTPM.lock();
+    if !vtpm.is_powered_on() {
+        return Err(SvsmReqError::invalid_request());
+    }
+    measure::measure_plane_image(&*vtpm, kind, data)
+}
+
+/// Get the TCG2 event log of the measurements extended into the PCRs by
+/// [`vtpm_init()`] and [`vtpm_measure_image()`].
+#[cfg(not(test))]
+pub fn vtpm_get_event_log() -> Vec<u8> {
+    measure::event_log()
+}
+
+/// Get the launch evidence binding the issuer of the EK certificate, or an
+/// empty buffer if the certificate was not provisioned.
+#[cfg(not(test))]
+pub fn vtpm_get_ek_cert_evidence() -> Vec<u8> {
+    VTPM.lock()
+        .ek_cert_evidence()
+        .map(<[u8]>::to_vec)
+        .unwrap_or_default()
+}
+
 /// Get the TPM manifest i.e the EK public key by calling the get_ekpub() implementation of the
 /// [`VtpmInterface`]
+#[cfg(not(test))]
 pub fn vtpm_get_manifest() -> Result<Vec<u8>, SvsmReqError> {
     let mut vtpm = VTPM.lock();
     vtpm.get_ekpub()
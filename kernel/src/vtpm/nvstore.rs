@@ -0,0 +1,484 @@
+// SPDX-License-Identifier: MIT
+
+//! Persistent storage of the vTPM NV state.
+//!
+//! The NV image of the TPM is kept on a block device in two slots, which
+//! are written alternately, so that an interrupted write never destroys
+//! the last good image. Each slot holds an [`NvHeader`] followed by the
+//! image sealed with AES-256-GCM, with the header as additional
+//! authenticated data. The sealing key is derived from a secret released by
+//! the attestation server, so only an SVSM which passed attestation can
+//! unseal the image.
+//!
+//! The header carries a counter which is incremented on every write.
+//! Loading picks the valid slot with the highest counter, so an old image
+//! left in the other slot is never preferred over a newer one.
+//!
+//! The counter is not secret, so the store is opened before attestation and
+//! the highest counter on the device is bound into the attestation
+//! evidence. An attestation server only releasing the secret for the newest
+//! counter it has seen thereby detects a host replaying an older disk
+//! image. As the counter in the header is only authenticated once the
+//! secret is known, [`NvStore::load()`] refuses an image whose counter
+//! differs from the one bound into the evidence.
+
+extern crate alloc;
+
+use crate::block::api::BlockDriver;
+use crate::crypto::aead::{Aes256Gcm, Aes256GcmTrait, AUTHTAG_SIZE, IV_SIZE, KEY_SIZE};
+use crate::error::SvsmError;
+use crate::protocols::errors::SvsmReqError;
+use crate::types::PAGE_SIZE;
+use crate::utils::vec::vec_sized;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use sha2::{Digest, Sha256};
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+const NV_MAGIC: [u8; 8] = *b"SVSMTPNV";
+const NV_VERSION: u32 = 1;
+
+/// Number of slots the NV image is written to alternately.
+const NV_SLOTS: usize = 2;
+
+/// Label mixed into the derivation of the sealing key.
+const NV_KEY_LABEL: &[u8] = b"COCONUT-SVSM vTPM NV sealing key\0";
+
+type NvDigest = [u8; 32];
+
+/// Header of a sealed NV image.
+#[repr(C)]
+#[derive(FromBytes, IntoBytes, Immutable, KnownLayout, Clone, Copy, Debug)]
+struct NvHeader {
+    magic: [u8; 8],
+    version: u32,
+    /// Size of the NV image in bytes, without the authentication tag.
+    size: u32,
+    /// Incremented on every write of the image.
+    counter: u64,
+    iv: [u8; IV_SIZE],
+    reserved: [u8; 4],
+}
+
+const NV_HEADER_SIZE: usize = size_of::<NvHeader>();
+
+/// Contents of one slot.
+#[derive(Debug)]
+enum NvSlot {
+    /// The slot was never written.
+    Empty,
+    /// The slot holds an image which cannot be unsealed. The counter is not
+    /// authenticated.
+    Corrupt(u64),
+    /// The slot holds a valid image.
+    Valid { counter: u64, image: Vec<u8> },
+}
+
+/// Sealed storage of an NV image of a fixed size on a block device.
+pub struct NvStore {
+    dev: Box<dyn BlockDriver + Send>,
+    /// Sealing key, known once the attestation secret is released.
+    key: Option<[u8; KEY_SIZE]>,
+    image_size: usize,
+    slot_size: usize,
+    /// Highest counter in the slot headers when the store was opened. It is
+    /// not authenticated.
+    stored_counter: u64,
+    /// Highest counter of a valid image on the device or written to it.
+    counter: u64,
+    next_slot: usize,
+    /// Digest of the image last loaded or saved.
+    digest: Option<NvDigest>,
+}
+
+impl core::fmt::Debug for NvStore {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("NvStore")
+            .field("image_size", &self.image_size)
+            .field("stored_counter", &self.stored_counter)
+            .field("counter", &self.counter)
+            .field("next_slot", &self.next_slot)
+            .finish_non_exhaustive()
+    }
+}
+
+fn nv_digest(image: &[u8]) -> NvDigest {
+    Sha256::digest(image).into()
+}
+
+/// Returns the header at the start of `buf`, if it holds one.
+fn nv_header(buf: &[u8]) -> Option<NvHeader> {
+    NvHeader::read_from_prefix(buf)
+        .ok()
+        .map(|(header, _)| header)
+        .filter(|header| header.magic == NV_MAGIC)
+}
+
+/// Derives the IV from the counter and the image, so that an IV is only
+/// ever reused to seal the very same image.
+fn nv_iv(counter: u64, digest: &NvDigest) -> [u8; IV_SIZE] {
+    let mut hasher = Sha256::new();
+    hasher.update(counter.to_le_bytes());
+    hasher.update(digest);
+    let mut iv = [0u8; IV_SIZE];
+    iv.copy_from_slice(&hasher.finalize()[..IV_SIZE]);
+    iv
+}
+
+impl NvStore {
+    /// Opens a store for NV images of `image_size` bytes on `dev`, and
+    /// reads the counters in its slot headers.
+    pub fn new(dev: Box<dyn BlockDriver + Send>, image_size: usize) -> Result<Self, SvsmReqError> {
+        let unit = PAGE_SIZE.max(1 << dev.block_size_log2());
+        let slot_size = (NV_HEADER_SIZE + image_size + AUTHTAG_SIZE).next_multiple_of(unit);
+        if u32::try_from(image_size).is_err() || slot_size * NV_SLOTS > dev.size() {
+            log::error!("vTPM NV: storage device too small");
+            return Err(SvsmReqError::invalid_parameter());
+        }
+
+        let mut store = Self {
+            dev,
+            key: None,
+            image_size,
+            slot_size,
+            stored_counter: 0,
+            counter: 0,
+            next_slot: 0,
+            digest: None,
+        };
+        for slot in 0..NV_SLOTS {
+            let buf = store.read_raw(slot)?;
+            if let Some(header) = nv_header(&buf) {
+                store.stored_counter = store.stored_counter.max(header.counter);
+            }
+        }
+        Ok(store)
+    }
+
+    /// Derives the sealing key from `secret`, which the attestation server
+    /// released for evidence bound to [`Self::stored_counter()`].
+    pub fn set_secret(&mut self, secret: &[u8]) {
+        let mut hasher = Sha256::new();
+        hasher.update(NV_KEY_LABEL);
+        hasher.update(secret);
+        self.key = Some(hasher.finalize().into());
+    }
+
+    /// Returns the highest counter in the slot headers when the store was
+    /// opened, which is the counter of the image [`Self::load()`] accepts.
+    /// It is 0 if the device holds no image.
+    pub fn stored_counter(&self) -> u64 {
+        self.stored_counter
+    }
+
+    /// Returns the highest counter of a valid image on the device or
+    /// written to it.
+    pub fn counter(&self) -> u64 {
+        self.counter
+    }
+
+    fn slot_block(&self, slot: usize) -> usize {
+        (slot * self.slot_size) >> self.dev.block_size_log2()
+    }
+
+    fn read_raw(&self, slot: usize) -> Result<Vec<u8>, SvsmReqError> {
+        let mut buf = vec_sized::<u8>(self.slot_size).map_err(|_| SvsmError::Mem)?;
+        self.dev.read_blocks(self.slot_block(slot), &mut buf)?;
+        Ok(buf)
+    }
+
+    fn read_slot(&self, slot: usize) -> Result<NvSlot, SvsmReqError> {
+        let key = self
+            .key
+            .as_ref()
+            .ok_or_else(SvsmReqError::invalid_request)?;
+        let buf = self.read_raw(slot)?;
+        let Some(header) = nv_header(&buf) else {
+            return Ok(NvSlot::Empty);
+        };
+        if header.version != NV_VERSION || header.size as usize != self.image_size {
+            return Ok(NvSlot::Corrupt(header.counter));
+        }
+
+        let mut image = vec_sized::<u8>(self.image_size).map_err(|_| SvsmError::Mem)?;
+        let sealed = &buf[NV_HEADER_SIZE..NV_HEADER_SIZE + self.image_size + AUTHTAG_SIZE];
+        match Aes256Gcm::decrypt(&header.iv, key, header.as_bytes(), sealed, &mut image) {
+            Ok(_) => Ok(NvSlot::Valid {
+                counter: header.counter,
+                image,
+            }),
+            Err(_) => Ok(NvSlot::Corrupt(header.counter)),
+        }
+    }
+
+    /// Loads the newest valid NV image. [`Self::set_secret()`] must have
+    /// been called before.
+    ///
+    /// # Returns
+    ///
+    /// The image, or `None` if the device holds no valid image. This is the
+    /// case on the first boot, but also when all images are corrupted or
+    /// were sealed with a different key. The caller is then expected to
+    /// manufacture a new TPM and save its NV image, which supersedes any
+    /// image on the device.
+    ///
+    /// An error is returned if the newest valid image is older than
+    /// [`Self::stored_counter()`], as falling back to it would roll back the
+    /// TPM state behind the back of the attestation server.
+    pub fn load(&mut self) -> Result<Option<Vec<u8>>, SvsmReqError> {
+        let mut newest: Option<(u64, usize, Vec<u8>)> = None;
+        // The counters of corrupted slots are not authenticated, so they
+        // only serve diagnostics and never advance `self.counter`.
+        let mut corrupt = false;
+        for slot in 0..NV_SLOTS {
+            match self.read_slot(slot)? {
+                NvSlot::Empty => {}
+                NvSlot::Corrupt(counter) => {
+                    log::warn!("vTPM NV: slot {slot} (counter {counter}) is corrupted");
+                    corrupt = true;
+                }
+                NvSlot::Valid { counter, image } => {
+                    self.counter = self.counter.max(counter);
+                    if newest.as_ref().is_none_or(|(c, ..)| counter > *c) {
+                        newest = Some((counter, slot, image));
+                    }
+                }
+            }
+        }
+
+        let Some((counter, slot, image)) = newest else {
+            if corrupt {
+                log::error!("vTPM NV: no valid image found, starting from scratch");
+            }
+            return Ok(None);
+        };
+        if counter != self.stored_counter {
+            log::error!(
+                "vTPM NV: newest valid image has counter {counter}, but {} was attested",
+                self.stored_counter
+            );
+            return Err(SvsmReqError::invalid_request());
+        }
+        self.next_slot = (slot + 1) % NV_SLOTS;
+        self.digest = Some(nv_digest(&image));
+        Ok(Some(image))
+    }
+
+    /// Seals and saves `image`, unless it is unchanged since it was last
+    /// loaded or saved.
+    pub fn save(&mut self, image: &[u8]) -> Result<(), SvsmReqError> {
+        if image.len() != self.image_size {
+            return Err(SvsmReqError::invalid_parameter());
+        }
+        let key = self.key.ok_or_else(SvsmReqError::invalid_request)?;
+        let digest = nv_digest(image);
+        if self.digest == Some(digest) {
+            return Ok(());
+        }
+
+        let counter = self
+            .counter
+            .checked_add(1)
+            .ok_or_else(SvsmReqError::invalid_request)?;
+        let header = NvHeader {
+            magic: NV_MAGIC,
+            version: NV_VERSION,
+            size: self.image_size as u32,
+            counter,
+            iv: nv_iv(counter, &digest),
+            reserved: [0; 4],
+        };
+
+        let mut buf = vec_sized::<u8>(self.slot_size).map_err(|_| SvsmError::Mem)?;
+        buf[..NV_HEADER_SIZE].copy_from_slice(header.as_bytes());
+        let sealed = &mut buf[NV_HEADER_SIZE..NV_HEADER_SIZE + self.image_size + AUTHTAG_SIZE];
+        Aes256Gcm::encrypt(&header.iv, &key, header.as_bytes(), image, sealed)?;
+
+        self.dev
+            .write_blocks(self.slot_block(self.next_slot), &buf)?;
+        self.dev.flush()?;
+
+        self.counter = counter;
+        self.next_slot = (self.next_slot + 1) % NV_SLOTS;
+        self.digest = Some(digest);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockDeviceError;
+    use crate::locking::SpinLock;
+    use alloc::sync::Arc;
+    use alloc::vec;
+
+    const BLOCK_SIZE_LOG2: u8 = 9;
+    const IMAGE_SIZE: usize = 100;
+    /// Each slot fits in a page.
+    const SLOT_SIZE: usize = PAGE_SIZE;
+    const SECRET: &[u8] = b"attestation secret";
+
+    /// Block device kept in memory, which the tests can tamper with while a
+    /// store uses it.
+    #[derive(Clone, Debug)]
+    struct MemDisk(Arc<SpinLock<Vec<u8>>>);
+
+    impl MemDisk {
+        fn new(size: usize) -> Self {
+            Self(Arc::new(SpinLock::new(vec![0; size])))
+        }
+
+        fn open(&self) -> NvStore {
+            let mut store = NvStore::new(Box::new(self.clone()), IMAGE_SIZE).unwrap();
+            store.set_secret(SECRET);
+            store
+        }
+
+        /// Flips a bit of the sealed image in `slot`.
+        fn corrupt(&self, slot: usize) {
+            self.0.lock()[slot * SLOT_SIZE + NV_HEADER_SIZE] ^= 1;
+        }
+
+        fn header(&self, slot: usize) -> Option<NvHeader> {
+            nv_header(&self.0.lock()[slot * SLOT_SIZE..])
+        }
+    }
+
+    impl BlockDriver for MemDisk {
+        fn read_blocks(&self, block_id: usize, buf: &mut [u8]) -> Result<(), SvsmError> {
+            let start = block_id << BLOCK_SIZE_LOG2;
+            let disk = self.0.lock();
+            let data = disk
+                .get(start..start + buf.len())
+                .ok_or(SvsmError::Block(BlockDeviceError::Failed))?;
+            buf.copy_from_slice(data);
+            Ok(())
+        }
+
+        fn write_blocks(&self, block_id: usize, buf: &[u8]) -> Result<(), SvsmError> {
+            let start = block_id << BLOCK_SIZE_LOG2;
+            let mut disk = self.0.lock();
+            let data = disk
+                .get_mut(start..start + buf.len())
+                .ok_or(SvsmError::Block(BlockDeviceError::Failed))?;
+            data.copy_from_slice(buf);
+            Ok(())
+        }
+
+        fn block_size_log2(&self) -> u8 {
+            BLOCK_SIZE_LOG2
+        }
+
+        fn size(&self) -> usize {
+            self.0.lock().len()
+        }
+
+        fn flush(&self) -> Result<(), SvsmError> {
+            Ok(())
+        }
+    }
+
+    fn image(byte: u8) -> Vec<u8> {
+        vec![byte; IMAGE_SIZE]
+    }
+
+    #[test]
+    fn empty_device() {
+        let disk = MemDisk::new(NV_SLOTS * SLOT_SIZE);
+        let mut store = NvStore::new(Box::new(disk.clone()), IMAGE_SIZE).unwrap();
+        assert_eq!(store.stored_counter(), 0);
+        assert!(store.load().is_err());
+
+        store.set_secret(SECRET);
+        assert_eq!(store.load().unwrap(), None);
+        store.save(&image(1)).unwrap();
+        assert_eq!(store.counter(), 1);
+
+        let mut store = disk.open();
+        assert_eq!(store.stored_counter(), 1);
+        assert_eq!(store.load().unwrap(), Some(image(1)));
+    }
+
+    #[test]
+    fn corrupt_slot() {
+        let disk = MemDisk::new(NV_SLOTS * SLOT_SIZE);
+        let mut store = disk.open();
+        store.save(&image(1)).unwrap();
+        store.save(&image(2)).unwrap();
+        disk.corrupt(0);
+
+        let mut store = disk.open();
+        assert_eq!(store.stored_counter(), 2);
+        assert_eq!(store.load().unwrap(), Some(image(2)));
+
+        // Without any valid image, a new TPM is manufactured.
+        disk.corrupt(1);
+        let mut store = disk.open();
+        assert_eq!(store.load().unwrap(), None);
+        assert_eq!(store.counter(), 0);
+    }
+
+    #[test]
+    fn newer_slot_corrupt() {
+        let disk = MemDisk::new(NV_SLOTS * SLOT_SIZE);
+        let mut store = disk.open();
+        store.save(&image(1)).unwrap();
+        store.save(&image(2)).unwrap();
+        disk.corrupt(1);
+
+        // The attested counter is 2, so falling back to image 1 would roll
+        // the TPM state back.
+        let mut store = disk.open();
+        assert_eq!(store.stored_counter(), 2);
+        assert!(store.load().is_err());
+    }
+
+    #[test]
+    fn counter_ordering() {
+        let disk = MemDisk::new(NV_SLOTS * SLOT_SIZE);
+        let mut store = disk.open();
+        for byte in 1..=3 {
+            store.save(&image(byte)).unwrap();
+        }
+        assert_eq!(disk.header(0).unwrap().counter, 3);
+        assert_eq!(disk.header(1).unwrap().counter, 2);
+
+        let mut store = disk.open();
+        assert_eq!(store.stored_counter(), 3);
+        assert_eq!(store.load().unwrap(), Some(image(3)));
+        assert_eq!(store.counter(), 3);
+
+        // An unchanged image is not written again.
+        store.save(&image(3)).unwrap();
+        assert_eq!(store.counter(), 3);
+
+        // The next image replaces the older one.
+        store.save(&image(4)).unwrap();
+        assert_eq!(store.counter(), 4);
+        assert_eq!(disk.header(0).unwrap().counter, 3);
+        assert_eq!(disk.header(1).unwrap().counter, 4);
+
+        let mut store = disk.open();
+        assert_eq!(store.stored_counter(), 4);
+        assert_eq!(store.load().unwrap(), Some(image(4)));
+    }
+
+    #[test]
+    fn size_mismatch() {
+        let disk = MemDisk::new(NV_SLOTS * SLOT_SIZE - 1);
+        assert!(NvStore::new(Box::new(disk), IMAGE_SIZE).is_err());
+
+        let disk = MemDisk::new(NV_SLOTS * SLOT_SIZE);
+        let mut store = disk.open();
+        assert!(store.save(&[0; IMAGE_SIZE - 1]).is_err());
+        store.save(&image(1)).unwrap();
+
+        // An image of another size is never unsealed.
+        let mut store = NvStore::new(Box::new(disk), IMAGE_SIZE + 1).unwrap();
+        store.set_secret(SECRET);
+        assert_eq!(store.stored_counter(), 1);
+        assert_eq!(store.load().unwrap(), None);
+    }
+}
@@ -0,0 +1,567 @@
+// SPDX-License-Identifier: MIT
+//
+// Copyright (c) Coconut-SVSM authors
+//
+
+//! EK certificate of the vTPM.
+//!
+//! The certificate follows the TCG EK Credential Profile for TPM Family 2.0
+//! and is provisioned at the NV index where guest software such as
+//! tpm2-tools and keylime looks for it.
+//!
+//! There is no manufacturer CA to issue it. The EK cannot sign either, as
+//! it is a restricted decryption key. Instead, the certificate is issued
+//! by a signing key which the vTPM creates at every boot, and which is
+//! bound to the launch of the SVSM: the certificate embeds the public key
+//! of the issuer together with the SHA-512 digest of the platform evidence
+//! (the SEV-SNP attestation report or the CCA realm token) whose report
+//! data is the SHA-512 digest of that public key. The evidence itself does
+//! not fit in an NV index, so the attest protocol serves it as a service
+//! manifest. A verifier checks the evidence against the digest, then the
+//! report data and the certificate signature.
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::protocols::attest::get_evidence;
+use crate::protocols::errors::SvsmReqError;
+use crate::vtpm::tcgtpm::tss::{self, AuthSession, NvPublic, SigScheme, Signature, TpmReader};
+use crate::vtpm::tcgtpm::tss::{SensitiveCreate, TPM_ALG_ECDSA, TPM_ALG_NULL, TPM_ALG_SHA256};
+use crate::vtpm::TcgTpmSimulatorInterface;
+
+/// NV index of the certificate of an RSA 2048 EK.
+pub const EK_CERT_RSA_NV_INDEX: u32 = 0x01C0_0002;
+/// NV index of the certificate of an ECC NIST P-256 EK.
+pub const EK_CERT_ECC_NV_INDEX: u32 = 0x01C0_000A;
+
+/// Largest certificate which fits in an NV index. It must not exceed
+/// `MAX_NV_INDEX_SIZE` in the TpmProfile.
+const EK_CERT_MAX_SIZE: usize = 2048;
+
+/// TPMA_NV attributes of the EK certificate NV index: PPWRITE, PPREAD,
+/// OWNERREAD, AUTHREAD, NO_DA and PLATFORMCREATE.
+const EK_CERT_NV_ATTRIBUTES: u32 =
+    (1 << 0) | (1 << 16) | (1 << 17) | (1 << 18) | (1 << 25) | (1 << 30);
+
+// TPM_PT values
+const TPM_PT_MANUFACTURER: u32 = 0x0105;
+const TPM_PT_FIRMWARE_VERSION_1: u32 = 0x010B;
+
+// TPM_ALG_ID values
+const TPM_ALG_RSA: u16 = 0x0001;
+const TPM_ALG_ECC: u16 = 0x0023;
+
+// TPM_ECC_CURVE values
+const TPM_ECC_NIST_P256: u16 = 0x0003;
+const TPM_ECC_NIST_P384: u16 = 0x0004;
+
+// Object identifiers
+const OID_RSA_ENCRYPTION: &[u128] = &[1, 2, 840, 113549, 1, 1, 1];
+const OID_EC_PUBLIC_KEY: &[u128] = &[1, 2, 840, 10045, 2, 1];
+const OID_PRIME256V1: &[u128] = &[1, 2, 840, 10045, 3, 1, 7];
+const OID_SECP384R1: &[u128] = &[1, 3, 132, 0, 34];
+const OID_ECDSA_WITH_SHA256: &[u128] = &[1, 2, 840, 10045, 4, 3, 2];
+const OID_SHA512: &[u128] = &[2, 16, 840, 1, 101, 3, 4, 2, 3];
+const OID_COMMON_NAME: &[u128] = &[2, 5, 4, 3];
+const OID_KEY_USAGE: &[u128] = &[2, 5, 29, 15];
+const OID_SUBJECT_ALT_NAME: &[u128] = &[2, 5, 29, 17];
+const OID_BASIC_CONSTRAINTS: &[u128] = &[2, 5, 29, 19];
+const OID_EXT_KEY_USAGE: &[u128] = &[2, 5, 29, 37];
+const OID_TCG_TPM_MANUFACTURER: &[u128] = &[2, 23, 133, 2, 1];
+const OID_TCG_TPM_MODEL: &[u128] = &[2, 23, 133, 2, 2];
+const OID_TCG_TPM_VERSION: &[u128] = &[2, 23, 133, 2, 3];
+const OID_TCG_KP_EK_CERTIFICATE: &[u128] = &[2, 23, 133, 8, 1];
+/// Extension holding the issuer public key and the digest of the launch
+/// evidence binding it. The OID is a UUID-based one (ITU-T X.667), which needs no
+/// registration.
+const OID_SVSM_LAUNCH_EVIDENCE: &[u128] = &[2, 25, 315821429856592565875866533862029747174];
+
+const EK_CERT_ISSUER_CN: &str = "COCONUT-SVSM vTPM EK issuer";
+const EK_CERT_TPM_MODEL: &str = "COCONUT-SVSM vTPM";
+
+/// The SVSM has no trusted time source, so the certificate is valid from
+/// a fixed date and does not expire (RFC 5280, section 4.1.2.5).
+const EK_CERT_NOT_BEFORE: &str = "000101000000Z";
+const EK_CERT_NOT_AFTER: &str = "99991231235959Z";
+
+// DER tags
+const DER_BOOLEAN: u8 = 0x01;
+const DER_INTEGER: u8 = 0x02;
+const DER_BIT_STRING: u8 = 0x03;
+const DER_OCTET_STRING: u8 = 0x04;
+const DER_NULL: u8 = 0x05;
+const DER_OID: u8 = 0x06;
+const DER_UTF8_STRING: u8 = 0x0C;
+const DER_UTC_TIME: u8 = 0x17;
+const DER_GENERALIZED_TIME: u8 = 0x18;
+const DER_SEQUENCE: u8 = 0x30;
+const DER_SET: u8 = 0x31;
+const DER_CONTEXT_0: u8 = 0xA0;
+const DER_CONTEXT_3: u8 = 0xA3;
+const DER_CONTEXT_4: u8 = 0xA4;
+
+fn der_tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let len = content.len();
+    let mut out = Vec::with_capacity(content.len() + 4);
+    out.push(tag);
+    if len < 0x80 {
+        out.push(len as u8);
+    } else if len <= 0xFF {
+        out.extend_from_slice(&[0x81, len as u8]);
+    } else {
+        out.push(0x82);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+fn der_seq(items: &[&[u8]]) -> Vec<u8> {
+    der_tlv(DER_SEQUENCE, &items.concat())
+}
+
+/// Encodes the big-endian unsigned integer `value`.
+fn der_uint(value: &[u8]) -> Vec<u8> {
+    let start = value.iter().position(|b| *b != 0).unwrap_or(value.len());
+    let value = &value[start..];
+    let mut content = Vec::with_capacity(value.len() + 1);
+    if value.first().is_none_or(|b| b & 0x80 != 0) {
+        content.push(0);
+    }
+    content.extend_from_slice(value);
+    der_tlv(DER_INTEGER, &content)
+}
+
+fn der_oid(arcs: &[u128]) -> Vec<u8> {
+    let mut content = Vec::new();
+    let first = arcs[0] * 40 + arcs[1];
+    for arc in core::iter::once(first).chain(arcs[2..].iter().copied()) {
+        let groups = (u128::BITS - arc.leading_zeros()).div_ceil(7).max(1);
+        for group in (0..groups).rev() {
+            let more = if group > 0 { 0x80 } else { 0 };
+            content.push(((arc >> (group * 7)) & 0x7F) as u8 | more);
+        }
+    }
+    der_tlv(DER_OID, &content)
+}
+
+fn der_bit_string(bits: &[u8]) -> Vec<u8> {
+    der_tlv(DER_BIT_STRING, &[&[0u8], bits].concat())
+}
+
+/// Encodes a Name with one attribute per relative distinguished name.
+fn der_name(attributes: &[(&[u128], &str)]) -> Vec<u8> {
+    let rdns: Vec<Vec<u8>> = attributes
+        .iter()
+        .map(|(oid, value)| {
+            let attribute = der_seq(&[&der_oid(oid), &der_tlv(DER_UTF8_STRING, value.as_bytes())]);
+            der_tlv(DER_SET, &attribute)
+        })
+        .collect();
+    der_tlv(DER_SEQUENCE, &rdns.concat())
+}
+
+fn der_extension(oid: &[u128], critical: bool, value: &[u8]) -> Vec<u8> {
+    let critical: &[u8] = if critical {
+        &[DER_BOOLEAN, 1, 0xFF]
+    } else {
+        &[]
+    };
+    der_seq(&[&der_oid(oid), critical, &der_tlv(DER_OCTET_STRING, value)])
+}
+
+/// Public key of a TPM object, parsed from its TPMT_PUBLIC.
+#[derive(Debug)]
+enum TpmPublicKey {
+    Rsa { modulus: Vec<u8>, exponent: u32 },
+    Ecc { curve: u16, x: Vec<u8>, y: Vec<u8> },
+}
+
+/// Skips a scheme, whose details are a hash algorithm for all schemes used
+/// by EKs and signing keys.
+fn skip_scheme(reader: &mut TpmReader<'_>) -> Result<(), SvsmReqError> {
+    if reader.u16()? != TPM_ALG_NULL {
+        reader.u16()?;
+    }
+    Ok(())
+}
+
+impl TpmPublicKey {
+    fn parse(tpmt_public: &[u8]) -> Result<Self, SvsmReqError> {
+        let mut reader = TpmReader::new(tpmt_public);
+        let key_type = reader.u16()?;
+        // nameAlg, objectAttributes and authPolicy
+        reader.u16()?;
+        reader.u32()?;
+        reader.tpm2b()?;
+        // symmetric
+        if reader.u16()? != TPM_ALG_NULL {
+            reader.u32()?;
+        }
+        skip_scheme(&mut reader)?;
+
+        match key_type {
+            TPM_ALG_RSA => {
+                // keyBits
+                reader.u16()?;
+                let exponent = match reader.u32()? {
+                    0 => 65537,
+                    exponent => exponent,
+                };
+                let modulus = reader.tpm2b()?.to_vec();
+                Ok(Self::Rsa { modulus, exponent })
+            }
+            TPM_ALG_ECC => {
+                let curve = reader.u16()?;
+                // kdf
+                skip_scheme(&mut reader)?;
+                let x = reader.tpm2b()?.to_vec();
+                let y = reader.tpm2b()?.to_vec();
+                Ok(Self::Ecc { curve, x, y })
+            }
+            _ => Err(SvsmReqError::invalid_request()),
+        }
+    }
+
+    /// Returns the DER SubjectPublicKeyInfo of the key.
+    fn spki(&self) -> Result<Vec<u8>, SvsmReqError> {
+        match self {
+            Self::Rsa { modulus, exponent } => {
+                let algorithm = der_seq(&[&der_oid(OID_RSA_ENCRYPTION), &[DER_NULL, 0]]);
+                let key = der_seq(&[&der_uint(modulus), &der_uint(&exponent.to_be_bytes())]);
+                Ok(der_seq(&[&algorithm, &der_bit_string(&key)]))
+            }
+            Self::Ecc { curve, x, y } => {
+                let (curve_oid, size) = match *curve {
+                    TPM_ECC_NIST_P256 => (OID_PRIME256V1, 32),
+                    TPM_ECC_NIST_P384 => (OID_SECP384R1, 48),
+                    _ => return Err(SvsmReqError::invalid_request()),
+                };
+                if x.len() > size || y.len() > size {
+                    return Err(SvsmReqError::invalid_request());
+                }
+                // Uncompressed point, with coordinates padded to the size
+                // of the curve.
+                let mut point = alloc::vec![0u8; 1 + 2 * size];
+                point[0] = 0x04;
+                point[1 + size - x.len()..1 + size].copy_from_slice(x);
+                point[1 + 2 * size - y.len()..].copy_from_slice(y);
+                let algorithm = der_seq(&[&der_oid(OID_EC_PUBLIC_KEY), &der_oid(curve_oid)]);
+                Ok(der_seq(&[&algorithm, &der_bit_string(&point)]))
+            }
+        }
+    }
+
+    /// Returns the NV index of the certificate of an EK with this key.
+    fn ek_cert_nv_index(&self) -> u32 {
+        match self {
+            Self::Rsa { .. } => EK_CERT_RSA_NV_INDEX,
+            Self::Ecc { .. } => EK_CERT_ECC_NV_INDEX,
+        }
+    }
+
+    /// Returns the DER KeyUsage of an EK with this key.
+    fn ek_key_usage(&self) -> Vec<u8> {
+        match self {
+            // keyEncipherment
+            Self::Rsa { .. } => der_tlv(DER_BIT_STRING, &[5, 0x20]),
+            // keyAgreement
+            Self::Ecc { .. } => der_tlv(DER_BIT_STRING, &[3, 0x08]),
+        }
+    }
+}
+
+/// Returns the TPMT_PUBLIC template of the key issuing the EK certificate:
+/// an unrestricted ECDSA NIST P-256 signing key, without authorization
+/// value.
+///
+/// The template is only known to the SVSM thanks to `unique`, so the guest
+/// cannot create the same key to issue certificates of its own.
+fn issuer_template(unique: &[u8]) -> Vec<u8> {
+    let mut template = Vec::with_capacity(28 + unique.len());
+    template.extend_from_slice(&[
+        0x00, 0x23, // type TPM_ALG_ECC
+        0x00, 0x0B, // nameAlg TPM_ALG_SHA256
+        0x00, 0x04, 0x04, 0x72, // objectAttributes { sign noDA userWithAuth
+        // sensitiveDataOrigin fixedParent fixedTPM }
+        0x00, 0x00, // authPolicy
+        // TPMS_ECC_PARMS {
+        0x00, 0x10, // symmetric TPM_ALG_NULL
+        0x00, 0x18, // scheme { TPM_ALG_ECDSA
+        0x00, 0x0B, // hashAlg TPM_ALG_SHA256 }
+        0x00, 0x03, // curveID TPM_ECC_NIST_P256
+        0x00, 0x10, // kdf TPM_ALG_NULL }
+    ]);
+    // unique { x, y }
+    template.extend_from_slice(&(unique.len() as u16).to_be_bytes());
+    template.extend_from_slice(unique);
+    template.extend_from_slice(&[0x00, 0x00]);
+    template
+}
+
+/// Returns the DER TBSCertificate of the EK.
+fn ek_tbs_certificate(
+    ek: &TpmPublicKey,
+    issuer_spki: &[u8],
+    evidence: &[u8],
+    manufacturer: u32,
+    firmware_version: u32,
+) -> Result<Vec<u8>, SvsmReqError> {
+    let ek_spki = ek.spki()?;
+
+    // A positive serial number of 16 bytes, unique for the EK.
+    let mut serial = [0u8; 16];
+    serial.copy_from_slice(&Sha256::digest(&ek_spki)[..16]);
+    serial[0] = (serial[0] & 0x7F) | 0x40;
+
+    let issuer = der_name(&[(OID_COMMON_NAME, EK_CERT_ISSUER_CN)]);
+    let validity = der_seq(&[
+        &der_tlv(DER_UTC_TIME, EK_CERT_NOT_BEFORE.as_bytes()),
+        &der_tlv(DER_GENERALIZED_TIME, EK_CERT_NOT_AFTER.as_bytes()),
+    ]);
+    // The subject is empty, the TPM is described by the alternative name.
+    let subject = der_seq(&[]);
+
+    let mut tpm_manufacturer = String::new();
+    let mut tpm_version = String::new();
+    // Writing to a String cannot fail.
+    let _ = write!(tpm_manufacturer, "id:{manufacturer:08X}");
+    let _ = write!(tpm_version, "id:{firmware_version:08X}");
+    let directory_name = der_name(&[
+        (OID_TCG_TPM_MANUFACTURER, tpm_manufacturer.as_str()),
+        (OID_TCG_TPM_MODEL, EK_CERT_TPM_MODEL),
+        (OID_TCG_TPM_VERSION, tpm_version.as_str()),
+    ]);
+    let alt_name = der_seq(&[&der_tlv(DER_CONTEXT_4, &directory_name)]);
+
+    let launch_evidence = der_seq(&[
+        issuer_spki,
+        &der_seq(&[&der_oid(OID_SHA512)]),
+        &der_tlv(DER_OCTET_STRING, &Sha512::digest(evidence)),
+    ]);
+
+    let extensions = der_seq(&[
+        &der_extension(OID_BASIC_CONSTRAINTS, true, &der_seq(&[])),
+        &der_extension(OID_KEY_USAGE, true, &ek.ek_key_usage()),
+        &der_extension(OID_SUBJECT_ALT_NAME, true, &alt_name),
+        &der_extension(
+            OID_EXT_KEY_USAGE,
+            false,
+            &der_seq(&[&der_oid(OID_TCG_KP_EK_CERTIFICATE)]),
+        ),
+        &der_extension(OID_SVSM_LAUNCH_EVIDENCE, false, &launch_evidence),
+    ]);
+
+    Ok(der_seq(&[
+        // Version v3
+        &der_tlv(DER_CONTEXT_0, &der_uint(&[2])),
+        &der_uint(&serial),
+        &der_seq(&[&der_oid(OID_ECDSA_WITH_SHA256)]),
+        &issuer,
+        &validity,
+        &subject,
+        &ek_spki,
+        &der_tlv(DER_CONTEXT_3, &extensions),
+    ]))
+}
+
+/// Builds the certificate of the EK `ekpub` and signs it with the issuer
+/// key `issuer_handle` loaded in `vtpm`.
+///
+/// # Returns
+///
+/// The certificate and the launch evidence whose digest it embeds.
+fn build_ek_cert<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    ek: &TpmPublicKey,
+    issuer_handle: u32,
+    issuer_spki: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>), SvsmReqError> {
+    let report_data: [u8; 64] = Sha512::digest(issuer_spki).into();
+    let evidence = get_evidence(&report_data)?;
+    let manufacturer = tss::get_tpm_property(vtpm, TPM_PT_MANUFACTURER)?;
+    let firmware_version = tss::get_tpm_property(vtpm, TPM_PT_FIRMWARE_VERSION_1)?;
+
+    let tbs = ek_tbs_certificate(ek, issuer_spki, &evidence, manufacturer, firmware_version)?;
+    let digest: [u8; 32] = Sha256::digest(&tbs).into();
+    let scheme = SigScheme {
+        scheme: TPM_ALG_ECDSA,
+        hash: TPM_ALG_SHA256,
+    };
+    let auth = AuthSession::empty_password();
+    let Signature::Ecdsa { r, s, .. } = tss::sign(vtpm, issuer_handle, &auth, &digest, scheme)?
+    else {
+        return Err(SvsmReqError::invalid_request());
+    };
+    let signature = der_seq(&[&der_uint(&r), &der_uint(&s)]);
+
+    let cert = der_seq(&[
+        &tbs,
+        &der_seq(&[&der_oid(OID_ECDSA_WITH_SHA256)]),
+        &der_bit_string(&signature),
+    ]);
+    if cert.len() > EK_CERT_MAX_SIZE {
+        log::error!("vTPM: EK certificate too large ({} bytes)", cert.len());
+        return Err(SvsmReqError::invalid_request());
+    }
+    Ok((cert, evidence))
+}
+
+/// Builds the certificate of the EK `ekpub` and writes it to the NV index
+/// defined for its key type, replacing any previous certificate.
+///
+/// The TPM must have been started.
+///
+/// # Arguments
+///
+/// * `vtpm`: The TPM the EK belongs to.
+/// * `ekpub`: The TPMT_PUBLIC of the EK.
+///
+/// # Returns
+///
+/// The launch evidence binding the issuer of the certificate, which the
+/// verifier needs to check the certificate.
+pub fn provision_ek_cert<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    ekpub: &[u8],
+) -> Result<Vec<u8>, SvsmReqError> {
+    let ek = TpmPublicKey::parse(ekpub)?;
+
+    let auth = AuthSession::empty_password();
+
+    let unique = tss::get_random(vtpm, 32)?;
+    let issuer = tss::create_primary(
+        vtpm,
+        tss::TPM_RH_ENDORSEMENT,
+        &auth,
+        &SensitiveCreate::default(),
+        &issuer_template(&unique),
+    )?;
+    let cert = TpmPublicKey::parse(&issuer.public)
+        .and_then(|issuer_key| issuer_key.spki())
+        .and_then(|issuer_spki| build_ek_cert(vtpm, &ek, issuer.handle, &issuer_spki));
+    tss::flush_context(vtpm, issuer.handle)?;
+    let (cert, evidence) = cert?;
+
+    let nv_index = ek.ek_cert_nv_index();
+    if tss::nv_is_defined(vtpm, nv_index)? {
+        tss::nv_undefine_space(vtpm, tss::TPM_RH_PLATFORM, &auth, nv_index)?;
+    }
+    let public = NvPublic {
+        nv_index,
+        name_alg: TPM_ALG_SHA256,
+        attributes: EK_CERT_NV_ATTRIBUTES,
+        auth_policy: Vec::new(),
+        data_size: cert.len() as u16,
+    };
+    tss::nv_define_space(vtpm, tss::TPM_RH_PLATFORM, &auth, &[], &public)?;
+    tss::nv_write(vtpm, tss::TPM_RH_PLATFORM, &auth, nv_index, 0, &cert)?;
+
+    log::info!("vTPM: EK certificate provisioned at NV index {nv_index:#010x}");
+    Ok(evidence)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vtpm::tcgtpm::ek_templates::DEFAULT_PUBLIC_AREA;
+
+    #[test]
+    fn der_tlv_length_forms() {
+        assert_eq!(der_tlv(DER_OCTET_STRING, &[]), [0x04, 0x00]);
+        assert_eq!(der_tlv(DER_OCTET_STRING, &[0xAB; 0x7F])[..2], [0x04, 0x7F]);
+
+        let long = der_tlv(DER_OCTET_STRING, &[0xAB; 0x80]);
+        assert_eq!(long[..3], [0x04, 0x81, 0x80]);
+        assert_eq!(long.len(), 3 + 0x80);
+
+        let longer = der_tlv(DER_OCTET_STRING, &[0xAB; 0x1234]);
+        assert_eq!(longer[..4], [0x04, 0x82, 0x12, 0x34]);
+        assert_eq!(longer.len(), 4 + 0x1234);
+    }
+
+    #[test]
+    fn der_uint_minimal() {
+        assert_eq!(der_uint(&[]), [0x02, 0x01, 0x00]);
+        assert_eq!(der_uint(&[0, 0, 0]), [0x02, 0x01, 0x00]);
+        assert_eq!(der_uint(&[0, 0, 2]), [0x02, 0x01, 0x02]);
+        assert_eq!(der_uint(&[0x7F, 0xFF]), [0x02, 0x02, 0x7F, 0xFF]);
+        // A leading zero keeps values with the top bit set positive.
+        assert_eq!(der_uint(&[0, 0x80]), [0x02, 0x02, 0x00, 0x80]);
+        assert_eq!(
+            der_uint(&65537u32.to_be_bytes()),
+            [0x02, 0x03, 0x01, 0x00, 0x01]
+        );
+    }
+
+    #[test]
+    fn der_oid_encoding() {
+        assert_eq!(
+            der_oid(OID_ECDSA_WITH_SHA256),
+            [0x06, 0x08, 0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x04, 0x03, 0x02]
+        );
+        assert_eq!(
+            der_oid(OID_SHA512),
+            [0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03]
+        );
+        assert_eq!(der_oid(OID_COMMON_NAME), [0x06, 0x03, 0x55, 0x04, 0x03]);
+        // X.690 example of a first subidentifier above 127.
+        assert_eq!(der_oid(&[2, 999, 3]), [0x06, 0x03, 0x88, 0x37, 0x03]);
+
+        // The UUID arc takes 128 bits, i.e. 19 groups of 7 bits.
+        let uuid = der_oid(OID_SVSM_LAUNCH_EVIDENCE);
+        assert_eq!(uuid[..3], [0x06, 20, 0x69]);
+        assert!(uuid[3..uuid.len() - 1].iter().all(|b| b & 0x80 != 0));
+        assert_eq!(uuid[uuid.len() - 1] & 0x80, 0);
+    }
+
+    #[test]
+    fn parse_rsa_ek_template() {
+        let key = TpmPublicKey::parse(&DEFAULT_PUBLIC_AREA).unwrap();
+        let TpmPublicKey::Rsa { modulus, exponent } = &key else {
+            panic!("not an RSA key: {key:?}");
+        };
+        assert_eq!(*exponent, 65537);
+        assert_eq!(modulus.len(), 256);
+        assert_eq!(key.ek_cert_nv_index(), EK_CERT_RSA_NV_INDEX);
+    }
+
+    #[test]
+    fn parse_ecc_issuer_template() {
+        let unique = [0x5A; 32];
+        let key = TpmPublicKey::parse(&issuer_template(&unique)).unwrap();
+        let TpmPublicKey::Ecc { curve, x, y } = &key else {
+            panic!("not an ECC key: {key:?}");
+        };
+        assert_eq!(*curve, TPM_ECC_NIST_P256);
+        assert_eq!(x.as_slice(), unique);
+        assert!(y.is_empty());
+        assert_eq!(key.ek_cert_nv_index(), EK_CERT_ECC_NV_INDEX);
+
+        // SEQUENCE { AlgorithmIdentifier, BIT STRING { 0x04, x, y } }, with
+        // the missing y coordinate padded with zeros.
+        let spki = key.spki().unwrap();
+        assert_eq!(spki[..2], [0x30, 0x59]);
+        assert_eq!(spki.len(), 2 + 0x59);
+        let point = &spki[spki.len() - 65..];
+        assert_eq!(point[0], 0x04);
+        assert_eq!(&point[1..33], &unique);
+        assert!(point[33..].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn parse_malformed() {
+        assert!(TpmPublicKey::parse(&[]).is_err());
+        assert!(
+            TpmPublicKey::parse(&DEFAULT_PUBLIC_AREA[..DEFAULT_PUBLIC_AREA.len() - 1]).is_err()
+        );
+        // TPM_ALG_KEYEDHASH is not an asymmetric key.
+        let mut keyed_hash = DEFAULT_PUBLIC_AREA;
+        keyed_hash[..2].copy_from_slice(&[0x00, 0x08]);
+        assert!(TpmPublicKey::parse(&keyed_hash).is_err());
+    }
+}
@@ -12,40 +12,205 @@
 mod wrapper;
 
 pub mod ek_templates;
-mod tss;
+// Unit tests do not provision the certificate, only build its parts.
+#[cfg_attr(test, allow(dead_code))]
+mod ekcert;
+pub mod tss;
 
 extern crate alloc;
 
 use alloc::vec::Vec;
 
+use crate::{
+    protocols::vtpm::TpmPlatformCommand,
+    types::PAGE_SIZE,
+    vtpm::{nvstore::NvStore, VtpmProtocolInterface},
+};
+
+#[cfg(not(test))]
 use core::ffi::c_void;
+#[cfg(not(test))]
 use libtcgtpm::bindings::{
-    TPM_Manufacture, TPM_TearDown, _plat__LocalitySet, _plat__NVDisable, _plat__NVEnable,
-    _plat__RunCommand, _plat__SetNvAvail, _plat__Signal_PowerOn, _plat__Signal_Reset,
+    TPM_Manufacture, TPM_TearDown, _plat__ClearCancel, _plat__ClearNvAvail, _plat__LocalitySet,
+    _plat__NVDisable, _plat__NVEnable, _plat__NvMemoryRead, _plat__NvMemoryWrite,
+    _plat__RunCommand, _plat__SetCancel, _plat__SetNvAvail, _plat__Signal_PowerOff,
+    _plat__Signal_PowerOn, _plat__Signal_Reset,
 };
 
+#[cfg(not(test))]
 use crate::{
     address::VirtAddr,
-    protocols::{errors::SvsmReqError, vtpm::TpmPlatformCommand},
-    types::PAGE_SIZE,
+    error::SvsmError,
+    protocols::errors::SvsmReqError,
+    utils::vec::vec_sized,
     vtpm::{
-        tcgtpm::ek_templates::DEFAULT_PUBLIC_AREA, TcgTpmSimulatorInterface, VtpmInterface,
-        VtpmProtocolInterface,
+        measure::replay_launch, tcgtpm::ek_templates::DEFAULT_PUBLIC_AREA,
+        TcgTpmSimulatorInterface, VtpmInterface,
     },
 };
 
-#[derive(Debug, Clone, Default)]
+/// Size of the NV memory of the TPM. It must match `NV_MEMORY_SIZE` in the
+/// TpmProfile the library is built with.
+pub const NV_MEMORY_SIZE: usize = 16384;
+
+#[derive(Debug, Default)]
 pub struct TcgTpm {
     is_powered_on: bool,
     ekpub: Option<Vec<u8>>,
+    ek_cert_evidence: Option<Vec<u8>>,
+    nv: Option<NvStore>,
 }
 
+#[cfg(not(test))]
 impl TcgTpm {
     pub const fn new() -> TcgTpm {
         TcgTpm {
             is_powered_on: false,
             ekpub: None,
+            ek_cert_evidence: None,
+            nv: None,
+        }
+    }
+
+    /// Makes the TPM keep its NV memory in `store`. It must be called
+    /// before [`VtpmInterface::init()`].
+    pub fn attach_nv_store(&mut self, store: NvStore) -> Result<(), SvsmReqError> {
+        if self.is_powered_on {
+            return Err(SvsmReqError::invalid_request());
+        }
+        self.nv = Some(store);
+        Ok(())
+    }
+
+    /// Unlocks the NV store attached with [`Self::attach_nv_store()`] with
+    /// the attestation secret. It must be called before
+    /// [`VtpmInterface::init()`].
+    pub fn set_nv_secret(&mut self, secret: &[u8]) -> Result<(), SvsmReqError> {
+        if self.is_powered_on {
+            return Err(SvsmReqError::invalid_request());
+        }
+        if let Some(nv) = self.nv.as_mut() {
+            nv.set_secret(secret);
+        }
+        Ok(())
+    }
+
+    /// Returns a copy of the NV memory of the TPM.
+    fn read_nv(&self) -> Result<Vec<u8>, SvsmReqError> {
+        let mut image = vec_sized::<u8>(NV_MEMORY_SIZE).map_err(|_| SvsmError::Mem)?;
+        // SAFETY: FFI call. `image` is NV_MEMORY_SIZE bytes long, return
+        // value is checked.
+        let result = unsafe {
+            _plat__NvMemoryRead(
+                0,
+                NV_MEMORY_SIZE as u32,
+                image.as_mut_ptr().cast::<c_void>(),
+            )
+        };
+        if result == 0 {
+            log::error!("_plat__NvMemoryRead failed");
+            return Err(SvsmReqError::incomplete());
+        }
+        Ok(image)
+    }
+
+    /// Overwrites the NV memory of the TPM with `image`.
+    fn restore_nv(&self, image: &[u8]) -> Result<(), SvsmReqError> {
+        if image.len() != NV_MEMORY_SIZE {
+            return Err(SvsmReqError::invalid_parameter());
+        }
+        // _plat__NvMemoryWrite() only reads from `data`, but it is not
+        // declared `const`.
+        // SAFETY: FFI call. `image` is NV_MEMORY_SIZE bytes long, return
+        // value is checked.
+        let result = unsafe {
+            _plat__NvMemoryWrite(0, NV_MEMORY_SIZE as u32, image.as_ptr() as *mut c_void)
+        };
+        if result == 0 {
+            log::error!("_plat__NvMemoryWrite failed");
+            return Err(SvsmReqError::incomplete());
+        }
+        Ok(())
+    }
+
+    /// Provisions the EK certificate. The TPM must be started.
+    fn provision_ek_cert(&mut self) -> Result<(), SvsmReqError> {
+        self.ek_cert_evidence = None;
+        let ekpub = self.get_ekpub()?;
+        self.ek_cert_evidence = Some(ekcert::provision_ek_cert(self, &ekpub)?);
+        Ok(())
+    }
+
+    /// Returns the launch evidence binding the issuer of the EK certificate,
+    /// if the certificate was provisioned.
+    pub fn ek_cert_evidence(&self) -> Option<&[u8]> {
+        self.ek_cert_evidence.as_deref()
+    }
+
+    /// Starts the powered on TPM and restores the state the SVSM owns: the
+    /// EK certificate, and the launch measurements which TPM2_Startup(CLEAR)
+    /// clears from the PCRs. This happens before the guest can send a
+    /// command, so that the guest cannot forge the measurements.
+    fn start(&mut self) -> Result<(), SvsmReqError> {
+        self.signal_nvon()?;
+        tss::startup(self, tss::TPM_SU_CLEAR)?;
+        // The vTPM is usable without EK certificate, e.g. if the platform
+        // evidence cannot be retrieved.
+        if let Err(e) = self.provision_ek_cert() {
+            log::error!("VTPM: failed to provision the EK certificate: {e:?}");
+        }
+        replay_launch(self)
+    }
+
+    /// Powers the TPM on, or resets it if `only_reset` is set, without
+    /// starting it.
+    fn power_on(&mut self, only_reset: bool) -> Result<(), SvsmReqError> {
+        if !only_reset {
+            // SAFETY: FFI call. No parameter, return value is checked.
+            let result = unsafe { _plat__Signal_PowerOn() };
+            if result != 0 {
+                log::error!("_plat__Signal_PowerOn failed rc={}", result);
+                return Err(SvsmReqError::incomplete());
+            }
+        }
+        // It calls TPM_init() within to indicate that a TPM2_Startup is required.
+        // SAFETY: FFI call. No parameter, return value is checked.
+        let result = unsafe { _plat__Signal_Reset() };
+        if result != 0 {
+            log::error!("_plat__Signal_Reset failed rc={}", result);
+            return Err(SvsmReqError::incomplete());
+        }
+        self.is_powered_on = true;
+
+        Ok(())
+    }
+
+    fn power_off(&mut self) {
+        // SAFETY: FFI call. No Parameters or return values.
+        unsafe { _plat__Signal_PowerOff() };
+        self.is_powered_on = false;
+    }
+
+    /// Manufactures the TPM from scratch, discarding the NV memory.
+    fn manufacture_fresh(&self) -> Result<(), SvsmReqError> {
+        let mut rc = self.manufacture(1)?;
+        if rc != 0 {
+            // SAFETY: FFI call. Parameter checked, no return value.
+            unsafe { _plat__NVDisable(1 as *mut c_void, 0) };
+            return Err(SvsmReqError::incomplete());
+        }
+
+        rc = self.manufacture(0)?;
+        if rc != 1 {
+            return Err(SvsmReqError::incomplete());
+        }
+
+        self.teardown()?;
+        rc = self.manufacture(1)?;
+        if rc != 0 {
+            return Err(SvsmReqError::incomplete());
         }
+        Ok(())
     }
 
     fn teardown(&self) -> Result<(), SvsmReqError> {
@@ -77,7 +242,13 @@ impl TcgTpm {
     }
 }
 
-const TPM_CMDS_SUPPORTED: &[TpmPlatformCommand] = &[TpmPlatformCommand::SendCommand];
+const TPM_CMDS_SUPPORTED: &[TpmPlatformCommand] = &[
+    TpmPlatformCommand::SendCommand,
+    TpmPlatformCommand::CancelOn,
+    TpmPlatformCommand::CancelOff,
+    TpmPlatformCommand::NvOn,
+    TpmPlatformCommand::NvOff,
+];
 
 impl VtpmProtocolInterface for TcgTpm {
     fn get_supported_commands(&self) -> &[TpmPlatformCommand] {
@@ -87,6 +258,7 @@ impl VtpmProtocolInterface for TcgTpm {
 
 pub const TPM_BUFFER_MAX_SIZE: usize = PAGE_SIZE;
 
+#[cfg(not(test))]
 impl TcgTpmSimulatorInterface for TcgTpm {
     fn send_tpm_command(&self, command: &[u8], locality: u8) -> Result<Vec<u8>, SvsmReqError> {
         if !self.is_powered_on {
@@ -133,22 +305,12 @@ impl TcgTpmSimulatorInterface for TcgTpm {
         if only_reset && !self.is_powered_on {
             return Err(SvsmReqError::invalid_request());
         }
-        if !only_reset {
-            // SAFETY: FFI call. No parameter, return value is checked.
-            let result = unsafe { _plat__Signal_PowerOn() };
-            if result != 0 {
-                log::error!("_plat__Signal_PowerOn failed rc={}", result);
-                return Err(SvsmReqError::incomplete());
-            }
+        self.power_on(only_reset)?;
+        if let Err(e) = self.start() {
+            // Never leave the guest a TPM without the launch measurements.
+            self.power_off();
+            return Err(e);
         }
-        // It calls TPM_init() within to indicate that a TPM2_Startup is required.
-        // SAFETY: FFI call. No parameter, return value is checked.
-        let result = unsafe { _plat__Signal_Reset() };
-        if result != 0 {
-            log::error!("_plat__Signal_Reset failed rc={}", result);
-            return Err(SvsmReqError::incomplete());
-        }
-        self.is_powered_on = true;
 
         Ok(())
     }
@@ -162,8 +324,55 @@ impl TcgTpmSimulatorInterface for TcgTpm {
 
         Ok(())
     }
+
+    fn signal_poweroff(&mut self) -> Result<(), SvsmReqError> {
+        if !self.is_powered_on {
+            return Ok(());
+        }
+        // Commands save the NV memory as they run, but make sure nothing
+        // is lost with the power.
+        self.sync_nv()?;
+        self.power_off();
+
+        Ok(())
+    }
+
+    fn signal_reset(&mut self) -> Result<(), SvsmReqError> {
+        self.signal_poweron(true)
+    }
+
+    fn signal_nvoff(&self) -> Result<(), SvsmReqError> {
+        if !self.is_powered_on {
+            return Err(SvsmReqError::invalid_request());
+        }
+        // SAFETY: FFI call. No Parameters or return values.
+        unsafe { _plat__ClearNvAvail() };
+
+        Ok(())
+    }
+
+    fn signal_cancel_on(&self) -> Result<(), SvsmReqError> {
+        if !self.is_powered_on {
+            return Err(SvsmReqError::invalid_request());
+        }
+        // SAFETY: FFI call. No Parameters or return values.
+        unsafe { _plat__SetCancel() };
+
+        Ok(())
+    }
+
+    fn signal_cancel_off(&self) -> Result<(), SvsmReqError> {
+        if !self.is_powered_on {
+            return Err(SvsmReqError::invalid_request());
+        }
+        // SAFETY: FFI call. No Parameters or return values.
+        unsafe { _plat__ClearCancel() };
+
+        Ok(())
+    }
 }
 
+#[cfg(not(test))]
 impl VtpmInterface for TcgTpm {
     fn get_ekpub(&mut self) -> Result<Vec<u8>, SvsmReqError> {
         if self.ekpub.is_none() {
@@ -179,43 +388,55 @@ impl VtpmInterface for TcgTpm {
     fn init(&mut self) -> Result<(), SvsmReqError> {
         // Initialize the TPM TCG following the same steps done in the Simulator:
         //
-        // 1. Manufacture it for the first time
-        // 2. Make sure it does not fail if it is re-manufactured
-        // 3. Teardown to indicate it needs to be manufactured
-        // 4. Manufacture it for the first time
-        // 5. Power it on indicating it requires startup. By default, OVMF will start
-        //    and selftest it.
+        // 1. Restore the NV memory if it was saved by a previous boot.
+        //    Otherwise:
+        //    a. Manufacture it for the first time
+        //    b. Make sure it does not fail if it is re-manufactured
+        //    c. Teardown to indicate it needs to be manufactured
+        //    d. Manufacture it for the first time
+        // 2. Power it on and start it, which provisions the EK certificate, so
+        //    that the SVSM can extend the PCRs with the launch measurements
+        //    before the guest runs. The TPM2_Startup of OVMF then fails with
+        //    TPM_RC_INITIALIZE, which it treats as the TPM being started
+        //    already.
+        // 3. Save the NV memory, so that a freshly manufactured TPM persists.
 
         // SAFETY: FFI call. Parameters and return values are checked.
-        let mut rc = unsafe { _plat__NVEnable(VirtAddr::null().as_mut_ptr::<c_void>(), 0) };
+        let rc = unsafe { _plat__NVEnable(VirtAddr::null().as_mut_ptr::<c_void>(), 0) };
         if rc != 0 {
             log::error!("_plat__NVEnable failed rc={}", rc);
             return Err(SvsmReqError::incomplete());
         }
 
-        rc = self.manufacture(1)?;
-        if rc != 0 {
-            // SAFETY: FFI call. Parameter checked, no return value.
-            unsafe { _plat__NVDisable(1 as *mut c_void, 0) };
-            return Err(SvsmReqError::incomplete());
+        let image = match self.nv.as_mut() {
+            Some(nv) => nv.load()?,
+            None => None,
+        };
+        match image {
+            Some(image) => self.restore_nv(&image)?,
+            None => self.manufacture_fresh()?,
         }
 
-        rc = self.manufacture(0)?;
-        if rc != 1 {
-            return Err(SvsmReqError::incomplete());
-        }
+        self.signal_poweron(false)?;
+        self.sync_nv()?;
 
-        self.teardown()?;
-        rc = self.manufacture(1)?;
-        if rc != 0 {
-            return Err(SvsmReqError::incomplete());
+        if let Some(nv) = self.nv.as_ref() {
+            log::info!("VTPM: NV state persisted, counter {}", nv.counter());
         }
 
-        self.signal_poweron(false)?;
-        self.signal_nvon()?;
-
         log::info!("VTPM: TPM 2.0 Reference Implementation initialized");
 
         Ok(())
     }
+
+    fn sync_nv(&mut self) -> Result<(), SvsmReqError> {
+        if self.nv.is_none() {
+            return Ok(());
+        }
+        let image = self.read_nv()?;
+        if let Some(nv) = self.nv.as_mut() {
+            nv.save(&image)?;
+        }
+        Ok(())
+    }
 }
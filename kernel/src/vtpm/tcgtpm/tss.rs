@@ -4,119 +4,1143 @@
 // Copyright (c) Coconut-SVSM authors
 //
 
-// This module is an incomplete software stack for constructing commands to send to the TPM.
-// It is not fully general for expressing all inputs to a command.
+//! A minimal TPM software stack for the commands the SVSM itself sends to
+//! the vTPM.
+//!
+//! Commands are built with [`TpmCommand`], which takes care of the header,
+//! the handle and authorization areas, and of the command size. Parameters
+//! are appended with the [`Marshal`] trait, and responses are split into
+//! handles, parameters and sessions by [`TpmCommand::send()`], and parsed
+//! with [`TpmReader`]. Typed wrappers are provided for the commands the
+//! SVSM needs.
+//!
+//! Only password sessions are built here. Other sessions can be used by
+//! computing their [`AuthSession`] outside of this module.
 
 extern crate alloc;
 
 use crate::protocols::errors::SvsmReqError;
-use crate::vtpm::{
-    tcgtpm::{TcgTpmSimulatorInterface, TPM_BUFFER_MAX_SIZE},
-    SvsmVTpmError,
-};
+use crate::vtpm::{tcgtpm::TPM_BUFFER_MAX_SIZE, SvsmVTpmError, TcgTpmSimulatorInterface};
 use alloc::vec::Vec;
 
-pub const TPM_RC_SUCCESS: u32 = 0;
+// TPM_ST values
+const TPM_ST_NO_SESSIONS: u16 = 0x8001;
+const TPM_ST_SESSIONS: u16 = 0x8002;
+const TPM_ST_HASHCHECK: u16 = 0x8024;
 
-// PREREQUISITE: CMD must be at least 10 bytes long.
-// A TPM command result contains
-//
-// Byte offset | Size | Description
-// ---
-// 0x00        | 2    | u16 ST tag
-// 0x02        | 4    | u32 response size
-// 0x06        | 4    | u32 response code
-fn tpm_cmd_rc(cmd: &[u8]) -> u32 {
-    u32::from_be_bytes(cmd[6..10].try_into().unwrap())
-}
-
-fn extend_empty_auth(buf: &mut Vec<u8>) {
-    // TPM_RS_PW(4) + nonce(2) + attributes(1) + pw(2)
-    buf.extend_from_slice(&[
-        0x00, 0x00, 0x00, 0x09, // Size
-        // TPM_RS_PW
-        0x40, 0x00, 0x00, 0x09, // nonce == empty buffer
-        0x00, 0x00, // session attributes = continueSession = 0x01
-        0x01, // password = empty buffer
-        0x00, 0x00,
-    ]);
-}
-
-fn create_mtauth_ek_cmd(tpmt_public: &[u8]) -> Vec<u8> {
-    let mut cmd = Vec::<u8>::with_capacity(TPM_BUFFER_MAX_SIZE);
-
-    // TPM Command header
-    cmd.extend_from_slice(&[
-        0x80, 0x02, // TPM_ST_SESSIONS
-        0x00, 0x00, 0x00, 0x00, // Placeholder for command size
-        0x00, 0x00, 0x01, 0x31, // TPM_CC_CREATEPRIMARY
-        0x40, 0x00, 0x00, 0x0B, // TPM_RH_ENDORSEMENT
-    ]);
-
-    // Authorization block
-    extend_empty_auth(&mut cmd);
-
-    // inSensitive parameter
-    //
-    // TPM2B_SENSITIVE_CREATE structure is defined in
-    // Table 132 — Definition of TPM2B_SENSITIVE_CREATE Structure,
-    // Trusted Platform Module Library Part 2: Structures
-    cmd.extend_from_slice(&[
-        0x00, 0x04, // sensitive data size
-        0x00, 0x00, 0x00, 0x00, // user auth
-    ]);
-
-    // inPublic parameter
-    // parameters size
-    cmd.extend_from_slice(&(tpmt_public.len() as u16).to_be_bytes());
-    // parameters
-    cmd.extend_from_slice(tpmt_public);
-
-    cmd.extend_from_slice(&[
-        0x00, 0x00, 0x00, 0x00, // outsideInfo parameter
-        0x00, 0x00, // pcr selection
-    ]);
-
-    // Update command size
-    let command_size = cmd.len();
-    cmd[2..6].copy_from_slice(&(command_size as u32).to_be_bytes());
-
-    cmd.resize(TPM_BUFFER_MAX_SIZE, 0);
-    cmd
-}
-
-/// Sends `cmd` to `vtpm` and returns the interpretation of its error mode.
+// TPM_CC values
+pub const TPM_CC_EVICTCONTROL: u32 = 0x0120;
+pub const TPM_CC_NV_UNDEFINESPACE: u32 = 0x0122;
+pub const TPM_CC_NV_DEFINESPACE: u32 = 0x012A;
+pub const TPM_CC_CREATEPRIMARY: u32 = 0x0131;
+pub const TPM_CC_NV_WRITE: u32 = 0x0137;
+pub const TPM_CC_PCR_EVENT: u32 = 0x013C;
+pub const TPM_CC_STARTUP: u32 = 0x0144;
+pub const TPM_CC_SHUTDOWN: u32 = 0x0145;
+pub const TPM_CC_NV_READ: u32 = 0x014E;
+pub const TPM_CC_CREATE: u32 = 0x0153;
+pub const TPM_CC_LOAD: u32 = 0x0157;
+pub const TPM_CC_QUOTE: u32 = 0x0158;
+pub const TPM_CC_SEQUENCE_UPDATE: u32 = 0x015C;
+pub const TPM_CC_SIGN: u32 = 0x015D;
+pub const TPM_CC_FLUSHCONTEXT: u32 = 0x0165;
+pub const TPM_CC_NV_READPUBLIC: u32 = 0x0169;
+pub const TPM_CC_GETCAPABILITY: u32 = 0x017A;
+pub const TPM_CC_GETRANDOM: u32 = 0x017B;
+pub const TPM_CC_PCR_READ: u32 = 0x017E;
+pub const TPM_CC_PCR_EXTEND: u32 = 0x0182;
+pub const TPM_CC_EVENT_SEQUENCE_COMPLETE: u32 = 0x0185;
+pub const TPM_CC_HASH_SEQUENCE_START: u32 = 0x0186;
+
+// TPM_RH and TPM_RS values
+pub const TPM_RH_OWNER: u32 = 0x4000_0001;
+pub const TPM_RH_NULL: u32 = 0x4000_0007;
+pub const TPM_RS_PW: u32 = 0x4000_0009;
+pub const TPM_RH_ENDORSEMENT: u32 = 0x4000_000B;
+pub const TPM_RH_PLATFORM: u32 = 0x4000_000C;
+
+// TPM_SU values
+pub const TPM_SU_CLEAR: u16 = 0x0000;
+pub const TPM_SU_STATE: u16 = 0x0001;
+
+// TPM_ALG_ID values
+pub const TPM_ALG_SHA1: u16 = 0x0004;
+pub const TPM_ALG_SHA256: u16 = 0x000B;
+pub const TPM_ALG_SHA384: u16 = 0x000C;
+pub const TPM_ALG_SHA512: u16 = 0x000D;
+pub const TPM_ALG_NULL: u16 = 0x0010;
+pub const TPM_ALG_RSASSA: u16 = 0x0014;
+pub const TPM_ALG_RSAPSS: u16 = 0x0016;
+pub const TPM_ALG_ECDSA: u16 = 0x0018;
+
+// TPM_CAP values
+pub const TPM_CAP_TPM_PROPERTIES: u32 = 0x0000_0006;
+
+// TPM_RC values
+pub const TPM_RC_SUCCESS: u32 = 0x000;
+pub const TPM_RC_HANDLE: u32 = 0x08B;
+
+/// TPMA_SESSION continueSession
+const TPMA_SESSION_CONTINUESESSION: u8 = 1 << 0;
+
+/// Largest chunk of data read or written by a single TPM2_NV_Read or
+/// TPM2_NV_Write. It must not exceed `MAX_NV_BUFFER_SIZE` in the TpmProfile.
+const NV_BUFFER_MAX_SIZE: usize = 1024;
+
+/// Largest chunk of data hashed by a single TPM2_SequenceUpdate, and
+/// largest event data of TPM2_PCR_Event. It must not exceed
+/// `MAX_DIGEST_BUFFER` in the TpmProfile.
+pub const DIGEST_BUFFER_MAX_SIZE: usize = 1024;
+
+/// Number of PCRs of the TPM, which are selected with 3 bytes.
+pub const TPM_PCR_COUNT: usize = 24;
+const PCR_SELECT_SIZE: u8 = 3;
+
+/// An empty TPML_PCR_SELECTION.
+const NO_PCRS: &[PcrSelection] = &[];
+
+/// Returns the size of the digests of the hash algorithm `alg`.
+pub fn digest_size(alg: u16) -> Option<usize> {
+    match alg {
+        TPM_ALG_SHA1 => Some(20),
+        TPM_ALG_SHA256 => Some(32),
+        TPM_ALG_SHA384 => Some(48),
+        TPM_ALG_SHA512 => Some(64),
+        _ => None,
+    }
+}
+
+/// A TPM response code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TpmRc(pub u32);
+
+impl TpmRc {
+    const FMT1: u32 = 1 << 7;
+    const FMT1_PARAMETER: u32 = 1 << 6;
+    const FMT0_WARNING: u32 = 1 << 11;
+
+    pub fn is_success(&self) -> bool {
+        self.0 == TPM_RC_SUCCESS
+    }
+
+    fn is_format1(&self) -> bool {
+        self.0 & Self::FMT1 != 0
+    }
+
+    /// Returns the error without the handle, session or parameter number,
+    /// to compare it to TPM_RC values.
+    pub fn code(&self) -> u32 {
+        if self.is_format1() {
+            self.0 & 0xBF
+        } else {
+            self.0
+        }
+    }
+
+    /// Returns whether the command may succeed if it is sent again.
+    pub fn is_warning(&self) -> bool {
+        !self.is_format1() && self.0 & Self::FMT0_WARNING != 0
+    }
+
+    fn number(&self) -> Option<u8> {
+        self.is_format1().then_some(((self.0 >> 8) & 0xF) as u8)
+    }
+
+    /// Returns the 1-based number of the parameter the error is about.
+    pub fn parameter(&self) -> Option<u8> {
+        self.number().filter(|_| self.0 & Self::FMT1_PARAMETER != 0)
+    }
+
+    /// Returns the 1-based number of the handle the error is about.
+    pub fn handle(&self) -> Option<u8> {
+        self.number()
+            .filter(|n| self.0 & Self::FMT1_PARAMETER == 0 && (1..8).contains(n))
+    }
+
+    /// Returns the 1-based number of the session the error is about.
+    pub fn session(&self) -> Option<u8> {
+        self.number()
+            .filter(|n| self.0 & Self::FMT1_PARAMETER == 0 && *n >= 8)
+            .map(|n| n - 8)
+    }
+}
+
+/// Types which can be marshaled as command parameters.
+pub trait Marshal {
+    fn marshal(&self, buf: &mut Vec<u8>);
+}
+
+impl Marshal for u8 {
+    fn marshal(&self, buf: &mut Vec<u8>) {
+        buf.push(*self);
+    }
+}
+
+impl Marshal for u16 {
+    fn marshal(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl Marshal for u32 {
+    fn marshal(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl Marshal for [u8] {
+    fn marshal(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(self);
+    }
+}
+
+/// A sized buffer (TPM2B). Buffers too large for a command are caught
+/// when the command is marshaled.
+#[derive(Clone, Copy, Debug)]
+pub struct Tpm2b<'a>(pub &'a [u8]);
+
+impl Marshal for Tpm2b<'_> {
+    fn marshal(&self, buf: &mut Vec<u8>) {
+        (self.0.len() as u16).marshal(buf);
+        self.0.marshal(buf);
+    }
+}
+
+/// Authorization area of a command (TPMS_AUTH_COMMAND).
+#[derive(Clone, Debug)]
+pub struct AuthSession {
+    pub handle: u32,
+    pub nonce: Vec<u8>,
+    pub attributes: u8,
+    pub hmac: Vec<u8>,
+}
+
+impl AuthSession {
+    /// Returns a password authorization with `password`.
+    pub fn password(password: &[u8]) -> Self {
+        Self {
+            handle: TPM_RS_PW,
+            nonce: Vec::new(),
+            attributes: TPMA_SESSION_CONTINUESESSION,
+            hmac: password.to_vec(),
+        }
+    }
+
+    /// Returns a password authorization with the empty password, which is
+    /// the authorization of all hierarchies after TPM2_Startup(CLEAR).
+    pub fn empty_password() -> Self {
+        Self::password(&[])
+    }
+}
+
+impl Marshal for AuthSession {
+    fn marshal(&self, buf: &mut Vec<u8>) {
+        self.handle.marshal(buf);
+        Tpm2b(&self.nonce).marshal(buf);
+        self.attributes.marshal(buf);
+        Tpm2b(&self.hmac).marshal(buf);
+    }
+}
+
+/// Authorization area of a response (TPMS_AUTH_RESPONSE).
+#[derive(Clone, Debug)]
+pub struct AuthResponse {
+    pub nonce: Vec<u8>,
+    pub attributes: u8,
+    pub hmac: Vec<u8>,
+}
+
+/// PCRs of one bank (TPMS_PCR_SELECTION), as a bitmap of PCR indices.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PcrSelection {
+    pub hash: u16,
+    pub pcrs: u32,
+}
+
+impl Marshal for PcrSelection {
+    fn marshal(&self, buf: &mut Vec<u8>) {
+        self.hash.marshal(buf);
+        PCR_SELECT_SIZE.marshal(buf);
+        self.pcrs.to_le_bytes()[..PCR_SELECT_SIZE as usize].marshal(buf);
+    }
+}
+
+/// A list of PCR selections (TPML_PCR_SELECTION).
+impl Marshal for [PcrSelection] {
+    fn marshal(&self, buf: &mut Vec<u8>) {
+        (self.len() as u32).marshal(buf);
+        for selection in self {
+            selection.marshal(buf);
+        }
+    }
+}
+
+/// Data for the sensitive area of a new object (TPM2B_SENSITIVE_CREATE).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SensitiveCreate<'a> {
+    pub user_auth: &'a [u8],
+    pub data: &'a [u8],
+}
+
+impl Marshal for SensitiveCreate<'_> {
+    fn marshal(&self, buf: &mut Vec<u8>) {
+        let mut sensitive = Vec::new();
+        Tpm2b(self.user_auth).marshal(&mut sensitive);
+        Tpm2b(self.data).marshal(&mut sensitive);
+        Tpm2b(&sensitive).marshal(buf);
+    }
+}
+
+/// Public area of an NV index (TPMS_NV_PUBLIC).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NvPublic {
+    pub nv_index: u32,
+    pub name_alg: u16,
+    pub attributes: u32,
+    pub auth_policy: Vec<u8>,
+    pub data_size: u16,
+}
+
+/// Marshals as a TPM2B_NV_PUBLIC.
+impl Marshal for NvPublic {
+    fn marshal(&self, buf: &mut Vec<u8>) {
+        let mut public = Vec::new();
+        self.nv_index.marshal(&mut public);
+        self.name_alg.marshal(&mut public);
+        self.attributes.marshal(&mut public);
+        Tpm2b(&self.auth_policy).marshal(&mut public);
+        self.data_size.marshal(&mut public);
+        Tpm2b(&public).marshal(buf);
+    }
+}
+
+/// A signature scheme (TPMT_SIG_SCHEME).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SigScheme {
+    pub scheme: u16,
+    pub hash: u16,
+}
+
+impl SigScheme {
+    /// Uses the scheme of the signing key.
+    pub const NULL: Self = Self {
+        scheme: TPM_ALG_NULL,
+        hash: TPM_ALG_NULL,
+    };
+}
+
+impl Marshal for SigScheme {
+    fn marshal(&self, buf: &mut Vec<u8>) {
+        self.scheme.marshal(buf);
+        if self.scheme != TPM_ALG_NULL {
+            self.hash.marshal(buf);
+        }
+    }
+}
+
+/// A signature (TPMT_SIGNATURE).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Signature {
+    Null,
+    Rsa {
+        scheme: u16,
+        hash: u16,
+        sig: Vec<u8>,
+    },
+    Ecdsa {
+        hash: u16,
+        r: Vec<u8>,
+        s: Vec<u8>,
+    },
+}
+
+impl Signature {
+    fn unmarshal(reader: &mut TpmReader<'_>) -> Result<Self, SvsmVTpmError> {
+        match reader.u16()? {
+            TPM_ALG_NULL => Ok(Self::Null),
+            scheme @ (TPM_ALG_RSASSA | TPM_ALG_RSAPSS) => Ok(Self::Rsa {
+                scheme,
+                hash: reader.u16()?,
+                sig: reader.tpm2b()?.to_vec(),
+            }),
+            TPM_ALG_ECDSA => Ok(Self::Ecdsa {
+                hash: reader.u16()?,
+                r: reader.tpm2b()?.to_vec(),
+                s: reader.tpm2b()?.to_vec(),
+            }),
+            _ => Err(SvsmVTpmError::MalformedResponse),
+        }
+    }
+}
+
+/// Reads TPM structures from a buffer. Reading past its end fails with
+/// [`SvsmVTpmError::MalformedResponse`], as it only parses data received
+/// from the TPM.
+#[derive(Debug)]
+pub struct TpmReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> TpmReader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], SvsmVTpmError> {
+        if self.buf.len() < len {
+            return Err(SvsmVTpmError::MalformedResponse);
+        }
+        let (bytes, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, SvsmVTpmError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, SvsmVTpmError> {
+        Ok(u16::from_be_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, SvsmVTpmError> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn tpm2b(&mut self) -> Result<&'a [u8], SvsmVTpmError> {
+        let len = self.u16()? as usize;
+        self.bytes(len)
+    }
+
+    /// Returns the data which was not read yet.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.buf
+    }
+
+    /// Reads a TPML_PCR_SELECTION.
+    pub fn pcr_selections(&mut self) -> Result<Vec<PcrSelection>, SvsmVTpmError> {
+        let count = self.u32()? as usize;
+        let mut selections = Vec::new();
+        for _ in 0..count {
+            let hash = self.u16()?;
+            let size = self.u8()? as usize;
+            let mut pcrs = [0u8; 4];
+            let select = self.bytes(size)?;
+            if select.len() > pcrs.len() {
+                return Err(SvsmVTpmError::MalformedResponse);
+            }
+            pcrs[..select.len()].copy_from_slice(select);
+            selections.push(PcrSelection {
+                hash,
+                pcrs: u32::from_le_bytes(pcrs),
+            });
+        }
+        Ok(selections)
+    }
+
+    /// Reads a TPML_DIGEST_VALUES, as (TPM_ALG_ID, digest) pairs.
+    pub fn digest_values(&mut self) -> Result<Vec<(u16, Vec<u8>)>, SvsmVTpmError> {
+        let count = self.u32()? as usize;
+        let mut digests = Vec::new();
+        for _ in 0..count {
+            let alg = self.u16()?;
+            let size = digest_size(alg).ok_or(SvsmVTpmError::MalformedResponse)?;
+            digests.push((alg, self.bytes(size)?.to_vec()));
+        }
+        Ok(digests)
+    }
+}
+
+/// A TPM command under construction.
+#[derive(Clone, Debug)]
+pub struct TpmCommand {
+    cc: u32,
+    handles: Vec<u32>,
+    sessions: Vec<AuthSession>,
+    params: Vec<u8>,
+    response_handles: usize,
+}
+
+/// The response to a [`TpmCommand`] which succeeded.
+#[derive(Clone, Debug)]
+pub struct TpmResponse {
+    pub handles: Vec<u32>,
+    pub params: Vec<u8>,
+    pub sessions: Vec<AuthResponse>,
+}
+
+impl TpmResponse {
+    /// Returns a reader of the response parameters.
+    pub fn reader(&self) -> TpmReader<'_> {
+        TpmReader::new(&self.params)
+    }
+}
+
+impl TpmCommand {
+    pub fn new(cc: u32) -> Self {
+        Self {
+            cc,
+            handles: Vec::new(),
+            sessions: Vec::new(),
+            params: Vec::new(),
+            response_handles: 0,
+        }
+    }
+
+    /// Appends `handle` to the handle area.
+    pub fn handle(mut self, handle: u32) -> Self {
+        self.handles.push(handle);
+        self
+    }
+
+    /// Appends `session` to the authorization area. Sessions are in the
+    /// order of the handles they authorize.
+    pub fn session(mut self, session: &AuthSession) -> Self {
+        self.sessions.push(session.clone());
+        self
+    }
+
+    /// Appends `param` to the parameter area.
+    pub fn param<M: Marshal + ?Sized>(mut self, param: &M) -> Self {
+        param.marshal(&mut self.params);
+        self
+    }
+
+    /// Sets the number of handles in the response, which precede its
+    /// parameters.
+    pub fn response_handles(mut self, count: usize) -> Self {
+        self.response_handles = count;
+        self
+    }
+
+    /// Returns the command as sent to the TPM.
+    pub fn marshal(&self) -> Result<Vec<u8>, SvsmVTpmError> {
+        let mut cmd = Vec::<u8>::with_capacity(TPM_BUFFER_MAX_SIZE);
+        let tag = if self.sessions.is_empty() {
+            TPM_ST_NO_SESSIONS
+        } else {
+            TPM_ST_SESSIONS
+        };
+        tag.marshal(&mut cmd);
+        0u32.marshal(&mut cmd); // Placeholder for command size
+        self.cc.marshal(&mut cmd);
+        for handle in &self.handles {
+            handle.marshal(&mut cmd);
+        }
+        if !self.sessions.is_empty() {
+            let mut auth = Vec::new();
+            for session in &self.sessions {
+                session.marshal(&mut auth);
+            }
+            (auth.len() as u32).marshal(&mut cmd);
+            cmd.extend_from_slice(&auth);
+        }
+        cmd.extend_from_slice(&self.params);
+
+        if cmd.len() > TPM_BUFFER_MAX_SIZE {
+            return Err(SvsmVTpmError::ReqError(SvsmReqError::invalid_parameter()));
+        }
+        let command_size = cmd.len() as u32;
+        cmd[2..6].copy_from_slice(&command_size.to_be_bytes());
+        Ok(cmd)
+    }
+
+    /// Sends the command to `vtpm` and splits its response.
+    ///
+    /// Returns:
+    ///
+    /// The response on success. If the TPM fails the command, the error
+    /// is [`SvsmVTpmError::CommandError`] with its response code.
+    pub fn send<T: TcgTpmSimulatorInterface>(
+        &self,
+        vtpm: &T,
+    ) -> Result<TpmResponse, SvsmVTpmError> {
+        let cmd = self.marshal()?;
+        let response = vtpm
+            .send_tpm_command(&cmd, 0)
+            .map_err(|_| SvsmVTpmError::ReqError(SvsmReqError::invalid_request()))?;
+
+        // Header: tag(2) + responseSize(4) + responseCode(4)
+        let mut reader = TpmReader::new(&response);
+        let tag = reader.u16()?;
+        let size = reader.u32()? as usize;
+        let rc = TpmRc(reader.u32()?);
+        if !rc.is_success() {
+            return Err(SvsmVTpmError::CommandError(rc));
+        }
+        if size != response.len() {
+            return Err(SvsmVTpmError::MalformedResponse);
+        }
+
+        let handles = (0..self.response_handles)
+            .map(|_| reader.u32())
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut sessions = Vec::new();
+        let params = match tag {
+            TPM_ST_SESSIONS => {
+                let params_size = reader.u32()? as usize;
+                let params = reader.bytes(params_size)?;
+                for _ in &self.sessions {
+                    sessions.push(AuthResponse {
+                        nonce: reader.tpm2b()?.to_vec(),
+                        attributes: reader.u8()?,
+                        hmac: reader.tpm2b()?.to_vec(),
+                    });
+                }
+                params
+            }
+            TPM_ST_NO_SESSIONS => reader.remaining(),
+            _ => return Err(SvsmVTpmError::MalformedResponse),
+        };
+
+        Ok(TpmResponse {
+            handles,
+            params: params.to_vec(),
+            sessions,
+        })
+    }
+}
+
+/// Runs TPM2_Startup of type `su` (a TPM_SU value).
+pub fn startup<T: TcgTpmSimulatorInterface>(vtpm: &T, su: u16) -> Result<(), SvsmVTpmError> {
+    TpmCommand::new(TPM_CC_STARTUP).param(&su).send(vtpm)?;
+    Ok(())
+}
+
+/// Runs TPM2_Shutdown of type `su` (a TPM_SU value).
+pub fn shutdown<T: TcgTpmSimulatorInterface>(vtpm: &T, su: u16) -> Result<(), SvsmVTpmError> {
+    TpmCommand::new(TPM_CC_SHUTDOWN).param(&su).send(vtpm)?;
+    Ok(())
+}
+
+/// Returns `size` random bytes from the TPM. The TPM may return fewer.
+pub fn get_random<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    size: u16,
+) -> Result<Vec<u8>, SvsmVTpmError> {
+    let response = TpmCommand::new(TPM_CC_GETRANDOM).param(&size).send(vtpm)?;
+    Ok(response.reader().tpm2b()?.to_vec())
+}
+
+/// The data returned by TPM2_GetCapability.
+#[derive(Clone, Debug)]
+pub struct CapabilityData {
+    pub more_data: bool,
+    pub capability: u32,
+    /// The marshaled TPMU_CAPABILITIES.
+    pub data: Vec<u8>,
+}
+
+/// Runs TPM2_GetCapability for up to `count` values of `capability`
+/// starting at `property`.
+pub fn get_capability<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    capability: u32,
+    property: u32,
+    count: u32,
+) -> Result<CapabilityData, SvsmVTpmError> {
+    let response = TpmCommand::new(TPM_CC_GETCAPABILITY)
+        .param(&capability)
+        .param(&property)
+        .param(&count)
+        .send(vtpm)?;
+    let mut reader = response.reader();
+    Ok(CapabilityData {
+        more_data: reader.u8()? != 0,
+        capability: reader.u32()?,
+        data: reader.remaining().to_vec(),
+    })
+}
+
+/// Returns the value of the TPM property `property` (a TPM_PT value).
+pub fn get_tpm_property<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    property: u32,
+) -> Result<u32, SvsmVTpmError> {
+    let caps = get_capability(vtpm, TPM_CAP_TPM_PROPERTIES, property, 1)?;
+    // TPML_TAGGED_TPM_PROPERTY
+    let mut reader = TpmReader::new(&caps.data);
+    if caps.capability != TPM_CAP_TPM_PROPERTIES || reader.u32()? == 0 || reader.u32()? != property
+    {
+        return Err(SvsmVTpmError::MalformedResponse);
+    }
+    reader.u32()
+}
+
+/// Extends PCR `pcr` with `digests`, one (TPM_ALG_ID, digest) pair per
+/// bank.
+pub fn pcr_extend<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    pcr: u32,
+    auth: &AuthSession,
+    digests: &[(u16, &[u8])],
+) -> Result<(), SvsmVTpmError> {
+    let mut cmd = TpmCommand::new(TPM_CC_PCR_EXTEND)
+        .handle(pcr)
+        .session(auth)
+        .param(&(digests.len() as u32));
+    for (hash, digest) in digests {
+        cmd = cmd.param(hash).param(*digest);
+    }
+    cmd.send(vtpm)?;
+    Ok(())
+}
+
+/// Extends PCR `pcr` with the digest of the event `data` in every bank.
+/// `data` must not exceed [`DIGEST_BUFFER_MAX_SIZE`].
 ///
-/// Arguments:
+/// Returns:
 ///
-/// * `vtpm`: An implementation of [`TcgTpmSimulatorInterface`] to send `cmd` to.
-/// * `cmd`: A command buffer.
-/// * `set_len`: If true, sets the command length in the command header to `cmd.len()` before
-///   sending the command.
+/// The digests the TPM computed, one (TPM_ALG_ID, digest) pair per bank.
+pub fn pcr_event<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    pcr: u32,
+    auth: &AuthSession,
+    data: &[u8],
+) -> Result<Vec<(u16, Vec<u8>)>, SvsmVTpmError> {
+    let response = TpmCommand::new(TPM_CC_PCR_EVENT)
+        .handle(pcr)
+        .session(auth)
+        .param(&Tpm2b(data))
+        .send(vtpm)?;
+    response.reader().digest_values()
+}
+
+/// Starts a hash sequence of `hash_alg`, or an event sequence hashing the
+/// data with all PCR bank algorithms if `hash_alg` is TPM_ALG_NULL.
 ///
 /// Returns:
 ///
-/// The command response on success, or an error.
-pub fn checked_send<T: TcgTpmSimulatorInterface>(
+/// The handle of the sequence, which is authorized with `auth`.
+pub fn hash_sequence_start<T: TcgTpmSimulatorInterface>(
     vtpm: &T,
-    cmd: &mut [u8],
-    set_len: bool,
-) -> Result<Vec<u8>, SvsmVTpmError> {
-    let command_size;
-    if set_len {
-        command_size = cmd.len();
-        cmd[2..6].copy_from_slice(&(command_size as u32).to_be_bytes());
-    } else {
-        command_size = u32::from_be_bytes(cmd[2..6].try_into().unwrap()) as usize;
+    auth: &[u8],
+    hash_alg: u16,
+) -> Result<u32, SvsmVTpmError> {
+    let response = TpmCommand::new(TPM_CC_HASH_SEQUENCE_START)
+        .param(&Tpm2b(auth))
+        .param(&hash_alg)
+        .response_handles(1)
+        .send(vtpm)?;
+    Ok(response.handles[0])
+}
+
+/// Adds `data` to the sequence `sequence`, splitting it in as many
+/// commands as needed.
+pub fn sequence_update<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    sequence: u32,
+    auth: &AuthSession,
+    data: &[u8],
+) -> Result<(), SvsmVTpmError> {
+    for chunk in data.chunks(DIGEST_BUFFER_MAX_SIZE) {
+        TpmCommand::new(TPM_CC_SEQUENCE_UPDATE)
+            .handle(sequence)
+            .session(auth)
+            .param(&Tpm2b(chunk))
+            .send(vtpm)?;
     }
-    let response = vtpm
-        .send_tpm_command(&cmd[..command_size], 0)
-        .map_err(|_| SvsmVTpmError::ReqError(SvsmReqError::invalid_request()))?;
-    let rc = tpm_cmd_rc(&response);
-    if rc != TPM_RC_SUCCESS {
-        return Err(SvsmVTpmError::CommandError(rc));
+    Ok(())
+}
+
+/// Completes the event sequence `sequence` with the last `data`, which must
+/// not exceed [`DIGEST_BUFFER_MAX_SIZE`], and extends PCR `pcr` with the
+/// resulting digests. On success, the TPM flushes the sequence.
+///
+/// Returns:
+///
+/// The digests of the sequence, one (TPM_ALG_ID, digest) pair per bank.
+pub fn event_sequence_complete<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    pcr: u32,
+    pcr_auth: &AuthSession,
+    sequence: u32,
+    sequence_auth: &AuthSession,
+    data: &[u8],
+) -> Result<Vec<(u16, Vec<u8>)>, SvsmVTpmError> {
+    let response = TpmCommand::new(TPM_CC_EVENT_SEQUENCE_COMPLETE)
+        .handle(pcr)
+        .handle(sequence)
+        .session(pcr_auth)
+        .session(sequence_auth)
+        .param(&Tpm2b(data))
+        .send(vtpm)?;
+    response.reader().digest_values()
+}
+
+/// The values read by TPM2_PCR_Read.
+#[derive(Clone, Debug)]
+pub struct PcrValues {
+    pub update_counter: u32,
+    /// The PCRs the digests belong to, which may be fewer than requested.
+    pub selections: Vec<PcrSelection>,
+    /// The digests, in the order of the selections and of the PCR indices.
+    pub digests: Vec<Vec<u8>>,
+}
+
+/// Reads the PCRs in `selections`.
+pub fn pcr_read<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    selections: &[PcrSelection],
+) -> Result<PcrValues, SvsmVTpmError> {
+    let response = TpmCommand::new(TPM_CC_PCR_READ)
+        .param(selections)
+        .send(vtpm)?;
+    let mut reader = response.reader();
+    let update_counter = reader.u32()?;
+    let selections = reader.pcr_selections()?;
+    // TPML_DIGEST
+    let count = reader.u32()? as usize;
+    let digests = (0..count)
+        .map(|_| reader.tpm2b().map(<[u8]>::to_vec))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(PcrValues {
+        update_counter,
+        selections,
+        digests,
+    })
+}
+
+/// Reads the public area and the name of the NV index `nv_index`.
+pub fn nv_read_public<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    nv_index: u32,
+) -> Result<(NvPublic, Vec<u8>), SvsmVTpmError> {
+    let response = TpmCommand::new(TPM_CC_NV_READPUBLIC)
+        .handle(nv_index)
+        .send(vtpm)?;
+    let mut reader = response.reader();
+    let mut public = TpmReader::new(reader.tpm2b()?);
+    let nv_public = NvPublic {
+        nv_index: public.u32()?,
+        name_alg: public.u16()?,
+        attributes: public.u32()?,
+        auth_policy: public.tpm2b()?.to_vec(),
+        data_size: public.u16()?,
+    };
+    Ok((nv_public, reader.tpm2b()?.to_vec()))
+}
+
+/// Returns whether the NV index `nv_index` is defined.
+pub fn nv_is_defined<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    nv_index: u32,
+) -> Result<bool, SvsmVTpmError> {
+    match nv_read_public(vtpm, nv_index) {
+        Ok(_) => Ok(true),
+        Err(SvsmVTpmError::CommandError(rc)) if rc.code() == TPM_RC_HANDLE => Ok(false),
+        Err(e) => Err(e),
     }
-    Ok(response)
+}
+
+/// Defines the NV index described by `public`, with the authorization value
+/// `nv_auth`.
+///
+/// Arguments:
+///
+/// * `vtpm`: An implementation of [`TcgTpmSimulatorInterface`] to send the command to.
+/// * `auth_handle`: TPM_RH_OWNER or TPM_RH_PLATFORM.
+/// * `auth`: The authorization of `auth_handle`.
+/// * `nv_auth`: The authorization value of the new NV index.
+/// * `public`: The public area of the new NV index.
+pub fn nv_define_space<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    auth_handle: u32,
+    auth: &AuthSession,
+    nv_auth: &[u8],
+    public: &NvPublic,
+) -> Result<(), SvsmVTpmError> {
+    TpmCommand::new(TPM_CC_NV_DEFINESPACE)
+        .handle(auth_handle)
+        .session(auth)
+        .param(&Tpm2b(nv_auth))
+        .param(public)
+        .send(vtpm)?;
+    Ok(())
+}
+
+/// Deletes the NV index `nv_index`, with the authorization `auth` of the
+/// hierarchy `auth_handle` it was defined in.
+pub fn nv_undefine_space<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    auth_handle: u32,
+    auth: &AuthSession,
+    nv_index: u32,
+) -> Result<(), SvsmVTpmError> {
+    TpmCommand::new(TPM_CC_NV_UNDEFINESPACE)
+        .handle(auth_handle)
+        .handle(nv_index)
+        .session(auth)
+        .send(vtpm)?;
+    Ok(())
+}
+
+/// Writes `data` at `offset` of the NV index `nv_index`.
+///
+/// Arguments:
+///
+/// * `vtpm`: An implementation of [`TcgTpmSimulatorInterface`] to send the commands to.
+/// * `auth_handle`: The handle authorizing the write: `nv_index` itself,
+///   TPM_RH_OWNER or TPM_RH_PLATFORM, depending on the NV index attributes.
+/// * `auth`: The authorization of `auth_handle`.
+/// * `nv_index`: The NV index to write to.
+/// * `offset`: The offset in the NV index to write at.
+/// * `data`: The data to write, which is split in as many commands as needed.
+pub fn nv_write<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    auth_handle: u32,
+    auth: &AuthSession,
+    nv_index: u32,
+    offset: u16,
+    data: &[u8],
+) -> Result<(), SvsmVTpmError> {
+    for (i, chunk) in data.chunks(NV_BUFFER_MAX_SIZE).enumerate() {
+        let chunk_offset = u16::try_from(i * NV_BUFFER_MAX_SIZE)
+            .ok()
+            .and_then(|o| o.checked_add(offset))
+            .ok_or(SvsmVTpmError::ReqError(SvsmReqError::invalid_parameter()))?;
+        TpmCommand::new(TPM_CC_NV_WRITE)
+            .handle(auth_handle)
+            .handle(nv_index)
+            .session(auth)
+            .param(&Tpm2b(chunk))
+            .param(&chunk_offset)
+            .send(vtpm)?;
+    }
+    Ok(())
+}
+
+/// Reads `size` bytes at `offset` of the NV index `nv_index`. The
+/// arguments are the same as for [`nv_write()`].
+pub fn nv_read<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    auth_handle: u32,
+    auth: &AuthSession,
+    nv_index: u32,
+    offset: u16,
+    size: u16,
+) -> Result<Vec<u8>, SvsmVTpmError> {
+    let mut data = Vec::with_capacity(size as usize);
+    while data.len() < size as usize {
+        let chunk_size = (size as usize - data.len()).min(NV_BUFFER_MAX_SIZE) as u16;
+        let chunk_offset = offset
+            .checked_add(data.len() as u16)
+            .ok_or(SvsmVTpmError::ReqError(SvsmReqError::invalid_parameter()))?;
+        let response = TpmCommand::new(TPM_CC_NV_READ)
+            .handle(auth_handle)
+            .handle(nv_index)
+            .session(auth)
+            .param(&chunk_size)
+            .param(&chunk_offset)
+            .send(vtpm)?;
+        let chunk = response.reader().tpm2b()?;
+        if chunk.len() != chunk_size as usize {
+            return Err(SvsmVTpmError::MalformedResponse);
+        }
+        data.extend_from_slice(chunk);
+    }
+    Ok(data)
+}
+
+/// A key created by TPM2_CreatePrimary.
+#[derive(Clone, Debug)]
+pub struct CreatedPrimary {
+    /// The transient handle of the loaded key.
+    pub handle: u32,
+    /// The marshaled TPMT_PUBLIC of the key.
+    pub public: Vec<u8>,
+    pub name: Vec<u8>,
+}
+
+/// Creates a primary key from `template` in `hierarchy`.
+///
+/// Arguments:
+///
+/// * `vtpm`: An implementation of [`TcgTpmSimulatorInterface`] to send the command to.
+/// * `hierarchy`: The TPM_RH handle of the hierarchy.
+/// * `auth`: The authorization of `hierarchy`.
+/// * `sensitive`: The authorization value and data of the key.
+/// * `template`: A marshaled TPMT_PUBLIC to use as the key creation template.
+pub fn create_primary<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    hierarchy: u32,
+    auth: &AuthSession,
+    sensitive: &SensitiveCreate<'_>,
+    template: &[u8],
+) -> Result<CreatedPrimary, SvsmVTpmError> {
+    let response = TpmCommand::new(TPM_CC_CREATEPRIMARY)
+        .handle(hierarchy)
+        .session(auth)
+        .param(sensitive)
+        .param(&Tpm2b(template))
+        .param(&Tpm2b(&[])) // outsideInfo
+        .param(NO_PCRS) // creationPCR
+        .response_handles(1)
+        .send(vtpm)?;
+    let mut reader = response.reader();
+    let public = reader.tpm2b()?.to_vec();
+    skip_creation_info(&mut reader)?;
+    Ok(CreatedPrimary {
+        handle: response.handles[0],
+        public,
+        name: reader.tpm2b()?.to_vec(),
+    })
+}
+
+/// Skips the creationData, creationHash and creationTicket of the response
+/// to TPM2_Create or TPM2_CreatePrimary.
+fn skip_creation_info(reader: &mut TpmReader<'_>) -> Result<(), SvsmVTpmError> {
+    reader.tpm2b()?;
+    reader.tpm2b()?;
+    // TPMT_TK_CREATION
+    reader.u16()?;
+    reader.u32()?;
+    reader.tpm2b()?;
+    Ok(())
+}
+
+/// An object created by TPM2_Create, to be loaded with [`load()`].
+#[derive(Clone, Debug)]
+pub struct CreatedObject {
+    /// The contents of the TPM2B_PRIVATE of the object.
+    pub private: Vec<u8>,
+    /// The marshaled TPMT_PUBLIC of the object.
+    pub public: Vec<u8>,
+}
+
+/// Creates an object from `template` under the key `parent`. The
+/// arguments are the same as for [`create_primary()`].
+pub fn create<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    parent: u32,
+    auth: &AuthSession,
+    sensitive: &SensitiveCreate<'_>,
+    template: &[u8],
+) -> Result<CreatedObject, SvsmVTpmError> {
+    let response = TpmCommand::new(TPM_CC_CREATE)
+        .handle(parent)
+        .session(auth)
+        .param(sensitive)
+        .param(&Tpm2b(template))
+        .param(&Tpm2b(&[])) // outsideInfo
+        .param(NO_PCRS) // creationPCR
+        .send(vtpm)?;
+    let mut reader = response.reader();
+    Ok(CreatedObject {
+        private: reader.tpm2b()?.to_vec(),
+        public: reader.tpm2b()?.to_vec(),
+    })
+}
+
+/// Loads `object` under the key `parent`.
+///
+/// Returns:
+///
+/// The transient handle and the name of the loaded object.
+pub fn load<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    parent: u32,
+    auth: &AuthSession,
+    object: &CreatedObject,
+) -> Result<(u32, Vec<u8>), SvsmVTpmError> {
+    let response = TpmCommand::new(TPM_CC_LOAD)
+        .handle(parent)
+        .session(auth)
+        .param(&Tpm2b(&object.private))
+        .param(&Tpm2b(&object.public))
+        .response_handles(1)
+        .send(vtpm)?;
+    Ok((response.handles[0], response.reader().tpm2b()?.to_vec()))
+}
+
+/// Makes the transient object `object` persistent at `persistent`, or
+/// evicts the persistent object `object` if both handles are the same.
+///
+/// Arguments:
+///
+/// * `vtpm`: An implementation of [`TcgTpmSimulatorInterface`] to send the command to.
+/// * `auth_handle`: TPM_RH_OWNER or TPM_RH_PLATFORM.
+/// * `auth`: The authorization of `auth_handle`.
+/// * `object`: The handle of the object.
+/// * `persistent`: The persistent handle of the object.
+pub fn evict_control<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    auth_handle: u32,
+    auth: &AuthSession,
+    object: u32,
+    persistent: u32,
+) -> Result<(), SvsmVTpmError> {
+    TpmCommand::new(TPM_CC_EVICTCONTROL)
+        .handle(auth_handle)
+        .handle(object)
+        .session(auth)
+        .param(&persistent)
+        .send(vtpm)?;
+    Ok(())
+}
+
+/// Unloads the transient object `handle`.
+pub fn flush_context<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    handle: u32,
+) -> Result<(), SvsmVTpmError> {
+    TpmCommand::new(TPM_CC_FLUSHCONTEXT)
+        .param(&handle)
+        .send(vtpm)?;
+    Ok(())
+}
+
+/// Signs `digest` with the key `key`, which must not be restricted.
+pub fn sign<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    key: u32,
+    auth: &AuthSession,
+    digest: &[u8],
+    scheme: SigScheme,
+) -> Result<Signature, SvsmVTpmError> {
+    let response = TpmCommand::new(TPM_CC_SIGN)
+        .handle(key)
+        .session(auth)
+        .param(&Tpm2b(digest))
+        .param(&scheme)
+        // validation: a NULL ticket is accepted for unrestricted keys
+        .param(&TPM_ST_HASHCHECK)
+        .param(&TPM_RH_NULL)
+        .param(&Tpm2b(&[]))
+        .send(vtpm)?;
+    Signature::unmarshal(&mut response.reader())
+}
+
+/// A quote of PCRs.
+#[derive(Clone, Debug)]
+pub struct Quote {
+    /// The marshaled TPMS_ATTEST which is signed.
+    pub attest: Vec<u8>,
+    pub signature: Signature,
+}
+
+/// Quotes the PCRs in `selections` with the key `key`, including
+/// `qualifying_data` in the quote.
+pub fn quote<T: TcgTpmSimulatorInterface>(
+    vtpm: &T,
+    key: u32,
+    auth: &AuthSession,
+    qualifying_data: &[u8],
+    scheme: SigScheme,
+    selections: &[PcrSelection],
+) -> Result<Quote, SvsmVTpmError> {
+    let response = TpmCommand::new(TPM_CC_QUOTE)
+        .handle(key)
+        .session(auth)
+        .param(&Tpm2b(qualifying_data))
+        .param(&scheme)
+        .param(selections)
+        .send(vtpm)?;
+    let mut reader = response.reader();
+    Ok(Quote {
+        attest: reader.tpm2b()?.to_vec(),
+        signature: Signature::unmarshal(&mut reader)?,
+    })
 }
 
 /// Uses `vtpm` to create an a primary key on the endorsement hierarchy.
@@ -135,12 +1159,229 @@ pub fn create_ek<T: TcgTpmSimulatorInterface>(
     vtpm: &T,
     tpmt_public: &[u8],
 ) -> Result<Vec<u8>, SvsmVTpmError> {
-    let mut cmd = create_mtauth_ek_cmd(tpmt_public);
+    let key = create_primary(
+        vtpm,
+        TPM_RH_ENDORSEMENT,
+        &AuthSession::empty_password(),
+        &SensitiveCreate::default(),
+        tpmt_public,
+    )?;
+    Ok(key.public)
+}
 
-    let mut response = checked_send(vtpm, &mut cmd, /*set_len=*/ false)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::vtpm::TpmPlatformCommand;
+    use crate::vtpm::VtpmProtocolInterface;
+    use alloc::vec;
 
-    // Get size (UINT16) of TPMT_PUBLIC at offset 18.
-    // Note this is output from the TPM, so its value is trusted.
-    let size_of_tpmt_public = u16::from_be_bytes([response[18], response[19]]) as usize;
-    Ok(response.drain(20..(20 + size_of_tpmt_public)).collect())
+    /// A TPM which answers every command with `response`.
+    struct MockTpm {
+        response: Vec<u8>,
+    }
+
+    impl VtpmProtocolInterface for MockTpm {
+        fn get_supported_commands(&self) -> &[TpmPlatformCommand] {
+            &[]
+        }
+    }
+
+    impl TcgTpmSimulatorInterface for MockTpm {
+        fn send_tpm_command(
+            &self,
+            _command: &[u8],
+            _locality: u8,
+        ) -> Result<Vec<u8>, SvsmReqError> {
+            Ok(self.response.clone())
+        }
+
+        fn signal_poweron(&mut self, _only_reset: bool) -> Result<(), SvsmReqError> {
+            Ok(())
+        }
+
+        fn signal_nvon(&self) -> Result<(), SvsmReqError> {
+            Ok(())
+        }
+
+        fn signal_poweroff(&mut self) -> Result<(), SvsmReqError> {
+            Ok(())
+        }
+
+        fn signal_reset(&mut self) -> Result<(), SvsmReqError> {
+            Ok(())
+        }
+
+        fn signal_nvoff(&self) -> Result<(), SvsmReqError> {
+            Ok(())
+        }
+
+        fn signal_cancel_on(&self) -> Result<(), SvsmReqError> {
+            Ok(())
+        }
+
+        fn signal_cancel_off(&self) -> Result<(), SvsmReqError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn command_without_sessions() {
+        let cmd = TpmCommand::new(TPM_CC_STARTUP)
+            .param(&TPM_SU_CLEAR)
+            .marshal()
+            .unwrap();
+        assert_eq!(cmd, [0x80, 0x01, 0, 0, 0, 12, 0, 0, 0x01, 0x44, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn command_with_password_session() {
+        let cmd = TpmCommand::new(TPM_CC_PCR_EXTEND)
+            .handle(4)
+            .session(&AuthSession::password(b"ab"))
+            .param(&0u32)
+            .marshal()
+            .unwrap();
+        #[rustfmt::skip]
+        let expected = [
+            0x80, 0x02, // tag TPM_ST_SESSIONS
+            0, 0, 0, 33, // commandSize
+            0, 0, 0x01, 0x82, // commandCode
+            0, 0, 0, 4, // pcrHandle
+            0, 0, 0, 11, // authorizationSize
+            0x40, 0, 0, 0x09, // sessionHandle TPM_RS_PW
+            0, 0, // nonce
+            0x01, // sessionAttributes continueSession
+            0, 2, b'a', b'b', // hmac
+            0, 0, 0, 0, // parameters
+        ];
+        assert_eq!(cmd, expected);
+    }
+
+    #[test]
+    fn command_too_large() {
+        let data = vec![0u8; TPM_BUFFER_MAX_SIZE];
+        let result = TpmCommand::new(TPM_CC_SEQUENCE_UPDATE)
+            .param(&Tpm2b(&data))
+            .marshal();
+        assert!(matches!(result, Err(SvsmVTpmError::ReqError(_))));
+    }
+
+    #[test]
+    fn reader_tpm2b() {
+        let mut reader = TpmReader::new(&[0, 2, 0xAA, 0xBB, 0xCC]);
+        assert_eq!(reader.tpm2b().unwrap(), [0xAA, 0xBB]);
+        assert_eq!(reader.remaining(), [0xCC]);
+
+        // Truncated size and truncated buffer.
+        assert!(TpmReader::new(&[0]).tpm2b().is_err());
+        assert!(TpmReader::new(&[0, 3, 0xAA, 0xBB]).tpm2b().is_err());
+        // A size larger than any TPM buffer.
+        let mut oversized = vec![0xFF, 0xFF];
+        oversized.resize(TPM_BUFFER_MAX_SIZE, 0);
+        assert!(matches!(
+            TpmReader::new(&oversized).tpm2b(),
+            Err(SvsmVTpmError::MalformedResponse)
+        ));
+    }
+
+    #[test]
+    fn reader_digest_values() {
+        let mut buf = vec![0, 0, 0, 1, 0x00, 0x0B];
+        buf.extend_from_slice(&[0x5A; 32]);
+        let digests = TpmReader::new(&buf).digest_values().unwrap();
+        assert_eq!(digests, [(TPM_ALG_SHA256, vec![0x5A; 32])]);
+
+        // Truncated digest.
+        assert!(TpmReader::new(&buf[..buf.len() - 1])
+            .digest_values()
+            .is_err());
+        // Unknown hash algorithm, whose digest size is unknown.
+        buf[4..6].copy_from_slice(&TPM_ALG_NULL.to_be_bytes());
+        assert!(TpmReader::new(&buf).digest_values().is_err());
+    }
+
+    #[test]
+    fn response_code_fields() {
+        // TPM_RC_HANDLE about handle 1.
+        let rc = TpmRc(0x18B);
+        assert_eq!(rc.code(), TPM_RC_HANDLE);
+        assert_eq!(rc.handle(), Some(1));
+        assert_eq!(rc.parameter(), None);
+        assert_eq!(rc.session(), None);
+        assert!(!rc.is_warning());
+
+        // TPM_RC_VALUE about parameter 2.
+        let rc = TpmRc(0x2C4);
+        assert_eq!(rc.code(), 0x084);
+        assert_eq!(rc.parameter(), Some(2));
+        assert_eq!(rc.handle(), None);
+
+        // TPM_RC_AUTH_FAIL about session 1.
+        let rc = TpmRc(0x98E);
+        assert_eq!(rc.code(), 0x08E);
+        assert_eq!(rc.session(), Some(1));
+        assert_eq!(rc.handle(), None);
+
+        // TPM_RC_RETRY, a format zero warning.
+        let rc = TpmRc(0x922);
+        assert_eq!(rc.code(), 0x922);
+        assert!(rc.is_warning());
+        assert_eq!(rc.parameter(), None);
+
+        // TPM_RC_INITIALIZE, a format zero error.
+        let rc = TpmRc(0x100);
+        assert!(!rc.is_success());
+        assert!(!rc.is_warning());
+        assert!(TpmRc(TPM_RC_SUCCESS).is_success());
+    }
+
+    #[test]
+    fn send_maps_response_code() {
+        let tpm = MockTpm {
+            response: vec![0x80, 0x01, 0, 0, 0, 10, 0, 0, 0x01, 0x8B],
+        };
+        let result = TpmCommand::new(TPM_CC_FLUSHCONTEXT)
+            .param(&0x8000_0000u32)
+            .send(&tpm);
+        assert!(matches!(
+            result,
+            Err(SvsmVTpmError::CommandError(rc)) if rc.code() == TPM_RC_HANDLE
+        ));
+    }
+
+    #[test]
+    fn send_splits_response() {
+        #[rustfmt::skip]
+        let response = vec![
+            0x80, 0x02, // tag TPM_ST_SESSIONS
+            0, 0, 0, 25, // responseSize
+            0, 0, 0, 0, // responseCode
+            0x80, 0, 0, 0x01, // handle
+            0, 0, 0, 2, // parameterSize
+            0xDE, 0xAD, // parameters
+            0, 0, // nonce
+            0x01, // sessionAttributes
+            0, 0, // hmac
+        ];
+        let tpm = MockTpm { response };
+        let auth = AuthSession::empty_password();
+        let cmd = TpmCommand::new(TPM_CC_CREATEPRIMARY)
+            .handle(TPM_RH_OWNER)
+            .session(&auth)
+            .response_handles(1);
+        let response = cmd.send(&tpm).unwrap();
+        assert_eq!(response.handles, [0x8000_0001]);
+        assert_eq!(response.params, [0xDE, 0xAD]);
+        assert_eq!(response.sessions.len(), 1);
+        assert_eq!(response.sessions[0].attributes, 0x01);
+
+        // The size in the header does not match the response.
+        let mut tpm = tpm;
+        tpm.response.push(0);
+        assert!(matches!(
+            cmd.send(&tpm),
+            Err(SvsmVTpmError::MalformedResponse)
+        ));
+    }
 }
@@ -93,6 +93,11 @@ pub struct AttestationRequest {
     pub evidence: String,
     /// Public key generated by SVSM to receive the secret
     pub key: AttestationKey,
+    /// Counter of the vTPM NV image that SVSM unseals with the secret, 0 if there is none. It is
+    /// bound into the evidence through [`crate::NegotiationParam::NvCounter`]. A server only
+    /// releasing the secret for the newest counter it has seen detects a replayed NV image.
+    #[serde(default)]
+    pub nv_counter: u64,
 }
 
 /// Response from proxy to SVSM indicating the status of attestation as well as an optional secret
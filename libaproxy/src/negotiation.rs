@@ -25,6 +25,9 @@ pub enum NegotiationParam {
     /// A base64-encoded byte array. This could represent a nonce or any other data the
     /// attestation server would like to embed in TEE evidence.
     Base64StdBytes(String),
+    /// Hash the counter of the vTPM NV image SVSM unseals with the released secret, as 8
+    /// little-endian bytes. The counter is also sent in [`crate::AttestationRequest::nv_counter`].
+    NvCounter,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
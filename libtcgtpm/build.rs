@@ -28,8 +28,14 @@ fn main() {
         .allowlist_function("_plat__SetNvAvail")
         .allowlist_function("_plat__Signal_PowerOn")
         .allowlist_function("_plat__Signal_Reset")
+        .allowlist_function("_plat__Signal_PowerOff")
+        .allowlist_function("_plat__SetCancel")
+        .allowlist_function("_plat__ClearCancel")
+        .allowlist_function("_plat__ClearNvAvail")
         .allowlist_function("_plat__NVDisable")
         .allowlist_function("_plat__NVEnable")
+        .allowlist_function("_plat__NvMemoryRead")
+        .allowlist_function("_plat__NvMemoryWrite")
         .allowlist_function("TPM_Manufacture")
         .allowlist_function("TPM_TearDown")
         .use_core()
@@ -1,62 +0,0 @@
-/* SPDX-License-Identifier: GPL-2.0-only */
-/*
- * Copyright (c) 2015, Linaro Limited
- */
-
-/*
- * This file provides common defines for ARM SMC Calling Convention as
- * specified in
- * https://developer.arm.com/docs/den0028/latest
- *
- * This code is up-to-date with version DEN 0028 C
- */
-
-#define ARM_SMCCC_STD_CALL	        0U
-#define ARM_SMCCC_FAST_CALL	        1U
-
-#define ARM_SMCCC_TYPE_SHIFT		31
-
-#define ARM_SMCCC_SMC_32		0
-#define ARM_SMCCC_SMC_64		1
-#define ARM_SMCCC_CALL_CONV_SHIFT	30
-
-#define ARM_SMCCC_OWNER_MASK		0x3F
-#define ARM_SMCCC_OWNER_SHIFT		24
-
-#define ARM_SMCCC_FUNC_MASK		0xFFFF
-
-#define ARM_SMCCC_CALL_VAL(type, calling_convention, owner, func_num) \
-	(((type) << ARM_SMCCC_TYPE_SHIFT) | \
-	((calling_convention) << ARM_SMCCC_CALL_CONV_SHIFT) | \
-	(((owner) & ARM_SMCCC_OWNER_MASK) << ARM_SMCCC_OWNER_SHIFT) | \
-	((func_num) & ARM_SMCCC_FUNC_MASK))
-
-#define ARM_SMCCC_OWNER_STANDARD	4
-
-struct arm_smccc_res {
-	unsigned long a0;
-	unsigned long a1;
-	unsigned long a2;
-	unsigned long a3;
-};
-
-/**
- * struct arm_smccc_quirk - Contains quirk information
- * @id: quirk identification
- * @state: quirk specific information
- * @a6: Qualcomm quirk entry for returning post-smc call contents of a6
- */
-struct arm_smccc_quirk {
-	int	id;
-	union {
-		unsigned long a6;
-	} state;
-};
-
-void __arm_smccc_smc(unsigned long a0, unsigned long a1,
-			unsigned long a2, unsigned long a3, unsigned long a4,
-			unsigned long a5, unsigned long a6, unsigned long a7,
-			struct arm_smccc_res *res, struct arm_smccc_quirk *quirk);
-
-#define arm_smccc_smc(...) __arm_smccc_smc(__VA_ARGS__, NULL)
-
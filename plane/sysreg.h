@@ -1,29 +0,0 @@
-#define ICH_LR_STATE    (3ULL << 62)
-
-/*
- * ARMv8 ARM reserves the following encoding for system registers:
- * (Ref: ARMv8 ARM, Section: "System instruction class encoding overview",
- *  C5.2, version:ARM DDI 0487A.f)
- *	[20-19] : Op0
- *	[18-16] : Op1
- *	[15-12] : CRn
- *	[11-8]  : CRm
- *	[7-5]   : Op2
- */
-#define Op0_shift	19
-#define Op0_mask	0x3
-#define Op1_shift	16
-#define Op1_mask	0x7
-#define CRn_shift	12
-#define CRn_mask	0xf
-#define CRm_shift	8
-#define CRm_mask	0xf
-#define Op2_shift	5
-#define Op2_mask	0x7
-
-#define sys_reg(op0, op1, crn, crm, op2) \
-	(((op0) << Op0_shift) | ((op1) << Op1_shift) | \
-	 ((crn) << CRn_shift) | ((crm) << CRm_shift) | \
-	 ((op2) << Op2_shift))
-
-#define SYS_TTBR1_EL1   sys_reg(3, 0, 2, 0, 1)
\ No newline at end of file
@@ -140,6 +140,7 @@ impl GlobalFeatureFlags {
     pub const PLATFORM_TYPE_NATIVE: u64 = 0;
     pub const PLATFORM_TYPE_SNP: u64 = 1;
     pub const PLATFORM_TYPE_TDP: u64 = 2;
+    pub const PLATFORM_TYPE_CCA: u64 = 3;
 
     pub fn is_snp(&self) -> bool {
         (self.bits() & 0x7) == Self::PLATFORM_TYPE_SNP
@@ -148,6 +149,10 @@ impl GlobalFeatureFlags {
     pub fn is_tdp(&self) -> bool {
         (self.bits() & 0x7) == Self::PLATFORM_TYPE_TDP
     }
+
+    pub fn is_cca(&self) -> bool {
+        (self.bits() & 0x7) == Self::PLATFORM_TYPE_CCA
+    }
 }
 
 impl From<u64> for GlobalFeatureFlags {